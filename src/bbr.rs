@@ -0,0 +1,268 @@
+//! Bandwidth and round-trip time estimation, as in BBR
+//! The sender keeps one of these, updated as ACKs arrive; the BBR strategy in congestion sizes its
+//! congestion window from the estimated bandwidth-delay product instead of the fixed window size
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const BW_FILTER_RTTS :u32 = 10;     // delivery rate is max-filtered over this many round trips
+const MIN_RTT_FILTER :Duration = Duration::from_secs(10);   // min RTT is min-filtered over this long
+
+/// What the estimator knew when a packet was sent; needed to compute a rate sample once it's acknowledged
+#[derive(Clone, Copy, Debug)]
+pub struct SendState {
+    pub sent: Instant,          // when the packet was (last) sent
+    delivered: u64,             // bytes delivered when it was sent
+    delivered_time: Instant     // when those bytes had been delivered
+}
+
+/// A windowed max or min filter: keeps only the samples that could still be the extreme
+struct WindowedFilter<T> {
+    samples: VecDeque<(Instant, T)>,
+    keep_max: bool
+}
+
+impl <T> WindowedFilter<T> where T: PartialOrd + Copy {
+    fn new(keep_max: bool) -> WindowedFilter<T> {
+        WindowedFilter { samples: VecDeque::new(), keep_max }
+    }
+
+    /// Add a sample taken now, and forget everything older than window
+    fn update(&mut self, now: Instant, sample: T, window: Duration) {
+        // a sample that's beaten by the new one can never be the extreme again
+        while let Some(&(_, s)) = self.samples.back() {
+            if (self.keep_max && s <= sample) || (!self.keep_max && s >= sample) {
+                self.samples.pop_back();
+            } else {
+                break;
+            }
+        }
+
+        self.samples.push_back((now, sample));
+
+        while let Some(&(t, _)) = self.samples.front() {
+            if now.duration_since(t) > window {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The extreme sample in the window
+    fn get(&self) -> Option<T> {
+        self.samples.front().map(|&(_, s)| s)
+    }
+}
+
+/// The sender's view of the path: how fast it delivers, and how long the round trip is
+pub struct BbrState {
+    delivered: u64,             // bytes acknowledged so far
+    delivered_time: Instant,    // when delivered last changed
+    in_flight: usize,           // packets sent but not yet acknowledged
+    bandwidth: WindowedFilter<f64>,     // delivery rate, in bytes/sec
+    min_rtt: WindowedFilter<Duration>
+}
+
+impl BbrState {
+    pub fn new() -> BbrState {
+        BbrState {
+            delivered: 0,
+            delivered_time: Instant::now(),
+            in_flight: 0,
+            bandwidth: WindowedFilter::new(true),
+            min_rtt: WindowedFilter::new(false)
+        }
+    }
+
+    /// Called when a new packet is sent, returns what needs to be kept w/the packet until it's acknowledged
+    pub fn on_send(&mut self) -> SendState {
+        self.in_flight += 1;
+
+        SendState { sent: Instant::now(), delivered: self.delivered, delivered_time: self.delivered_time }
+    }
+
+    /// Called when a packet of the given size is acknowledged
+    pub fn on_ack(&mut self, state: &SendState, bytes: usize) {
+        let now = Instant::now();
+
+        self.in_flight = self.in_flight.saturating_sub(1);
+        self.delivered += bytes as u64;
+        self.delivered_time = now;
+
+        let rtt = now.duration_since(state.sent);
+
+        self.min_rtt.update(now, rtt, MIN_RTT_FILTER);
+
+        // the rate over the longer of the send and ACK intervals, so ACK compression can't inflate it
+        let interval = rtt.max(now.duration_since(state.delivered_time));
+        let secs = interval.as_secs() as f64 + interval.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        if secs > 0.0 {
+            let rate = (self.delivered - state.delivered) as f64 / secs;
+            let window = self.min_rtt.get().unwrap() * BW_FILTER_RTTS;
+
+            self.bandwidth.update(now, rate, window);
+        }
+    }
+
+    /// The number of packets sent, but not yet acknowledged
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// The max-filtered delivery rate, in bytes/sec
+    pub fn bandwidth(&self) -> Option<f64> {
+        self.bandwidth.get()
+    }
+
+    /// The min-filtered round-trip time
+    pub fn min_rtt(&self) -> Option<Duration> {
+        self.min_rtt.get()
+    }
+}
+
+/// Smoothed round-trip time and its variation, as in RFC 6298
+/// Unlike the min RTT, this follows queueing delay, so it's what a retransmit timeout should come from
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration
+}
+
+impl RttEstimator {
+    pub fn new() -> RttEstimator {
+        RttEstimator { srtt: None, rttvar: Duration::from_secs(0) }
+    }
+
+    /// Add a round-trip time sample; never one from a retransmitted packet, as it's not known which send was ACKed
+    pub fn update(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            },
+            Some(srtt) => {
+                let err = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+
+                // alpha = 1/8, beta = 1/4
+                self.rttvar = self.rttvar * 3 / 4 + err / 4;
+                self.srtt = Some(srtt * 7 / 8 + rtt / 8);
+            }
+        }
+    }
+
+    /// The smoothed round-trip time, None until there's been a sample
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// The round-trip time variation
+    pub fn rttvar(&self) -> Duration {
+        self.rttvar
+    }
+
+    /// The retransmit timeout from RFC 6298: SRTT + max(G, 4 * RTTVAR), w/a 1ms clock granularity
+    /// None until there's been a sample
+    pub fn rto(&self) -> Option<Duration> {
+        self.srtt.map(|srtt| srtt + (self.rttvar * 4).max(Duration::from_millis(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use std::thread;
+
+    use bbr::{BbrState, RttEstimator, WindowedFilter};
+
+    #[test]
+    fn windowed_max() {
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        let mut filter = WindowedFilter::new(true);
+
+        assert_eq!(None, filter.get());
+
+        filter.update(start, 5, window);
+        filter.update(start + Duration::from_secs(1), 3, window);
+        assert_eq!(Some(5), filter.get());
+
+        // the max ages out, leaving the next best
+        filter.update(start + Duration::from_secs(11), 1, window);
+        assert_eq!(Some(3), filter.get());
+
+        filter.update(start + Duration::from_secs(12), 4, window);
+        assert_eq!(Some(4), filter.get());
+    }
+
+    #[test]
+    fn windowed_min() {
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        let mut filter = WindowedFilter::new(false);
+
+        filter.update(start, 5, window);
+        filter.update(start + Duration::from_secs(1), 7, window);
+        assert_eq!(Some(5), filter.get());
+
+        filter.update(start + Duration::from_secs(12), 9, window);
+        assert_eq!(Some(9), filter.get());
+    }
+
+    #[test]
+    fn rtt_estimator() {
+        let mut rtt = RttEstimator::new();
+
+        assert_eq!(None, rtt.srtt());
+        assert_eq!(None, rtt.rto());
+
+        // the first sample is taken as is, w/half of it as the variation
+        rtt.update(Duration::from_millis(100));
+        assert_eq!(Some(Duration::from_millis(100)), rtt.srtt());
+        assert_eq!(Duration::from_millis(50), rtt.rttvar());
+        assert_eq!(Some(Duration::from_millis(300)), rtt.rto());
+
+        rtt.update(Duration::from_millis(180));
+        assert_eq!(Some(Duration::from_millis(110)), rtt.srtt());
+        assert_eq!(Duration::from_millis(57) + Duration::from_micros(500), rtt.rttvar());
+
+        // and it settles on a steady RTT
+        for _ in 0..100 {
+            rtt.update(Duration::from_millis(40));
+        }
+
+        let srtt = rtt.srtt().unwrap();
+
+        assert!(srtt >= Duration::from_millis(40) && srtt < Duration::from_millis(41), "{:?}", srtt);
+        assert!(rtt.rttvar() < Duration::from_millis(1), "{:?}", rtt.rttvar());
+
+        // w/next to no variation, the clock granularity keeps it from being just the RTT
+        assert!(rtt.rto().unwrap() >= srtt + Duration::from_millis(1));
+    }
+
+    #[test]
+    fn bdp_estimate() {
+        let mut bbr = BbrState::new();
+
+        // no estimate yet
+        assert!(bbr.bandwidth().is_none() && bbr.min_rtt().is_none());
+
+        let states = (0..4).map(|_| bbr.on_send()).collect::<Vec<_>>();
+        assert_eq!(4, bbr.in_flight());
+
+        thread::sleep(Duration::from_millis(20));
+
+        for state in &states {
+            bbr.on_ack(state, 1000);
+        }
+
+        assert_eq!(0, bbr.in_flight());
+
+        let rtt = bbr.min_rtt().expect("No RTT");
+        assert!(rtt >= Duration::from_millis(20));
+
+        // 4000 bytes in about 20ms
+        let bw = bbr.bandwidth().expect("No bandwidth");
+        assert!(bw > 100_000.0 && bw <= 200_000.0, "{}", bw);
+    }
+}
@@ -1,19 +1,107 @@
-use std::net::{UdpSocket, SocketAddr};
+use std::net::{UdpSocket, SocketAddr, ToSocketAddrs, IpAddr, Ipv6Addr};
 use std::io::{Error as IOError, ErrorKind};
-use std::time::{Instant, Duration};
-use std::sync::{Mutex, Arc};
-use std::thread;
+use std::time::{Instant, Duration, SystemTime, UNIX_EPOCH};
+use std::sync::{Mutex, Arc, Condvar};
+use std::sync::mpsc::{self, channel};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::collections::{HashMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::mem;
+use std::cell::Cell;
+
+use rand::{thread_rng, Rng};
 
 use transport::Transport;
-use sliding_window::SlidingWindow;
-use config::Configuration;
+use sliding_window::{SlidingWindow, CloseOnDrop};
+use pool::{BufferPool, PooledBuf};
+use config::{Configuration, ChecksumKind};
+use error::QcpError;
 use socket::Socket;
+use fec::{Parity, PARITY_HEADER_SIZE};
+use bbr::{BbrState, SendState, RttEstimator};
+use congestion::{self, CongestionControl};
+use crc32::Crc32;
+use crc32c::crc32c_append;
+use xxhash_rust::xxh3::Xxh3Default;
+use sha256::{hmac_sha256, mac_eq, Sha256, DIGEST_SIZE};
+use crypto::{self, Cipher, PacketAuth, derive_key, TAG_SIZE, AUTH_OVERHEAD};
 
 const MAX_PACKET_SIZE :usize = 1500;    // max size of a packet to be sent over the wire
 pub const MAX_PAYLOAD_SIZE :usize = 1452;   // max payload size to ensure the packet is <= MAX_PACKET_SIZE
 
+const PACKET_OVERHEAD :usize = MAX_PACKET_SIZE - MAX_PAYLOAD_SIZE;   // the most a Message adds to its payload
+const MIN_PATH_MTU :usize = 576;        // every IPv4 host must accept a datagram this large, don't bother w/smaller paths
+
+const WINDOW_STALL_TIMEOUT :Duration = Duration::from_secs(1);  // how long to wait for the reader to make room in a full window
+const MAX_RTO :Duration = Duration::from_secs(60);   // never wait longer than this to retransmit
+const UNIDIRECTIONAL_CONNECTS :usize = 3;   // times to send the Connect when there's no way to know if it made it
+const REVERSE_ATTEMPTS :usize = 5;         // times to send a Reverse before giving up on its ACK
+const STOP_POLL_INTERVAL :Duration = Duration::from_millis(100);  // how long a background thread waits for a packet before checking if it should stop
+const READ_RETRIES :usize = 5;              // transient errors in a row a read is tried again after, see is_retryable
+const READ_RETRY_DELAY :Duration = Duration::from_millis(10);   // how long to wait before trying again
+const MAX_BATCH :usize = 64;                // packets write_all sends w/one send_batch
+const MAX_RECV_BATCH :usize = 32;           // packets the receiver takes w/one recv_batch, then handles one at a time
+const PINGS_PER_IDLE_TIMEOUT :u32 = 4;     // Pings sent to a quiet receiver before the idle timeout gives up on it
+const DUP_ACK_THRESHOLD :usize = 3;         // ACKs that leave the window's start where it was before it's resent, see the ACK thread
+const FINISH_TOTAL_SIZE :usize = 8;    // bytes in the total at the start of a Finish
+const SEQ_RANGE_SIZE :usize = 16;       // bytes in a SeqRange, the start then the end
+const MAX_SACK_RANGES :usize = 32;      // ranges in one SelectiveAck, so it fits even the smallest path
+const CONNECT_NONCE_SIZE :usize = 16;       // random bytes in an authenticated Connect, so every handshake's MACs are different; and its Acknowledge
+const WINDOW_SIZE_SIZE :usize = 8;          // the receiver's window size, at the start of its Acknowledge
+const ACK_ADDR_SIZE :usize = 18;            // the address the sender reads ACKs on, at the end of its Connect: IPv6, then the port
+pub const MAX_STREAMS :usize = 64;          // streams sharing one socket, see connect_streams
+pub const MAX_WINDOW_SIZE :usize = 1 << 20;  // packets in a sliding window, which has a slot for each up front
+const STREAM_QUEUE_SIZE :usize = 4096;      // packets held for a stream that isn't reading, before they're dropped like a full socket buffer
+const DUPLEX :u32 = 1 << 31;                // set in the streams asked for and agreed to, when both ends send, see BbrConnection
+const CHECKSUM_SHIFT :u32 = 16;             // where the checksum is in the streams asked for and agreed to, see checksum_bits
+const CHECKSUM_MASK :u32 = 0xFF << CHECKSUM_SHIFT;
+const HANDSHAKE_CHECKSUM :ChecksumKind = ChecksumKind::Crc32;   // of the Connect and its Acknowledge, sent before there's one agreed on
+const PROTOCOL_VERSION :u16 = 3;            // on the Connect; bumped whenever the wire format changes in a way an older peer would get wrong
+const PROTOCOL_MAGIC :u32 = 0x7163_7021;    // "qcp!", on the Connect so anything else is turned away
+const PACING_GAIN :f64 = 1.25;              // the pacing rate over the congestion window per RTT, so pacing alone never holds the sender back
+const SPIN_SLEEP :Duration = Duration::from_micros(500);    // how much of a pacing delay is spun through, as a sleep can run over by more than a packet's gap
+const MIN_WINDOW :usize = 16;               // packets the window is never tuned below, see target_window
+const WINDOW_GAIN :f64 = 2.0;               // times the bandwidth-delay product the window is tuned to, so it's never what limits the rate
+const WINDOW_TUNE_INTERVAL :Duration = Duration::from_millis(50);  // how often the window is tuned, when there's a --max-window
+
+// MACed along w/the nonce, so the sender's MAC can't be reflected back as the receiver's
+const CONNECT_LABEL :&[u8] = b"qcp connect";
+const ACK_LABEL :&[u8] = b"qcp acknowledge";
+
 use flatbuffers::FlatBufferBuilder;
-use message_generated::bbr::{get_root_as_message, Message, MessageArgs, Type};
+use message_generated::bbr::{get_root_as_message, Message, MessageArgs, SeqRange, Type};
+
+thread_local! {
+    // the connection this thread is working on, 0 until there is one
+    static CONN_ID: Cell<u32> = Cell::new(0);
+}
+
+// every log line in this module starts w/the id of the connection it's about,
+// so the lines of the send and ACK threads, or of several transfers, can be told apart
+macro_rules! conn_log {
+    ($lvl:expr, $($arg:tt)+) => (log!($lvl, "[{:08x}] {}", ::bbr_transport::CONN_ID.with(|id| id.get()), format_args!($($arg)+)))
+}
+
+macro_rules! warn { ($($arg:tt)+) => (conn_log!(::log::Level::Warn, $($arg)+)) }
+macro_rules! info { ($($arg:tt)+) => (conn_log!(::log::Level::Info, $($arg)+)) }
+macro_rules! debug { ($($arg:tt)+) => (conn_log!(::log::Level::Debug, $($arg)+)) }
+macro_rules! trace { ($($arg:tt)+) => (conn_log!(::log::Level::Trace, $($arg)+)) }
+
+/// Starts a new connection on this thread, w/a random id for its log lines
+fn new_conn_id() {
+    set_conn_id(thread_rng().gen());
+}
+
+/// The id of the connection this thread is working on, to carry over to another w/set_conn_id
+fn conn_id() -> u32 {
+    CONN_ID.with(|id| id.get())
+}
+
+/// Carries a connection's id over to another thread working on it
+fn set_conn_id(conn_id: u32) {
+    CONN_ID.with(|id| id.set(conn_id));
+}
 
 pub fn buf2string(buf: &[u8]) -> String {
     let mut ret = String::new();
@@ -28,398 +116,7213 @@ pub fn buf2string(buf: &[u8]) -> String {
 pub struct Sender<T> {
     socket: T,
     remote_addr: SocketAddr,
+    stream_id: u32,         // in every packet, 0 unless it's one of several streams
     seq_num: u64,
-    window: Arc<SlidingWindow<(Instant, Vec<u8>)>>
+    window: Arc<SlidingWindow<(SendState, u32, PooledBuf)>>,   // packets in flight, w/the number of times each was retransmitted
+    pool: BufferPool,       // the packets in the window are checked out of it, and go back once acknowledged; it keeps two windows' worth
+    bbr: Arc<Mutex<BbrState>>,
+    cc: Arc<Mutex<Box<dyn CongestionControl>>>,     // sizes the congestion window, from --cc
+    rtt: Arc<Mutex<RttEstimator>>,     // smoothed RTT, from ACKs of packets that weren't retransmitted
+    acked: Arc<Condvar>,    // signaled w/bbr whenever an ACK makes room in the congestion window
+    progress: Arc<Mutex<Progress>>,
+    cipher: Option<Cipher>, // encrypts payloads, when encrypting
+    auth: Option<PacketAuth>,   // tags every packet after the handshake, when there's a pre-shared key
+    checksum: ChecksumKind, // of every packet after the handshake, agreed on in it
+    unidirectional: bool,   // no ACKs, send parity instead
+    fec_block_size: usize,
+    path_mtu: usize,        // the largest packet that makes it to the receiver
+    max_payload_size: usize,    // the largest payload that fits in a packet of path_mtu
+    payload_size: Option<usize>,    // the most data put in a packet, if less than fits
+    parity: Parity,         // parity of the current block, when unidirectional
+    pacer: Option<Pacer>,   // when --max-rate is given
+    nonblocking: bool,      // write_all returns WouldBlock instead of waiting for room, from --nonblocking
+    resume: usize,          // how much of the buf passed to write_all was sent before it returned WouldBlock
+    next_send: Option<Instant>, // when the next packet can go, spaced out by the pacing rate
+    coalesce: bool,         // write_all holds less than a packet's worth until more is written, from --coalesce
+    pending: Vec<u8>,       // what write_all is holding, always less than a packet's worth
+    bytes_transferred: AtomicU64,   // payload bytes sent, not counting headers or retransmits
+    counters: Arc<Counters>,        // packets sent, retransmitted, and acknowledged twice; for stats
+    started: Instant,               // when the handshake finished, for stats
+    reverse: Arc<SlidingWindow<Vec<u8>>>,   // Reverse payloads from the receiver, in order for read
+    reverse_leftover: Vec<u8>,      // the part of the last Reverse payload that didn't fit in the caller's buf
+    hash: Option<Sha256>,           // of everything written, sent w/the Finish when verifying
+    stop: Arc<AtomicBool>,          // tells the thread reading ACKs to exit
+    thread: Option<JoinHandle<()>>, // the thread reading ACKs, none when unidirectional
+    errors: mpsc::Receiver<IOError> // why the thread reading ACKs exited, if it was an error
 }
 
 pub struct Receiver<T> {
     socket: T,
-    remote_addr: SocketAddr,
-    window: Arc<SlidingWindow<Vec<u8>>>
+    remote_addr: Arc<Mutex<SocketAddr>>,   // moves when a tagged packet comes from somewhere new, see start
+    stream_id: u32,                 // in every packet, 0 unless it's one of several streams
+    window: Arc<SlidingWindow<(Type, PooledBuf)>>,  // Messages, and the control messages read needs to see in order
+    pool: BufferPool,               // the payloads in the window are checked out of it, and go back once read; it keeps two windows' worth
+    buffered: Arc<AtomicU64>,       // payload bytes in the window, waiting to be read
+    bytes_transferred: AtomicU64,   // payload bytes read
+    received: u64,                  // payload bytes read, or skipped over by next_file; checked against the Finish's total
+    leftover: Option<PooledBuf>,    // the part of the last packet that didn't fit in the caller's buf
+    next_file: Option<FileHeader>,  // a FileStart that read ran into, waiting for next_file
+    skip: Option<u64>,              // a Skip that read ran into, waiting for take_skip
+    in_file: bool,                  // next_file returned a header, and its FileEnd hasn't been read yet
+    finished: bool,                 // read everything up to the Finish
+    hash: Option<Sha256>,           // of everything read, checked against the Finish's when verifying
+    cipher: Option<Cipher>,         // encrypts Reverse payloads, when encrypting
+    auth: Option<PacketAuth>,       // tags every packet after the handshake, when there's a pre-shared key
+    checksum: ChecksumKind,         // of every packet after the handshake, agreed on in it
+    unidirectional: bool,           // no way to send anything back
+    rto: Duration,                  // how long to wait for a Reverse to be acknowledged before resending it
+    reverse_seq_num: u64,           // the next Reverse's sequence number
+    reverse_acked: Arc<(Mutex<u64>, Condvar)>,  // one past the last Reverse the sender acknowledged, signaled as it grows
+    counters: Arc<RecvCounters>,    // what the thread reading packets saw, for ReceiverStats
+    started: Instant,               // when the handshake finished
+    stop: Arc<AtomicBool>,          // tells the thread reading packets to exit
+    thread: Option<JoinHandle<()>>, // the thread reading packets
+    errors: mpsc::Receiver<IOError> // why the thread reading packets exited, if it was an error
 }
 
-/// Constructs a simple message w/out a payload
-fn construct_message<'a>(msg_type: Type, seq_num: u64) -> FlatBufferBuilder<'a> {
-    let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+/// Both ends sending and receiving at once, over one socket
+/// Stream 0 carries what the end that connected sends, and stream 1 what the end that listened sends,
+/// each w/its own window, so neither direction waits on the other
+pub struct BbrConnection<T> {
+    sender: Sender<StreamSocket<T>>,
+    recver: Receiver<StreamSocket<T>>
+}
 
-    let msg = Message::create(&mut fbb, &MessageArgs { msg_type, seq_num, payload: None });
+/// Announces the next file, when sending more than one, or when preserving its attributes
+/// The only file, lone or w/an empty name, goes wherever the receiver was told to put it, unless it was given --output-dir
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileHeader {
+    pub name: String,
+    pub len: u64,                   // UNKNOWN_LEN when it's a stream, like a pipe, w/out one
+    pub mode: Option<u32>,          // the Unix permissions, when preserving them
+    pub mtime: Option<SystemTime>,  // the modification time, when preserving it
+    pub lone: bool,                 // the only file; named only for a receiver putting it in a directory
+    pub block_size: Option<u32>,    // the sender wants the hashes of the receiver's copy, in blocks this size, and only sends what differs
+    pub verify_only: bool           // the sender only wants the hashes, and the receiver's length, to compare; nothing is sent or written
+}
 
-    fbb.finish(msg, None);
+/// The length of a file that's a stream, like stdin from a pipe, whose end is only known once it's reached
+/// The receiver can't set anything aside for it, so it grows as it arrives; the Finish has the total sent
+pub const UNKNOWN_LEN :u64 = u64::MAX;
 
-    // TODO: Remove the need to return the FBB
-    return fbb;
-}
+const HAS_MODE :u8 = 0x01;     // a FileStart carries the mode
+const HAS_MTIME :u8 = 0x02;    // a FileStart carries the mtime
+const LONE :u8 = 0x04;         // a FileStart is for the only file
+const HAS_BLOCK_SIZE :u8 = 0x08;   // a FileStart carries the block size, for a delta
+const VERIFY_ONLY :u8 = 0x10;  // a FileStart only asks for the block hashes; it needs a block size
 
-impl <T: 'static> Sender<T> where T: Socket + Send + Sync {
-    /// Connect, via BBR, to a remote host
-    pub fn connect(socket: T, config: &Configuration) -> Result<impl Transport, IOError> {
-        let remote_addr = config.addr();
+impl FileHeader {
+    /// The payload of a FileStart: the length, which attributes follow, the attributes, then the name
+    /// The mtime is seconds and nanoseconds since the epoch
+    fn encode(&self) -> Vec<u8> {
+        let mut payload = self.len.to_be_bytes().to_vec();
+        let flags = if self.mode.is_some() { HAS_MODE } else { 0 } | if self.mtime.is_some() { HAS_MTIME } else { 0 } | if self.lone { LONE } else { 0 } | if self.block_size.is_some() { HAS_BLOCK_SIZE } else { 0 } | if self.verify_only { VERIFY_ONLY } else { 0 };
 
-        // set the read and write timeouts to 3s
-        socket.set_read_timeout(Some(Duration::new(3, 0)))?;
-        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+        payload.push(flags);
 
-        // construct the Connect message
-        let msg_data = construct_message(Type::Connect, 0);
-        let msg_data = msg_data.finished_data();
+        if let Some(mode) = self.mode {
+            payload.extend_from_slice(&mode.to_be_bytes());
+        }
 
-        if msg_data.len() > MAX_PACKET_SIZE {
-            panic!("Packet size too large: {}", msg_data.len());
+        if let Some(mtime) = self.mtime {
+            // before the epoch is rare enough to just call it the epoch
+            let since = mtime.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+
+            payload.extend_from_slice(&since.as_secs().to_be_bytes());
+            payload.extend_from_slice(&since.subsec_nanos().to_be_bytes());
         }
 
-        // send the connection message
-        socket.send_to(&msg_data, remote_addr).expect("Could not send connect message");
+        if let Some(block_size) = self.block_size {
+            payload.extend_from_slice(&block_size.to_be_bytes());
+        }
 
-        let mut buf = vec![0; MAX_PACKET_SIZE];
+        payload.extend_from_slice(self.name.as_bytes());
 
-        for i in 0..3 {
-            let ret = socket.recv_from(&mut buf);
+        return payload;
+    }
 
-            debug!("{}: {:?}", i, ret);
+    fn decode(payload: &[u8]) -> Result<FileHeader, IOError> {
+        let too_short = || IOError::new(ErrorKind::InvalidData, "FileStart too short");
 
-            if let Result::Err(e) = ret {
-                // check for other errors than a blocking one
-                if e.kind() != ErrorKind::WouldBlock {
-                    return Err(e);
-                    // check to see if we've tried enough time
-                } else if i >= 2 {
-                    return Err(IOError::new(ErrorKind::ConnectionAborted, "Did not get Acknowledge on Connect"));
-                }
-            } else {
-                break; // it all worked!
-            }
+        if payload.len() < 9 {
+            return Err(too_short());
         }
 
-        debug!("RET: {}", buf2string(&buf));
+        let mut len = [0u8; 8];
 
-        let ack = get_root_as_message(&buf);
+        len.copy_from_slice(&payload[..8]);
 
-        if ack.msg_type() != Type::Acknowledge {
-            return Err(IOError::new(ErrorKind::ConnectionAborted, "Got other message type than Acknowledge on Connect"));
-        }
+        let flags = payload[8];
+        let mut rest = &payload[9..];
 
-        if ack.seq_num() != 0 {
-            return Err(IOError::new(ErrorKind::InvalidData, "Acknowledged wrong sequence number"));
-        }
+        let mode = if flags & HAS_MODE == 0 { None } else {
+            if rest.len() < 4 {
+                return Err(too_short());
+            }
 
-        let window = Arc::new(SlidingWindow::new(config.window_size()));
+            let mut mode = [0u8; 4];
 
-        let recv_socket :T = socket.try_clone()?;
-        let recv_window = window.clone();
+            mode.copy_from_slice(&rest[..4]);
+            rest = &rest[4..];
 
-        thread::spawn(move || {
-            // we'll only wait for 1s for an Ack
-            recv_socket.set_read_timeout(Some(Duration::from_secs(1))).expect("Could not set read timeout");
+            Some(u32::from_be_bytes(mode))
+        };
 
-            let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mtime = if flags & HAS_MTIME == 0 { None } else {
+            if rest.len() < 12 {
+                return Err(too_short());
+            }
 
-            loop {
-                // attempt to read an ack
-                let res = recv_socket.recv_from(&mut buf);
+            let mut secs = [0u8; 8];
+            let mut nanos = [0u8; 4];
 
-                // waited for an Ack, but didn't come
-                if let Err(e) = res {
-                    if e.kind() != ErrorKind::WouldBlock {
-                        panic!("Unknown error reading ACK: {:?}", e);
-                    }
+            secs.copy_from_slice(&rest[..8]);
+            nanos.copy_from_slice(&rest[8..12]);
+            rest = &rest[12..];
 
-                    // find the first one that matches the predicate
-                    let loc = recv_window.find_first(|t :&(Instant, Vec<u8>)| t.0.elapsed() > Duration::from_secs(3));
+            let nanos = u32::from_be_bytes(nanos);
 
-                    // we're able to find any old enough, loop back around
-                    if loc.is_none() {
-                        continue;
-                    }
+            if nanos >= 1_000_000_000 {
+                return Err(IOError::new(ErrorKind::InvalidData, "FileStart mtime has too many nanoseconds"));
+            }
 
-                    let loc = loc.unwrap() as u64;
+            UNIX_EPOCH.checked_add(Duration::new(u64::from_be_bytes(secs), nanos))
+        };
 
-                    // remove the packet from the window, so we can update the time
-                    let (_, packet) = recv_window.remove(loc).expect("Error removing item we previously found");
+        let block_size = if flags & HAS_BLOCK_SIZE == 0 { None } else {
+            if rest.len() < 4 {
+                return Err(too_short());
+            }
 
-                    // re-send the packet
-                    recv_socket.send_to(&packet, remote_addr);
+            let mut block_size = [0u8; 4];
 
-                    // re-insert the packet with an updated timeout
-                    recv_window.insert(loc, (Instant::now(), packet));
-                } else if res.is_ok() {
-                    // otherwise, we got a message
-                    let (amt, _) = res.unwrap();
-                    let ack = get_root_as_message(&buf[0..amt]);
+            block_size.copy_from_slice(&rest[..4]);
+            rest = &rest[4..];
 
-                    if ack.msg_type() != Type::Acknowledge {
-                        panic!("Got non-ack message");
-                    }
+            match u32::from_be_bytes(block_size) {
+                0 => return Err(IOError::new(ErrorKind::InvalidData, "FileStart block size is 0")),
+                block_size => Some(block_size)
+            }
+        };
 
-                    // remove it from the sliding window
-                    let (sent_time, _) = recv_window.remove(ack.seq_num()).expect("Acknowledging bad sequence number");
+        // there's nothing to compare w/out blocks to hash
+        if flags & VERIFY_ONLY != 0 && block_size.is_none() {
+            return Err(IOError::new(ErrorKind::InvalidData, "FileStart is verify only, w/out a block size"));
+        }
 
-                    // TODO: deal with the instant values
-                }
-            }
-        });
+        // the receiver hashes its copy's blocks as far as the sender's length, so there has to be one
+        if block_size.is_some() && u64::from_be_bytes(len) == UNKNOWN_LEN {
+            return Err(IOError::new(ErrorKind::InvalidData, "FileStart has a block size, but no length"));
+        }
 
-        return Ok(Sender { socket, remote_addr, seq_num: 0, window });
+        match String::from_utf8(rest.to_vec()) {
+            Ok(name) => return Ok(FileHeader { name, len: u64::from_be_bytes(len), mode, mtime, lone: flags & LONE != 0, block_size, verify_only: flags & VERIFY_ONLY != 0 }),
+            Err(_) => return Err(IOError::new(ErrorKind::InvalidData, "File name is not UTF-8"))
+        }
     }
 }
 
-impl <T: 'static> Receiver<T> where T: Socket + Send + Sync {
-    /// Listens for an incoming connection
-    pub fn listen(socket: T, config: &Configuration) -> Result<impl Transport, IOError> {
-        // set the write timeouts to 3s
-        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
-
-        let mut buf = vec![0; MAX_PACKET_SIZE];
-        let (buf_size, remote_addr) = socket.recv_from(&mut buf)?;
+/// The payload of a Skip: the number of bytes the receiver keeps
+fn decode_skip(payload: &[u8]) -> Result<u64, IOError> {
+    if payload.len() != 8 {
+        return Err(IOError::new(ErrorKind::InvalidData, "Malformed Skip"));
+    }
 
-        let msg = get_root_as_message(&buf);
+    let mut len = [0u8; 8];
 
-        if msg.msg_type() != Type::Connect {
-            return Err(IOError::new(ErrorKind::ConnectionAborted, "Got non-connect message"));
-        }
+    len.copy_from_slice(payload);
 
-        // construct the ACK message
-        let ack_data = construct_message(Type::Acknowledge, msg.seq_num());
-        let ack_data = ack_data.finished_data();
+    return Ok(u64::from_be_bytes(len));
+}
 
-        // send the ACK message
-        socket.send_to(ack_data, remote_addr);
+/// How much of a transfer has been acknowledged, and who to tell about it
+struct Progress {
+    acked: u64,     // payload bytes acknowledged
+    total: u64,     // payload bytes expected, if known; otherwise 0
+    callback: Option<Box<dyn Fn(u64, u64) + Send>>,
+    report: Option<(Duration, Box<dyn Fn(&ProgressReport) + Send>)>,    // called no more often than the interval, see set_report
+    last_report: Option<(Instant, u64)>     // when report was last called, and what was acknowledged then
+}
 
-        let window = Arc::new(SlidingWindow::new(config.window_size()));
+impl Progress {
+    fn new() -> Progress {
+        Progress { acked: 0, total: 0, callback: None, report: None, last_report: None }
+    }
+}
 
-        let socket_clone :T = socket.try_clone()?;
-        let recv_window = window.clone();
+/// How a transfer's going, from Sender::set_report
+#[derive(Clone, Debug)]
+pub struct ProgressReport {
+    pub acked: u64,     // payload bytes acknowledged
+    pub total: u64,     // payload bytes expected, if known; otherwise 0
+    pub rate_bps: u64,  // payload bits per second acknowledged since the last report
+    pub rtt: Duration   // smoothed round-trip time
+}
 
-        thread::spawn(move || {
-            socket_clone.set_read_timeout(None).expect("Could not set read timeout");
+impl ProgressReport {
+    /// A line of JSON, for something other than a person to read
+    pub fn to_json(&self) -> String {
+        let rtt_ms = self.rtt.as_secs() as f64 * 1000.0 + self.rtt.subsec_nanos() as f64 / 1_000_000.0;
 
-            let mut buf = vec![0; MAX_PACKET_SIZE];
+        return format!("{{\"acked\":{},\"total\":{},\"rate_bps\":{},\"rtt_ms\":{:.3}}}", self.acked, self.total, self.rate_bps, rtt_ms);
+    }
+}
 
-            loop {
-                // read a message
-                let res = socket_clone.recv_from(&mut buf);
+/// A token bucket, keeping the average send rate under --max-rate
+/// It holds at most one packet's worth, so there's never more than a packet's burst
+struct Pacer {
+    rate: f64,          // bytes per second
+    tokens: f64,        // bytes that can be sent now; negative once a packet is sent on credit
+    refilled: Instant   // when tokens was last topped up
+}
 
-                if let Err(e) = res {
-                    panic!("Error reading message: {:?}", e);
-                }
+impl Pacer {
+    fn new(bits_per_sec: u64) -> Pacer {
+        Pacer { rate: bits_per_sec as f64 / 8.0, tokens: MAX_PACKET_SIZE as f64, refilled: Instant::now() }
+    }
 
-                let (amt, _) = res.expect("Error unwrapping OK");
-                let message = get_root_as_message(&buf[0..amt]);
+    /// Takes len bytes from the bucket, returning how long to wait before sending them
+    /// A packet is always allowed on credit, so even a cap below one packet per second makes progress
+    fn reserve(&mut self, len: usize, now: Instant) -> Duration {
+        let elapsed = now.duration_since(self.refilled);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
 
-                if message.msg_type() != Type::Message {
-                    panic!("Unexpected message type: {:?}", message.msg_type());
-                }
+        self.tokens = (self.tokens + elapsed * self.rate).min(MAX_PACKET_SIZE as f64);
+        self.refilled = now;
+        self.tokens -= len as f64;
 
-                let seq_num = message.seq_num();
+        if self.tokens >= 0.0 {
+            return Duration::from_secs(0);
+        }
 
-                let (start, end) = recv_window.window();
+        return Duration::from_nanos((-self.tokens / self.rate * 1_000_000_000.0) as u64);
+    }
+}
 
-                // check to see if the message is old
-                // messages that are >= end, we'll simply block on insert waiting for the
-                // reader to pick-up everything else
-                if seq_num < start {
-                    continue;
-                }
+/// Sleeps for dur, spinning through the last SPIN_SLEEP of it, as the OS can wake up late by more than a packet's gap
+fn sleep_precisely(dur: Duration) {
+    let deadline = Instant::now() + dur;
 
-                let payload = message.payload().expect("No payload for message");
+    if dur > SPIN_SLEEP {
+        thread::sleep(dur - SPIN_SLEEP);
+    }
 
-                debug!("RECV PACKET: {} at {}", payload.len(), seq_num);
+    while Instant::now() < deadline {
+        thread::yield_now();
+    }
+}
 
-                // insert the packet into the window
-                recv_window.insert(seq_num, payload.to_vec());
+/// What the receiver has seen of a block of packets, when unidirectional
+struct FecBlock {
+    received: Vec<bool>,            // which packets of the block we've seen
+    parity: Parity,                 // parity of the packets we've seen
+    block_parity: Option<Vec<u8>>   // the sender's parity for the whole block, once it arrives
+}
 
-                let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
-                let ack = Message::create(&mut fbb, &MessageArgs { msg_type: Type::Acknowledge, seq_num, payload: None });
+impl FecBlock {
+    fn new(block_size: usize) -> FecBlock {
+        FecBlock { received: vec![false; block_size], parity: Parity::new(), block_parity: None }
+    }
+}
 
-                fbb.finish(ack, None);
+/// Rebuilds the missing packet of a block, once we have its parity and all but one of its packets
+/// Forgets about the block once it's complete, or can't be recovered
+/// A recovered packet is added to buffered, even past the limit, as it can't be asked for again
+fn recover_block(blocks: &mut HashMap<u64, FecBlock>, block_start: u64, window: &SlidingWindow<(Type, PooledBuf)>, buffered: &AtomicU64, pool: &BufferPool) {
+    let recovered = match blocks.get(&block_start) {
+        None => return,
+        Some(block) => {
+            let have = block.parity.count();
 
-                let ack_buf = fbb.finished_data().to_vec();
+            if have == block.received.len() {
+                None // got everything, nothing to recover
+            } else if have + 1 < block.received.len() || block.block_parity.is_none() {
+                return; // wait for more of the block
+            } else {
+                let missing = block.received.iter().position(|&r| !r).expect("No missing packet in block");
+                let payload = block.parity.recover(block.block_parity.as_ref().unwrap());
 
-                if ack_buf.len() > MAX_PACKET_SIZE {
-                    panic!("About to send ACK packet larger than max packet: {} > {}", ack_buf.len(), MAX_PACKET_SIZE);
+                if payload.is_none() {
+                    warn!("Bad parity for block at {}", block_start);
                 }
 
-                socket_clone.send_to(&ack_buf, remote_addr);
+                payload.map(|p| (block_start + missing as u64, p))
             }
-        });
+        }
+    };
+
+    blocks.remove(&block_start);
+
+    if let Some((seq_num, payload)) = recovered {
+        let (start, end) = window.window();
 
-        return Ok(Receiver { socket, remote_addr, window });
+        debug!("RECOVERED PACKET: {} at {}", payload.len(), seq_num);
+
+        let len = payload.len() as u64;
+
+        if seq_num < start || seq_num >= end {
+            warn!("Recovered packet outside the window, dropping: {} -> {}; {}", start, end, seq_num);
+        } else if let Err(e) = window.insert(seq_num, (Type::Message, pool.wrap(payload))) {
+            warn!("Error inserting recovered packet {}: {}", seq_num, e);
+        } else {
+            buffered.fetch_add(len, Ordering::AcqRel);
+        }
     }
 }
 
-impl <T> Transport for Sender<T> where T: Socket {
-    fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
-        panic!("Not implemented");
-    }
+/// Constructs the Connect message, asking for streams streams, checksummed w/checksum after it; w/a key it carries a fresh nonce,
+/// and a MAC of it to prove we know the key
+/// ack_addr, after the nonce, is where we read ACKs, so the receiver sends them there; see ack_addr
+/// Also returns the nonce, if there is one
+fn construct_connect<'a>(key: Option<&[u8]>, streams: u32, checksum: ChecksumKind, ack_addr: Option<SocketAddr>) -> (FlatBufferBuilder<'a>, Option<[u8; CONNECT_NONCE_SIZE]>) {
+    let streams = streams | checksum_bits(checksum);
+    let ack_addr = ack_addr.map(encode_ack_addr);
+    let ack_addr = ack_addr.as_ref().map_or(&[][..], |addr| &addr[..]);
 
-    fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
-        let chunk_it = buf.chunks(MAX_PAYLOAD_SIZE);
+    match key {
+        None if ack_addr.is_empty() => return (construct_message(HANDSHAKE_CHECKSUM, Type::Connect, streams, 0), None),
+        None => return (construct_payload_message(HANDSHAKE_CHECKSUM, Type::Connect, streams, 0, ack_addr), None),
+        Some(key) => {
+            let mut nonce = [0u8; CONNECT_NONCE_SIZE];
 
-        for chunk in chunk_it {
-            debug!("CHUNK LEN: {}", chunk.len());
+            thread_rng().fill(&mut nonce);
 
-            // construct the message w/the payload
-            let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
-            let payload = Some(fbb.create_vector(chunk));
-            let msg = Message::create(&mut fbb, &MessageArgs { msg_type: Type::Message, seq_num: self.seq_num, payload });
+            let payload = [&nonce[..], ack_addr].concat();
+            let mac = hmac_sha256(key, &[CONNECT_LABEL, &payload]);
 
-            fbb.finish(msg, None);
-            let msg_buf = fbb.finished_data().to_vec();
+            return (construct_mac_message(HANDSHAKE_CHECKSUM, Type::Connect, streams, 0, &payload, &mac), Some(nonce));
+        }
+    }
+}
 
-            if msg_buf.len() > MAX_PACKET_SIZE {
-                panic!("About to send a packet larger than max packet: {} > {}", msg_buf.len(), MAX_PACKET_SIZE);
-            }
+/// The address the sender reads ACKs on, as it's carried in the Connect: IPv6, w/IPv4 mapped into it, then the port
+fn encode_ack_addr(addr: SocketAddr) -> [u8; ACK_ADDR_SIZE] {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip
+    };
+    let mut encoded = [0u8; ACK_ADDR_SIZE];
 
-            debug!("SENDING SEQ: {} LEN: {}", self.seq_num, msg_buf.len());
-            trace!("PACKET: {}", buf2string(msg_buf.as_slice()));
+    encoded[..16].copy_from_slice(&ip.octets());
+    encoded[16..].copy_from_slice(&addr.port().to_be_bytes());
 
-            let mut end = { self.window.window().1 };
+    return encoded;
+}
 
-//            // wait for a slot in the window
-//            while end <= self.seq_num {
-//                let window = self.window.window();
-//                panic!("Yielding on write_all: {} -> {}; {}", window.0, window.1, self.seq_num);
-//                thread::yield_now();
-//
-//                end = { self.window.window().1 };
-//            }
+fn decode_ack_addr(encoded: &[u8]) -> Option<SocketAddr> {
+    if encoded.len() != ACK_ADDR_SIZE {
+        return None;
+    }
 
-//            {
-                self.socket.send_to(&msg_buf, self.remote_addr); // send the packet
-                self.window.insert(self.seq_num, (Instant::now(), msg_buf)); // insert into the window
-                self.seq_num += 1; // bump our sequence number
-//            }
+    let mut ip = [0u8; 16];
+    let mut port = [0u8; 2];
 
-        }
+    ip.copy_from_slice(&encoded[..16]);
+    port.copy_from_slice(&encoded[16..]);
 
-        return Ok( () );
+    let ip = Ipv6Addr::from(ip);
+    let ip = ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4);
+
+    return Some(SocketAddr::new(ip, u16::from_be_bytes(port)));
+}
+
+/// Where the receiver sends the Acknowledge and its ACKs: the address the Connect says the sender reads them on,
+/// if it's on the same host the Connect came from
+/// Any other host could be the sender's own address behind a NAT, or someone else's entirely, so they go back where the Connect came from
+/// A duplex end's socket is connected to where the Connect came from, so everything goes there too
+fn ack_addr(carried: Option<SocketAddr>, from: SocketAddr, duplex: bool) -> SocketAddr {
+    match carried {
+        Some(addr) if !duplex && addr.ip() == from.ip() && addr.port() != 0 => addr,
+        _ => from
     }
 }
 
-impl <T> Transport for Receiver<T> where T: Socket {
-    fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
-        let packet = self.window.pop();
+/// The MAC the receiver puts on its Acknowledge of an authenticated Connect, to prove it knows the key too
+/// It covers the Acknowledge's payload and the streams agreed to, so the receiver's nonce and window size can't be swapped out
+fn ack_mac(key: &[u8], nonce: &[u8], streams: u32, payload: &[u8]) -> [u8; DIGEST_SIZE] {
+    hmac_sha256(key, &[ACK_LABEL, nonce, &streams.to_be_bytes(), payload])
+}
 
-        buf[..packet.len()].copy_from_slice(packet.as_slice());
+/// The nonces from both ends of the handshake, which key everything after it
+/// When unidirectional there's no Acknowledge, so only the Connect's
+fn session_nonce(connect_nonce: Option<&[u8]>, ack_nonce: &[u8]) -> Vec<u8> {
+    [connect_nonce.unwrap_or(&[]), ack_nonce].concat()
+}
 
-        debug!("READ: {} length buf", packet.len());
+/// The cipher for payloads, if we're encrypting, and what tags every packet, if there's a key
+/// Both are keyed from the pre-shared key and the nonces from the handshake
+fn session_keys(config: &Configuration, nonce: &[u8]) -> Result<(Option<Cipher>, Option<PacketAuth>), QcpError> {
+    let auth = config.key().map(|key| PacketAuth::new(key, nonce));
 
-        return Ok(packet.len());
+    if !config.encrypt() {
+        return Ok((None, auth));
     }
 
-    fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
-        panic!("Not implemented");
+    match config.key() {
+        None => return Err(QcpError::Config("Encryption requires a pre-shared key".to_string())),
+        Some(key) => return Ok((Some(Cipher::new(derive_key(key, nonce))), auth))
     }
 }
 
+/// The nonce for encrypting a packet's payload; the type, stream, and sequence number never repeat together in a transfer
+fn packet_nonce(msg_type: Type, stream_id: u32, seq_num: u64) -> [u8; crypto::NONCE_SIZE] {
+    let mut nonce = [0u8; crypto::NONCE_SIZE];
 
-#[cfg(test)]
-mod tests {
-    use simplelog::{TermLogger, LevelFilter, Config};
+    nonce[0] = msg_type as u8;
+    nonce[2..4].copy_from_slice(&(stream_id as u16).to_be_bytes());   // there are never more than MAX_STREAMS
+    nonce[4..].copy_from_slice(&seq_num.to_be_bytes());
 
-    use bbr_transport::{Sender, Receiver, buf2string, MAX_PAYLOAD_SIZE, MAX_PACKET_SIZE};
-    use config::Configuration;
-    use socket::Socket;
-    use transport::Transport;
-    use std::net::{SocketAddr, UdpSocket};
-    use std::thread;
+    return nonce;
+}
 
-    use flatbuffers::FlatBufferBuilder;
-    use message_generated::bbr::{get_root_as_message, Message, MessageArgs, Type};
+/// Asks for the kernel buffers config says, if it says, logging what was granted, as it's often clamped
+/// One the socket can't have only gets a warning; the transfer still works, it may just drop more at high rates
+fn set_buffer_sizes<T: Socket>(socket: &T, config: &Configuration) {
+    if let Some(size) = config.so_rcvbuf() {
+        match socket.set_recv_buffer_size(size).and_then(|_| socket.recv_buffer_size()) {
+            Ok(granted) if granted < size => warn!("Asked for a {} byte receive buffer, but only got {}; raise net.core.rmem_max for more", size, granted),
+            Ok(granted) => info!("Receive buffer is {} bytes", granted),
+            Err(e) => warn!("Could not set the receive buffer to {} bytes: {}", size, e)
+        }
+    }
 
-    use socket::mocks::PacketDroppingSocket;
-    use rand::{thread_rng, Rng};
+    if let Some(size) = config.so_sndbuf() {
+        match socket.set_send_buffer_size(size).and_then(|_| socket.send_buffer_size()) {
+            Ok(granted) if granted < size => warn!("Asked for a {} byte send buffer, but only got {}; raise net.core.wmem_max for more", size, granted),
+            Ok(granted) => info!("Send buffer is {} bytes", granted),
+            Err(e) => warn!("Could not set the send buffer to {} bytes: {}", size, e)
+        }
+    }
+}
 
-    #[test]
-    fn udp_connect() {
-        TermLogger::init(LevelFilter::Debug, Config::default()).unwrap();
-        let local_addr = SocketAddr::new("0.0.0.0".parse().unwrap(), 1234);
-        let socket = UdpSocket::bind(local_addr).expect("Couldn't bind socket");
+/// Finds the largest packet, up to MAX_PACKET_SIZE, that can be sent to the connected peer w/out fragmenting
+/// Probes are padded out to each size, and sending one that's too large fails w/EMSGSIZE
+/// Don't Fragment is left set, so the packets sent later fail the same way if the path shrinks
+fn discover_path_mtu<T: Socket>(socket: &T, auth: &Option<PacketAuth>, checksum: ChecksumKind) -> Result<usize, IOError> {
+    if let Err(e) = socket.set_dont_fragment(true) {
+        warn!("Could not set Don't Fragment, assuming a path MTU of {}: {}", MAX_PACKET_SIZE, e);
+        return Ok(MAX_PACKET_SIZE);
+    }
 
-        let t = Sender::<UdpSocket>::connect(socket, &Default::default());
+    let probe = construct_message(checksum, Type::Probe, 0, 0).finished_data().to_vec();
 
-        assert!(t.is_err());
+    // anything after the message is ignored, so pad w/zeros to the size we want to try, w/room for the tag
+    let fits = |size: usize| -> Result<bool, IOError> {
+        let mut packet = probe.clone();
 
-        println!("{:?}", t.err());
-    }
+        packet.resize(tagged_mtu(size, auth), 0);
 
-    #[test]
-    fn udp_listen() {
-        TermLogger::init(LevelFilter::Debug, Config::default()).unwrap();
-        let local_addr = SocketAddr::new("0.0.0.0".parse().unwrap(), 1234);
-        let socket = UdpSocket::bind(local_addr).expect("Couldno't bind socket");
+        let packet = sign_packet(auth, &packet);
 
-        let t = Receiver::<UdpSocket>::listen(socket, &Default::default());
+        match socket.send(&packet) {
+            Ok(_) => return Ok(true),
+            Err(ref e) if e.raw_os_error() == Some(libc::EMSGSIZE) => return Ok(false),
+            Err(e) => return Err(e)
+        }
+    };
+
+    if fits(MAX_PACKET_SIZE)? {
+        return Ok(MAX_PACKET_SIZE);
     }
 
-    fn encode_decode(seq_num: u64) {
-        let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
-        let payload = thread_rng().gen_iter::<u8>().take(MAX_PAYLOAD_SIZE).collect::<Vec<u8>>();
+    if !fits(MIN_PATH_MTU)? {
+        return Err(IOError::new(ErrorKind::InvalidInput, format!("Path MTU is smaller than {}", MIN_PATH_MTU)));
+    }
 
-        let buf = fbb.create_vector(&payload);
+    // halve the range between what fits and what doesn't, until they meet
+    let (mut good, mut bad) = (MIN_PATH_MTU, MAX_PACKET_SIZE);
 
-        let msg = Message::create(&mut fbb, &MessageArgs { msg_type: Type::Message, seq_num, payload: Some(buf) });
+    while bad - good > 1 {
+        let size = (good + bad) / 2;
 
-        fbb.finish(msg, None);
+        if fits(size)? {
+            good = size;
+        } else {
+            bad = size;
+        }
+    }
 
-        let msg_buf = fbb.finished_data();
+    debug!("PATH MTU: {}", good);
 
-        println!("PACKET: {} {}", msg_buf.len(), buf2string(msg_buf));
-        assert!(msg_buf.len() <= MAX_PACKET_SIZE);
+    return Ok(good);
+}
 
-        let msg = get_root_as_message(&msg_buf);
-        let payload = msg.payload().expect("Error getting payload");
+/// The largest payload whose Message fits in a packet of path_mtu, on stream_id
+/// The payload is padded for alignment, so the overhead varies a little w/its length; and stream 0 leaves out its id
+/// Any checksum but none takes the same room
+fn max_payload_size(path_mtu: usize, stream_id: u32) -> usize {
+    let mut size = path_mtu - PACKET_OVERHEAD;
 
-        println!("TYPE: {:?}", msg.msg_type());
-        println!("SEQ NUM: {:x}", msg.seq_num());
-        println!("PAYLOAD: {} {}", payload.len(), buf2string(payload));
-        assert!(payload.len() <= MAX_PAYLOAD_SIZE);
+    while construct_payload_message(ChecksumKind::Crc32, Type::Message, stream_id, u64::MAX, &vec![0; size]).finished_data().len() > path_mtu {
+        size -= 1;
     }
 
-    #[test]
-    fn multiple_encode_decode() {
-        TermLogger::init(LevelFilter::Debug, Config::default()).unwrap();
+    return size;
+}
 
-        encode_decode(0); println!();
-        encode_decode(0xAA); println!();
-        encode_decode(0xAABB); println!();
-        encode_decode(0xAABBCC); println!();
-        encode_decode(0xAABBCCDD); println!();
-        encode_decode(0xAABBCCDD_11); println!();
-        encode_decode(0xAABBCCDD_1122); println!();
-        encode_decode(0xAABBCCDD_112233); println!();
-        encode_decode(0xAABBCCDD_11223344); println!();
+/// The window size, in packets of packet_size bytes, for a path w/the given bandwidth (bytes/sec) and min RTT
+/// WINDOW_GAIN times the bandwidth-delay product, so the window can grow until something else limits the rate
+/// Never less than MIN_WINDOW, or more than max_window
+fn target_window(bandwidth: f64, min_rtt: Duration, packet_size: usize, max_window: usize) -> usize {
+    let secs = min_rtt.as_secs() as f64 + min_rtt.subsec_nanos() as f64 / 1_000_000_000.0;
+    let bdp = bandwidth * secs / packet_size as f64;
+
+    return ((bdp * WINDOW_GAIN).ceil() as usize).max(MIN_WINDOW).min(max_window);
+}
+
+/// Handshakes w/each of config's addresses in turn, until one answers
+/// nonce is the Connect's, when there's a key
+/// Returns the address that answered, the round-trip time of the exchange, the receiver's window size, the receiver's nonce,
+/// and the streams it agreed to
+fn handshake_any<T: Socket>(socket: &T, msg_data: &[u8], config: &Configuration, nonce: Option<&[u8]>) -> Result<(SocketAddr, Duration, Option<usize>, Vec<u8>, usize), QcpError> {
+    let mut last_err = QcpError::Config("No address to connect to".to_string());
+    let psk = config.key().and_then(|key| nonce.map(|nonce| (key, nonce)));
+
+    for &addr in config.addrs() {
+        match handshake(socket, msg_data, addr, psk, config.checksum(), config.connect_retries(), config.connect_timeout()) {
+            Ok((from, rtt, window_size, ack_nonce, streams)) => return Ok((from, rtt, window_size, ack_nonce, streams)),
+            Err(e) => {
+                warn!("Couldn't connect to {}: {}", addr, e);
+                last_err = e;
+            }
+        }
     }
 
-    #[test]
-    fn packet_drops() {
-        TermLogger::init(LevelFilter::Debug, Config::default()).unwrap();
+    return Err(last_err);
+}
 
-        let mock_socket = PacketDroppingSocket::new();
-        let duplex_socket = mock_socket.duplex();
+/// Sends the Connect message, and waits for it to be acknowledged
+/// The Connect is sent up to retries times, waiting timeout for the Acknowledge after each
+/// Anything else that shows up in the meantime, or an Acknowledge that doesn't check out, is discarded
+/// When psk is given, the pre-shared key and the Connect's nonce, the Acknowledge must carry a MAC under the key,
+/// or the receiver doesn't know it, along w/a nonce of the receiver's own
+/// The receiver has to agree to checksum, the one the Connect asked for
+/// Returns where the Acknowledge came from, the round-trip time of the exchange,
+/// the window size the receiver put in its Acknowledge if it did, the receiver's nonce; empty w/out a key,
+/// and the streams it agreed to
+fn handshake<T: Socket>(socket: &T, msg_data: &[u8], remote_addr: SocketAddr, psk: Option<(&[u8], &[u8])>, checksum: ChecksumKind, retries: usize, timeout: Duration) -> Result<(SocketAddr, Duration, Option<usize>, Vec<u8>, usize), QcpError> {
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+    let mut rejected :Option<IOError> = None;
 
-        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
-            let config = Configuration::default();
-            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
-            let mut buf = vec![0xAA; MAX_PAYLOAD_SIZE];
+    for attempt in 1..=retries {
+        // timed from the last Connect, an Acknowledge of an earlier one that was only slow makes the RTT look shorter
+        let start = Instant::now();
 
-            for _ in 0..100 {
-                sender.write_all(&buf).expect("Error calling write_all");
-            }
-        }).expect("Error spawning send thread");
+        // send the connection message, again if it or its Acknowledge was lost
+        socket.send_to(msg_data, remote_addr)?;
 
-        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
-            let config = Configuration::default();
-            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
-            let mut buf = vec![0xAA; MAX_PAYLOAD_SIZE];
+        while let Some(remaining) = timeout.checked_sub(start.elapsed()).filter(|r| *r > Duration::new(0, 0)) {
+            let ret = recv_retrying(|| socket.recv_from_timeout(&mut buf, remaining));
 
-            for _ in 0..100 {
-                if recver.read(&mut buf).expect("Error calling read") != buf.len() {
-                    panic!("Did not read everything");
+            debug!("{}: {:?}", attempt, ret);
+
+            let (amt, from) = match ret {
+                // an empty datagram carries nothing, not even a malformed Acknowledge
+                Ok((0, _)) => continue,
+                Ok(ret) => ret,
+                // on to the next attempt
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(QcpError::Io(e))
+            };
+
+            debug!("RET: {}", buf2string(&buf[..amt]));
+
+            match check_acknowledge(&buf[..amt], psk, checksum) {
+                Ok((window_size, ack_nonce, streams)) => return Ok((from, start.elapsed(), window_size, ack_nonce, streams)),
+                Err(e) => {
+                    warn!("Discarding packet while waiting for Acknowledge: {}", e);
+                    rejected = Some(e);
                 }
             }
-        }).expect("Error spawning recv thread");
+        }
+    }
+
+    return Err(match rejected {
+        // the last thing that got in the way says more than a timeout
+        Some(e) => QcpError::Handshake(IOError::new(e.kind(), format!("Did not get a valid Acknowledge on Connect after {} attempts: {}", retries, e))),
+        None => QcpError::Timeout(IOError::new(ErrorKind::ConnectionAborted, format!("Did not get Acknowledge on Connect after {} attempts", retries)))
+    });
+}
+
+/// Checks a packet that arrived in answer to the Connect is its Acknowledge
+/// Returns the window size the receiver put in it if it did, the receiver's nonce; empty w/out a key,
+/// and the streams it agreed to, one if it didn't say
+/// The receiver has to have agreed to checksum, an older one that doesn't say only knows CRC-32
+fn check_acknowledge(packet: &[u8], psk: Option<(&[u8], &[u8])>, checksum: ChecksumKind) -> Result<(Option<usize>, Vec<u8>, usize), IOError> {
+    let ack = match parse_message(packet) {
+        Some(ack) => ack,
+        None => return Err(IOError::new(ErrorKind::InvalidData, "Malformed Acknowledge of Connect"))
+    };
+
+    if !verify_checksum(HANDSHAKE_CHECKSUM, &ack) {
+        return Err(IOError::new(ErrorKind::InvalidData, "Bad checksum on Acknowledge of Connect"));
+    }
+
+    if ack.msg_type() != Type::Acknowledge {
+        return Err(IOError::new(ErrorKind::ConnectionAborted, "Got other message type than Acknowledge on Connect"));
+    }
+
+    if ack.seq_num() != 0 {
+        return Err(IOError::new(ErrorKind::InvalidData, "Acknowledged wrong sequence number"));
+    }
+
+    if ack.stream_id() & CHECKSUM_MASK != checksum_bits(checksum) {
+        return Err(IOError::new(ErrorKind::ConnectionRefused, format!("Receiver does not use {:?} checksums", checksum)));
+    }
+
+    let payload = ack.payload().unwrap_or(&[]);
+    let streams = (ack.stream_id() & !CHECKSUM_MASK).max(1) as usize;
+
+    let (key, nonce) = match psk {
+        Some(psk) => psk,
+        None => return Ok((decode_window_size(payload), Vec::new(), streams))
+    };
+
+    if !ack.mac().map_or(false, |mac| mac_eq(&ack_mac(key, nonce, ack.stream_id(), payload), mac)) {
+        return Err(IOError::new(ErrorKind::PermissionDenied, "Receiver could not prove it knows the pre-shared key"));
+    }
+
+    // the window size, then the receiver's nonce
+    if payload.len() != WINDOW_SIZE_SIZE + CONNECT_NONCE_SIZE {
+        return Err(IOError::new(ErrorKind::InvalidData, "Acknowledge of an authenticated Connect has no nonce"));
+    }
+
+    let (window_size, ack_nonce) = payload.split_at(WINDOW_SIZE_SIZE);
+
+    return Ok((decode_window_size(window_size), ack_nonce.to_vec(), streams));
+}
+
+/// The receiver's window size, as it's sent in the payload of its Acknowledge of the Connect
+fn encode_window_size(window_size: usize) -> [u8; WINDOW_SIZE_SIZE] {
+    (window_size as u64).to_be_bytes()
+}
+
+fn decode_window_size(payload: &[u8]) -> Option<usize> {
+    if payload.len() != WINDOW_SIZE_SIZE {
+        return None;
+    }
+
+    let mut window_size = [0u8; WINDOW_SIZE_SIZE];
+
+    window_size.copy_from_slice(payload);
+
+    return Some(u64::from_be_bytes(window_size) as usize);
+}
+
+/// Counts what the Sender and the thread reading ACKs did, for TransferStats
+#[derive(Default)]
+struct Counters {
+    packets_sent: AtomicU64,    // first sends of Messages, control messages, and parity
+    retransmits: AtomicU64,     // resends, whether timed out, NACKed or after duplicate ACKs
+    duplicate_acks: AtomicU64   // ACKs for something no longer in the window
+}
 
-        send_handle.join();
-        recv_handle.join();
+/// A summary of a transfer so far, from Sender::stats
+#[derive(Clone, Debug)]
+pub struct TransferStats {
+    pub bytes: u64,             // payload bytes sent, not counting headers or retransmits
+    pub duration: Duration,     // since connecting
+    pub packets_sent: u64,
+    pub retransmits: u64,
+    pub duplicate_acks: u64,
+    pub min_rtt: Option<Duration>,  // None until something is acknowledged, as w/the rest of the estimates
+    pub smoothed_rtt: Duration,
+    pub bandwidth: Option<f64>,     // bytes/sec, including headers
+    pub path_mtu: usize
+}
+
+impl TransferStats {
+    /// Payload bytes sent per second
+    pub fn throughput(&self) -> f64 {
+        let secs = self.duration.as_secs() as f64 + self.duration.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        if secs == 0.0 { 0.0 } else { self.bytes as f64 / secs }
+    }
+}
+
+impl fmt::Display for TransferStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.duration.as_secs() as f64 + self.duration.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        write!(f, "Sent {} bytes in {:.3}s ({:.3} MB/s): packets={} retransmits={} duplicate_acks={} path_mtu={}",
+               self.bytes, secs, self.throughput() / 1_000_000.0, self.packets_sent, self.retransmits, self.duplicate_acks, self.path_mtu)?;
+
+        // nothing is acknowledged when unidirectional, so there's nothing to estimate from
+        if let (Some(min_rtt), Some(bandwidth)) = (self.min_rtt, self.bandwidth) {
+            write!(f, " min_rtt={:?} srtt={:?} bandwidth={:.3} MB/s", min_rtt, self.smoothed_rtt, bandwidth / 1_000_000.0)?;
+        }
+
+        return Ok( () );
+    }
+}
+
+/// Counts what the thread reading packets saw, for ReceiverStats
+#[derive(Default)]
+struct RecvCounters {
+    out_of_order: AtomicU64,    // packets that came after one w/a higher sequence number
+    late: AtomicU64,            // packets for what was already read, dropped but ACKed again
+    duplicates: AtomicU64,      // packets for what was already in the window
+    max_reorder: AtomicU64      // the furthest behind the highest sequence number a packet has come
+}
+
+/// A summary of what a Receiver has seen so far, from Receiver::stats
+/// Retransmits count as out of order too, they come after what was sent since
+#[derive(Clone, Debug)]
+pub struct ReceiverStats {
+    pub bytes: u64,             // payload bytes read
+    pub duration: Duration,     // since the handshake
+    pub out_of_order: u64,
+    pub late: u64,
+    pub duplicates: u64,
+    pub max_reorder: u64        // in packets
+}
+
+impl fmt::Display for ReceiverStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.duration.as_secs() as f64 + self.duration.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        write!(f, "Received {} bytes in {:.3}s: out_of_order={} late={} duplicates={} max_reorder={}",
+               self.bytes, secs, self.out_of_order, self.late, self.duplicates, self.max_reorder)
+    }
+}
+
+/// What a --connect-only probe learned about the path to the receiver
+#[derive(Debug)]
+pub struct ProbeResult {
+    pub peer: SocketAddr,           // the address that answered the Connect
+    pub rtt: Duration,
+    pub window_size: usize,         // the receiver's, from its Acknowledge
+    pub max_packet_size: usize      // the path MTU
+}
+
+impl fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rtt_ms = self.rtt.as_secs() as f64 * 1000.0 + self.rtt.subsec_nanos() as f64 / 1_000_000.0;
+
+        write!(f, "{} is reachable: rtt={:.3}ms window={} max_packet={}", self.peer, rtt_ms, self.window_size, self.max_packet_size)
+    }
+}
+
+/// The retransmit timeout before any backoff: RFC 6298's, from the smoothed RTT and its variation
+/// The configured RTO is the floor, and all there is until an ACK gives a sample
+fn base_rto(rto: Duration, rtt: &Mutex<RttEstimator>) -> Duration {
+    match rtt.lock().unwrap().rto() {
+        Some(estimate) => rto.max(estimate),
+        None => rto
+    }
+}
+
+/// The retransmit timeout for a packet that's already been retransmitted, doubling each time
+fn backoff(base: Duration, retransmits: u32) -> Duration {
+    base.checked_mul(1 << retransmits.min(16)).unwrap_or(MAX_RTO).min(MAX_RTO)
+}
+
+/// A running checksum of the kind agreed on in the handshake
+enum Checksum {
+    None,
+    Crc32(Crc32),
+    Crc32c(u32),
+    Xxh3(Box<Xxh3Default>)     // too large to keep inline w/the others
+}
+
+impl Checksum {
+    fn new(kind: ChecksumKind) -> Checksum {
+        match kind {
+            ChecksumKind::None => Checksum::None,
+            ChecksumKind::Crc32 => Checksum::Crc32(Crc32::new()),
+            ChecksumKind::Crc32c => Checksum::Crc32c(0),
+            ChecksumKind::Xxh3 => Checksum::Xxh3(Box::new(Xxh3Default::new()))
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match *self {
+            Checksum::None => (),
+            Checksum::Crc32(ref mut crc) => crc.update(bytes),
+            Checksum::Crc32c(ref mut crc) => *crc = crc32c_append(*crc, bytes),
+            Checksum::Xxh3(ref mut xxh) => xxh.update(bytes)
+        }
+    }
+
+    /// Always 0 w/out a checksum, which is what every message then carries
+    fn finish(&self) -> u32 {
+        match *self {
+            Checksum::None => 0,
+            Checksum::Crc32(ref crc) => crc.finish(),
+            Checksum::Crc32c(crc) => crc,
+            Checksum::Xxh3(ref xxh) => xxh.digest() as u32
+        }
+    }
+}
+
+/// The checksum's bits in the streams asked for and agreed to
+/// CRC-32's are 0, so an older end that doesn't say is taken to use it
+fn checksum_bits(kind: ChecksumKind) -> u32 {
+    let id = match kind {
+        ChecksumKind::Crc32 => 0,
+        ChecksumKind::Crc32c => 1,
+        ChecksumKind::Xxh3 => 2,
+        ChecksumKind::None => 3
+    };
+
+    return id << CHECKSUM_SHIFT;
+}
+
+/// Computes the checksum of a message's fields
+fn compute_checksum(checksum: ChecksumKind, msg_type: Type, stream_id: u32, seq_num: u64, payload: Option<&[u8]>) -> u32 {
+    let mut crc = Checksum::new(checksum);
+
+    crc.update(&[msg_type as u8]);
+    crc.update(&stream_id.to_be_bytes());
+    crc.update(&seq_num.to_be_bytes());
+
+    if let Some(payload) = payload {
+        crc.update(payload);
+    }
+
+    return crc.finish();
+}
+
+/// Checks that buf holds a well-formed Message: every offset its accessors follow, and every field they read, is inside buf
+/// flatbuffers 0.5 has no verifier, and get_root_as_message trusts whatever it's given, so reading a crafted packet could go out of bounds
+fn verify_message(buf: &[u8]) -> bool {
+    let u16_at = |loc: usize| buf.get(loc..loc + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let u32_at = |loc: usize| buf.get(loc..loc + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    // the offset to the root table comes first, and the table must start after it
+    let table = match u32_at(0) {
+        Some(table) if table >= 4 => table as usize,
+        _ => return false
+    };
+
+    // the table starts w/a signed offset back to its vtable
+    let vtable = match u32_at(table) {
+        Some(soffset) => table as i64 - soffset as i32 as i64,
+        None => return false
+    };
+
+    if vtable < 0 {
+        return false;
+    }
+
+    let vtable = vtable as usize;
+
+    // the vtable starts w/its own size, then the table's, then a 2 byte offset for each field
+    let (vtable_size, table_size) = match (u16_at(vtable), u16_at(vtable + 2)) {
+        (Some(vtable_size), Some(table_size)) => (vtable_size, table_size),
+        _ => return false
+    };
+
+    if vtable_size < 4 || vtable_size % 2 != 0 || vtable + vtable_size > buf.len() || table_size < 4 || table + table_size > buf.len() {
+        return false;
+    }
+
+    let fields = [(Message::VT_MSG_TYPE, 1), (Message::VT_SEQ_NUM, 8), (Message::VT_PAYLOAD, 4), (Message::VT_CHECKSUM, 4), (Message::VT_MAC, 4), (Message::VT_HASH, 4), (Message::VT_RANGES, 4), (Message::VT_STREAM_ID, 4), (Message::VT_VERSION, 2), (Message::VT_MAGIC, 4)];
+
+    for &(voffset, size) in fields.iter() {
+        let voffset = voffset as usize;
+
+        // a vtable shorter than the field, or a 0 offset, means it's absent and the default is used
+        let offset = if voffset < vtable_size { u16_at(vtable + voffset).unwrap() } else { 0 };
+
+        if offset == 0 {
+            continue;
+        }
+
+        if offset < 4 || offset + size > table_size {
+            return false;
+        }
+
+        let loc = table + offset;
+
+        if voffset == Message::VT_MSG_TYPE as usize {
+            // only the values in the schema, Skip is the last
+            let msg_type = buf[loc] as i8;
+
+            if msg_type < Type::Error as i8 || msg_type > Type::Skip as i8 {
+                return false;
+            }
+        } else if voffset == Message::VT_PAYLOAD as usize || voffset == Message::VT_MAC as usize || voffset == Message::VT_HASH as usize || voffset == Message::VT_RANGES as usize {
+            // the ranges are copied out as SeqRanges, so they have to be whole ones
+            let elem_size = if voffset == Message::VT_RANGES as usize { SEQ_RANGE_SIZE } else { 1 };
+
+            // an offset to the vector, which is its length then its elements
+            // checked, a crafted offset or length mustn't wrap around to something that looks in bounds
+            let vector = match loc.checked_add(u32_at(loc).unwrap() as usize) {
+                Some(vector) => vector,
+                None => return false
+            };
+
+            match u32_at(vector).and_then(|len| vector.checked_add(4)?.checked_add((len as usize).checked_mul(elem_size)?)) {
+                Some(end) if end <= buf.len() => (),
+                _ => return false
+            }
+        }
+    }
+
+    return true;
+}
+
+/// Parses a packet into a Message, or None if it isn't a well-formed one
+fn parse_message(buf: &[u8]) -> Option<Message<'_>> {
+    if !verify_message(buf) {
+        return None;
+    }
+
+    return Some(get_root_as_message(buf));
+}
+
+/// Computes the checksum of a SelectiveAck, which has its ranges in place of a payload
+fn compute_sack_checksum(checksum: ChecksumKind, stream_id: u32, seq_num: u64, ranges: &[SeqRange]) -> u32 {
+    let mut crc = Checksum::new(checksum);
+
+    crc.update(&[Type::SelectiveAck as u8]);
+    crc.update(&stream_id.to_be_bytes());
+    crc.update(&seq_num.to_be_bytes());
+
+    for range in ranges {
+        crc.update(&range.start().to_be_bytes());
+        crc.update(&range.end().to_be_bytes());
+    }
+
+    return crc.finish();
+}
+
+/// Returns true if the message's checksum, of the kind checksum, matches its contents
+/// A Finish has no payload, so its checksum covers the hash in its place; a SelectiveAck's covers its ranges
+/// A Connect's is always HANDSHAKE_CHECKSUM, so a repeated one can be answered after the handshake
+fn verify_checksum(checksum: ChecksumKind, msg: &Message) -> bool {
+    match msg.msg_type() {
+        Type::Connect => msg.checksum() == compute_checksum(HANDSHAKE_CHECKSUM, Type::Connect, msg.stream_id(), msg.seq_num(), msg.payload()),
+        Type::Finish => msg.checksum() == compute_checksum(checksum, Type::Finish, msg.stream_id(), msg.seq_num(), msg.hash()),
+        Type::SelectiveAck => msg.checksum() == compute_sack_checksum(checksum, msg.stream_id(), msg.seq_num(), &seq_ranges(msg)),
+        msg_type => msg.checksum() == compute_checksum(checksum, msg_type, msg.stream_id(), msg.seq_num(), msg.payload())
+    }
+}
+
+/// Tags a packet sent after the handshake, when there's a pre-shared key; a copy of it otherwise
+/// Only the Connect and its Acknowledge go untagged, they're authenticated w/their own MACs
+fn sign_packet(auth: &Option<PacketAuth>, packet: &[u8]) -> Vec<u8> {
+    match *auth {
+        None => return packet.to_vec(),
+        Some(ref auth) => return auth.sign(packet)
+    }
+}
+
+/// Like sign_packet, but appends the packet to buf, so one from a BufferPool can be used
+fn sign_packet_into(auth: &Option<PacketAuth>, packet: &[u8], buf: &mut Vec<u8>) {
+    match *auth {
+        None => buf.extend_from_slice(packet),
+        Some(ref auth) => auth.sign_into(packet, buf)
+    }
+}
+
+/// Checks and strips the tag of a packet received after the handshake, when there's a pre-shared key
+/// None when it isn't there or isn't right: someone other than the peer sent it, or it was altered on the way
+fn verify_packet<'a>(auth: &Option<PacketAuth>, packet: &'a [u8]) -> Option<&'a [u8]> {
+    match *auth {
+        None => return Some(packet),
+        Some(ref auth) => return auth.verify(packet)
+    }
+}
+
+/// The room left for a Message in a packet of path_mtu, after the session id and tag if packets are tagged
+fn tagged_mtu(path_mtu: usize, auth: &Option<PacketAuth>) -> usize {
+    if auth.is_some() { path_mtu - AUTH_OVERHEAD } else { path_mtu }
+}
+
+/// The payload of a message, decrypted if there's a cipher
+/// Returns None, after logging why, if it can't be decrypted
+fn open_payload(cipher: &Option<Cipher>, msg: &Message) -> Option<Vec<u8>> {
+    let mut payload = Vec::new();
+
+    return if open_payload_into(cipher, msg, &mut payload) { Some(payload) } else { None };
+}
+
+/// Like open_payload, but appends the payload to buf, so one from a BufferPool can be used
+/// Returns false, after logging why, if it can't be decrypted
+fn open_payload_into(cipher: &Option<Cipher>, msg: &Message, buf: &mut Vec<u8>) -> bool {
+    let payload = match msg.payload() {
+        Some(payload) => payload,
+        None => {
+            warn!("Dropping {:?} {}: no payload", msg.msg_type(), msg.seq_num());
+            return false;
+        }
+    };
+
+    match *cipher {
+        None => buf.extend_from_slice(payload),
+        Some(ref cipher) => match cipher.open(&packet_nonce(msg.msg_type(), msg.stream_id(), msg.seq_num()), &[], payload) {
+            Some(plaintext) => buf.extend_from_slice(&plaintext),
+            None => {
+                warn!("Dropping {:?} {}: could not decrypt payload", msg.msg_type(), msg.seq_num());
+                return false;
+            }
+        }
+    }
+
+    return true;
+}
+
+/// Collapses sorted sequence numbers into runs of consecutive ones
+fn to_ranges(seq_nums: &BTreeSet<u64>) -> Vec<SeqRange> {
+    let mut ranges :Vec<SeqRange> = Vec::new();
+
+    for &seq_num in seq_nums {
+        match ranges.last_mut() {
+            Some(range) if range.end() == seq_num => *range = SeqRange::new(range.start(), seq_num + 1),
+            _ => ranges.push(SeqRange::new(seq_num, seq_num + 1))
+        }
+    }
+
+    return ranges;
+}
+
+/// Where a message's ranges are in its buffer, starting w/their length, or None if it has none
+fn ranges_loc(msg: &Message) -> Option<usize> {
+    let table = msg._tab;
+
+    let field = match table.vtable().get(Message::VT_RANGES) as usize {
+        0 => return None,
+        offset => table.loc + offset
+    };
+
+    let offset = table.buf.get(field..field + 4)?;
+
+    return Some(field + u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize);
+}
+
+/// Every range of a message, even empty ones; none if it has none
+/// Copied out field by field, the packet's buffer needn't be aligned for a SeqRange's u64s
+fn seq_ranges(msg: &Message) -> Vec<SeqRange> {
+    let buf = msg._tab.buf;
+    let u64_at = |b: &[u8]| { let mut bytes = [0u8; 8]; bytes.copy_from_slice(b); u64::from_le_bytes(bytes) };
+
+    let ranges = ranges_loc(msg).and_then(|loc| {
+        let len = buf.get(loc..loc + 4)?;
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+
+        return buf.get(loc + 4..loc + 4 + len * SEQ_RANGE_SIZE);
+    });
+
+    match ranges {
+        Some(ranges) => return ranges.chunks(SEQ_RANGE_SIZE).map(|r| SeqRange::new(u64_at(&r[..8]), u64_at(&r[8..]))).collect(),
+        None => return Vec::new()
+    }
+}
+
+/// The ranges of a SelectiveAck, or None if it has none or any of them is empty
+fn read_ranges(msg: &Message) -> Option<Vec<SeqRange>> {
+    let ranges = seq_ranges(msg);
+
+    if ranges.is_empty() || ranges.iter().any(|r| r.start() >= r.end()) {
+        return None;
+    }
+
+    return Some(ranges);
+}
+
+/// Acknowledges everything in pending on stream_id, w/as few SelectiveAcks as fit it, and clears it
+/// One that can't be sent is only logged, the sender retransmits what it covered and it's acknowledged again
+fn send_sacks<T: Socket>(socket: &T, remote_addr: SocketAddr, auth: &Option<PacketAuth>, checksum: ChecksumKind, stream_id: u32, pending: &mut BTreeSet<u64>) {
+    let ranges = to_ranges(pending);
+
+    for chunk in ranges.chunks(MAX_SACK_RANGES) {
+        debug!("SACKING: {:?}", chunk.iter().map(|r| (r.start(), r.end())).collect::<Vec<_>>());
+
+        if let Err(e) = socket.send_to(&sign_packet(auth, construct_sack(checksum, stream_id, chunk[0].start(), chunk).finished_data()), remote_addr) {
+            warn!("Error sending SelectiveAck {}: {}", chunk[0].start(), e);
+        }
+    }
+
+    pending.clear();
+}
+
+/// The version and magic a message of msg_type carries: only the Connect's are set, the rest don't need the room
+fn handshake_fields(msg_type: Type) -> (u16, u32) {
+    if msg_type == Type::Connect { (PROTOCOL_VERSION, PROTOCOL_MAGIC) } else { (0, 0) }
+}
+
+/// Constructs a simple message w/out a payload
+fn construct_message<'a>(checksum: ChecksumKind, msg_type: Type, stream_id: u32, seq_num: u64) -> FlatBufferBuilder<'a> {
+    let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+
+    let checksum = compute_checksum(checksum, msg_type, stream_id, seq_num, None);
+    let (version, magic) = handshake_fields(msg_type);
+    let msg = Message::create(&mut fbb, &MessageArgs { msg_type, seq_num, payload: None, checksum, mac: None, hash: None, ranges: None, stream_id, version, magic });
+
+    fbb.finish(msg, None);
+
+    // TODO: Remove the need to return the FBB
+    return fbb;
+}
+
+/// Constructs a message w/a payload
+fn construct_payload_message<'a>(checksum: ChecksumKind, msg_type: Type, stream_id: u32, seq_num: u64, payload: &[u8]) -> FlatBufferBuilder<'a> {
+    let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+
+    let checksum = compute_checksum(checksum, msg_type, stream_id, seq_num, Some(payload));
+    let payload = Some(fbb.create_vector(payload));
+    let (version, magic) = handshake_fields(msg_type);
+    let msg = Message::create(&mut fbb, &MessageArgs { msg_type, seq_num, payload, checksum, mac: None, hash: None, ranges: None, stream_id, version, magic });
+
+    fbb.finish(msg, None);
+
+    return fbb;
+}
+
+/// Constructs a SelectiveAck of ranges
+fn construct_sack<'a>(checksum: ChecksumKind, stream_id: u32, seq_num: u64, ranges: &[SeqRange]) -> FlatBufferBuilder<'a> {
+    let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+
+    let checksum = compute_sack_checksum(checksum, stream_id, seq_num, ranges);
+    let ranges = Some(fbb.create_vector(ranges));
+    let msg = Message::create(&mut fbb, &MessageArgs { msg_type: Type::SelectiveAck, seq_num, payload: None, checksum, mac: None, hash: None, ranges, stream_id, version: 0, magic: 0 });
+
+    fbb.finish(msg, None);
+
+    return fbb;
+}
+
+/// Constructs the Finish, w/the total bytes sent; UNKNOWN_LEN if there isn't one, then the hash of everything sent when verifying
+/// The total is what makes a stream of unknown length whole; the receiver checks it got that much
+fn construct_finish<'a>(checksum: ChecksumKind, stream_id: u32, seq_num: u64, hash: Option<&[u8]>, total: u64) -> FlatBufferBuilder<'a> {
+    let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+
+    let hash = [&total.to_be_bytes()[..], hash.unwrap_or(&[])].concat();
+    let checksum = compute_checksum(checksum, Type::Finish, stream_id, seq_num, Some(&hash));
+    let hash = Some(fbb.create_vector(&hash));
+    let msg = Message::create(&mut fbb, &MessageArgs { msg_type: Type::Finish, seq_num, payload: None, checksum, mac: None, hash, ranges: None, stream_id, version: 0, magic: 0 });
+
+    fbb.finish(msg, None);
+
+    return fbb;
+}
+
+/// Constructs a control message w/a payload, authenticated by mac
+fn construct_mac_message<'a>(checksum: ChecksumKind, msg_type: Type, stream_id: u32, seq_num: u64, payload: &[u8], mac: &[u8]) -> FlatBufferBuilder<'a> {
+    let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+
+    let checksum = compute_checksum(checksum, msg_type, stream_id, seq_num, Some(payload));
+    let payload = Some(fbb.create_vector(payload));
+    let mac = Some(fbb.create_vector(mac));
+    let (version, magic) = handshake_fields(msg_type);
+    let msg = Message::create(&mut fbb, &MessageArgs { msg_type, seq_num, payload, checksum, mac, hash: None, ranges: None, stream_id, version, magic });
+
+    fbb.finish(msg, None);
+
+    return fbb;
+}
+
+/// Whether a socket error is worth trying again after a short wait, rather than the end of the transfer
+/// A signal interrupting the read, or the kernel running short of buffers for a moment, is; anything else isn't
+fn is_retryable(e: &IOError) -> bool {
+    if e.kind() == ErrorKind::Interrupted {
+        return true;
+    }
+
+    match e.raw_os_error() {
+        Some(code) => return code == libc::ENOBUFS || code == libc::ENOMEM,
+        None => return false
+    }
+}
+
+/// Calls recv until it succeeds, or fails w/an error that isn't retryable, or READ_RETRIES in a row that are
+fn recv_retrying<R, F>(mut recv: F) -> Result<R, IOError> where F: FnMut() -> Result<R, IOError> {
+    let mut retries = 0;
+
+    loop {
+        match recv() {
+            Err(ref e) if retries < READ_RETRIES && is_retryable(e) => {
+                retries += 1;
+                debug!("Reading again after {:?}, {} of {}", e, retries, READ_RETRIES);
+                thread::sleep(READ_RETRY_DELAY);
+            },
+            res => return res
+        }
+    }
+}
+
+/// The stream a packet is for
+/// A tag comes after the message, and is ignored here, the stream's thread checks it
+/// The handshake, and anything that isn't a message, are stream 0's to deal w/
+fn stream_of(packet: &[u8]) -> usize {
+    match parse_message(packet) {
+        Some(ref msg) if msg.msg_type() != Type::Connect => msg.stream_id() as usize,
+        _ => 0
+    }
+}
+
+/// The streams sharing a socket, see Sender::connect_streams
+/// There's no thread of its own: whichever stream is waiting for a packet reads the socket for all of them,
+/// and queues what's for the others
+struct StreamDemux<T> {
+    socket: T,
+    queues: Mutex<(bool, Vec<VecDeque<(Vec<u8>, SocketAddr)>>)>,  // whether a stream is reading the socket, and each stream's packets
+    queued: Condvar     // signaled whenever a packet is queued, or the stream reading the socket stops
+}
+
+impl <T> StreamDemux<T> where T: Socket {
+    fn new(socket: T, streams: usize) -> StreamDemux<T> {
+        StreamDemux { socket, queues: Mutex::new((false, vec![VecDeque::new(); streams])), queued: Condvar::new() }
+    }
+
+    /// Waits at most dur for a packet for stream_id, reading the socket if no other stream is
+    fn recv_from_timeout(&self, stream_id: usize, buf: &mut [u8], dur: Duration) -> Result<(usize, SocketAddr), IOError> {
+        let deadline = Instant::now() + dur;
+        let mut packet = vec![0; MAX_PACKET_SIZE];
+        let mut queues = self.queues.lock().unwrap();
+
+        loop {
+            if let Some((queued, addr)) = queues.1[stream_id].pop_front() {
+                let amt = buf.len().min(queued.len());
+
+                buf[..amt].copy_from_slice(&queued[..amt]);
+
+                return Ok((amt, addr));
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining == Duration::new(0, 0) {
+                return Err(IOError::new(ErrorKind::WouldBlock, "Timed out waiting for packet"));
+            }
+
+            // another stream is reading, it'll queue anything for us
+            if queues.0 {
+                queues = self.queued.wait_timeout(queues, remaining).unwrap().0;
+                continue;
+            }
+
+            queues.0 = true;
+            drop(queues);
+
+            let res = self.socket.recv_from_timeout(&mut packet, remaining);
+
+            queues = self.queues.lock().unwrap();
+            queues.0 = false;
+
+            // someone else has to read the socket now
+            self.queued.notify_all();
+
+            let (amt, addr) = res?;
+            let id = stream_of(&packet[..amt]);
+
+            if id == stream_id {
+                let amt = buf.len().min(amt);
+
+                buf[..amt].copy_from_slice(&packet[..amt]);
+
+                return Ok((amt, addr));
+            }
+
+            match queues.1.get_mut(id) {
+                Some(ref queue) if queue.len() >= STREAM_QUEUE_SIZE => debug!("Dropping {} byte packet: stream {} isn't reading", amt, id),
+                Some(queue) => queue.push_back((packet[..amt].to_vec(), addr)),
+                None => warn!("Dropping {} byte packet for stream {}: there are only {}", amt, id, queues.1.len())
+            }
+        }
+    }
+}
+
+/// One of the streams sharing a socket: everything sent goes straight out, only the packets for this stream are received
+pub struct StreamSocket<T> {
+    demux: Arc<StreamDemux<T>>,
+    stream_id: usize
+}
+
+impl <T> Socket for StreamSocket<T> where T: Socket {
+    fn send_to<A: ToSocketAddrs + fmt::Debug>(&self, buf: &[u8], addr: A) -> Result<usize, IOError> {
+        return self.demux.socket.send_to(buf, addr);
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), IOError> {
+        loop {
+            match self.recv_from_timeout(buf, STOP_POLL_INTERVAL) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => continue,
+                res => return res
+            }
+        }
+    }
+
+    fn connect(&self, addr: SocketAddr) -> Result<(), IOError> {
+        return self.demux.socket.connect(addr);
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize, IOError> {
+        return self.demux.socket.send(buf);
+    }
+
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> Result<usize, IOError> {
+        return self.demux.socket.send_batch(packets);
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Result<usize, IOError> {
+        return self.recv_from(buf).map(|(amt, _)| amt);
+    }
+
+    fn recv_from_timeout(&self, buf: &mut [u8], dur: Duration) -> Result<(usize, SocketAddr), IOError> {
+        return self.demux.recv_from_timeout(self.stream_id, buf, dur);
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> Result<(), IOError> {
+        return self.demux.socket.set_read_timeout(dur);
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> Result<(), IOError> {
+        return self.demux.socket.set_write_timeout(dur);
+    }
+
+    fn set_dont_fragment(&self, on: bool) -> Result<(), IOError> {
+        return self.demux.socket.set_dont_fragment(on);
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> Result<(), IOError> {
+        return self.demux.socket.set_recv_buffer_size(size);
+    }
+
+    fn recv_buffer_size(&self) -> Result<usize, IOError> {
+        return self.demux.socket.recv_buffer_size();
+    }
+
+    fn set_send_buffer_size(&self, size: usize) -> Result<(), IOError> {
+        return self.demux.socket.set_send_buffer_size(size);
+    }
+
+    fn send_buffer_size(&self) -> Result<usize, IOError> {
+        return self.demux.socket.send_buffer_size();
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, IOError> {
+        return self.demux.socket.local_addr();
+    }
+
+    fn try_clone(&self) -> Result<Self, IOError> {
+        return Ok(StreamSocket { demux: self.demux.clone(), stream_id: self.stream_id });
+    }
+}
+
+impl <T: 'static> Sender<T> where T: Socket + Send + Sync {
+    /// Connect, via BBR, to a remote host
+    pub fn connect(socket: T, config: &Configuration) -> Result<Sender<T>, QcpError> {
+        new_conn_id();
+
+        // set the write timeout to 3s, reads are timed per-call
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+        set_buffer_sizes(&socket, config);
+
+        // construct the Connect message, w/where the receiver's ACKs go: right back to this socket
+        let (msg_data, nonce) = construct_connect(config.key(), 0, config.checksum(), socket.local_addr().ok());
+        let msg_data = msg_data.finished_data();
+
+        let nonce = nonce.as_ref().map(|n| &n[..]);
+
+        if msg_data.len() > MAX_PACKET_SIZE {
+            panic!("Packet size too large: {}", msg_data.len());
+        }
+
+        // w/out a return path there's no Acknowledge to wait for, or ACKs to read
+        // so there's no telling which address works, and only the first is used
+        if config.unidirectional() {
+            let remote_addr = config.addr();
+            let (cipher, auth) = session_keys(config, &session_nonce(nonce, &[]))?;
+
+            socket.connect(remote_addr)?;
+
+            for _ in 0..UNIDIRECTIONAL_CONNECTS {
+                socket.send(&msg_data)?;
+            }
+
+            let path_mtu = discover_path_mtu(&socket, &auth, config.checksum())?;
+
+            let window = Arc::new(SlidingWindow::new(config.window_size()));
+
+            let bbr = Arc::new(Mutex::new(BbrState::new()));
+            let progress = Arc::new(Mutex::new(Progress::new()));
+            let reverse = Arc::new(SlidingWindow::new(config.window_size()));
+            let (_, errors) = channel();
+            let max_payload_size = max_payload_size(tagged_mtu(path_mtu, &auth), 0);
+            let pool = BufferPool::new(MAX_PACKET_SIZE, 2 * config.window_size());
+            let hash = if config.verify() { Some(Sha256::new()) } else { None };
+
+            return Ok(Sender {
+                socket,
+                remote_addr,
+                stream_id: 0,
+                seq_num: 0,
+                window,
+                pool,
+                bbr,
+                cc: Arc::new(Mutex::new(congestion::new(config.congestion(), MAX_PACKET_SIZE))),
+                rtt: Arc::new(Mutex::new(RttEstimator::new())),
+                acked: Arc::new(Condvar::new()),
+                progress,
+                cipher,
+                auth,
+                checksum: config.checksum(),
+                unidirectional: true,
+                fec_block_size: config.fec_block_size(),
+                path_mtu,
+                max_payload_size,
+                payload_size: config.payload_size(),
+                parity: Parity::new(),
+                pacer: config.max_rate().map(Pacer::new),
+                nonblocking: config.nonblocking(),
+                resume: 0,
+                next_send: None,
+                coalesce: config.coalesce(),
+                pending: Vec::new(),
+                bytes_transferred: AtomicU64::new(0),
+                counters: Arc::new(Counters::default()),
+                started: Instant::now(),
+                reverse,
+                reverse_leftover: Vec::new(),
+                hash,
+                stop: Arc::new(AtomicBool::new(false)),
+                thread: None,
+                errors
+            });
+        }
+
+        let (remote_addr, handshake_rtt, peer_window, ack_nonce, _) = handshake_any(&socket, &msg_data, config, nonce)?;
+        let keys = session_keys(config, &session_nonce(nonce, &ack_nonce))?;
+
+        // only ever talk to the receiver from here on, so the kernel drops anything spoofed
+        socket.connect(remote_addr)?;
+
+        let path_mtu = discover_path_mtu(&socket, &keys.1, config.checksum())?;
+
+        return Ok(Sender::start(socket, remote_addr, config, 0, Some(handshake_rtt), peer_window, keys, path_mtu)?);
+    }
+
+    /// Connect, via BBR, to a remote host w/up to streams independent streams over the one socket, after one handshake
+    /// The Connect asks for streams of them, and the Acknowledge says how many the receiver agreed to, which can be fewer
+    /// Each Sender has its own window and congestion control, and sends every packet w/its stream's id
+    pub fn connect_streams(socket: T, config: &Configuration, streams: usize) -> Result<Vec<Sender<StreamSocket<T>>>, QcpError> {
+        if streams == 0 || streams > MAX_STREAMS {
+            return Err(QcpError::Config(format!("Streams must be between 1 and {}", MAX_STREAMS)));
+        }
+
+        // the receiver has to say how many streams it agreed to
+        if config.unidirectional() {
+            return Err(QcpError::Config("Cannot open streams over a unidirectional link".to_string()));
+        }
+
+        new_conn_id();
+
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+        set_buffer_sizes(&socket, config);
+
+        let (msg_data, nonce) = construct_connect(config.key(), streams as u32, config.checksum(), socket.local_addr().ok());
+        let nonce = nonce.as_ref().map(|n| &n[..]);
+        let (remote_addr, handshake_rtt, peer_window, ack_nonce, agreed) = handshake_any(&socket, msg_data.finished_data(), config, nonce)?;
+
+        if agreed > streams {
+            return Err(QcpError::Protocol(IOError::new(ErrorKind::InvalidData, format!("Receiver agreed to {} streams, only asked for {}", agreed, streams))));
+        }
+
+        let keys = session_keys(config, &session_nonce(nonce, &ack_nonce))?;
+
+        socket.connect(remote_addr)?;
+
+        let path_mtu = discover_path_mtu(&socket, &keys.1, config.checksum())?;
+        let demux = Arc::new(StreamDemux::new(socket, agreed));
+
+        debug!("{} of {} streams agreed to", agreed, streams);
+
+        return Ok((0..agreed).map(|id| {
+            let socket = StreamSocket { demux: demux.clone(), stream_id: id };
+
+            Sender::start(socket, remote_addr, config, id as u32, Some(handshake_rtt), peer_window, keys.clone(), path_mtu)
+        }).collect::<Result<Vec<_>, IOError>>()?);
+    }
+
+    /// Starts sending on stream_id once the handshake is done, w/the thread reading its ACKs
+    /// socket is already connected to remote_addr, keys are the cipher and packet auth from session_keys
+    /// handshake_rtt is the first RTT sample, if this end timed the handshake
+    /// peer_window is the receiver's window size, from its Acknowledge; the window is never tuned past it
+    fn start(socket: T, remote_addr: SocketAddr, config: &Configuration, stream_id: u32, handshake_rtt: Option<Duration>, peer_window: Option<usize>, keys: (Option<Cipher>, Option<PacketAuth>), path_mtu: usize) -> Result<Sender<T>, IOError> {
+        let conn_id = conn_id();
+        let (cipher, auth) = keys;
+
+        let window = Arc::new(SlidingWindow::new(config.window_size()));
+
+        let bbr = Arc::new(Mutex::new(BbrState::new()));
+        let cc = Arc::new(Mutex::new(congestion::new(config.congestion(), MAX_PACKET_SIZE)));
+        let rtt = Arc::new(Mutex::new(RttEstimator::new()));
+
+        if let Some(handshake_rtt) = handshake_rtt {
+            rtt.lock().unwrap().update(handshake_rtt);
+        }
+
+        let acked = Arc::new(Condvar::new());
+        let progress = Arc::new(Mutex::new(Progress::new()));
+        let reverse = Arc::new(SlidingWindow::new(config.window_size()));
+
+        let recv_socket :T = socket.try_clone()?;
+        let recv_window = window.clone();
+        let recv_bbr = bbr.clone();
+        let recv_cc = cc.clone();
+        let recv_rtt = rtt.clone();
+        let recv_acked = acked.clone();
+        let recv_progress = progress.clone();
+        let started = Instant::now();
+        let recv_started = started;
+        let counters = Arc::new(Counters::default());
+        let recv_counters = counters.clone();
+        let recv_reverse = reverse.clone();
+        let recv_cipher = cipher.clone();
+        let recv_auth = auth.clone();
+        let overhead = if cipher.is_some() { TAG_SIZE } else { 0 };   // not counted as progress
+        let rto = config.rto();
+        let max_retransmits = config.max_retransmits();
+        let checksum = config.checksum();
+        let idle_timeout = config.idle_timeout();
+        let max_window = config.max_window().map(|max| max.min(peer_window.unwrap_or_else(|| config.window_size())));
+        let stop = Arc::new(AtomicBool::new(false));
+        let recv_stop = stop.clone();
+        let (recv_errors, errors) = channel();
+
+        let thread = thread::spawn(move || {
+            set_conn_id(conn_id);
+
+            // if this thread dies, write_all needs to know instead of waiting on ACKs forever
+            let _guard = CloseOnDrop::new(recv_window.clone());
+            let _reverse_guard = CloseOnDrop::new(recv_reverse.clone());   // and read needs to know too
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+
+            // remove it from the sliding window
+            // it's not there if this is a duplicate, or it was already acknowledged by an ACK for a retransmit
+            let on_ack = |seq_num: u64| {
+                let (state, retransmits, packet) :(SendState, u32, PooledBuf) = match recv_window.remove(seq_num) {
+                    Ok(t) => t,
+                    Err(e) => {
+                        debug!("Ignoring ACK for {}: {}", seq_num, e);
+                        recv_counters.duplicate_acks.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                };
+                let msg = get_root_as_message(&packet);
+
+                let estimate = {
+                    let mut bbr = recv_bbr.lock().unwrap();
+
+                    bbr.on_ack(&state, packet.len());
+                    (bbr.bandwidth(), bbr.min_rtt())
+                };
+
+                // Karn's algorithm: there's no telling which send a retransmitted packet's ACK is for
+                let rtt = if retransmits == 0 { Some(state.sent.elapsed()) } else { None };
+
+                {
+                    let mut cc = recv_cc.lock().unwrap();
+
+                    cc.on_ack(packet.len() as u64, rtt);
+
+                    if let (Some(bandwidth), Some(min_rtt)) = estimate {
+                        cc.on_estimate(bandwidth, min_rtt);
+                    }
+                }
+
+                if let Some(rtt) = rtt {
+                    recv_rtt.lock().unwrap().update(rtt);
+                }
+                recv_acked.notify_all();
+
+                // only file contents count, not file headers
+                let payload_len = if msg.msg_type() != Type::Message { 0 } else { msg.payload().map_or(0, |p| p.len() - overhead) as u64 };
+                let mut progress = recv_progress.lock().unwrap();
+
+                progress.acked += payload_len;
+
+                if let Some(ref callback) = progress.callback {
+                    callback(progress.acked, progress.total);
+                }
+
+                // every interval, and once everything's acknowledged
+                let now = Instant::now();
+                let (last, last_acked) = progress.last_report.unwrap_or((recv_started, 0));
+                let done = progress.total > 0 && progress.acked >= progress.total && last_acked < progress.total;
+                let due = match progress.report {
+                    Some((interval, _)) => done || now.duration_since(last) >= interval,
+                    None => false
+                };
+
+                if due {
+                    let elapsed = now.duration_since(last);
+                    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+                    let report = ProgressReport {
+                        acked: progress.acked,
+                        total: progress.total,
+                        rate_bps: if secs == 0.0 { 0 } else { ((progress.acked - last_acked) as f64 * 8.0 / secs) as u64 },
+                        rtt: recv_rtt.lock().unwrap().srtt().unwrap_or(Duration::from_secs(0))
+                    };
+
+                    if let Some((_, ref callback)) = progress.report {
+                        callback(&report);
+                    }
+
+                    progress.last_report = Some((now, progress.acked));
+                }
+            };
+
+            let mut last_scan = Instant::now();
+            let mut last_heard = Instant::now();    // from the receiver, anything at all
+            let mut last_ping = Instant::now();
+            let mut last_tune = Instant::now();
+            let mut cumulative = recv_window.window().0;    // where the window started after the last ACK
+            let mut dup_acks = 0;
+
+            while !recv_stop.load(Ordering::Acquire) {
+                // the receiver's gone, or the path to it is; let write_all or flush know, instead of retransmitting forever
+                if last_heard.elapsed() >= idle_timeout {
+                    warn!("Nothing from the receiver in {:?}, giving up", idle_timeout);
+                    let _ = recv_errors.send(IOError::new(ErrorKind::TimedOut, format!("Nothing heard from the receiver in {:?}", idle_timeout)));
+                    break;
+                }
+
+                // a quiet receiver might just have nothing to ACK, so give it something to answer
+                let ping_interval = idle_timeout / PINGS_PER_IDLE_TIMEOUT;
+
+                if last_heard.elapsed() >= ping_interval && last_ping.elapsed() >= ping_interval {
+                    last_ping = Instant::now();
+
+                    debug!("SENDING PING");
+
+                    if let Err(e) = recv_socket.send(&sign_packet(&recv_auth, construct_message(checksum, Type::Ping, stream_id, 0).finished_data())) {
+                        warn!("Error sending Ping: {}", e);
+                    }
+                }
+
+                // grow or shrink the window toward the bandwidth-delay product, once there's an estimate of it
+                // only by more than an eighth, so it isn't copied for every small change in the estimate
+                if let Some(max_window) = max_window {
+                    if last_tune.elapsed() >= WINDOW_TUNE_INTERVAL {
+                        last_tune = Instant::now();
+
+                        let estimate = {
+                            let bbr = recv_bbr.lock().unwrap();
+
+                            (bbr.bandwidth(), bbr.min_rtt())
+                        };
+
+                        if let (Some(bandwidth), Some(min_rtt)) = estimate {
+                            let target = target_window(bandwidth, min_rtt, path_mtu, max_window);
+                            let (start, end) = recv_window.window();
+                            let size = (end - start) as usize;
+
+                            if target.max(size) - target.min(size) > size / 8 {
+                                // it can't shrink past what's still in flight, that's tried again next time
+                                match recv_window.resize(target) {
+                                    Ok(()) => debug!("Window resized from {} to {} packets", size, target),
+                                    Err(e) => debug!("Couldn't resize the window from {} to {} packets: {}", size, target, e)
+                                }
+                            }
+                        }
+                    }
+                }
+
+                let base = base_rto(rto, &recv_rtt);
+                let scan_interval = (base / 4).max(Duration::from_millis(1)).min(Duration::from_secs(1));
+
+                // retransmit anything that's waited too long, even while other ACKs keep arriving
+                // update in place, as removing it would slide the window past it if it's first
+                if last_scan.elapsed() >= scan_interval {
+                    last_scan = Instant::now();
+
+                    let mut given_up = None;
+
+                    while let Some(loc) = recv_window.find_first(|t :&(SendState, u32, PooledBuf)| t.0.sent.elapsed() > backoff(base, t.1)) {
+                        given_up = recv_window.update(loc, |t| {
+                            // it's been retransmitted as often as it's allowed, the receiver or the path to it is gone
+                            if max_retransmits.map_or(false, |max| t.1 >= max) {
+                                return Some((loc, t.1));
+                            }
+
+                            t.1 += 1;
+
+                            debug!("RESENDING SEQ: {} ATTEMPT: {}", loc, t.1);
+
+                            recv_cc.lock().unwrap().on_timeout(t.2.len() as u64);
+
+                            // there's no one to tell from here, and it's resent again after another timeout anyway
+                            if let Err(e) = recv_socket.send(&t.2) {
+                                warn!("Error resending {}: {}", loc, e);
+                            }
+
+                            recv_counters.retransmits.fetch_add(1, Ordering::Relaxed);
+                            t.0.sent = Instant::now();
+
+                            None
+                        }).expect("Error updating item we previously found");
+
+                        if given_up.is_some() {
+                            break;
+                        }
+                    }
+
+                    // let write_all or flush know, instead of retransmitting forever
+                    if let Some((loc, retransmits)) = given_up {
+                        warn!("No ACK for {} after {} retransmits, giving up", loc, retransmits);
+                        let _ = recv_errors.send(IOError::new(ErrorKind::ConnectionAborted, format!("No ACK for packet {} after {} retransmits", loc, retransmits)));
+                        break;
+                    }
+                }
+
+                // attempt to read an ack, waiting no longer than until the next scan, or to check if we should stop
+                let res = recv_retrying(|| recv_socket.recv_from_timeout(&mut buf, scan_interval.min(STOP_POLL_INTERVAL)));
+
+                // waited for an Ack, but didn't come
+                if let Err(e) = res {
+                    // nothing more is coming, let write_all or flush know why
+                    if e.kind() != ErrorKind::WouldBlock {
+                        warn!("Error reading ACK: {:?}", e);
+                        let _ = recv_errors.send(e);
+                        break;
+                    }
+                } else if res.is_ok() {
+                    // otherwise, we got a message
+                    let (amt, _) = res.unwrap();
+
+                    // an empty datagram carries nothing, not even a malformed ACK
+                    if amt == 0 {
+                        continue;
+                    }
+
+                    // nothing from anyone but the receiver counts, or an ACK could be forged
+                    let packet = match verify_packet(&recv_auth, &buf[0..amt]) {
+                        Some(packet) => packet,
+                        None => {
+                            warn!("Dropping {} byte packet: not authenticated", amt);
+                            continue;
+                        }
+                    };
+
+                    let ack = match parse_message(packet) {
+                        Some(ack) => ack,
+                        None => {
+                            warn!("Dropping {} byte packet: not a valid message", amt);
+                            continue;
+                        }
+                    };
+
+                    // a corrupt ACK is as good as a lost one, the packet will be retransmitted
+                    if !verify_checksum(checksum, &ack) {
+                        warn!("Dropping ACK w/bad checksum for {}", ack.seq_num());
+                        continue;
+                    }
+
+                    // another stream's, or an Acknowledge of a repeated Connect, which carries the streams agreed to
+                    if ack.stream_id() != stream_id {
+                        debug!("Dropping {:?} {} for stream {}", ack.msg_type(), ack.seq_num(), ack.stream_id());
+                        continue;
+                    }
+
+                    last_heard = Instant::now();
+
+                    // data from the receiver, ACKed even if we already have it, as the first ACK might have been lost
+                    // unless there's no room because nobody's reading, then the receiver resends it later
+                    if ack.msg_type() == Type::Reverse {
+                        let loc = ack.seq_num();
+
+                        if loc >= recv_reverse.window().1 {
+                            debug!("Dropping Reverse {}: nothing is reading them", loc);
+                            continue;
+                        }
+
+                        let payload = match open_payload(&recv_cipher, &ack) {
+                            Some(payload) => payload,
+                            None => continue
+                        };
+
+                        if let Err(e) = recv_reverse.insert(loc, payload) {
+                            debug!("Ignoring Reverse {}: {}", loc, e);
+                        }
+
+                        // if this is lost, the receiver resends the Reverse
+                        if let Err(e) = recv_socket.send(&sign_packet(&recv_auth, construct_message(checksum, Type::Acknowledge, stream_id, loc).finished_data())) {
+                            warn!("Error acknowledging Reverse {}: {}", loc, e);
+                        }
+
+                        continue;
+                    }
+
+                    // the receiver is missing a packet, resend it now rather than waiting for it to time out
+                    // update in place, as removing and re-inserting would slide the window past it if it's first
+                    if ack.msg_type() == Type::NegativeAcknowledge {
+                        let loc = ack.seq_num();
+
+                        let res = recv_window.update(loc, |t| {
+                            debug!("RESENDING NACKED SEQ: {}", loc);
+
+                            recv_cc.lock().unwrap().on_loss(t.2.len() as u64);
+
+                            if let Err(e) = recv_socket.send(&t.2) {
+                                warn!("Error resending NACKed {}: {}", loc, e);
+                            }
+
+                            recv_counters.retransmits.fetch_add(1, Ordering::Relaxed);
+                            t.0.sent = Instant::now();
+                        });
+
+                        // already acknowledged, by a retransmit that beat the NACK
+                        if let Err(e) = res {
+                            debug!("Ignoring NACK for {}: {}", loc, e);
+                        }
+
+                        continue;
+                    }
+
+                    match ack.msg_type() {
+                        Type::Acknowledge => on_ack(ack.seq_num()),
+                        // everything in the ranges at once, only what's still in the window, so a bogus range can't keep us busy
+                        Type::SelectiveAck => {
+                            let ranges = match read_ranges(&ack) {
+                                Some(ranges) => ranges,
+                                None => {
+                                    warn!("Dropping malformed SelectiveAck {}", ack.seq_num());
+                                    continue;
+                                }
+                            };
+
+                            for range in ranges {
+                                let (start, end) = recv_window.window();
+
+                                for seq_num in range.start().max(start)..range.end().min(end) {
+                                    on_ack(seq_num);
+                                }
+                            }
+                        },
+                        // the other end finding the path MTU, when it's sending too
+                        Type::Probe => (),
+                        // the answer to a Ping, it's enough that it came
+                        Type::Pong => (),
+                        t => warn!("Dropping unexpected {:?} {}", t, ack.seq_num())
+                    }
+
+                    if ack.msg_type() != Type::Acknowledge && ack.msg_type() != Type::SelectiveAck {
+                        continue;
+                    }
+
+                    // packets after the window's start are arriving but it isn't, so it was likely lost
+                    // resend it on the third ACK that doesn't move the start, once for each place it's stuck, rather than waiting for the timeout
+                    let start = recv_window.window().0;
+
+                    if start != cumulative {
+                        cumulative = start;
+                        dup_acks = 0;
+                        continue;
+                    }
+
+                    dup_acks += 1;
+
+                    if dup_acks == DUP_ACK_THRESHOLD {
+                        let res = recv_window.update(start, |t| {
+                            debug!("FAST RESENDING SEQ: {}", start);
+
+                            recv_cc.lock().unwrap().on_loss(t.2.len() as u64);
+
+                            if let Err(e) = recv_socket.send(&t.2) {
+                                warn!("Error fast resending {}: {}", start, e);
+                            }
+
+                            recv_counters.retransmits.fetch_add(1, Ordering::Relaxed);
+                            t.0.sent = Instant::now();
+                        });
+
+                        // nothing's in flight, the ACKs were for packets already acknowledged
+                        if let Err(e) = res {
+                            debug!("Not fast resending {}: {}", start, e);
+                        }
+                    }
+                }
+            }
+        });
+
+        let max_payload_size = max_payload_size(tagged_mtu(path_mtu, &auth), stream_id);
+        let pool = BufferPool::new(MAX_PACKET_SIZE, 2 * config.window_size());
+        let hash = if config.verify() { Some(Sha256::new()) } else { None };
+
+        return Ok(Sender {
+            socket,
+            remote_addr,
+            stream_id,
+            seq_num: 0,
+            window,
+            pool,
+            bbr,
+            cc,
+            rtt,
+            acked,
+            progress,
+            cipher,
+            auth,
+            checksum: config.checksum(),
+            unidirectional: false,
+            fec_block_size: config.fec_block_size(),
+            path_mtu,
+            max_payload_size,
+            payload_size: config.payload_size(),
+            parity: Parity::new(),
+            pacer: config.max_rate().map(Pacer::new),
+            nonblocking: config.nonblocking(),
+            resume: 0,
+            next_send: None,
+            coalesce: config.coalesce(),
+            pending: Vec::new(),
+            bytes_transferred: AtomicU64::new(0),
+            counters,
+            started,
+            reverse,
+            reverse_leftover: Vec::new(),
+            hash,
+            stop,
+            thread: Some(thread),
+            errors
+        });
+    }
+}
+
+impl <T> Sender<T> where T: Socket {
+    /// The number of payload bytes sent so far, not counting headers or retransmits
+    pub fn bytes_transferred(&self) -> u64 {
+        self.bytes_transferred.load(Ordering::Acquire)
+    }
+
+    /// A summary of the transfer so far: call it after finish for the whole thing
+    pub fn stats(&self) -> TransferStats {
+        TransferStats {
+            bytes: self.bytes_transferred(),
+            duration: self.started.elapsed(),
+            packets_sent: self.counters.packets_sent.load(Ordering::Relaxed),
+            retransmits: self.counters.retransmits.load(Ordering::Relaxed),
+            duplicate_acks: self.counters.duplicate_acks.load(Ordering::Relaxed),
+            min_rtt: self.min_rtt(),
+            smoothed_rtt: self.smoothed_rtt(),
+            bandwidth: self.estimated_bandwidth(),
+            path_mtu: self.path_mtu()
+        }
+    }
+
+    /// Calls callback w/(bytes acknowledged, total bytes) every time an ACK arrives, from the thread reading ACKs
+    /// The total is 0 unless set w/set_total_bytes; nothing is ever acknowledged when unidirectional
+    pub fn set_progress(&mut self, callback: Box<dyn Fn(u64, u64) + Send>) {
+        self.progress.lock().unwrap().callback = Some(callback);
+    }
+
+    /// Calls callback w/a ProgressReport, from the thread reading ACKs, as they arrive but no more often than every interval
+    /// It's called once more when everything's acknowledged, if the total was set w/set_total_bytes
+    pub fn set_report(&mut self, interval: Duration, callback: Box<dyn Fn(&ProgressReport) + Send>) {
+        self.progress.lock().unwrap().report = Some((interval, callback));
+    }
+
+    /// Sets the total number of bytes that will be written, for reporting progress
+    pub fn set_total_bytes(&mut self, total: u64) {
+        self.progress.lock().unwrap().total = total;
+    }
+
+    /// The estimated bandwidth to the receiver, in bytes/sec (including headers)
+    /// None until something has been acknowledged
+    pub fn estimated_bandwidth(&self) -> Option<f64> {
+        self.bbr.lock().unwrap().bandwidth()
+    }
+
+    /// The minimum round-trip time seen recently
+    /// None until something has been acknowledged
+    pub fn min_rtt(&self) -> Option<Duration> {
+        self.bbr.lock().unwrap().min_rtt()
+    }
+
+    /// The largest packet that makes it to the receiver, found when connecting
+    pub fn path_mtu(&self) -> usize {
+        self.path_mtu
+    }
+
+    /// Bytes sent but not yet acknowledged, whole packets as they went out
+    /// Zero when unidirectional, as nothing's kept for retransmits
+    pub fn inflight_bytes(&self) -> u64 {
+        self.window.fold(0, |sum, t| sum + t.2.len() as u64)
+    }
+
+    /// The largest payload that fits in a packet on the path
+    fn max_payload_size(&self) -> usize {
+        self.max_payload_size
+    }
+
+    /// The smoothed round-trip time, starting from the handshake's
+    /// Zero when unidirectional, as nothing is ever acknowledged
+    pub fn smoothed_rtt(&self) -> Duration {
+        self.rtt.lock().unwrap().srtt().unwrap_or(Duration::from_secs(0))
+    }
+
+    /// Whether the congestion window has room for another packet
+    /// The congestion window comes from the --cc strategy, w/the window size as an upper bound
+    fn cwnd_has_room(&self, bbr: &BbrState) -> bool {
+        let (start, end) = self.window.window();
+
+        // in packets, and always room for at least one
+        let cwnd = (self.cc.lock().unwrap().cwnd() / MAX_PACKET_SIZE as u64).max(1).min(end - start);
+
+        return (bbr.in_flight() as u64) < cwnd;
+    }
+
+    /// Whether the next packet can be sent w/out waiting: the window has room for its sequence number,
+    /// and the congestion window for one more in flight
+    /// Only ACKs change either, and they only ever make room
+    fn has_room(&self) -> bool {
+        if self.seq_num >= self.window.window().1 {
+            return false;
+        }
+
+        return self.cwnd_has_room(&self.bbr.lock().unwrap());
+    }
+
+    /// Waits until the congestion window has room for another packet, and records that it's being sent
+    fn wait_for_cwnd(&self) -> Result<SendState, IOError> {
+        let mut bbr = self.bbr.lock().unwrap();
+
+        loop {
+            if self.cwnd_has_room(&bbr) {
+                return Ok(bbr.on_send());
+            }
+
+            if self.window.is_closed() {
+                return Err(aborted(&self.errors, "window closed"));
+            }
+
+            // sleep until an ACK comes in; the timeout is only so a dead ACK thread's closed window is noticed
+            bbr = self.acked.wait_timeout(bbr, Duration::from_millis(100)).unwrap().0;
+        }
+    }
+
+    /// Performs the Connect handshake, then disconnects w/out sending any data
+    /// Used to check the receiver is reachable before starting a transfer
+    pub fn probe(socket: T, config: &Configuration) -> Result<ProbeResult, QcpError> {
+        if config.unidirectional() {
+            return Err(QcpError::Config("Cannot probe a unidirectional link".to_string()));
+        }
+
+        new_conn_id();
+
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+
+        let (msg_data, nonce) = construct_connect(config.key(), 0, config.checksum(), socket.local_addr().ok());
+        let nonce = nonce.as_ref().map(|n| &n[..]);
+        let (remote_addr, rtt, window_size, ack_nonce, _) = handshake_any(&socket, msg_data.finished_data(), config, nonce)?;
+        let (_, auth) = session_keys(config, &session_nonce(nonce, &ack_nonce))?;
+
+        // find the path MTU the same way a transfer would
+        socket.connect(remote_addr)?;
+
+        let path_mtu = discover_path_mtu(&socket, &auth, config.checksum())?;
+
+        // let the receiver know we're not sending anything
+        socket.send(&sign_packet(&auth, construct_message(config.checksum(), Type::Disconnect, 0, 0).finished_data()))?;
+
+        // an older receiver doesn't say, so all we know is what we'd ask for
+        let window_size = window_size.unwrap_or_else(|| config.window_size());
+
+        return Ok(ProbeResult { peer: remote_addr, rtt, window_size, max_packet_size: path_mtu });
+    }
+
+    /// Copies as much of a Reverse payload as fits into buf, keeping the rest for the next read
+    fn copy_reverse(&mut self, buf: &mut[u8], packet: Vec<u8>) -> usize {
+        let amt = buf.len().min(packet.len());
+
+        buf[..amt].copy_from_slice(&packet[..amt]);
+        self.reverse_leftover = packet[amt..].to_vec();
+
+        return amt;
+    }
+
+    /// The most of what's written that goes in one packet
+    /// Less room in the parity's packet for its length header, when unidirectional, and for the tag when encrypting
+    fn chunk_size(&self) -> usize {
+        let chunk_size = if self.unidirectional { self.max_payload_size() - PARITY_HEADER_SIZE } else { self.max_payload_size() };
+        let chunk_size = if self.cipher.is_some() { chunk_size - TAG_SIZE } else { chunk_size };
+
+        return self.payload_size.map_or(chunk_size, |payload_size| payload_size.min(chunk_size));
+    }
+
+    /// Sends buf as a sequence of packets, as much as fits in each, see write_all
+    fn send_chunks(&mut self, buf: &[u8]) -> Result<(), IOError> {
+        let chunk_size = self.chunk_size();
+        let resume = mem::replace(&mut self.resume, 0);
+
+        if resume > buf.len() {
+            return Err(IOError::new(ErrorKind::InvalidInput, "Not the same buf as the write_all that returned WouldBlock"));
+        }
+
+        // in the window, but not sent yet; they go out a batch at a time, but never wait on anything unsent
+        let mut unsent = self.seq_num;
+
+        for (i, chunk) in buf[resume..].chunks(chunk_size).enumerate() {
+            debug!("CHUNK LEN: {}", chunk.len());
+
+            // nothing's waiting on ACKs when unidirectional
+            if self.nonblocking && !self.unidirectional && !self.has_room() {
+                self.send_window(unsent)?;
+                self.resume = resume + i * chunk_size;
+                return Err(IOError::new(ErrorKind::WouldBlock, "Window is full"));
+            }
+
+            if let Some(ref mut hash) = self.hash {
+                hash.update(chunk);
+            }
+
+            // construct the message w/the payload, encrypted first so the checksum covers what's sent
+            let sealed = self.cipher.as_ref().map(|c| c.seal(&packet_nonce(Type::Message, self.stream_id, self.seq_num), &[], chunk));
+            let payload = sealed.as_ref().map_or(chunk, |s| &s[..]);
+            let mut msg_buf = self.pool.get();
+
+            sign_packet_into(&self.auth, construct_payload_message(self.checksum, Type::Message, self.stream_id, self.seq_num, payload).finished_data(), &mut msg_buf);
+
+            if msg_buf.len() > MAX_PACKET_SIZE {
+                panic!("About to send a packet larger than max packet: {} > {}", msg_buf.len(), MAX_PACKET_SIZE);
+            }
+
+            debug!("SENDING SEQ: {} LEN: {}", self.seq_num, msg_buf.len());
+            trace!("PACKET: {}", buf2string(msg_buf.as_slice()));
+
+            // spaced out, rather than sent as fast as the loop runs, so there's no burst for a queue along the way to drop
+            if let Some(delay) = self.pace(msg_buf.len()) {
+                unsent = self.send_window(unsent)?;
+                sleep_precisely(delay);
+            }
+
+            // the whole packet counts against the cap, but retransmits and control messages don't
+            if let Some(ref mut pacer) = self.pacer {
+                let delay = pacer.reserve(msg_buf.len(), Instant::now());
+
+                if delay > Duration::from_secs(0) {
+                    unsent = self.send_window(unsent)?;
+                    thread::sleep(delay);
+                }
+            }
+
+            // no ACKs are coming, so there's nothing to keep around for retransmits
+            // instead, close out every block w/its parity
+            if self.unidirectional {
+                self.socket.send(&msg_buf)?;
+                self.parity.add(chunk);
+                self.seq_num += 1;
+                self.bytes_transferred.fetch_add(chunk.len() as u64, Ordering::AcqRel);
+                self.counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+
+                if self.parity.count() == self.fec_block_size {
+                    self.send_parity()?;
+                }
+
+                continue;
+            }
+
+            // the ACKs that make room only come for what's been sent
+            if !self.has_room() {
+                unsent = self.send_window(unsent)?;
+
+                debug!("WAITING FOR ROOM: {} packets, {} bytes in flight{}", self.window.len(), self.inflight_bytes(), if self.window.is_full() { ", window full" } else { "" });
+            }
+
+            // wait for room in the congestion window, and then the window, so no more than a window's worth
+            // is ever in flight no matter how large buf is; insert before sending, so the ACK can't beat us to it
+            // when nonblocking there's already room in both, so neither waits
+            let state = self.wait_for_cwnd()?;
+            let inserted = if self.nonblocking {
+                self.window.try_insert(self.seq_num, (state, 0, msg_buf))
+            } else {
+                self.window.insert(self.seq_num, (state, 0, msg_buf))
+            };
+
+            if let Err(e) = inserted {
+                return Err(aborted(&self.errors, e));
+            }
+
+            self.seq_num += 1; // bump our sequence number
+            self.bytes_transferred.fetch_add(chunk.len() as u64, Ordering::AcqRel);
+            self.counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+
+            if self.seq_num - unsent >= MAX_BATCH as u64 {
+                unsent = self.send_window(unsent)?;
+            }
+        }
+
+        self.send_window(unsent)?;
+
+        return Ok( () );
+    }
+
+    /// How fast to send, in bytes/sec: PACING_GAIN times the congestion window per smoothed RTT
+    /// None when unidirectional, as there's no RTT or congestion window
+    fn pacing_rate(&self) -> Option<f64> {
+        if self.unidirectional {
+            return None;
+        }
+
+        let srtt = self.rtt.lock().unwrap().srtt()?;
+        let secs = srtt.as_secs() as f64 + srtt.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        if secs == 0.0 {
+            return None;
+        }
+
+        // the window size caps the congestion window, as in cwnd_has_room, and BBR's is unlimited until there's an estimate
+        let (start, end) = self.window.window();
+        let cwnd = self.cc.lock().unwrap().cwnd().min((end - start) * MAX_PACKET_SIZE as u64);
+
+        return Some(PACING_GAIN * cwnd as f64 / secs);
+    }
+
+    /// Moves the next send time along by a packet of len bytes at the pacing rate
+    /// Returns how long to wait before sending it, None if it can go now
+    /// Time spent idle isn't banked, so it never allows a burst to catch up
+    fn pace(&mut self, len: usize) -> Option<Duration> {
+        let rate = self.pacing_rate()?;
+        let now = Instant::now();
+        let next_send = self.next_send.filter(|&next_send| next_send > now).unwrap_or(now);
+
+        self.next_send = Some(next_send + Duration::from_nanos((len as f64 / rate * 1_000_000_000.0) as u64));
+
+        return if next_send > now { Some(next_send - now) } else { None };
+    }
+
+    /// Sends what write_all was holding, in a packet of its own
+    fn send_pending(&mut self) -> Result<(), IOError> {
+        if self.pending.is_empty() {
+            return Ok( () );
+        }
+
+        let mut pending = mem::replace(&mut self.pending, Vec::new());
+
+        self.send_chunks(&pending)?;
+
+        // kept, so the next one doesn't allocate
+        pending.clear();
+        self.pending = pending;
+
+        return Ok( () );
+    }
+
+    /// Sends a control message the receiver needs to see in order w/the Messages
+    /// It takes the next sequence number, so it's retransmitted like any other packet
+    fn send_control(&mut self, msg_type: Type, payload: Option<&[u8]>) -> Result<(), IOError> {
+        // what was written before it goes first
+        self.send_pending()?;
+
+        let msg = match payload {
+            None => construct_message(self.checksum, msg_type, self.stream_id, self.seq_num),
+            Some(payload) => {
+                let sealed = self.cipher.as_ref().map(|c| c.seal(&packet_nonce(msg_type, self.stream_id, self.seq_num), &[], payload));
+
+                construct_payload_message(self.checksum, msg_type, self.stream_id, self.seq_num, sealed.as_ref().map_or(payload, |s| &s[..]))
+            }
+        };
+
+        return self.send_sequenced(msg.finished_data());
+    }
+
+    /// Sends an already constructed message that took the next sequence number
+    fn send_sequenced(&mut self, msg_buf: &[u8]) -> Result<(), IOError> {
+        let mut signed = self.pool.get();
+
+        sign_packet_into(&self.auth, msg_buf, &mut signed);
+
+        // nothing will be acknowledged, so just repeat it like the Connect
+        if self.unidirectional {
+            for _ in 0..UNIDIRECTIONAL_CONNECTS {
+                self.socket.send(&signed)?;
+            }
+
+            self.seq_num += 1;
+            self.counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+
+            return Ok( () );
+        }
+
+        let state = self.wait_for_cwnd()?;
+
+        if let Err(e) = self.window.insert(self.seq_num, (state, 0, signed)) {
+            return Err(aborted(&self.errors, e));
+        }
+
+        self.seq_num += 1;
+        self.send_window(self.seq_num - 1)?;
+        self.counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+
+        return Ok( () );
+    }
+
+    /// Sends the packets already in the window from from up to seq_num, w/as few send_batch calls as it takes
+    /// They're sent from there, rather than copies, and inserted first so their ACKs can't beat them there
+    /// Returns seq_num, where the next batch starts
+    fn send_window(&self, mut from: u64) -> Result<u64, IOError> {
+        // nothing's kept in the window when unidirectional, it's sent as it's written
+        if self.unidirectional {
+            return Ok(self.seq_num);
+        }
+
+        while from < self.seq_num {
+            let sent = self.window.inspect_range(from, self.seq_num, |packets| {
+                let batch = packets.iter().map(|t| (&t.2[..], self.remote_addr)).collect::<Vec<_>>();
+
+                self.socket.send_batch(&batch)
+            });
+
+            match sent {
+                Ok(Ok(0)) => return Err(IOError::new(ErrorKind::WriteZero, "None of the batch was sent")),
+                Ok(Ok(sent)) => from += sent as u64,
+                Ok(Err(e)) => return Err(e),
+                Err(e) => return Err(aborted(&self.errors, e))
+            }
+        }
+
+        return Ok(from);
+    }
+
+    /// Sends the parity for the current block, and starts a new one
+    fn send_parity(&mut self) -> Result<(), IOError> {
+        let block_start = self.seq_num - self.parity.count() as u64;
+
+        let sealed = self.cipher.as_ref().map(|c| c.seal(&packet_nonce(Type::Parity, self.stream_id, block_start), &[], self.parity.bytes()));
+        let fbb = construct_payload_message(self.checksum, Type::Parity, self.stream_id, block_start, sealed.as_ref().map_or(self.parity.bytes(), |s| &s[..]));
+        let msg_buf = sign_packet(&self.auth, fbb.finished_data());
+
+        if msg_buf.len() > MAX_PACKET_SIZE {
+            panic!("About to send a parity packet larger than max packet: {} > {}", msg_buf.len(), MAX_PACKET_SIZE);
+        }
+
+        debug!("SENDING PARITY: {} LEN: {}", block_start, msg_buf.len());
+
+        self.socket.send(&msg_buf)?;
+        self.parity = Parity::new();
+        self.counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+
+        return Ok( () );
+    }
+}
+
+/// What the receiver keeps from the handshake: the Connect and its Acknowledge, to answer a repeated Connect,
+/// and the cipher and packet auth from session_keys
+#[derive(Clone)]
+struct Accepted {
+    connect_data: Vec<u8>,
+    ack_data: Vec<u8>,
+    cipher: Option<Cipher>,
+    auth: Option<PacketAuth>,
+    peer: Option<Arc<Mutex<SocketAddr>>>,  // where the sender is now, shared by the streams; None when the socket's connected to it
+    ack_addr: SocketAddr                    // where the sender reads ACKs, see ack_addr
+}
+
+/// Waits for a Connect, and acknowledges it w/our window size, agreeing to as many streams as it asks for up to max_streams
+/// The Acknowledge, and every ACK after it, goes to the address the Connect says the sender reads them on, see ack_addr
+/// Anything from an address config doesn't allow is ignored, w/out an Acknowledge
+/// socket is connected to the sender once it's accepted, unless there's a key: then the sender can move, see Receiver::start;
+/// or unless its ACKs go somewhere else
+/// duplex is whether this end sends too, which the Connect has to have asked for, see BbrConnection
+/// Returns the sender's address, what's needed from the handshake after it, and the streams agreed to
+fn accept<T: Socket>(socket: &T, config: &Configuration, max_streams: usize, duplex: bool) -> Result<(SocketAddr, Accepted, usize), QcpError> {
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+
+    // an empty datagram isn't a malformed Connect, it's nothing at all, so keep waiting for one
+    let (buf_size, remote_addr) = loop {
+        match recv_retrying(|| socket.recv_from(&mut buf))? {
+            (0, addr) => debug!("Ignoring empty datagram from {}", addr),
+            (_, addr) if !config.allowed(addr.ip()) => warn!("Ignoring packet from {}, it isn't allowed", addr),
+            res => break res
+        }
+    };
+
+    let msg = match parse_message(&buf[..buf_size]) {
+        Some(msg) => msg,
+        None => return Err(QcpError::Handshake(IOError::new(ErrorKind::InvalidData, "Malformed Connect")))
+    };
+
+    if !verify_checksum(HANDSHAKE_CHECKSUM, &msg) {
+        return Err(QcpError::Checksum(IOError::new(ErrorKind::InvalidData, "Bad checksum on Connect")));
+    }
+
+    if msg.msg_type() != Type::Connect {
+        return Err(QcpError::Handshake(IOError::new(ErrorKind::ConnectionAborted, "Got non-connect message")));
+    }
+
+    // w/out the magic it's not from qcp, or from one too old to say what version it speaks
+    if msg.magic() != PROTOCOL_MAGIC {
+        warn!("Rejecting Connect from {}: not from qcp, or from an older one", remote_addr);
+        return Err(QcpError::Handshake(IOError::new(ErrorKind::InvalidData, "Connect is not from qcp, or from a version too old to say")));
+    }
+
+    if msg.version() != PROTOCOL_VERSION {
+        warn!("Rejecting Connect from {}: protocol version {}, not {}", remote_addr, msg.version(), PROTOCOL_VERSION);
+        return Err(QcpError::Handshake(IOError::new(ErrorKind::Unsupported, format!("Sender speaks protocol version {}, not {}", msg.version(), PROTOCOL_VERSION))));
+    }
+
+    let unidirectional = config.unidirectional();
+
+    // both ends have to expect to send, or one would be left waiting on the other
+    if ((msg.stream_id() & DUPLEX) != 0) != duplex {
+        return Err(QcpError::Handshake(IOError::new(ErrorKind::ConnectionRefused, if duplex { "Sender won't receive anything back" } else { "Sender wants something sent back" })));
+    }
+
+    // both ends have to checksum the same way, or neither would take anything the other sends
+    if msg.stream_id() & CHECKSUM_MASK != checksum_bits(config.checksum()) {
+        return Err(QcpError::Handshake(IOError::new(ErrorKind::ConnectionRefused, format!("Sender does not use {:?} checksums", config.checksum()))));
+    }
+
+    // a Connect that doesn't say wants one, like an older sender's
+    let streams = ((msg.stream_id() & !DUPLEX & !CHECKSUM_MASK).max(1) as usize).min(max_streams);
+    let agreed = (if duplex { streams as u32 | DUPLEX } else { streams as u32 }) | checksum_bits(config.checksum());
+
+    let payload = msg.payload().unwrap_or(&[]);
+
+    // the whole payload is under the MAC, so the address the ACKs go to can't be swapped out either
+    if let Some(key) = config.key() {
+        if !msg.mac().map_or(false, |mac| mac_eq(&hmac_sha256(key, &[CONNECT_LABEL, payload]), mac)) {
+            warn!("Rejecting Connect from {}: it does not prove it knows the pre-shared key", remote_addr);
+            return Err(QcpError::Handshake(IOError::new(ErrorKind::PermissionDenied, "Connect not authenticated w/the pre-shared key")));
+        }
+    }
+
+    // the nonce, when the sender has a key and so a MAC, then the address it reads ACKs on, if it has one
+    // a sender w/a key we don't know still gets its Acknowledge, and turns us down itself
+    let nonce_size = if msg.mac().is_some() { CONNECT_NONCE_SIZE } else { 0 };
+
+    if payload.len() != nonce_size && payload.len() != nonce_size + ACK_ADDR_SIZE {
+        return Err(QcpError::Handshake(IOError::new(ErrorKind::InvalidData, format!("Malformed Connect: {} byte payload", payload.len()))));
+    }
+
+    let (connect_nonce, carried) = payload.split_at(nonce_size);
+    let ack_addr = ack_addr(decode_ack_addr(carried), remote_addr, duplex);
+
+    if ack_addr != remote_addr {
+        debug!("Sending ACKs to {}, not {}", ack_addr, remote_addr);
+    }
+
+    // construct the ACK message w/our window size, proving we know the key too if there is one
+    // along w/a nonce of our own, so the session's keys are fresh even if the Connect was replayed
+    let window_size = encode_window_size(config.window_size());
+    let (ack_data, nonce) = match config.key() {
+        None => (construct_payload_message(HANDSHAKE_CHECKSUM, Type::Acknowledge, agreed, msg.seq_num(), &window_size), Vec::new()),
+        Some(key) => {
+            let mut ack_nonce = [0u8; CONNECT_NONCE_SIZE];
+
+            thread_rng().fill(&mut ack_nonce);
+
+            let payload = [&window_size[..], &ack_nonce[..]].concat();
+            let ack_data = construct_mac_message(HANDSHAKE_CHECKSUM, Type::Acknowledge, agreed, msg.seq_num(), &payload, &ack_mac(key, connect_nonce, agreed, &payload));
+
+            // w/out a return path the sender never sees our nonce, so there's only its
+            (ack_data, session_nonce(Some(connect_nonce), if unidirectional { &[][..] } else { &ack_nonce[..] }))
+        }
+    };
+    let ack_data = ack_data.finished_data().to_vec();
+    let connect_data = buf[..buf_size].to_vec();
+    let (cipher, auth) = session_keys(config, &nonce)?;
+
+    // send the ACK message, if anyone can hear it
+    if !unidirectional {
+        socket.send_to(&ack_data, ack_addr)?;
+    }
+
+    // w/a key, a sender that moves (a NAT rebinding, a new network) proves it's still the sender by tagging what it sends,
+    // but a duplex end's Sender sends on the connected socket, so can't follow it
+    // a socket connected to the sender can't send its ACKs anywhere else, so the thread filters for it instead
+    // otherwise only ever hear from the sender from here on, so the kernel drops anything from anyone else
+    let peer = if (auth.is_some() && !duplex) || ack_addr != remote_addr {
+        Some(Arc::new(Mutex::new(remote_addr)))
+    } else {
+        socket.connect(remote_addr)?;
+        None
+    };
+
+    return Ok((remote_addr, Accepted { connect_data, ack_data, cipher, auth, peer, ack_addr }, streams));
+}
+
+impl <T: 'static> Receiver<T> where T: Socket + Send + Sync {
+    /// Listens for an incoming connection
+    pub fn listen(socket: T, config: &Configuration) -> Result<Receiver<T>, QcpError> {
+        new_conn_id();
+
+        // set the write timeouts to 3s
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+        set_buffer_sizes(&socket, config);
+
+        let (remote_addr, accepted, _) = accept(&socket, config, 1, false)?;
+
+        return Ok(Receiver::start(socket, remote_addr, config, 0, accepted)?);
+    }
+
+    /// Listens for an incoming connection of up to max_streams streams over the one socket, see Sender::connect_streams
+    /// Returns a Receiver for each stream the sender asked for, up to max_streams
+    pub fn listen_streams(socket: T, config: &Configuration, max_streams: usize) -> Result<Vec<Receiver<StreamSocket<T>>>, QcpError> {
+        if max_streams == 0 || max_streams > MAX_STREAMS {
+            return Err(QcpError::Config(format!("Streams must be between 1 and {}", MAX_STREAMS)));
+        }
+
+        // the sender has to hear how many streams we agreed to
+        if config.unidirectional() {
+            return Err(QcpError::Config("Cannot accept streams over a unidirectional link".to_string()));
+        }
+
+        new_conn_id();
+
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+        set_buffer_sizes(&socket, config);
+
+        let (remote_addr, accepted, streams) = accept(&socket, config, max_streams, false)?;
+        let demux = Arc::new(StreamDemux::new(socket, streams));
+
+        debug!("Agreed to {} streams", streams);
+
+        return Ok((0..streams).map(|id| {
+            let socket = StreamSocket { demux: demux.clone(), stream_id: id };
+
+            Receiver::start(socket, remote_addr, config, id as u32, accepted.clone())
+        }).collect::<Result<Vec<_>, IOError>>()?);
+    }
+
+    /// Starts receiving on stream_id once the handshake is done, w/the thread reading its packets
+    /// When accepted has a peer and a key, a packet from anywhere else that's tagged for the session moves the sender there,
+    /// for every stream, and everything sent back goes there from then on
+    fn start(socket: T, remote_addr: SocketAddr, config: &Configuration, stream_id: u32, accepted: Accepted) -> Result<Receiver<T>, IOError> {
+        let conn_id = conn_id();
+        let Accepted { connect_data, ack_data, cipher, auth, peer, ack_addr } = accepted;
+        let migrate = peer.is_some() && auth.is_some();
+        let peer = peer.unwrap_or_else(|| Arc::new(Mutex::new(remote_addr)));
+        let unidirectional = config.unidirectional();
+        let fec_block_size = config.fec_block_size() as u64;
+        let ack_every = config.ack_every();
+        let ack_delay = config.ack_delay();
+        let checksum = config.checksum();
+        let idle_timeout = config.idle_timeout();
+
+        let window = Arc::new(SlidingWindow::new(config.window_size()));
+        let pool = BufferPool::new(MAX_PACKET_SIZE, 2 * config.window_size());
+
+        let socket_clone :T = socket.try_clone()?;
+        let recv_window = window.clone();
+        let recv_pool = pool.clone();
+        let recv_cipher = cipher.clone();
+        let recv_auth = auth.clone();
+        let recv_peer = peer.clone();
+        let reverse_acked = Arc::new((Mutex::new(0), Condvar::new()));
+        let recv_reverse_acked = reverse_acked.clone();
+        let buffered = Arc::new(AtomicU64::new(0));
+        let recv_buffered = buffered.clone();
+        let recv_buffer_bytes = config.recv_buffer_bytes() as u64;
+        let counters = Arc::new(RecvCounters::default());
+        let recv_counters = counters.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let recv_stop = stop.clone();
+        let (recv_errors, errors) = channel();
+
+        let thread = thread::spawn(move || {
+            set_conn_id(conn_id);
+
+            // if this thread dies, read needs to know instead of waiting on packets forever
+            let _guard = CloseOnDrop::new(recv_window.clone());
+
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+            let mut batch = vec![(vec![0; MAX_PACKET_SIZE], 0, remote_addr); MAX_RECV_BATCH];
+            let mut batched = 0..0;     // the packets in batch not handled yet
+            let mut remote_addr = remote_addr;  // a copy of recv_peer, checked whenever a packet comes from elsewhere
+            let mut ack_addr = ack_addr;        // where everything we send goes, which follows the sender if it moves
+            let mut stalled = false;    // the reader hasn't made room in the window for a while
+            let mut over_limit = false; // the reader has left more than recv_buffer_bytes in the window
+            let mut blocks :HashMap<u64, FecBlock> = HashMap::new();    // blocks we're collecting for recovery, when unidirectional
+            let mut next_seq_num = 0;   // one past the highest sequence number seen, anything lower that's missing was lost
+            let mut pending = BTreeSet::new();  // received, but not yet acknowledged
+            let mut sack_due :Option<Instant> = None;   // when pending has to be acknowledged by
+            let mut last_heard = Instant::now();    // from the sender, anything at all
+
+            while !recv_stop.load(Ordering::Acquire) {
+                // the sender's gone, or the path from it is; let read know, instead of waiting forever
+                // a sender w/nothing to send still Pings, unless it's unidirectional
+                if last_heard.elapsed() >= idle_timeout {
+                    warn!("Nothing from the sender in {:?}, giving up", idle_timeout);
+                    let _ = recv_errors.send(IOError::new(ErrorKind::TimedOut, format!("Nothing heard from the sender in {:?}", idle_timeout)));
+                    break;
+                }
+
+                // acknowledge a batch at a time, rather than every packet
+                if pending.len() >= ack_every || sack_due.map_or(false, |due| Instant::now() >= due) {
+                    send_sacks(&socket_clone, ack_addr, &recv_auth, checksum, stream_id, &mut pending);
+                    sack_due = None;
+                }
+
+                // read as many messages as are waiting, waiting no longer than until we check if we should stop, or acknowledge what's pending
+                if batched.len() == 0 {
+                    let timeout = match sack_due {
+                        Some(due) => due.saturating_duration_since(Instant::now()).max(Duration::from_millis(1)),
+                        None => STOP_POLL_INTERVAL
+                    };
+
+                    match recv_retrying(|| socket_clone.recv_batch(&mut batch, timeout)) {
+                        Ok(received) => batched = 0..received,
+                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => continue,
+                        Err(e) => {
+                            // nothing more is coming, let read know why
+                            warn!("Error reading message: {:?}", e);
+                            let _ = recv_errors.send(e);
+                            break;
+                        }
+                    }
+                }
+
+                // swapped in whole, rather than copied
+                let next = batched.next().expect("No packet in the batch");
+                let (amt, src) = (batch[next].1, batch[next].2);
+
+                mem::swap(&mut buf, &mut batch[next].0);
+
+                // an empty datagram carries nothing, not even a malformed message
+                if amt == 0 {
+                    continue;
+                }
+
+                // another stream may have seen the sender move already
+                if src != remote_addr && *recv_peer.lock().unwrap() != remote_addr {
+                    remote_addr = *recv_peer.lock().unwrap();
+                    ack_addr = remote_addr;
+                }
+
+                if src != remote_addr {
+                    // only the session's key could have tagged it, so it's the sender from somewhere new
+                    if migrate && verify_packet(&recv_auth, &buf[0..amt]).is_some() {
+                        info!("Sender moved from {} to {}", remote_addr, src);
+                        remote_addr = src;
+                        ack_addr = src;
+                        *recv_peer.lock().unwrap() = src;
+                    } else {
+                        // in case the socket doesn't filter on its own, like the mocks
+                        warn!("Dropping {} byte packet from {}, not the sender", amt, src);
+                        continue;
+                    }
+                }
+
+                last_heard = Instant::now();
+
+                let packet = match verify_packet(&recv_auth, &buf[0..amt]) {
+                    Some(packet) => packet,
+                    // the Connect comes before the session key, so a repeated one is the only thing untagged
+                    None if buf[0..amt] == connect_data[..] => &buf[0..amt],
+                    None => {
+                        warn!("Dropping {} byte packet: not authenticated", amt);
+                        continue;
+                    }
+                };
+
+                let message = match parse_message(packet) {
+                    Some(message) => message,
+                    None => {
+                        warn!("Dropping {} byte packet: not a valid message", amt);
+                        continue;
+                    }
+                };
+
+                // don't insert (or ACK) anything corrupt, the sender will retransmit it
+                if !verify_checksum(checksum, &message) {
+                    warn!("Dropping packet w/bad checksum for {}", message.seq_num());
+                    continue;
+                }
+
+                // another stream's; only the Connect's stream_id is something else, the streams asked for
+                if message.stream_id() != stream_id && message.msg_type() != Type::Connect {
+                    warn!("Dropping {:?} {} for stream {}", message.msg_type(), message.seq_num(), message.stream_id());
+                    continue;
+                }
+
+                match message.msg_type() {
+                    Type::Message | Type::Finish | Type::FileStart | Type::FileEnd | Type::Skip => (),
+                    Type::Parity if unidirectional => {
+                        let block_start = message.seq_num();
+                        let parity = match open_payload(&recv_cipher, &message) {
+                            Some(parity) => parity,
+                            None => continue
+                        };
+                        let start = recv_window.window().0;
+
+                        // forget about blocks the reader has moved past
+                        blocks.retain(|&b, _| b + fec_block_size > start);
+
+                        if block_start + fec_block_size > start {
+                            blocks.entry(block_start).or_insert_with(|| FecBlock::new(fec_block_size as usize)).block_parity = Some(parity);
+                            recover_block(&mut blocks, block_start, &recv_window, &recv_buffered, &recv_pool);
+                        }
+
+                        continue;
+                    },
+                    // nothing more is coming, close the window on the way out
+                    Type::Disconnect => {
+                        debug!("Peer disconnected");
+                        break;
+                    },
+                    // the sender got one of our Reverses, they're sent one at a time so it's the latest
+                    Type::Acknowledge if !unidirectional => {
+                        let (ref lock, ref acked) = *recv_reverse_acked;
+                        let mut reverse_acked = lock.lock().unwrap();
+
+                        *reverse_acked = (*reverse_acked).max(message.seq_num() + 1);
+                        acked.notify_all();
+                        continue;
+                    },
+                    // the sender finding the path MTU
+                    Type::Probe => continue,
+                    // the sender hasn't heard from us in a while, let it know we're still here
+                    Type::Ping if !unidirectional => {
+                        debug!("Answering Ping");
+                        if let Err(e) = socket_clone.send_to(&sign_packet(&recv_auth, construct_message(checksum, Type::Pong, stream_id, message.seq_num()).finished_data()), ack_addr) {
+                            warn!("Error sending Pong: {}", e);
+                        }
+
+                        continue;
+                    },
+                    // the Connect is repeated when unidirectional, or when our Acknowledge was lost
+                    Type::Connect => {
+                        if !unidirectional && buf[0..amt] == connect_data[..] {
+                            debug!("Acknowledging repeated Connect");
+                            if let Err(e) = socket_clone.send_to(&ack_data, ack_addr) {
+                                warn!("Error acknowledging repeated Connect: {}", e);
+                            }
+                        } else {
+                            debug!("Ignoring repeated Connect");
+                        }
+
+                        continue;
+                    },
+                    t => {
+                        warn!("Dropping unexpected {:?} {}", t, message.seq_num());
+                        continue;
+                    }
+                }
+
+                let seq_num = message.seq_num();
+
+                // behind something w/a higher sequence number, by how far
+                if seq_num + 1 < next_seq_num {
+                    recv_counters.out_of_order.fetch_add(1, Ordering::Relaxed);
+                    recv_counters.max_reorder.fetch_max(next_seq_num - 1 - seq_num, Ordering::Relaxed);
+                }
+
+                let (start, end) = recv_window.window();
+
+                // check to see if the message is old: it's already been read, so our ACK must have been lost
+                if seq_num < start {
+                    recv_counters.late.fetch_add(1, Ordering::Relaxed);
+
+                    if !unidirectional {
+                        debug!("ACKING OLD SEQ: {}", seq_num);
+                        pending.insert(seq_num);
+                        sack_due = sack_due.or_else(|| Some(Instant::now() + ack_delay));
+                    }
+
+                    continue;
+                }
+
+                // no room in the window, because the reader isn't keeping up (or isn't reading at all)
+                // give the reader a chance to make room, so a slow reader applies backpressure
+                // if it doesn't, drop the packet w/out an ACK (a zero window), so the sender backs off
+                // and retransmits later, rather than waiting forever for room
+                if seq_num >= end {
+                    // what's pending is owed already, it can't sit unsent while we wait
+                    if !stalled && !pending.is_empty() {
+                        send_sacks(&socket_clone, ack_addr, &recv_auth, checksum, stream_id, &mut pending);
+                        sack_due = None;
+                    }
+
+                    // once stalled, don't wait again until the reader makes progress
+                    let room = if stalled { seq_num < recv_window.window().1 } else { recv_window.wait_for_room(seq_num, WINDOW_STALL_TIMEOUT) };
+
+                    if !room {
+                        if !stalled {
+                            warn!("Receive window full for {:?}, is anyone calling read? {} -> {}; {}", WINDOW_STALL_TIMEOUT, start, end, seq_num);
+                            stalled = true;
+                        }
+
+                        continue;
+                    }
+                }
+
+                stalled = false;
+
+                // the Finish and file markers go into the window too, so read sees them after everything before them
+                // the Finish carries the sender's hash, if there is one, and its total, in place of a payload
+                // checked out of the pool, and returned once it's read, so there's no allocation per packet
+                let mut payload = recv_pool.get();
+
+                if message.msg_type() == Type::Finish {
+                    debug!("RECV {:?} at {}", message.msg_type(), seq_num);
+                    payload.extend_from_slice(message.hash().unwrap_or(&[]));
+                } else if message.msg_type() == Type::FileEnd {
+                    debug!("RECV {:?} at {}", message.msg_type(), seq_num);
+                } else if !open_payload_into(&recv_cipher, &message, &mut payload) {
+                    continue;   // not ACKed, if it was only corrupt the sender will retransmit it
+                }
+
+                debug!("RECV PACKET: {} at {}", payload.len(), seq_num);
+
+                // holding too much for the reader already, so treat it like a full window: no insert, and no ACK
+                // the next packet the reader needs is always taken, or it would wait on it forever
+                // w/out ACKs there's no backpressure, and nothing dropped would come back
+                if !unidirectional && seq_num != recv_window.window().0 && recv_buffered.load(Ordering::Acquire) + payload.len() as u64 > recv_buffer_bytes {
+                    if !over_limit {
+                        warn!("Receive buffer over {} bytes, is anyone calling read? Not acknowledging {}", recv_buffer_bytes, seq_num);
+                        over_limit = true;
+                    }
+
+                    continue;
+                }
+
+                over_limit = false;
+
+                let len = payload.len() as u64;
+
+                // the parity needs it too, once it's in the window; another from the pool, not an allocation
+                let for_parity = if unidirectional && message.msg_type() == Type::Message { Some(payload.clone()) } else { None };
+
+                // insert the packet into the window
+                // a retransmit of something we already have is still ACKed, so the sender stops retransmitting it
+                let inserted = match recv_window.insert(seq_num, (message.msg_type(), payload)) {
+                    Ok(()) => {
+                        recv_buffered.fetch_add(len, Ordering::AcqRel);
+                        true
+                    },
+                    Err(e) => {
+                        debug!("Dropping duplicate {}: {}", seq_num, e);
+                        recv_counters.duplicates.fetch_add(1, Ordering::Relaxed);
+                        false
+                    }
+                };
+
+                // no ACKs on a unidirectional link, collect the block for recovery instead
+                if unidirectional {
+                    if let (true, Some(payload)) = (inserted, for_parity) {
+                        let block_start = seq_num - seq_num % fec_block_size;
+
+                        {
+                            let block = blocks.entry(block_start).or_insert_with(|| FecBlock::new(fec_block_size as usize));
+
+                            // past the end of a last block the Finish cut short
+                            match block.received.get_mut((seq_num - block_start) as usize) {
+                                Some(received) => *received = true,
+                                None => continue
+                            }
+
+                            block.parity.add(&payload);
+                        }
+
+                        recover_block(&mut blocks, block_start, &recv_window, &recv_buffered, &recv_pool);
+                    }
+
+                    // the last block is cut short by the Finish, which is how the partial block's parity is known to be complete
+                    if inserted && message.msg_type() == Type::Finish && seq_num % fec_block_size != 0 {
+                        let block_start = seq_num - seq_num % fec_block_size;
+
+                        blocks.entry(block_start).or_insert_with(|| FecBlock::new(fec_block_size as usize)).received.truncate((seq_num - block_start) as usize);
+                        recover_block(&mut blocks, block_start, &recv_window, &recv_buffered, &recv_pool);
+                    }
+
+                    continue;
+                }
+
+                pending.insert(seq_num);
+
+                // nothing follows the Finish, so don't keep the sender waiting on it
+                sack_due = if message.msg_type() == Type::Finish { Some(Instant::now()) } else { sack_due.or_else(|| Some(Instant::now() + ack_delay)) };
+
+                // a jump in sequence numbers means the packets in between were lost (or reordered)
+                // NACK them once, when the gap opens; if the retransmit is lost too, the sender's timeout catches it
+                if inserted && seq_num > next_seq_num {
+                    for loc in recv_window.missing(next_seq_num, seq_num) {
+                        debug!("NACKING SEQ: {}", loc);
+
+                        // the sender's timeout catches it if this is lost too
+                        if let Err(e) = socket_clone.send_to(&sign_packet(&recv_auth, construct_message(checksum, Type::NegativeAcknowledge, stream_id, loc).finished_data()), ack_addr) {
+                            warn!("Error NACKing {}: {}", loc, e);
+                        }
+                    }
+                }
+
+                next_seq_num = next_seq_num.max(seq_num + 1);
+            }
+
+            // the sender is still waiting on whatever's pending, it's done even if we're stopping
+            if !pending.is_empty() {
+                send_sacks(&socket_clone, ack_addr, &recv_auth, checksum, stream_id, &mut pending);
+            }
+        });
+
+        let hash = if config.verify() { Some(Sha256::new()) } else { None };
+
+        return Ok(Receiver {
+            socket,
+            remote_addr: peer,
+            stream_id,
+            window,
+            pool,
+            buffered,
+            bytes_transferred: AtomicU64::new(0),
+            received: 0,
+            leftover: None,
+            next_file: None,
+            skip: None,
+            in_file: false,
+            finished: false,
+            hash,
+            cipher,
+            auth,
+            checksum: config.checksum(),
+            unidirectional,
+            rto: config.rto(),
+            reverse_seq_num: 0,
+            reverse_acked,
+            counters,
+            started: Instant::now(),
+            stop,
+            thread: Some(thread),
+            errors
+        });
+    }
+}
+
+impl <T> Transport for Sender<T> where T: Socket {
+    /// Reads up to buf.len() bytes of what the receiver wrote w/write_all; whatever of a packet doesn't fit is returned by the next read
+    /// This is independent of what's written here, and blocks until the receiver writes something
+    /// What write_all was holding is sent first, the receiver might be waiting on it to write anything
+    fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
+        if self.unidirectional {
+            return Err(IOError::new(ErrorKind::InvalidInput, "Cannot read when unidirectional"));
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.send_pending()?;
+
+        let packet = if !self.reverse_leftover.is_empty() {
+            mem::replace(&mut self.reverse_leftover, Vec::new())
+        } else {
+            self.reverse.pop().map_err(|e| aborted(&self.errors, e))?
+        };
+
+        return Ok(self.copy_reverse(buf, packet));
+    }
+
+    /// Like read, but waits no longer than timeout for the receiver to write something
+    fn recv_timeout(&mut self, buf: &mut[u8], timeout: Duration) -> Result<Option<usize>, IOError> {
+        if self.unidirectional {
+            return Err(IOError::new(ErrorKind::InvalidInput, "Cannot read when unidirectional"));
+        }
+
+        if buf.is_empty() {
+            return Ok(Some(0));
+        }
+
+        self.send_pending()?;
+
+        let packet = if !self.reverse_leftover.is_empty() {
+            mem::replace(&mut self.reverse_leftover, Vec::new())
+        } else {
+            match self.reverse.pop_timeout(timeout) {
+                Some(packet) => packet,
+                None if self.reverse.is_closed() => return Err(aborted(&self.errors, "window closed")),
+                None => return Ok(None)
+            }
+        };
+
+        return Ok(Some(self.copy_reverse(buf, packet)));
+    }
+
+    /// Sends whatever write_all was holding, and blocks until everything sent has been acknowledged
+    fn flush(&mut self) -> Result<(), IOError> {
+        self.send_pending()?;
+
+        // nothing is ever acknowledged
+        if self.unidirectional {
+            return Ok( () );
+        }
+
+        let mut bbr = self.bbr.lock().unwrap();
+
+        // the window slides past everything once it's all been acknowledged
+        while self.window.window().0 < self.seq_num {
+            if self.window.is_closed() {
+                return Err(aborted(&self.errors, "not everything was acknowledged"));
+            }
+
+            // as in wait_for_cwnd, the timeout is only so a dead ACK thread's closed window is noticed
+            bbr = self.acked.wait_timeout(bbr, STOP_POLL_INTERVAL).unwrap().0;
+        }
+
+        return Ok( () );
+    }
+
+    /// Sends buf as a sequence of packets, blocking whenever the window is full
+    /// until the receiver has acknowledged enough to make room
+    /// When coalescing, only whole packets are sent, and the rest is held until more is written, or it's flushed
+    /// When nonblocking, returns WouldBlock instead; call it again w/the same buf, and only what wasn't sent is
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
+        // nothing's held when nonblocking, a WouldBlock has to be about buf alone
+        if !self.coalesce || self.nonblocking {
+            return self.send_chunks(buf);
+        }
+
+        let chunk_size = self.chunk_size();
+        let mut buf = buf;
+
+        // top up what's held, it's sent once it's a packet's worth
+        if !self.pending.is_empty() {
+            let amt = (chunk_size - self.pending.len()).min(buf.len());
+
+            self.pending.extend_from_slice(&buf[..amt]);
+            buf = &buf[amt..];
+
+            if self.pending.len() < chunk_size {
+                return Ok( () );
+            }
+
+            self.send_pending()?;
+        }
+
+        // the whole packets go now, and what's left waits for more
+        let whole = buf.len() - buf.len() % chunk_size;
+
+        self.send_chunks(&buf[..whole])?;
+        self.pending.extend_from_slice(&buf[whole..]);
+
+        return Ok( () );
+    }
+
+    /// Tells the receiver nothing more is coming, and waits for everything sent to be acknowledged
+    /// The Finish takes the next sequence number, so it's retransmitted like any other packet
+    fn finish(&mut self) -> Result<(), IOError> {
+        debug!("SENDING FINISH: {}", self.seq_num);
+
+        // the rest of what was written goes before it, and into the last block
+        self.send_pending()?;
+
+        // the last block is short, but its parity is needed all the same or a loss in it can't be recovered
+        if self.unidirectional && self.parity.count() > 0 {
+            self.send_parity()?;
+        }
+
+        let hash = self.hash.take().map(|hash| hash.finish());
+        let msg = construct_finish(self.checksum, self.stream_id, self.seq_num, hash.as_ref().map(|h| &h[..]), self.bytes_transferred());
+
+        self.send_sequenced(msg.finished_data())?;
+        self.flush()?;
+
+        debug!("{} packets sent w/{} buffers", self.seq_num, self.pool.allocated());
+
+        return Ok( () );
+    }
+
+    /// Announces the next file, when sending more than one; everything written until end_file is its contents
+    fn start_file(&mut self, header: &FileHeader) -> Result<(), IOError> {
+        if self.unidirectional {
+            return Err(IOError::new(ErrorKind::InvalidInput, "Cannot send multiple files when unidirectional"));
+        }
+
+        let payload = header.encode();
+        let overhead = if self.cipher.is_some() { TAG_SIZE } else { 0 };
+
+        if payload.len() + overhead > self.max_payload_size() {
+            return Err(IOError::new(ErrorKind::InvalidInput, format!("File name too long: {}", header.name)));
+        }
+
+        debug!("SENDING FILE START: {} {:?}", self.seq_num, header);
+
+        return self.send_control(Type::FileStart, Some(&payload));
+    }
+
+    fn sends_headers(&self) -> bool {
+        return !self.unidirectional;
+    }
+
+    /// Marks the end of the file announced by start_file
+    fn end_file(&mut self) -> Result<(), IOError> {
+        debug!("SENDING FILE END: {}", self.seq_num);
+
+        return self.send_control(Type::FileEnd, None);
+    }
+
+    /// Tells the receiver to keep the next len bytes of its copy of the file, in place of sending them
+    /// They're never sent, so they no longer count toward the total for progress
+    fn skip(&mut self, len: u64) -> Result<(), IOError> {
+        if self.unidirectional {
+            return Err(IOError::new(ErrorKind::InvalidInput, "Cannot skip what the receiver has when unidirectional"));
+        }
+
+        debug!("SENDING SKIP: {} LEN: {}", self.seq_num, len);
+
+        {
+            let mut progress = self.progress.lock().unwrap();
+
+            progress.total = progress.total.saturating_sub(len);
+        }
+
+        return self.send_control(Type::Skip, Some(&len.to_be_bytes()));
+    }
+
+    fn stats(&self) -> Option<TransferStats> {
+        return Some(Sender::stats(self));
+    }
+}
+
+/// The error that stopped a background thread, or else that the transfer was aborted for reason
+fn aborted<D: fmt::Display>(errors: &mpsc::Receiver<IOError>, reason: D) -> IOError {
+    match errors.try_recv() {
+        Ok(e) => return e,
+        Err(_) => return IOError::new(ErrorKind::ConnectionAborted, format!("Transfer aborted: {}", reason))
+    }
+}
+
+/// Tells a background thread to stop, and waits for it to exit
+/// It checks in between packets, so this takes at most STOP_POLL_INTERVAL, or however long it's waiting on the window
+fn stop_thread(stop: &AtomicBool, thread: Option<JoinHandle<()>>) {
+    stop.store(true, Ordering::Release);
+
+    if let Some(thread) = thread {
+        if thread.join().is_err() {
+            warn!("Background thread panicked");
+        }
+    }
+}
+
+impl <T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        stop_thread(&self.stop, self.thread.take());
+    }
+}
+
+impl <T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        stop_thread(&self.stop, self.thread.take());
+    }
+}
+
+impl <T> Receiver<T> {
+    /// The number of payload bytes read so far
+    pub fn bytes_transferred(&self) -> u64 {
+        self.bytes_transferred.load(Ordering::Acquire)
+    }
+
+    /// A summary of what's been received so far: logged once the Finish is read
+    pub fn stats(&self) -> ReceiverStats {
+        ReceiverStats {
+            bytes: self.bytes_transferred(),
+            duration: self.started.elapsed(),
+            out_of_order: self.counters.out_of_order.load(Ordering::Relaxed),
+            late: self.counters.late.load(Ordering::Relaxed),
+            duplicates: self.counters.duplicates.load(Ordering::Relaxed),
+            max_reorder: self.counters.max_reorder.load(Ordering::Relaxed)
+        }
+    }
+
+    /// The next packet, or control message, in order
+    /// Waits no longer than timeout if there is one, returning None if nothing arrives in time
+    fn pop(&self, timeout: Option<Duration>) -> Result<Option<(Type, PooledBuf)>, IOError> {
+        let (msg_type, packet) = match timeout {
+            None => self.window.pop().map_err(|e| aborted(&self.errors, e))?,
+            Some(timeout) => match self.window.pop_timeout(timeout) {
+                Some(t) => t,
+                None if self.window.is_closed() => return Err(aborted(&self.errors, "window closed")),
+                None => return Ok(None)
+            }
+        };
+
+        self.buffered.fetch_sub(packet.len() as u64, Ordering::AcqRel);
+
+        return Ok(Some((msg_type, packet)));
+    }
+
+    /// What read does, waiting no longer than timeout for a packet if there is one
+    /// Returns None only if that times out
+    fn read_within(&mut self, buf: &mut[u8], timeout: Option<Duration>) -> Result<Option<usize>, IOError> {
+        if buf.is_empty() {
+            return Ok(Some(0));
+        }
+
+        let mut packet = if let Some(leftover) = self.leftover.take() {
+            leftover
+        } else {
+            if self.finished || self.next_file.is_some() || self.skip.is_some() {
+                return Ok(Some(0));
+            }
+
+            let (msg_type, packet) = match self.pop(timeout)? {
+                Some(t) => t,
+                None => return Ok(None)
+            };
+
+            debug!("READ: {:?} {} length buf", msg_type, packet.len());
+
+            match msg_type {
+                // nothing more is coming
+                Type::Finish => {
+                    self.finished = true;
+                    self.in_file = false;
+                    info!("{}", self.stats());
+                    self.check_finish(&packet)?;
+                    return Ok(Some(0));
+                },
+                Type::FileEnd => {
+                    self.in_file = false;
+                    return Ok(Some(0));
+                },
+                // the file ended w/out a FileEnd, hold onto the next one until asked for it
+                Type::FileStart => {
+                    self.next_file = Some(FileHeader::decode(&packet)?);
+                    self.in_file = false;
+                    return Ok(Some(0));
+                },
+                // the receiver's copy is the same for a while, hold onto how long until asked for it
+                Type::Skip => {
+                    self.skip = Some(decode_skip(&packet)?);
+                    return Ok(Some(0));
+                },
+                _ => packet
+            }
+        };
+
+        let amt = buf.len().min(packet.len());
+
+        buf[..amt].copy_from_slice(&packet[..amt]);
+
+        // kept in the same buffer, rather than copied out to a new one
+        if amt < packet.len() {
+            packet.drain(..amt);
+            self.leftover = Some(packet);
+        }
+
+        if let Some(ref mut hash) = self.hash {
+            hash.update(&buf[..amt]);
+        }
+
+        self.received += amt as u64;
+        self.bytes_transferred.fetch_add(amt as u64, Ordering::AcqRel);
+
+        return Ok(Some(amt));
+    }
+
+    /// Checks the sender's total, from the start of the Finish, against what arrived, then its hash, the rest of it
+    /// The total's always there, but it's only checked if the sender had one
+    fn check_finish(&mut self, finish: &[u8]) -> Result<(), IOError> {
+        if finish.len() != FINISH_TOTAL_SIZE && finish.len() != FINISH_TOTAL_SIZE + DIGEST_SIZE {
+            return Err(IOError::new(ErrorKind::InvalidData, format!("Malformed Finish: {} bytes, not the sender's total and maybe its hash", finish.len())));
+        }
+
+        let (total, hash) = finish.split_at(FINISH_TOTAL_SIZE);
+        let mut total_bytes = [0u8; FINISH_TOTAL_SIZE];
+
+        total_bytes.copy_from_slice(total);
+
+        let total = u64::from_be_bytes(total_bytes);
+
+        if total != UNKNOWN_LEN && total != self.received {
+            return Err(IOError::new(ErrorKind::InvalidData, format!("Sender sent {} bytes, but {} arrived", total, self.received)));
+        }
+
+        return self.check_hash(hash);
+    }
+
+    /// Checks the sender's hash, from the Finish, against the hash of everything read
+    /// Nothing to check unless verifying
+    fn check_hash(&mut self, sent_hash: &[u8]) -> Result<(), IOError> {
+        let hash = match self.hash.take() {
+            Some(hash) => hash.finish(),
+            None => return Ok( () )
+        };
+
+        if sent_hash.is_empty() {
+            return Err(IOError::new(ErrorKind::InvalidData, "No hash from the sender to verify, it needs --verify too"));
+        }
+
+        if !mac_eq(&hash, sent_hash) {
+            return Err(IOError::new(ErrorKind::InvalidData, "Hash of everything received doesn't match the sender's"));
+        }
+
+        debug!("Hash verified");
+
+        return Ok( () );
+    }
+}
+
+impl <T> Transport for Receiver<T> where T: Socket {
+    /// Reads up to buf.len() bytes; whatever of a packet doesn't fit is returned by the next read
+    /// When the sender is sending files one by one, returns 0 at the end of each file until next_file is called
+    fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
+        // w/out a timeout it never gives up
+        return self.read_within(buf, None).map(|amt| amt.unwrap_or(0));
+    }
+
+    /// Like read, but returns None if no packet arrives within timeout
+    fn recv_timeout(&mut self, buf: &mut[u8], timeout: Duration) -> Result<Option<usize>, IOError> {
+        return self.read_within(buf, Some(timeout));
+    }
+
+    /// Sends buf back to the sender, for its read; this is independent of what's read here
+    /// Each packet is resent until it's acknowledged before the next is sent, so it's only meant for small amounts
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
+        if self.unidirectional {
+            return Err(IOError::new(ErrorKind::InvalidInput, "Cannot write when unidirectional"));
+        }
+
+        let max_payload_size = max_payload_size(MAX_PACKET_SIZE, self.stream_id);
+        let chunk_size = if self.cipher.is_some() { max_payload_size - TAG_SIZE } else { max_payload_size };
+        let chunk_size = if self.auth.is_some() { chunk_size - AUTH_OVERHEAD } else { chunk_size };
+
+        for chunk in buf.chunks(chunk_size) {
+            let seq_num = self.reverse_seq_num;
+            let sealed = self.cipher.as_ref().map(|c| c.seal(&packet_nonce(Type::Reverse, self.stream_id, seq_num), &[], chunk));
+            let mut msg_buf = self.pool.get();
+
+            sign_packet_into(&self.auth, construct_payload_message(self.checksum, Type::Reverse, self.stream_id, seq_num, sealed.as_ref().map_or(chunk, |s| &s[..])).finished_data(), &mut msg_buf);
+
+            let mut attempts = 0;
+            let (ref lock, ref acked) = *self.reverse_acked;
+            let mut reverse_acked = lock.lock().unwrap();
+
+            while *reverse_acked <= seq_num {
+                if self.window.is_closed() {
+                    return Err(aborted(&self.errors, "window closed"));
+                }
+
+                if attempts == REVERSE_ATTEMPTS {
+                    return Err(IOError::new(ErrorKind::TimedOut, format!("Reverse {} never acknowledged", seq_num)));
+                }
+
+                debug!("SENDING REVERSE: {} ATTEMPT: {}", seq_num, attempts);
+
+                let remote_addr = *self.remote_addr.lock().unwrap();
+
+                self.socket.send_to(&msg_buf, remote_addr)?;
+                attempts += 1;
+
+                let sent = Instant::now();
+
+                // the wait is cut short so a dead thread's closed window is noticed
+                while *reverse_acked <= seq_num && sent.elapsed() < self.rto && !self.window.is_closed() {
+                    let wait = (self.rto - sent.elapsed().min(self.rto)).min(STOP_POLL_INTERVAL);
+
+                    reverse_acked = acked.wait_timeout(reverse_acked, wait).unwrap().0;
+                }
+            }
+
+            self.reverse_seq_num += 1;
+        }
+
+        return Ok( () );
+    }
+
+    /// Waits for the next file, when the sender is sending more than one
+    /// Whatever's left of the current file is skipped; read returns the new file's contents, and 0 at its end
+    /// Returns None at the end of the transfer, or if the sender isn't sending files one by one
+    fn next_file(&mut self) -> Result<Option<FileHeader>, IOError> {
+        // whatever's left of the current file includes what the receiver's copy already had
+        self.skip = None;
+
+        // skipped, but it was still sent, so it's still part of the hash, and the total
+        if let Some(leftover) = self.leftover.take() {
+            if let Some(ref mut hash) = self.hash {
+                hash.update(&leftover);
+            }
+
+            self.received += leftover.len() as u64;
+        }
+
+        if let Some(header) = self.next_file.take() {
+            self.in_file = true;
+            return Ok(Some(header));
+        }
+
+        while !self.finished {
+            let (msg_type, packet) = self.pop(None)?.expect("Popped w/out a timeout");
+
+            match msg_type {
+                Type::FileStart => {
+                    self.in_file = true;
+                    return Ok(Some(FileHeader::decode(&packet)?));
+                },
+                Type::Finish => {
+                    self.finished = true;
+                    self.in_file = false;
+                    info!("{}", self.stats());
+                    self.check_finish(&packet)?;
+                },
+                Type::FileEnd => self.in_file = false,
+                // never sent, so it's not part of the hash
+                Type::Skip => (),
+                // the rest of the current file, skip it up to its FileEnd
+                _ if self.in_file => {
+                    if let Some(ref mut hash) = self.hash {
+                        hash.update(&packet);
+                    }
+
+                    self.received += packet.len() as u64;
+                },
+                // a single stream w/out headers, leave it for read
+                _ => {
+                    self.leftover = Some(packet);
+                    return Ok(None);
+                }
+            }
+        }
+
+        return Ok(None);
+    }
+
+    /// How many bytes of the receiver's copy the sender said to keep, once read has returned 0 for it
+    fn take_skip(&mut self) -> Option<u64> {
+        return self.skip.take();
+    }
+}
+
+impl <T: 'static> BbrConnection<T> where T: Socket + Send + Sync {
+    /// Connect, via BBR, to a remote host that sends back at the same time, see BbrConnection::listen
+    pub fn connect(socket: T, config: &Configuration) -> Result<BbrConnection<T>, QcpError> {
+        // the receiver has to be able to answer, it's sending too
+        if config.unidirectional() {
+            return Err(QcpError::Config("Cannot send both ways over a unidirectional link".to_string()));
+        }
+
+        new_conn_id();
+
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+        set_buffer_sizes(&socket, config);
+
+        // the listening end connects to wherever this came from, so there's no other address to read on
+        let (msg_data, nonce) = construct_connect(config.key(), 2 | DUPLEX, config.checksum(), None);
+        let nonce = nonce.as_ref().map(|n| &n[..]);
+        let (remote_addr, handshake_rtt, peer_window, ack_nonce, agreed) = handshake_any(&socket, msg_data.finished_data(), config, nonce)?;
+
+        // only a receiver calling BbrConnection::listen sets DUPLEX in what it agrees to
+        if agreed != (2 | DUPLEX) as usize {
+            return Err(QcpError::Handshake(IOError::new(ErrorKind::ConnectionRefused, "Receiver won't send anything back")));
+        }
+
+        let (cipher, auth) = session_keys(config, &session_nonce(nonce, &ack_nonce))?;
+
+        socket.connect(remote_addr)?;
+
+        let path_mtu = discover_path_mtu(&socket, &auth, config.checksum())?;
+        let demux = Arc::new(StreamDemux::new(socket, 2));
+
+        let sender = Sender::start(StreamSocket { demux: demux.clone(), stream_id: 0 }, remote_addr, config, 0, Some(handshake_rtt), peer_window, (cipher.clone(), auth.clone()), path_mtu)?;
+
+        // no Connect comes the other way, so there's nothing for this end's receiver to answer
+        let accepted = Accepted { connect_data: Vec::new(), ack_data: Vec::new(), cipher, auth, peer: None, ack_addr: remote_addr };
+        let recver = Receiver::start(StreamSocket { demux, stream_id: 1 }, remote_addr, config, 1, accepted)?;
+
+        return Ok(BbrConnection { sender, recver });
+    }
+
+    /// Listens for a connection from BbrConnection::connect, sending back at the same time
+    /// A Connect from Sender::connect or connect_streams is refused, as that sender would never read what's sent
+    pub fn listen(socket: T, config: &Configuration) -> Result<BbrConnection<T>, QcpError> {
+        if config.unidirectional() {
+            return Err(QcpError::Config("Cannot send both ways over a unidirectional link".to_string()));
+        }
+
+        new_conn_id();
+
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+        set_buffer_sizes(&socket, config);
+
+        let (remote_addr, accepted, _) = accept(&socket, config, 2, true)?;
+        let keys = (accepted.cipher.clone(), accepted.auth.clone());
+        let path_mtu = discover_path_mtu(&socket, &keys.1, config.checksum())?;
+        let demux = Arc::new(StreamDemux::new(socket, 2));
+
+        let recver = Receiver::start(StreamSocket { demux: demux.clone(), stream_id: 0 }, remote_addr, config, 0, accepted)?;
+
+        // this end didn't time the handshake, so the RTT isn't known until the first ACK
+        // the connecting end's window size isn't sent either, it's assumed to be the same as this end's
+        let sender = Sender::start(StreamSocket { demux, stream_id: 1 }, remote_addr, config, 1, None, None, keys, path_mtu)?;
+
+        return Ok(BbrConnection { sender, recver });
+    }
+
+    /// The sending and receiving halves, to use from different threads
+    pub fn split(self) -> (Sender<StreamSocket<T>>, Receiver<StreamSocket<T>>) {
+        return (self.sender, self.recver);
+    }
+}
+
+impl <T> Transport for BbrConnection<T> where T: Socket {
+    /// What's been written goes first, even less than a packet of it, as the other end might be waiting on it
+    fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
+        self.sender.send_pending()?;
+
+        return self.recver.read(buf);
+    }
+
+    fn recv_timeout(&mut self, buf: &mut[u8], timeout: Duration) -> Result<Option<usize>, IOError> {
+        self.sender.send_pending()?;
+
+        return self.recver.recv_timeout(buf, timeout);
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
+        return self.sender.write_all(buf);
+    }
+
+    fn flush(&mut self) -> Result<(), IOError> {
+        return self.sender.flush();
+    }
+
+    fn finish(&mut self) -> Result<(), IOError> {
+        return self.sender.finish();
+    }
+
+    fn start_file(&mut self, header: &FileHeader) -> Result<(), IOError> {
+        return self.sender.start_file(header);
+    }
+
+    fn sends_headers(&self) -> bool {
+        return self.sender.sends_headers();
+    }
+
+    fn end_file(&mut self) -> Result<(), IOError> {
+        return self.sender.end_file();
+    }
+
+    fn skip(&mut self, len: u64) -> Result<(), IOError> {
+        return self.sender.skip(len);
+    }
+
+    fn next_file(&mut self) -> Result<Option<FileHeader>, IOError> {
+        return self.recver.next_file();
+    }
+
+    fn take_skip(&mut self) -> Option<u64> {
+        return self.recver.take_skip();
+    }
+
+    fn stats(&self) -> Option<TransferStats> {
+        return Some(self.sender.stats());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use simplelog::{TermLogger, LevelFilter, Config};
+
+    use bbr_transport::{Sender, Receiver, BbrConnection, FileHeader, Pacer, Checksum, buf2string, construct_message, construct_payload_message, construct_connect, encode_ack_addr, decode_ack_addr, ack_addr, construct_finish, construct_mac_message, packet_nonce, session_nonce, compute_checksum, verify_checksum, parse_message, max_payload_size, to_ranges, ranges_loc, read_ranges, construct_sack, is_retryable, recv_retrying, target_window, MAX_PAYLOAD_SIZE, UNKNOWN_LEN, MAX_STREAMS, MAX_PACKET_SIZE, MIN_PATH_MTU, WINDOW_STALL_TIMEOUT, UNIDIRECTIONAL_CONNECTS, CONNECT_NONCE_SIZE, WINDOW_SIZE_SIZE, READ_RETRIES, PROTOCOL_VERSION, PROTOCOL_MAGIC, MIN_WINDOW, PACING_GAIN, VERIFY_ONLY};
+    use fec::PARITY_HEADER_SIZE;
+    use crypto::{Cipher, PacketAuth, derive_key, TAG_SIZE};
+    use config::{Configuration, CongestionKind, ChecksumKind, Cidr};
+    use error::QcpError;
+    use socket::Socket;
+    use transport::Transport;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::io::{Error as IOError, ErrorKind, Read, Write};
+    use std::fs::{self, File};
+    use std::{env, process};
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use flatbuffers::FlatBufferBuilder;
+    use message_generated::bbr::{get_root_as_message, Message, MessageArgs, SeqRange, Type};
+
+    use socket::mocks::PacketDroppingSocket;
+    #[cfg(unix)]
+    use socket::UnixDatagramSocket;
+    use sliding_window::tests::thread_cpu_time;
+    use rand::{thread_rng, Rng, SeedableRng};
+    use xxhash_rust::xxh3::xxh3_64;
+    use rand::prng::XorShiftRng;
+
+    #[test]
+    fn udp_connect() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+        let local_addr = SocketAddr::new("0.0.0.0".parse().unwrap(), 1234);
+        let socket = UdpSocket::bind(local_addr).expect("Couldn't bind socket");
+
+        let t = Sender::<UdpSocket>::connect(socket, &Default::default());
+
+        assert!(t.is_err());
+
+        println!("{:?}", t.err());
+    }
+
+    #[test]
+    fn udp_listen() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+        let local_addr = SocketAddr::new("0.0.0.0".parse().unwrap(), 0);
+        let socket = UdpSocket::bind(local_addr).expect("Couldno't bind socket");
+
+        // nobody is connecting, so don't wait forever for the Connect
+        socket.set_read_timeout(Some(Duration::from_secs(1))).expect("Couldn't set read timeout");
+
+        let t = Receiver::<UdpSocket>::listen(socket, &Default::default());
+
+        assert!(t.is_err());
+    }
+
+    #[test]
+    fn udp_strangers_ignored() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let addr = socket.local_addr().expect("Error getting local addr");
+        let peer = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let stranger = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+
+        peer.set_read_timeout(Some(Duration::from_secs(1))).expect("Couldn't set read timeout");
+        stranger.set_read_timeout(Some(Duration::from_millis(500))).expect("Couldn't set read timeout");
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), addr).expect("Error sending Connect");
+
+        let mut recver = Receiver::<UdpSocket>::listen(socket, &Configuration::default()).expect("Couldn't create receiver");
+
+        peer.recv_from(&mut buf).expect("Error reading Acknowledge");
+
+        // once connected, nothing from anyone else is read, or acknowledged
+        stranger.send_to(&data_message(0, &[0xAA; 16]), addr).expect("Error sending data");
+
+        assert!(stranger.recv_from(&mut buf).is_err());
+
+        peer.send_to(&data_message(0, &[0xBB; 16]), addr).expect("Error sending data");
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+        assert!(buf[..16].iter().all(|&b| b == 0xBB));
+    }
+
+    #[test]
+    fn udp_acks_to_sender() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let addr = socket.local_addr().expect("Error getting local addr");
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mut config = Configuration::default();
+
+        // the sender sends from one socket, and reads its ACKs on another
+        let sending = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let acks = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let acks_addr = acks.local_addr().expect("Error getting local addr");
+
+        config.set_ack_every(1);
+        sending.set_read_timeout(Some(Duration::from_millis(500))).expect("Couldn't set read timeout");
+        acks.set_read_timeout(Some(Duration::from_secs(1))).expect("Couldn't set read timeout");
+        sending.send_to(construct_connect(None, 0, ChecksumKind::Crc32, Some(acks_addr)).0.finished_data(), addr).expect("Error sending Connect");
+
+        let _recver = Receiver::<UdpSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        // the Acknowledge goes where the Connect said
+        let (amt, from) = acks.recv_from(&mut buf).expect("Error reading Acknowledge");
+
+        assert_eq!(addr, from);
+        assert_eq!(Type::Acknowledge, get_root_as_message(&buf[..amt]).msg_type());
+
+        sending.send_to(&data_message(0, &[0xBB; 16]), addr).expect("Error sending data");
+
+        // and so does the ACK of the data, sent from somewhere else
+        let (amt, from) = acks.recv_from(&mut buf).expect("Error reading ACK");
+        let msg_type = get_root_as_message(&buf[..amt]).msg_type();
+
+        assert_eq!(addr, from);
+        assert!(msg_type == Type::Acknowledge || msg_type == Type::SelectiveAck, "Expected an ACK, got {:?}", msg_type);
+
+        // none of it went back to the socket the sender sent from
+        assert_eq!(ErrorKind::WouldBlock, sending.recv_from(&mut buf).expect_err("Read an ACK on the sending socket").kind());
+    }
+
+    #[test]
+    fn ack_addr_carried() {
+        let from :SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let carried = |addr: &str| decode_ack_addr(&encode_ack_addr(addr.parse().unwrap()));
+
+        for addr in vec!["127.0.0.1:5000", "[::1]:5000", "0.0.0.0:0", "[::]:6000"] {
+            assert_eq!(Some(addr.parse::<SocketAddr>().unwrap()), carried(addr));
+        }
+
+        assert_eq!(None, decode_ack_addr(&[0; 6]));
+
+        // where the Connect says, when it's on the host the Connect came from
+        assert_eq!("127.0.0.1:5000".parse::<SocketAddr>().unwrap(), ack_addr(carried("127.0.0.1:5000"), from, false));
+
+        // otherwise back where it came from: another host, like the sender's own address behind a NAT, a wildcard, or nothing at all
+        assert_eq!(from, ack_addr(carried("10.0.0.2:5000"), from, false));
+        assert_eq!(from, ack_addr(carried("0.0.0.0:5000"), from, false));
+        assert_eq!(from, ack_addr(carried("127.0.0.1:0"), from, false));
+        assert_eq!(from, ack_addr(None, from, false));
+
+        // a duplex end's socket is connected to where the Connect came from
+        assert_eq!(from, ack_addr(carried("127.0.0.1:5000"), from, true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+        let path = env::temp_dir().join(format!("qcp_unix_socket_{}.sock", process::id()));
+
+        let _ = fs::remove_file(&path);
+
+        // bound before the sender sends anything, or there'd be no one at path yet
+        let socket = UnixDatagramSocket::bind(&path).expect("Couldn't bind socket");
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut recver = Receiver::<UnixDatagramSocket>::listen(socket, &Configuration::default()).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut received = Vec::new();
+
+            loop {
+                let amt = recver.read(&mut buf).expect("Error calling read");
+
+                if amt == 0 {
+                    break;
+                }
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            received
+        }).expect("Error spawning recv thread");
+
+        let socket = UnixDatagramSocket::connect_to(&path).expect("Couldn't bind socket");
+        let mut sender = Sender::<UnixDatagramSocket>::connect(socket, &Configuration::default()).expect("Couldn't call connect");
+        let data = (0..100_000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        sender.write_all(&data).expect("Error calling write_all");
+        sender.finish().expect("Error calling finish");
+
+        assert_eq!(MAX_PACKET_SIZE, sender.path_mtu());
+        assert!(data == recv_handle.join().expect("Recv thread panicked"));
+
+        // the paths go w/the sockets
+        drop(sender);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn recv_idle_timeout() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const IDLE_TIMEOUT :Duration = Duration::from_millis(500);
+
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let addr = socket.local_addr().expect("Error getting local addr");
+        let peer = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mut config = Configuration::default();
+
+        config.set_idle_timeout(IDLE_TIMEOUT);
+        peer.set_read_timeout(Some(Duration::from_secs(1))).expect("Couldn't set read timeout");
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), addr).expect("Error sending Connect");
+
+        let mut recver = Receiver::<UdpSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        peer.recv_from(&mut buf).expect("Error reading Acknowledge");
+
+        // then the sender vanishes, w/out a Disconnect
+        let start = Instant::now();
+        let err = recver.read(&mut buf).expect_err("Read w/nothing sent");
+
+        assert_eq!(ErrorKind::TimedOut, err.kind());
+        assert!(start.elapsed() >= IDLE_TIMEOUT / 2, "Gave up after only {:?}", start.elapsed());
+        assert!(start.elapsed() < IDLE_TIMEOUT * 4, "Took {:?} to give up", start.elapsed());
+    }
+
+    #[test]
+    fn udp_allow() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let addr = socket.local_addr().expect("Error getting local addr");
+        let peer = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let stranger = UdpSocket::bind("127.0.0.2:0").expect("Couldn't bind socket");
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+
+        peer.set_read_timeout(Some(Duration::from_secs(1))).expect("Couldn't set read timeout");
+        stranger.set_read_timeout(Some(Duration::from_millis(500))).expect("Couldn't set read timeout");
+
+        let mut config = Configuration::default();
+        config.set_allow(vec![Cidr::parse("127.0.0.1/32").unwrap(), Cidr::parse("::1").unwrap()]);
+
+        // the stranger gets in first, but isn't answered
+        stranger.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), addr).expect("Error sending Connect");
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), addr).expect("Error sending Connect");
+
+        let mut recver = Receiver::<UdpSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        assert!(stranger.recv_from(&mut buf).is_err());
+
+        let (amt, from) = peer.recv_from(&mut buf).expect("Error reading Acknowledge");
+
+        assert_eq!(addr, from);
+        assert_eq!(Type::Acknowledge, get_root_as_message(&buf[..amt]).msg_type());
+
+        peer.send_to(&data_message(0, &[0xBB; 16]), addr).expect("Error sending data");
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+        assert!(buf[..16].iter().all(|&b| b == 0xBB));
+    }
+
+    fn encode_decode(seq_num: u64) {
+        let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+        let payload = thread_rng().gen_iter::<u8>().take(MAX_PAYLOAD_SIZE).collect::<Vec<u8>>();
+
+        let buf = fbb.create_vector(&payload);
+
+        let checksum = compute_checksum(ChecksumKind::Crc32, Type::Message, 0, seq_num, Some(&payload));
+        let msg = Message::create(&mut fbb, &MessageArgs { msg_type: Type::Message, seq_num, payload: Some(buf), checksum, mac: None, hash: None, ranges: None, stream_id: 0, version: 0, magic: 0 });
+
+        fbb.finish(msg, None);
+
+        let msg_buf = fbb.finished_data();
+
+        println!("PACKET: {} {}", msg_buf.len(), buf2string(msg_buf));
+        assert!(msg_buf.len() <= MAX_PACKET_SIZE);
+
+        let msg = get_root_as_message(&msg_buf);
+        let payload = msg.payload().expect("Error getting payload");
+
+        println!("TYPE: {:?}", msg.msg_type());
+        println!("SEQ NUM: {:x}", msg.seq_num());
+        println!("PAYLOAD: {} {}", payload.len(), buf2string(payload));
+        assert!(payload.len() <= MAX_PAYLOAD_SIZE);
+    }
+
+    /// Reads every field, as the transport would
+    fn read_fields(msg: &Message) {
+        let _ = (msg.msg_type(), msg.seq_num(), msg.checksum(), msg.stream_id());
+        let _ = (msg.payload().map(|p| p.iter().fold(0u8, |a, &b| a ^ b)), msg.mac().map(|m| m.len()));
+    }
+
+    #[test]
+    fn parse_untrusted() {
+        let mut rng = XorShiftRng::from_seed([0x5A; 16]);
+
+        // random bytes are almost never a message, but must not panic either way
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0, 64);
+            let buf = rng.gen_iter::<u8>().take(len).collect::<Vec<u8>>();
+
+            if let Some(msg) = parse_message(&buf) {
+                read_fields(&msg);
+            }
+        }
+
+        let valid = vec![
+            construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 7).finished_data().to_vec(),
+            construct_payload_message(ChecksumKind::Crc32, Type::Message, 0, 0xAABBCCDD, &[0xAA; 100]).finished_data().to_vec(),
+            construct_mac_message(ChecksumKind::Crc32, Type::Connect, 0, 0, &[0xBB; 16], &[0xCC; 32]).finished_data().to_vec()
+        ];
+
+        for packet in &valid {
+            let msg = parse_message(packet).expect("Valid message rejected");
+
+            assert!(verify_checksum(ChecksumKind::Crc32, &msg));
+
+            // every truncation, as if the end of the packet was lost
+            for len in 0..packet.len() {
+                if let Some(msg) = parse_message(&packet[..len]) {
+                    read_fields(&msg);
+                }
+            }
+
+            // and random corruption, which is far more likely to get past the root offset than random bytes
+            for _ in 0..10_000 {
+                let mut buf = packet.clone();
+
+                for _ in 0..rng.gen_range(1, 4) {
+                    let loc = rng.gen_range(0, buf.len());
+                    buf[loc] = rng.gen();
+                }
+
+                if let Some(msg) = parse_message(&buf) {
+                    read_fields(&msg);
+                }
+            }
+
+            // the largest offset or length in every spot one could be, so the bounds checks would wrap if unchecked
+            for loc in 0..packet.len() - 3 {
+                let mut buf = packet.clone();
+
+                buf[loc..loc + 4].copy_from_slice(&[0xFF; 4]);
+
+                if let Some(msg) = parse_message(&buf) {
+                    read_fields(&msg);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn multiple_encode_decode() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        encode_decode(0); println!();
+        encode_decode(0xAA); println!();
+        encode_decode(0xAABB); println!();
+        encode_decode(0xAABBCC); println!();
+        encode_decode(0xAABBCCDD); println!();
+        encode_decode(0xAABBCCDD_11); println!();
+        encode_decode(0xAABBCCDD_1122); println!();
+        encode_decode(0xAABBCCDD_112233); println!();
+        encode_decode(0xAABBCCDD_11223344); println!();
+    }
+
+    #[test]
+    fn packet_drops() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        // a fifth of everything is dropped, the data and the ACKs both
+        let mock_socket = PacketDroppingSocket::new().with_bernoulli_model(0.2);
+        let duplex_socket = mock_socket.duplex();
+        let send_stats = mock_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_rto(Duration::from_millis(100));
+            config.set_connect_retries(10);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..100 {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            // dropping the sender stops retransmits, so wait until the last of the dropped packets made it
+            sender.flush().expect("Error calling flush");
+
+            // every chunk was acknowledged by the time flush returned, the last one too, so the window's slid past them all
+            assert_eq!(100, sender.seq_num);
+            assert_eq!(100, sender.window.window().0);
+            assert_eq!(0, sender.inflight_bytes());
+        }).expect("Error spawning send thread");
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for i in 0..100 {
+                assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+                assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+            }
+
+            // dropping the receiver stops its ACKs, so hand it back to live until the sender's flushed
+            recver
+        }).expect("Error spawning recv thread");
+
+        send_handle.join().expect("Send thread panicked");
+        recv_handle.join().expect("Recv thread panicked");
+
+        // everything got through, so whatever was dropped was sent again
+        info!("Packet drops: {} of {} sent were dropped", send_stats.dropped_count(), send_stats.sent_count());
+
+        assert!(send_stats.dropped_count() > 0);
+        assert!(send_stats.sent_count() > 100 + send_stats.dropped_count());
+    }
+
+    #[test]
+    fn empty_datagrams() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // ahead of the Connect, so listen sees one first
+        mock_socket.send_to(&[], "127.0.0.1:1234").expect("Error sending empty datagram");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..10 {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+
+                // and in between the data, for the receive thread
+                sender.socket.send_to(&[], "127.0.0.1:1234").expect("Error sending empty datagram");
+            }
+
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        for i in 0..10 {
+            assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+            assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+        }
+
+        // the receive thread was still around for the Finish
+        assert_eq!(0, recver.read(&mut buf).expect("Error calling read"));
+
+        send_handle.join().expect("Send thread panicked");
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const IDLE_TIMEOUT :Duration = Duration::from_millis(500);
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+        let acks = duplex_socket.try_clone().expect("Error cloning socket");
+
+        // kept around after reading, so it's still there answering Pings that never make it back
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for i in 0..3 {
+                assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+                assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+            }
+
+            recver
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+
+        config.set_idle_timeout(IDLE_TIMEOUT);
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        sender.write_all(&vec![0; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+        sender.flush().expect("Error calling flush");
+
+        assert_eq!(0, sender.inflight_bytes());
+
+        // nothing to ACK, but the Pongs say the receiver's still there
+        thread::sleep(IDLE_TIMEOUT * 2);
+
+        sender.write_all(&vec![1; MAX_PAYLOAD_SIZE]).expect("Error calling write_all after being idle");
+        sender.flush().expect("Error calling flush after being idle");
+
+        // the receiver still gets everything, but nothing it sends back arrives
+        acks.cut();
+
+        let start = Instant::now();
+
+        sender.write_all(&vec![2; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+
+        // the whole packet, header and all, until it's acknowledged
+        assert!(sender.inflight_bytes() > MAX_PAYLOAD_SIZE as u64, "{}", sender.inflight_bytes());
+
+        let err = sender.flush().expect_err("Flushed w/out any ACKs");
+
+        assert_eq!(ErrorKind::TimedOut, err.kind());
+        assert!(start.elapsed() >= IDLE_TIMEOUT / 2, "Gave up after only {:?}", start.elapsed());
+        assert!(start.elapsed() < IDLE_TIMEOUT + Duration::from_secs(2), "Gave up after {:?}", start.elapsed());
+
+        // the ACK for the last packet, and the Pongs, were all lost
+        assert!(acks.dropped_count() > 1);
+
+        drop(sender);
+        recv_handle.join().expect("Recv thread panicked");
+    }
+
+    #[test]
+    fn buffer_pool() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const PACKETS :usize = 1000;
+        const WINDOW_SIZE :usize = 32;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+
+            config.set_window_size(WINDOW_SIZE);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for i in 0..PACKETS {
+                assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+                assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+            }
+
+            assert_eq!(0, recver.read(&mut buf).expect("Error calling read"));
+
+            recver.pool.allocated()
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+
+        config.set_window_size(WINDOW_SIZE);
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        for i in 0..PACKETS {
+            sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+        }
+
+        sender.finish().expect("Error calling finish");
+
+        let recv_allocated = recv_handle.join().expect("Recv thread panicked");
+
+        // w/out the pool, every packet was at least one allocation on each end; now it's only what's out at once
+        info!("Buffers allocated for {} packets: {} sending, {} receiving", PACKETS, sender.pool.allocated(), recv_allocated);
+
+        assert!(sender.pool.allocated() <= 2 * WINDOW_SIZE, "Sender allocated {}", sender.pool.allocated());
+        assert!(recv_allocated <= 2 * WINDOW_SIZE, "Receiver allocated {}", recv_allocated);
+    }
+
+    #[test]
+    fn burst_loss() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // drop data on its way to the receiver in bursts, ACKs always make it back
+        let mock_socket = mock_socket.with_burst_model(0.02, 0.4, 0.0, 1.0);
+        let stats_socket = mock_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..100 {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            // dropping the sender stops retransmits, so wait until the last of the dropped packets made it
+            sender.flush().expect("Error calling flush");
+        }).expect("Error spawning send thread");
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for i in 0..100 {
+                assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+                assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+            }
+        }).expect("Error spawning recv thread");
+
+        send_handle.join().expect("Send thread panicked");
+        recv_handle.join().expect("Recv thread panicked");
+
+        // everything past the Connect and the first copy of each packet is a retransmit
+        let dropped = stats_socket.dropped_count();
+        let retransmits = stats_socket.sent_count() - 101;
+
+        info!("Burst loss: {} packets dropped, {} retransmits", dropped, retransmits);
+
+        assert!(dropped > 0);
+        assert!(retransmits >= dropped);
+    }
+
+    #[test]
+    fn encrypted_burst_loss() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let mock_socket = mock_socket.with_burst_model(0.02, 0.4, 0.0, 1.0);
+        let stats_socket = mock_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_key(Some(b"secret".to_vec()));
+            config.set_encrypt(true);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..100 {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_key(Some(b"secret".to_vec()));
+            config.set_encrypt(true);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut received = Vec::new();
+
+            loop {
+                let amt = recver.read(&mut buf).expect("Error calling read");
+
+                if amt == 0 {
+                    break;
+                }
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            received
+        }).expect("Error spawning recv thread");
+
+        send_handle.join().expect("Send thread panicked");
+
+        let received = recv_handle.join().expect("Recv thread panicked");
+        let expected = (0..100).flat_map(|i| vec![i as u8; MAX_PAYLOAD_SIZE]).collect::<Vec<u8>>();
+
+        assert!(expected == received);
+        assert!(stats_socket.dropped_count() > 0);
+    }
+
+    #[test]
+    fn undecryptable_packet_dropped() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mut config = Configuration::default();
+        config.set_key(Some(b"secret".to_vec()));
+        config.set_encrypt(true);
+
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        let (connect, nonce) = construct_connect(config.key(), 0, ChecksumKind::Crc32, None);
+        peer.send_to(connect.finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        // the session is keyed by the receiver's nonce too, from its Acknowledge
+        let session = session_nonce(nonce.as_ref().map(|n| &n[..]), &ack_nonce(&peer));
+        let cipher = Cipher::new(derive_key(b"secret", &session));
+        let auth = PacketAuth::new(b"secret", &session);
+        let nonce = packet_nonce(Type::Message, 0, 0);
+
+        // plaintext, the wrong key, and a tampered payload are all dropped w/out an ACK, even when tagged
+        let mut tampered = cipher.seal(&nonce, &[], &[0xAA; 16]);
+        tampered[0] ^= 0x01;
+
+        peer.send_to(&auth.sign(&data_message(0, &[0xAA; 16 + TAG_SIZE])), config.addr()).expect("Error sending data");
+        peer.send_to(&auth.sign(&data_message(0, &Cipher::new([0; 32]).seal(&nonce, &[], &[0xAA; 16]))), config.addr()).expect("Error sending data");
+        peer.send_to(&auth.sign(&data_message(0, &tampered)), config.addr()).expect("Error sending data");
+
+        // as is a good one w/out a tag, or w/a tag from another session
+        peer.send_to(&data_message(0, &cipher.seal(&nonce, &[], &[0xBB; 16])), config.addr()).expect("Error sending data");
+        peer.send_to(&PacketAuth::new(b"secret", &[0; 16]).sign(&data_message(0, &cipher.seal(&nonce, &[], &[0xBB; 16]))), config.addr()).expect("Error sending data");
+
+        assert!(drain_acks(&peer).is_empty());
+
+        peer.send_to(&auth.sign(&data_message(0, &cipher.seal(&nonce, &[], &[0xBB; 16]))), config.addr()).expect("Error sending data");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+        assert!(buf[..16].iter().all(|&b| b == 0xBB));
+    }
+
+    /// Reads the Acknowledge of an authenticated Connect, returning the receiver's nonce from it
+    fn ack_nonce(socket: &PacketDroppingSocket) -> Vec<u8> {
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (amt, _) = socket.recv_from_timeout(&mut buf, Duration::from_secs(1)).expect("Error reading Acknowledge");
+        let ack = get_root_as_message(&buf[..amt]);
+
+        assert_eq!(Type::Acknowledge, ack.msg_type());
+
+        return ack.payload().expect("Acknowledge w/out a payload")[WINDOW_SIZE_SIZE..].to_vec();
+    }
+
+    #[test]
+    fn replayed_connect_rekeyed() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mut config = Configuration::default();
+        config.set_key(Some(b"secret".to_vec()));
+
+        let (connect, _) = construct_connect(config.key(), 0, ChecksumKind::Crc32, None);
+        let mut nonces = Vec::new();
+
+        // the same Connect, as if recorded and played back, gets a different nonce from the receiver each time
+        for _ in 0..2 {
+            let peer = PacketDroppingSocket::new();
+            let socket = peer.duplex();
+
+            peer.send_to(connect.finished_data(), config.addr()).expect("Error sending Connect");
+
+            let _recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+            nonces.push(ack_nonce(&peer));
+        }
+
+        assert_eq!(CONNECT_NONCE_SIZE, nonces[0].len());
+        assert_ne!(nonces[0], nonces[1]);
+    }
+
+    /// Builds a data message, as the Sender would put it on the wire
+    fn data_message(seq_num: u64, payload: &[u8]) -> Vec<u8> {
+        return construct_payload_message(ChecksumKind::Crc32, Type::Message, 0, seq_num, payload).finished_data().to_vec();
+    }
+
+    /// Reads ACKs until none show up for a bit, returning their sequence numbers
+    /// Each SelectiveAck counts as every sequence number in its ranges, and NACKs are skipped
+    fn drain_acks(socket: &PacketDroppingSocket) -> Vec<u64> {
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mut acks = Vec::new();
+
+        while let Ok((amt, _)) = socket.recv_from_timeout(&mut buf, Duration::from_millis(200)) {
+            let ack = get_root_as_message(&buf[0..amt]);
+
+            match ack.msg_type() {
+                Type::Acknowledge => acks.push(ack.seq_num()),
+                Type::SelectiveAck => {
+                    for range in read_ranges(&ack).expect("Malformed SelectiveAck") {
+                        acks.extend(range.start()..range.end());
+                    }
+                },
+                Type::NegativeAcknowledge => (),
+                t => panic!("Unexpected {:?}", t)
+            }
+        }
+
+        return acks;
+    }
+
+    #[test]
+    fn full_window_stops_acks() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let window_size = config.window_size() as u64;
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        // connect by hand, so we control exactly what's sent
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+
+        // fill the window, and then some, w/out ever reading
+        for seq_num in 0..window_size + 10 {
+            peer.send_to(&data_message(seq_num, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        }
+
+        // only what fits in the window is acknowledged
+        assert_eq!((0..window_size).collect::<Vec<u64>>(), drain_acks(&peer));
+
+        // once the window has stalled, the packets past it are read and dropped, not left behind an insert spinning for room
+        thread::sleep(WINDOW_STALL_TIMEOUT + Duration::from_millis(500));
+
+        assert_eq!(0, peer.queued());
+        assert!(drain_acks(&peer).is_empty());
+
+        // once the reader makes room, the retransmit of the next packet is accepted
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+
+        peer.send_to(&data_message(window_size, &[0xBB; 16]), config.addr()).expect("Error sending data");
+
+        assert_eq!(vec![window_size], drain_acks(&peer));
+    }
+
+    #[test]
+    fn recv_buffer_stops_acks() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const LIMIT :u64 = 10;  // packets
+
+        let mut config = Configuration::default();
+        config.set_recv_buffer_bytes(LIMIT as usize * 16);
+
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+
+        // flood it, w/plenty of room left in the window, and never read
+        for seq_num in 0..5 * LIMIT {
+            peer.send_to(&data_message(seq_num, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        }
+
+        // only what fits in the buffer is acknowledged
+        assert_eq!((0..LIMIT).collect::<Vec<u64>>(), drain_acks(&peer));
+
+        // reading makes room for one more
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+
+        peer.send_to(&data_message(LIMIT, &[0xBB; 16]), config.addr()).expect("Error sending data");
+        peer.send_to(&data_message(LIMIT + 1, &[0xBB; 16]), config.addr()).expect("Error sending data");
+
+        assert_eq!(vec![LIMIT], drain_acks(&peer));
+    }
+
+    #[test]
+    fn duplicate_data() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+
+        // a retransmit that crossed paths w/the ACK
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+
+        // both are acknowledged, usually in the same SelectiveAck
+        let acks = drain_acks(&peer);
+
+        assert!(!acks.is_empty() && acks.iter().all(|&seq_num| seq_num == 0), "{:?}", acks);
+
+        peer.send_to(construct_finish(ChecksumKind::Crc32, 0, 1, None, 16).finished_data(), config.addr()).expect("Error sending Finish");
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+        assert_eq!(0, recver.read(&mut buf).expect("Error calling read"));
+
+        // and one after it's been read is still ACKed
+        assert_eq!(vec![1], drain_acks(&peer));
+
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+    }
+
+    #[test]
+    fn finish_total() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        // the sender says it sent more than arrived
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        peer.send_to(construct_finish(ChecksumKind::Crc32, 0, 1, None, 32).finished_data(), config.addr()).expect("Error sending Finish");
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+
+        let err = recver.read(&mut buf).expect_err("Read to the end of a short transfer");
+
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert!(err.to_string().contains("32 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn finish_layout() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        // each Finish is the end of its own transfer: w/a total that isn't known, then w/out one at all
+        for (finish, ok) in vec![(construct_finish(ChecksumKind::Crc32, 0, 1, None, UNKNOWN_LEN), true), (construct_message(ChecksumKind::Crc32, Type::Finish, 0, 1), false)] {
+            let peer = PacketDroppingSocket::new();
+            let socket = peer.duplex();
+
+            peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+            peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+            peer.send_to(finish.finished_data(), config.addr()).expect("Error sending Finish");
+
+            assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+
+            match recver.read(&mut buf) {
+                Ok(amt) => assert!(ok && amt == 0, "Read {} past a Finish w/out a total", amt),
+                Err(e) => assert!(!ok && e.kind() == ErrorKind::InvalidData, "{}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn recv_timeout() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        // nothing's been sent
+        let start = Instant::now();
+
+        assert_eq!(None, recver.recv_timeout(&mut buf, Duration::from_millis(100)).expect("Error calling recv_timeout"));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+
+        assert_eq!(Some(16), recver.recv_timeout(&mut buf, Duration::from_secs(5)).expect("Error calling recv_timeout"));
+
+        // the end of the stream isn't a timeout
+        peer.send_to(construct_finish(ChecksumKind::Crc32, 0, 1, None, 16).finished_data(), config.addr()).expect("Error sending Finish");
+
+        assert_eq!(Some(0), recver.recv_timeout(&mut buf, Duration::from_secs(5)).expect("Error calling recv_timeout"));
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(is_retryable(&IOError::new(ErrorKind::Interrupted, "signal")));
+        assert!(is_retryable(&IOError::from_raw_os_error(libc::EINTR)));
+        assert!(is_retryable(&IOError::from_raw_os_error(libc::ENOBUFS)));
+        assert!(!is_retryable(&IOError::new(ErrorKind::ConnectionReset, "reset")));
+        assert!(!is_retryable(&IOError::from_raw_os_error(libc::ECONNREFUSED)));
+
+        // only so many in a row
+        let mut calls = 0;
+        let res :Result<(), IOError> = recv_retrying(|| { calls += 1; Err(IOError::new(ErrorKind::Interrupted, "signal")) });
+
+        assert_eq!(ErrorKind::Interrupted, res.expect_err("Retried forever").kind());
+        assert_eq!(READ_RETRIES + 1, calls);
+    }
+
+    #[test]
+    fn interrupted_read_retried() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+        let failing = socket.try_clone().expect("Error cloning socket");
+
+        // waiting for the Connect
+        failing.fail_next_recv(ErrorKind::Interrupted);
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+
+        // and in the listen thread, which keeps going
+        failing.fail_next_recv(ErrorKind::Interrupted);
+        peer.send_to(&data_message(1, &[0xBB; 16]), config.addr()).expect("Error sending data");
+
+        assert_eq!(16, recver.read(&mut buf).expect("Interrupted read was fatal"));
+        assert_eq!(&[0xBB; 16], &buf[..16]);
+    }
+
+    #[test]
+    fn listen_thread_error_fails_read() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+        let failing = socket.try_clone().expect("Error cloning socket");
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        // one good packet, then one the listen thread skips
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Error, 0, 1).finished_data(), config.addr()).expect("Error sending Error");
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+
+        // then the socket fails, and read gets the reason
+        failing.fail_next_recv(ErrorKind::ConnectionReset);
+
+        let err = recver.read(&mut buf).expect_err("Read after the listen thread died");
+
+        assert_eq!(ErrorKind::ConnectionReset, err.kind());
+
+        // the error is only reported once
+        let err = recver.read(&mut buf).expect_err("Read after the listen thread died");
+
+        assert_eq!(ErrorKind::ConnectionAborted, err.kind());
+    }
+
+    #[test]
+    fn garbage_packets_skipped() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let contents = (0..5 * MAX_PAYLOAD_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+        let to_recver = mock_socket.try_clone().expect("Error cloning socket");
+        let to_sender = duplex_socket.try_clone().expect("Error cloning socket");
+
+        // too short for a message, a root offset past the end, and a type nobody expects
+        let garbage = vec![vec![0xFF; 3], vec![0xFF; 16], construct_message(ChecksumKind::Crc32, Type::Error, 0, 0).finished_data().to_vec()];
+
+        let send_contents = contents.clone();
+        let send_garbage = garbage.clone();
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for packet in &send_garbage {
+                to_recver.send_to(packet, config.addr()).expect("Error sending garbage");
+            }
+
+            sender.write_all(&send_contents).expect("Error calling write_all");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+
+        for packet in &garbage {
+            to_sender.send_to(packet, config.addr()).expect("Error sending garbage");
+        }
+
+        let mut received = Vec::new();
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        loop {
+            let amt = recver.read(&mut buf).expect("Error calling read");
+
+            if amt == 0 {
+                break;
+            }
+
+            received.extend_from_slice(&buf[..amt]);
+        }
+
+        send_handle.join().expect("Send thread panicked");
+        assert!(contents == received);
+    }
+
+    #[test]
+    fn connect_retries() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const TIMEOUT :Duration = Duration::from_millis(100);
+
+        let mock_socket = PacketDroppingSocket::new();
+        let peer = mock_socket.duplex();
+
+        // a receiver that never hears the first two Connects
+        let peer_handle = thread::Builder::new().name("peer".into()).spawn(move || {
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+            let mut connects = 0;
+
+            while connects < 3 {
+                let (amt, addr) = peer.recv_from(&mut buf).expect("Error reading Connect");
+
+                assert_eq!(Type::Connect, get_root_as_message(&buf[..amt]).msg_type());
+                connects += 1;
+
+                if connects == 3 {
+                    peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 0).finished_data(), addr).expect("Error sending Acknowledge");
+                }
+            }
+        }).expect("Error spawning peer thread");
+
+        let mut config = Configuration::default();
+        config.set_connect_timeout(TIMEOUT);
+
+        let start = Instant::now();
+
+        Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        assert!(start.elapsed() >= 2 * TIMEOUT, "{:?}", start.elapsed());
+        peer_handle.join().expect("Peer thread panicked");
+
+        // and nobody at all
+        config.set_connect_retries(2);
+
+        let start = Instant::now();
+        let err = Sender::<PacketDroppingSocket>::connect(PacketDroppingSocket::new(), &config).err().expect("Connected to nobody");
+
+        assert_eq!(ErrorKind::ConnectionAborted, err.kind());
+        assert!(err.to_string().contains("2 attempts"), "{}", err);
+        assert!(start.elapsed() >= 2 * TIMEOUT, "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn connect_discards_garbage() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let peer = mock_socket.duplex();
+
+        // a single Connect, answered w/junk, the wrong message, and the wrong sequence number before the real Acknowledge
+        let peer_handle = thread::Builder::new().name("peer".into()).spawn(move || {
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+            let (_, addr) = peer.recv_from(&mut buf).expect("Error reading Connect");
+
+            peer.send_to(&[0xFF; 64], addr).expect("Error sending junk");
+            peer.send_to(construct_message(ChecksumKind::Crc32, Type::Finish, 0, 0).finished_data(), addr).expect("Error sending Finish");
+            peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 7).finished_data(), addr).expect("Error sending Acknowledge");
+            peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 0).finished_data(), addr).expect("Error sending Acknowledge");
+        }).expect("Error spawning peer thread");
+
+        let mut config = Configuration::default();
+
+        config.set_connect_retries(1);
+        config.set_connect_timeout(Duration::from_secs(1));
+
+        Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+        peer_handle.join().expect("Peer thread panicked");
+
+        // only junk, until the budget runs out
+        let mock_socket = PacketDroppingSocket::new();
+        let peer = mock_socket.duplex();
+
+        config.set_connect_retries(2);
+        config.set_connect_timeout(Duration::from_millis(100));
+
+        peer.send_to(&[0xFF; 64], config.addr()).expect("Error sending junk");
+
+        let err = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).err().expect("Connected to junk");
+
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert!(err.to_string().contains("2 attempts"), "{}", err);
+    }
+
+    #[test]
+    fn file_header() {
+        let headers = vec![
+            FileHeader { name: "plain.txt".to_string(), len: 1234, mode: None, mtime: None, lone: false, block_size: None, verify_only: false },
+            FileHeader { name: String::new(), len: 0, mode: Some(0o755), mtime: Some(UNIX_EPOCH + Duration::new(1_500_000_000, 123_456_789)), lone: false, block_size: None, verify_only: false },
+            FileHeader { name: "mode_only".to_string(), len: 5, mode: Some(0o600), mtime: None, lone: false, block_size: None, verify_only: false },
+            FileHeader { name: "lone.bin".to_string(), len: 7, mode: None, mtime: None, lone: true, block_size: None, verify_only: false },
+            FileHeader { name: "delta.bin".to_string(), len: 65536, mode: Some(0o644), mtime: None, lone: false, block_size: Some(4096), verify_only: false },
+            FileHeader { name: "verify.bin".to_string(), len: 65536, mode: None, mtime: None, lone: true, block_size: Some(4096), verify_only: true }
+        ];
+
+        for header in headers {
+            assert_eq!(header, FileHeader::decode(&header.encode()).expect("Error decoding header"));
+        }
+
+        // attributes the flags promise, but aren't there
+        let mut truncated = FileHeader { name: String::new(), len: 0, mode: Some(0o755), mtime: None, lone: false, block_size: None, verify_only: false }.encode();
+        truncated.truncate(10);
+
+        assert!(FileHeader::decode(&truncated).is_err());
+        assert!(FileHeader::decode(&[0; 8]).is_err());
+
+        // a delta in blocks of nothing
+        let mut zero_blocks = FileHeader { name: "delta.bin".to_string(), len: 1, mode: None, mtime: None, lone: false, block_size: Some(1), verify_only: false }.encode();
+        zero_blocks[9..13].copy_from_slice(&[0; 4]);
+
+        assert!(FileHeader::decode(&zero_blocks).is_err());
+
+        // verify only, w/no blocks to hash
+        let mut no_blocks = FileHeader { name: "verify.bin".to_string(), len: 1, mode: None, mtime: None, lone: false, block_size: None, verify_only: false }.encode();
+        no_blocks[8] |= VERIFY_ONLY;
+
+        assert!(FileHeader::decode(&no_blocks).is_err());
+    }
+
+    #[test]
+    fn seq_ranges() {
+        let seq_nums = vec![0, 1, 2, 5, 7, 8].into_iter().collect();
+        let ranges = to_ranges(&seq_nums);
+
+        assert_eq!(vec![SeqRange::new(0, 3), SeqRange::new(5, 6), SeqRange::new(7, 9)], ranges);
+
+        let sack = construct_sack(ChecksumKind::Crc32, 0, 0, &ranges);
+        let msg = parse_message(sack.finished_data()).expect("Malformed SelectiveAck");
+
+        assert!(verify_checksum(ChecksumKind::Crc32, &msg));
+        assert_eq!(Some(ranges.clone()), read_ranges(&msg));
+
+        // wherever the packet happens to be in memory
+        let unaligned = [&[0u8][..], sack.finished_data()].concat();
+
+        assert_eq!(Some(ranges.clone()), read_ranges(&parse_message(&unaligned[1..]).expect("Malformed unaligned SelectiveAck")));
+
+        // an empty range, and none at all
+        let sack = construct_sack(ChecksumKind::Crc32, 0, 4, &[SeqRange::new(4, 4)]);
+
+        assert_eq!(None, read_ranges(&parse_message(sack.finished_data()).expect("Malformed SelectiveAck")));
+        assert_eq!(None, read_ranges(&get_root_as_message(construct_message(ChecksumKind::Crc32, Type::SelectiveAck, 0, 0).finished_data())));
+
+        // a length that runs past the end of the packet
+        let mut packet = construct_sack(ChecksumKind::Crc32, 0, 0, &ranges).finished_data().to_vec();
+
+        // the length comes right before the ranges themselves
+        let len_loc = ranges_loc(&get_root_as_message(&packet)).expect("No ranges");
+
+        packet[len_loc..len_loc + 4].copy_from_slice(&1000u32.to_le_bytes());
+
+        assert!(parse_message(&packet).is_none());
+    }
+
+    #[test]
+    fn selective_ack() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        // 10 packets w/5 missing, all queued before the receiver starts so they're acknowledged together
+        for seq_num in (0..5).chain(6..11) {
+            peer.send_to(&data_message(seq_num, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        }
+
+        let _recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mut sacks = Vec::new();
+
+        while let Ok((amt, _)) = peer.recv_from_timeout(&mut buf, Duration::from_millis(200)) {
+            let msg = get_root_as_message(&buf[0..amt]);
+
+            if msg.msg_type() == Type::SelectiveAck {
+                sacks.push(read_ranges(&msg).expect("Malformed SelectiveAck"));
+            }
+        }
+
+        assert_eq!(vec![vec![SeqRange::new(0, 5), SeqRange::new(6, 11)]], sacks);
+    }
+
+    #[test]
+    fn repeated_connect_acknowledged() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+        let connect = construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data().to_vec();
+
+        peer.send_to(&connect, config.addr()).expect("Error sending Connect");
+
+        let _recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        // as if the first Acknowledge was lost
+        peer.send_to(&connect, config.addr()).expect("Error sending Connect");
+
+        assert_eq!(vec![0, 0], drain_acks(&peer));
+    }
+
+    #[test]
+    fn drop_stops_listen_thread() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+        let window = recver.window.clone();
+
+        assert!(!window.is_closed());
+
+        // nothing is sent, so only the stop flag gets the thread out of its loop
+        let start = Instant::now();
+
+        drop(recver);
+
+        assert!(start.elapsed() < Duration::from_secs(1), "{:?}", start.elapsed());
+
+        // the thread closes the window on its way out, and let go of its clone
+        assert!(window.is_closed());
+        assert_eq!(1, Arc::strong_count(&window));
+    }
+
+    #[test]
+    fn probe() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const PATH_MTU :usize = 1237;
+        const DELAY :Duration = Duration::from_millis(20);
+
+        let mock_socket = PacketDroppingSocket::new().with_path_mtu(PATH_MTU).with_delay(DELAY);
+        let duplex_socket = mock_socket.duplex();
+
+        // everything the prober sends, to be sure none of it's data
+        let sent_types = Arc::new(Mutex::new(Vec::new()));
+        let sent_types_clone = sent_types.clone();
+        let mock_socket = mock_socket.with_tamper(move |packet: &mut Vec<u8>| {
+            if let Some(msg) = parse_message(packet) {
+                sent_types_clone.lock().unwrap().push(msg.msg_type());
+            }
+        });
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_window_size(64);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            // the prober disconnects w/out sending anything
+            recver.read(&mut buf).expect_err("Read data from a probe")
+        }).expect("Error spawning recv thread");
+
+        // the receiver's window, not ours
+        let config = Configuration::default();
+        let result = Sender::<PacketDroppingSocket>::probe(mock_socket, &config).expect("Error probing");
+
+        // the address that answered; the mock uses the same one for either end
+        assert_eq!("127.0.0.1:8080".parse::<SocketAddr>().unwrap(), result.peer);
+        assert_eq!(64, result.window_size);
+        assert_eq!(PATH_MTU, result.max_packet_size);
+        assert!(result.rtt >= DELAY && result.rtt < Duration::from_secs(1), "{:?}", result.rtt);
+
+        let sent_types = sent_types.lock().unwrap();
+
+        assert_eq!(Some(&Type::Connect), sent_types.first());
+        assert_eq!(Some(&Type::Disconnect), sent_types.last());
+        assert!(!sent_types.contains(&Type::Message), "{:?}", *sent_types);
+
+        let err = recv_handle.join().expect("Recv thread panicked");
+
+        assert_eq!(ErrorKind::ConnectionAborted, err.kind());
+    }
+
+    #[test]
+    fn large_write_bounded_in_flight() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const WINDOW_SIZE :usize = 8;
+        const CHUNKS :usize = WINDOW_SIZE * 10;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+        let stats_socket = mock_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_window_size(WINDOW_SIZE);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            // ten windows worth, in a single call; the last chunk is short
+            let mut buf = (0..CHUNKS).flat_map(|i| vec![i as u8; MAX_PAYLOAD_SIZE]).collect::<Vec<u8>>();
+            buf.truncate(buf.len() - 100);
+
+            sender.write_all(&buf).expect("Error calling write_all");
+        }).expect("Error spawning send thread");
+
+        let mut config = Configuration::default();
+        config.set_window_size(WINDOW_SIZE);
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        for i in 0..CHUNKS {
+            thread::sleep(Duration::from_millis(2));
+
+            // the sender can be at most a window ahead of the receiver's window, which is at most a window ahead of us
+            let data_sent = stats_socket.sent_count() - 1;
+            assert!(data_sent as usize <= i + 2 * WINDOW_SIZE + 1, "{} packets sent, only {} read", data_sent, i);
+
+            let amt = recver.read(&mut buf).expect("Error calling read");
+            let expected = if i == CHUNKS - 1 { MAX_PAYLOAD_SIZE - 100 } else { MAX_PAYLOAD_SIZE };
+
+            assert_eq!(expected, amt);
+            assert!(buf[..amt].iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+        }
+
+        send_handle.join().expect("Send thread panicked");
+
+        // nothing was retransmitted
+        assert_eq!(1 + CHUNKS as u64, stats_socket.sent_count());
+    }
+
+    #[test]
+    fn max_payload_fits() {
+        assert_eq!(MAX_PAYLOAD_SIZE, max_payload_size(MAX_PACKET_SIZE, 0));
+
+        for path_mtu in MIN_PATH_MTU..MAX_PACKET_SIZE {
+            // stream 0 leaves out its id, the rest carry it
+            for &stream_id in &[0, MAX_STREAMS as u32 - 1] {
+                let size = max_payload_size(path_mtu, stream_id);
+
+                assert!(construct_payload_message(ChecksumKind::Crc32, Type::Message, stream_id, u64::MAX, &vec![0; size]).finished_data().len() <= path_mtu);
+            }
+        }
+    }
+
+    #[test]
+    fn transfer_stats() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 100;
+
+        let contents = (0..CHUNKS * MAX_PAYLOAD_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // drop data on its way to the receiver, ACKs always make it back
+        let mock_socket = mock_socket.with_bernoulli_model(0.05);
+
+        let send_contents = contents.clone();
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_rto(Duration::from_millis(100));
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            sender.write_all(&send_contents).expect("Error calling write_all");
+            sender.finish().expect("Error calling finish");
+
+            return sender.stats();
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        while recver.read(&mut buf).expect("Error calling read") != 0 { }
+
+        let stats = send_handle.join().expect("Send thread panicked");
+
+        info!("{}", stats);
+
+        assert_eq!(contents.len() as u64, stats.bytes);
+        assert_eq!(CHUNKS as u64 + 1, stats.packets_sent);     // and the Finish
+        assert!(stats.retransmits > 0);
+        assert!(stats.throughput() > 0.0);
+    }
+
+    #[test]
+    fn loss_based_congestion_control() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 100;
+
+        let contents = (0..CHUNKS * MAX_PAYLOAD_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+
+        for &kind in [CongestionKind::Reno, CongestionKind::Cubic].iter() {
+            let mock_socket = PacketDroppingSocket::new();
+            let duplex_socket = mock_socket.duplex();
+            let mock_socket = mock_socket.with_bernoulli_model(0.05);
+
+            let send_contents = contents.clone();
+            let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+                let mut config = Configuration::default();
+                config.set_rto(Duration::from_millis(100));
+                config.set_congestion(kind);
+
+                let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+                sender.write_all(&send_contents).expect("Error calling write_all");
+                sender.finish().expect("Error calling finish");
+            }).expect("Error spawning send thread");
+
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut received = Vec::new();
+
+            loop {
+                let amt = recver.read(&mut buf).expect("Error calling read");
+
+                if amt == 0 {
+                    break;
+                }
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            send_handle.join().expect("Send thread panicked");
+
+            assert!(contents == received, "Wrong contents w/{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn path_mtu_discovery() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const PATH_MTU :usize = 1237;
+
+        let contents = (0..5 * MAX_PAYLOAD_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+
+        let mock_socket = PacketDroppingSocket::new().with_path_mtu(PATH_MTU);
+        let duplex_socket = mock_socket.duplex();
+
+        let send_contents = contents.clone();
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            // anything larger would fail to send
+            sender.write_all(&send_contents).expect("Error calling write_all");
+            sender.finish().expect("Error calling finish");
+
+            return sender.path_mtu();
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut received = Vec::new();
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        loop {
+            let amt = recver.read(&mut buf).expect("Error calling read");
+
+            if amt == 0 {
+                break;
+            }
+
+            received.extend_from_slice(&buf[..amt]);
+        }
+
+        assert_eq!(PATH_MTU, send_handle.join().expect("Send thread panicked"));
+        assert!(contents == received);
+    }
+
+    #[test]
+    fn partial_reads() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let contents = (0..3 * MAX_PAYLOAD_SIZE - 7).map(|i| i as u8).collect::<Vec<u8>>();
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_contents = contents.clone();
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            sender.write_all(&send_contents).expect("Error calling write_all");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut received = Vec::new();
+        let mut buf = vec![0; 100];
+
+        loop {
+            let amt = recver.read(&mut buf).expect("Error calling read");
+
+            if amt == 0 {
+                break;
+            }
+
+            assert!(amt <= 100);
+            received.extend_from_slice(&buf[..amt]);
+        }
+
+        send_handle.join().expect("Send thread panicked");
+
+        assert!(contents == received);
+        assert_eq!(contents.len() as u64, recver.bytes_transferred());
+    }
+
+    #[test]
+    fn reverse() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        // more than a packet's worth, so the reply's packets have to come out in order
+        let reply = (0..MAX_PAYLOAD_SIZE + 10).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_reply = reply.clone();
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            sender.write_all(b"request").expect("Error calling write_all");
+
+            let mut received = Vec::new();
+            let mut buf = vec![0; 100];
+
+            while received.len() < send_reply.len() {
+                let amt = sender.read(&mut buf).expect("Error calling read");
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            sender.finish().expect("Error calling finish");
+
+            assert!(send_reply == received);
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        let amt = recver.read(&mut buf).expect("Error calling read");
+
+        assert_eq!(b"request", &buf[..amt]);
+
+        recver.write_all(&reply).expect("Error calling write_all");
+
+        assert_eq!(0, recver.read(&mut buf).expect("Error calling read"));
+
+        send_handle.join().expect("Send thread panicked");
+    }
+
+    #[test]
+    fn duplex() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        // a few packets each way, and different, so nothing sent can be mistaken for what came back
+        let outbound = (0..3 * MAX_PAYLOAD_SIZE).map(|i| (i * 3) as u8).collect::<Vec<u8>>();
+        let inbound = (0..3 * MAX_PAYLOAD_SIZE + 10).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // each end writes everything, then reads everything, so both directions are in flight at once
+        fn exchange<T: Socket>(conn: &mut BbrConnection<T>, send: &[u8], expected: &[u8]) {
+            conn.write_all(send).expect("Error calling write_all");
+
+            let mut received = Vec::new();
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            while received.len() < expected.len() {
+                let amt = conn.read(&mut buf).expect("Error calling read");
+
+                assert!(amt > 0, "Finished after {} bytes", received.len());
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            assert!(expected == &received[..]);
+
+            conn.finish().expect("Error calling finish");
+
+            assert_eq!(0, conn.read(&mut buf).expect("Error calling read"));
+        }
+
+        let (send_outbound, send_inbound) = (outbound.clone(), inbound.clone());
+        let connect_handle = thread::Builder::new().name("connect".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut conn = BbrConnection::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            exchange(&mut conn, &send_outbound, &send_inbound);
+        }).expect("Error spawning connect thread");
+
+        let config = Configuration::default();
+        let mut conn = BbrConnection::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't call listen");
+
+        exchange(&mut conn, &inbound, &outbound);
+
+        connect_handle.join().expect("Connect thread panicked");
+    }
+
+    #[test]
+    fn duplex_refused() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // a receiver that isn't sending anything back turns away a connection that expects it to
+        let connect_handle = thread::Builder::new().name("connect".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_connect_retries(1);
+            config.set_connect_timeout(Duration::from_millis(500));
+
+            assert!(BbrConnection::<PacketDroppingSocket>::connect(mock_socket, &config).is_err());
+        }).expect("Error spawning connect thread");
+
+        let config = Configuration::default();
+        let err = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).err().expect("Expected listen to fail");
+
+        assert_eq!(ErrorKind::ConnectionRefused, err.kind());
+
+        connect_handle.join().expect("Connect thread panicked");
+    }
+
+    #[test]
+    fn bytes_transferred() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        // a file that doesn't end on a packet boundary
+        let path = env::temp_dir().join(format!("qcp_bytes_transferred_{}", process::id()));
+        let contents = (0..10 * MAX_PAYLOAD_SIZE + 123).map(|i| i as u8).collect::<Vec<u8>>();
+
+        File::create(&path).and_then(|mut f| f.write_all(&contents)).expect("Error writing source file");
+
+        let file_size = fs::metadata(&path).expect("Error reading metadata").len();
+        let packets = (file_size as usize + MAX_PAYLOAD_SIZE - 1) / MAX_PAYLOAD_SIZE;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_path = path.clone();
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+            let mut file = File::open(&send_path).expect("Error opening source file");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            loop {
+                let amt = file.read(&mut buf).expect("Error reading source file");
+
+                if amt == 0 {
+                    break;
+                }
+
+                sender.write_all(&buf[0..amt]).expect("Error calling write_all");
+            }
+
+            sender.bytes_transferred()
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut received = Vec::new();
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        for _ in 0..packets {
+            let amt = recver.read(&mut buf).expect("Error calling read");
+            received.extend_from_slice(&buf[..amt]);
+        }
+
+        assert_eq!(file_size, send_handle.join().expect("Send thread panicked"));
+        assert_eq!(file_size, recver.bytes_transferred());
+        assert!(contents == received);
+
+        fs::remove_file(&path).expect("Error removing source file");
+    }
+
+    #[test]
+    fn finish() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // the Connect and 3 chunks make it, the first Finish doesn't; so it has to be retransmitted
+        let mock_socket = mock_socket.with_periodic_model(100, 4);
+        let stats_socket = mock_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..3 {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        let mut chunks = 0;
+
+        // the same loop as main
+        loop {
+            let amt = recver.read(&mut buf).expect("Error calling read");
+
+            if amt == 0 {
+                break;
+            }
+
+            assert!(buf[..amt].iter().all(|&b| b == chunks as u8), "Wrong payload for packet {}", chunks);
+            chunks += 1;
+        }
+
+        assert_eq!(3, chunks);
+        assert_eq!(0, recver.read(&mut buf).expect("Error calling read after finish"));
+
+        send_handle.join().expect("Send thread panicked");
+
+        assert_eq!(1, stats_socket.dropped_count());
+        assert_eq!(6, stats_socket.sent_count());
+    }
+
+    #[test]
+    fn next_file_skips_rest() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+
+            config.set_verify(true);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            sender.start_file(&FileHeader { name: "first".to_string(), len: 3 * MAX_PAYLOAD_SIZE as u64, mode: None, mtime: None, lone: false, block_size: None, verify_only: false }).expect("Error starting first file");
+
+            for i in 0..3 {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            sender.end_file().expect("Error ending first file");
+            sender.start_file(&FileHeader { name: "second".to_string(), len: 16, mode: None, mtime: None, lone: false, block_size: None, verify_only: false }).expect("Error starting second file");
+            sender.write_all(&[0xAA; 16]).expect("Error calling write_all");
+            sender.end_file().expect("Error ending second file");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let mut config = Configuration::default();
+
+        config.set_verify(true);
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!("first", recver.next_file().expect("Error calling next_file").expect("No first file").name);
+
+        // only part of the first chunk, the rest of it and the 2 chunks after are skipped
+        assert_eq!(10, recver.read(&mut buf[..10]).expect("Error calling read"));
+        assert_eq!("second", recver.next_file().expect("Error calling next_file").expect("No second file").name);
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+        assert!(buf[..16].iter().all(|&b| b == 0xAA));
+        assert_eq!(0, recver.read(&mut buf).expect("Error calling read"));
+
+        // the skipped chunks are still part of the hash
+        assert!(recver.next_file().expect("Error verifying the hash").is_none());
+
+        send_handle.join().expect("Send thread panicked");
+    }
+
+    /// Sends 3 chunks w/--verify on both ends, returning what the receiver's read loop ended w/
+    /// When tampering, the second chunk's payload is changed and its checksum recomputed, so only the hash can catch it
+    fn verify_transfer(tamper: bool) -> Result<usize, IOError> {
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let mock_socket = if !tamper { mock_socket } else {
+            mock_socket.with_tamper(|packet: &mut Vec<u8>| {
+                let tampered = {
+                    let msg = get_root_as_message(packet);
+
+                    if msg.msg_type() != Type::Message || msg.seq_num() != 1 {
+                        return;
+                    }
+
+                    let mut payload = msg.payload().expect("Message w/out a payload").to_vec();
+
+                    payload[0] ^= 0xFF;
+
+                    construct_payload_message(ChecksumKind::Crc32, Type::Message, 0, 1, &payload).finished_data().to_vec()
+                };
+
+                *packet = tampered;
+            })
+        };
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+
+            config.set_verify(true);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..3 {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let mut config = Configuration::default();
+
+        config.set_verify(true);
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        let mut total = 0;
+
+        let res = loop {
+            match recver.read(&mut buf) {
+                Ok(0) => break Ok(total),
+                Ok(amt) => total += amt,
+                Err(e) => break Err(e)
+            }
+        };
+
+        send_handle.join().expect("Send thread panicked");
+
+        return res;
+    }
+
+    #[test]
+    fn verify_hash() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        assert_eq!(3 * MAX_PAYLOAD_SIZE, verify_transfer(false).expect("Error verifying an intact transfer"));
+
+        // every packet passed its checksum, but the hash at the end doesn't match
+        let err = verify_transfer(true).expect_err("Tampered transfer verified");
+
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn verify_needs_sender_hash() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mut config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        config.set_verify(true);
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+        peer.send_to(construct_finish(ChecksumKind::Crc32, 0, 1, None, 16).finished_data(), config.addr()).expect("Error sending Finish");
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+        assert_eq!(ErrorKind::InvalidData, recver.read(&mut buf).expect_err("Finish w/out a hash verified").kind());
+    }
+
+    #[test]
+    fn pacer() {
+        let start = Instant::now();
+
+        // 100 bytes per second, well under a packet per second
+        let mut pacer = Pacer::new(800);
+
+        // the first packet's already in the bucket
+        assert_eq!(Duration::from_secs(0), pacer.reserve(MAX_PACKET_SIZE, start));
+
+        // the next has to wait for all of its bytes, but it's still sent
+        let delay = pacer.reserve(1000, start);
+
+        assert_eq!(Duration::from_secs(10), delay);
+
+        // having waited that long, the debt is paid off and the next is paced the same
+        assert_eq!(Duration::from_secs(10), pacer.reserve(1000, start + delay));
+
+        // idle time only ever fills the bucket to one packet
+        assert_eq!(Duration::from_secs(0), pacer.reserve(MAX_PACKET_SIZE, start + Duration::from_secs(3600)));
+        assert_eq!(Duration::from_secs(1), pacer.reserve(100, start + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn max_rate() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 30;
+        const RATE :u64 = 800_000;     // 100,000 bytes per second
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+
+            config.set_max_rate(Some(RATE));
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+            let start = Instant::now();
+
+            sender.write_all(&vec![0xAB; CHUNKS * MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+
+            let elapsed = start.elapsed();
+
+            sender.finish().expect("Error calling finish");
+
+            elapsed
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        let mut total = 0;
+
+        loop {
+            let amt = recver.read(&mut buf).expect("Error calling read");
+
+            if amt == 0 {
+                break;
+            }
+
+            total += amt;
+        }
+
+        let elapsed = send_handle.join().expect("Send thread panicked");
+
+        assert_eq!(CHUNKS * MAX_PAYLOAD_SIZE, total);
+
+        // only the first packet goes out for free, the payloads alone take at least this long at the cap
+        let min_secs = (CHUNKS * MAX_PAYLOAD_SIZE - MAX_PACKET_SIZE) as f64 / (RATE / 8) as f64;
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        assert!(secs >= min_secs, "Sent in {}s, but the cap allows no less than {}s", secs, min_secs);
+    }
+
+    #[test]
+    fn paced() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const DELAY :u64 = 25;     // ms, each way
+        const CHUNKS :usize = 10;  // Reno's initial window, so they're all sent before the first ACK comes back
+
+        let mock_socket = PacketDroppingSocket::new().with_delay(Duration::from_millis(DELAY));
+        let duplex_socket = mock_socket.duplex();
+
+        // when each Message went out, and how large it was
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sent_clone = sent.clone();
+        let mock_socket = mock_socket.with_tamper(move |packet: &mut Vec<u8>| {
+            if parse_message(packet).map_or(false, |msg| msg.msg_type() == Type::Message) {
+                sent_clone.lock().unwrap().push((Instant::now(), packet.len()));
+            }
+        });
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            while recver.read(&mut buf).expect("Error calling read") != 0 { }
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+        config.set_congestion(CongestionKind::Reno);
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        sender.write_all(&vec![0xAB; CHUNKS * MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+        sender.finish().expect("Error calling finish");
+        recv_handle.join().expect("Recv thread panicked");
+
+        let sent = sent.lock().unwrap();
+
+        assert!(sent.len() >= CHUNKS, "Only {} sent", sent.len());
+
+        // the window's 10 packets per RTT, w/PACING_GAIN to spare, so each gap is this much of the RTT
+        let rtt = (2 * DELAY) as f64 / 1000.0;
+        let rate = PACING_GAIN * (CHUNKS * MAX_PACKET_SIZE) as f64 / rtt;
+        let expected = sent[..CHUNKS - 1].iter().map(|&(_, len)| len as f64 / rate).sum::<f64>();
+        let elapsed = sent[CHUNKS - 1].0.duration_since(sent[0].0);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+
+        info!("{} packets in {}s, expected {}s", CHUNKS, elapsed, expected);
+
+        // the handshake's RTT is a little over the delays, so the gaps are a little longer
+        assert!(elapsed >= expected * 0.9 && elapsed <= expected * 1.3, "{} packets in {}s over an RTT of {}s, expected {}s", CHUNKS, elapsed, rtt, expected);
+    }
+
+    #[test]
+    fn fast_retransmit() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 20;
+        const LOST :u64 = 5;
+        const RTO :Duration = Duration::from_secs(3);
+
+        let mock_socket = PacketDroppingSocket::new();
+
+        // NACKs are lost, so only the duplicate ACKs can say LOST is missing
+        let duplex_socket = mock_socket.duplex().with_tamper(|packet: &mut Vec<u8>| {
+            if parse_message(packet).map_or(false, |msg| msg.msg_type() == Type::NegativeAcknowledge) {
+                packet.clear();
+            }
+        });
+
+        // the first send of LOST never arrives, the packets after it do
+        let mut lost = false;
+        let mock_socket = mock_socket.with_tamper(move |packet: &mut Vec<u8>| {
+            if !lost && parse_message(packet).map_or(false, |msg| msg.msg_type() == Type::Message && msg.seq_num() == LOST) {
+                lost = true;
+                packet.clear();
+            }
+        });
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_ack_every(1);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut total = 0;
+
+            loop {
+                match recver.read(&mut buf).expect("Error calling read") {
+                    0 => break total,
+                    amt => total += amt
+                }
+            }
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+        config.set_rto(RTO);
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+        let start = Instant::now();
+
+        sender.write_all(&vec![0xAB; CHUNKS * MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+        sender.finish().expect("Error calling finish");
+
+        let elapsed = start.elapsed();
+
+        assert_eq!(CHUNKS * MAX_PAYLOAD_SIZE, recv_handle.join().expect("Recv thread panicked"));
+        assert!(elapsed < RTO / 2, "Took {:?}, waited for the timeout", elapsed);
+        assert!(sender.stats().retransmits >= 1, "{}", sender.stats());
+    }
+
+    #[test]
+    fn max_retransmits() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const MAX_RETRANSMITS :u32 = 3;
+        const LOST :u64 = 2;
+
+        let mock_socket = PacketDroppingSocket::new();
+
+        // NACKs are lost too, so only the timeouts resend LOST
+        let duplex_socket = mock_socket.duplex().with_tamper(|packet: &mut Vec<u8>| {
+            if parse_message(packet).map_or(false, |msg| msg.msg_type() == Type::NegativeAcknowledge) {
+                packet.clear();
+            }
+        });
+
+        // every send of LOST is dropped, however many times it's tried
+        let sends = Arc::new(Mutex::new(0));
+        let sends_clone = sends.clone();
+        let mock_socket = mock_socket.with_tamper(move |packet: &mut Vec<u8>| {
+            if parse_message(packet).map_or(false, |msg| msg.msg_type() == Type::Message && msg.seq_num() == LOST) {
+                *sends_clone.lock().unwrap() += 1;
+                packet.clear();
+            }
+        });
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_idle_timeout(Duration::from_secs(1));
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            // it never gets LOST, so never gets to the end, just gives up on the sender
+            loop {
+                match recver.read(&mut buf) {
+                    Ok(0) => panic!("Read to the end w/out LOST"),
+                    Ok(_) => (),
+                    Err(_) => break
+                }
+            }
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+        config.set_rto(Duration::from_millis(20));
+        config.set_max_retransmits(Some(MAX_RETRANSMITS));
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        let err = sender.write_all(&vec![0xAB; 10 * MAX_PAYLOAD_SIZE]).and_then(|_| sender.finish()).expect_err("Finished w/out LOST");
+
+        assert_eq!(ErrorKind::ConnectionAborted, err.kind());
+
+        // the first send, every retransmit after a timeout, and maybe one after duplicate ACKs
+        let sends = *sends.lock().unwrap();
+
+        assert!(sends > MAX_RETRANSMITS && sends <= MAX_RETRANSMITS + 2, "{} sends", sends);
+
+        recv_handle.join().expect("Recv thread panicked");
+    }
+
+    #[test]
+    fn target_window_bounds() {
+        // 1,500,000 bytes/sec for 100ms is 100 packets, and twice that is the target
+        assert_eq!(200, target_window(1_500_000.0, Duration::from_millis(100), MAX_PACKET_SIZE, 4096));
+
+        // no more than the max, no less than the min
+        assert_eq!(128, target_window(1_500_000.0, Duration::from_millis(100), MAX_PACKET_SIZE, 128));
+        assert_eq!(MIN_WINDOW, target_window(1_500.0, Duration::from_millis(1), MAX_PACKET_SIZE, 4096));
+        assert_eq!(8, target_window(1_500.0, Duration::from_millis(1), MAX_PACKET_SIZE, 8));
+    }
+
+    /// Sends at RATE bits/sec over a path w/DELAY each way, starting w/a window of window_size packets
+    /// Returns the size of the window at the end, and the bandwidth-delay product in packets
+    fn tuned_window(window_size: usize) -> (usize, usize) {
+        const DELAY :u64 = 25;     // ms, each way
+        const RATE :u64 = 16_000_000;  // 2,000,000 bytes per second
+        const CHUNKS :usize = 1400;
+
+        let mock_socket = PacketDroppingSocket::new().with_delay(Duration::from_millis(DELAY));
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+
+            config.set_window_size(4096);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            while recver.read(&mut buf).expect("Error calling read") != 0 { }
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+
+        config.set_window_size(window_size);
+        config.set_max_window(Some(4096));
+        config.set_max_rate(Some(RATE));
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        sender.write_all(&vec![0xAB; CHUNKS * MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+        sender.flush().expect("Error calling flush");
+
+        let (start, end) = sender.window.window();
+
+        sender.finish().expect("Error calling finish");
+        recv_handle.join().expect("Recv thread panicked");
+
+        let bdp = (RATE / 8) as usize * (2 * DELAY) as usize / 1000 / MAX_PACKET_SIZE;
+
+        return ((end - start) as usize, bdp);
+    }
+
+    #[test]
+    fn window_tuned_to_bdp() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        // too small for the path, so it grows
+        let (size, bdp) = tuned_window(MIN_WINDOW);
+
+        assert!(size >= bdp && size <= 4 * bdp, "Window of {} packets, for a BDP of {}", size, bdp);
+
+        // far too large, so it shrinks
+        let (size, bdp) = tuned_window(2048);
+
+        assert!(size >= bdp && size <= 4 * bdp, "Window of {} packets, for a BDP of {}", size, bdp);
+    }
+
+    #[test]
+    fn bbr_estimates_converge() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const RTT :u64 = 50;   // ms
+        const CHUNKS :usize = 400;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let peer = mock_socket.duplex();
+
+        // a receiver that holds every ACK for RTT
+        let peer_handle = thread::Builder::new().name("peer".into()).spawn(move || {
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+            let mut pending = VecDeque::new();
+            let mut acked = 0;
+
+            while acked < CHUNKS + 1 {
+                if let Ok((amt, addr)) = peer.recv_from_timeout(&mut buf, Duration::from_millis(1)) {
+                    let seq_num = get_root_as_message(&buf[..amt]).seq_num();
+                    pending.push_back((Instant::now() + Duration::from_millis(RTT), seq_num, addr));
+                }
+
+                while pending.front().map(|&(due, _, _)| due <= Instant::now()).unwrap_or(false) {
+                    let (_, seq_num, addr) = pending.pop_front().unwrap();
+
+                    peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, seq_num).finished_data(), addr).expect("Error sending ACK");
+                    acked += 1;
+                }
+            }
+        }).expect("Error spawning peer thread");
+
+        let mut config = Configuration::default();
+        config.set_window_size(64);
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+        let start = Instant::now();
+
+        for _ in 0..CHUNKS {
+            sender.write_all(&[0xAA; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+        }
+
+        peer_handle.join().expect("Peer thread panicked");
+
+        let elapsed = start.elapsed();
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+        let goodput = (CHUNKS * MAX_PACKET_SIZE) as f64 / elapsed;
+
+        let min_rtt = sender.min_rtt().expect("No RTT estimate");
+        let bw = sender.estimated_bandwidth().expect("No bandwidth estimate");
+
+        info!("min RTT: {:?}, bandwidth: {:.0} B/s, goodput: {:.0} B/s", min_rtt, bw, goodput);
+
+        assert!(min_rtt >= Duration::from_millis(RTT) && min_rtt < Duration::from_millis(RTT * 3 / 2), "{:?}", min_rtt);
+        assert!(bw > goodput / 2.0 && bw < goodput * 3.0, "{} vs {}", bw, goodput);
+    }
+
+    #[test]
+    fn smoothed_rtt_converges() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const DELAY :u64 = 25;  // ms, each way
+
+        let mock_socket = PacketDroppingSocket::new().with_delay(Duration::from_millis(DELAY));
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            while recver.read(&mut buf).expect("Error calling read") != 0 { }
+        }).expect("Error spawning recv thread");
+
+        let config = Configuration::default();
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        // one at a time, so there's no queueing to inflate the samples
+        for _ in 0..30 {
+            sender.write_all(&[0xAA; 16]).expect("Error calling write_all");
+            sender.flush().expect("Error calling flush");
+        }
+
+        sender.finish().expect("Error calling finish");
+        recv_handle.join().expect("Recv thread panicked");
+
+        let srtt = sender.smoothed_rtt();
+
+        assert!(srtt >= Duration::from_millis(2 * DELAY) && srtt < Duration::from_millis(3 * DELAY), "{:?}", srtt);
+    }
+
+    #[test]
+    fn smoothed_rtt_w_jitter() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const DELAY :u64 = 20;  // ms, each way
+        const JITTER :u64 = 10; // ms, each way; so the RTT is 50ms on average
+
+        let mock_socket = PacketDroppingSocket::new().with_delay(Duration::from_millis(DELAY)).with_jitter(Duration::from_millis(JITTER));
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            while recver.read(&mut buf).expect("Error calling read") != 0 { }
+        }).expect("Error spawning recv thread");
+
+        let config = Configuration::default();
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        for _ in 0..30 {
+            sender.write_all(&[0xAA; 16]).expect("Error calling write_all");
+            sender.flush().expect("Error calling flush");
+        }
+
+        sender.finish().expect("Error calling finish");
+        recv_handle.join().expect("Recv thread panicked");
+
+        let srtt = sender.smoothed_rtt();
+
+        // anywhere from 40ms to 60ms a sample, plus however long the ACK was held
+        assert!(srtt >= Duration::from_millis(2 * DELAY) && srtt < Duration::from_millis(2 * (DELAY + JITTER) + 15), "{:?}", srtt);
+    }
+
+    #[test]
+    fn nack_retransmits() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 10;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // drop the 5th chunk, after the Connect
+        let mock_socket = mock_socket.with_periodic_model(1000, 5);
+        let send_stats = mock_socket.try_clone().expect("Error cloning socket");
+        let recv_stats = duplex_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..CHUNKS {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            // the receiver ACKs after handing the packet to read, so wait for the ACKs before counting them
+            sender.flush().expect("Error calling flush");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        let start = Instant::now();
+
+        for i in 0..CHUNKS {
+            assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+            assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+        }
+
+        // the mock's RTT is next to nothing, so anything close to the 3s timeout wasn't the NACK
+        assert!(start.elapsed() < Duration::from_millis(500), "Took {:?}", start.elapsed());
+
+        send_handle.join().expect("Send thread panicked");
+
+        // the dropped chunk was resent exactly once, after the receiver NACKed it once
+        // and the chunks were acknowledged a few at a time, not one ACK each
+        assert_eq!(1, send_stats.dropped_count());
+        assert_eq!(1 + CHUNKS as u64 + 1, send_stats.sent_count());
+        assert!(recv_stats.sent_count() < 1 + CHUNKS as u64 + 1, "{}", recv_stats.sent_count());
+    }
+
+    #[test]
+    fn ack_every() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 10;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+        let recv_stats = duplex_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            for i in 0..CHUNKS {
+                sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+            }
+
+            sender.flush().expect("Error calling flush");
+        }).expect("Error spawning send thread");
+
+        // only a full batch is acknowledged, the delay is long enough the flush would notice it
+        let mut config = Configuration::default();
+        config.set_ack_every(5);
+        config.set_ack_delay(Duration::from_secs(2));
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        let start = Instant::now();
+
+        for i in 0..CHUNKS {
+            assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+            assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+        }
+
+        send_handle.join().expect("Send thread panicked");
+
+        assert!(start.elapsed() < Duration::from_secs(1), "Took {:?}", start.elapsed());
+
+        // the Connect's Acknowledge, then one SelectiveAck per batch of 5
+        assert_eq!(1 + 2, recv_stats.sent_count());
+    }
+
+    #[test]
+    fn checksum() {
+        let packet = data_message(7, &[0xAA; 100]);
+
+        assert!(verify_checksum(ChecksumKind::Crc32, &get_root_as_message(&packet)));
+
+        // flip every byte of the payload, and the seq_num, in turn
+        let payload_start = packet.windows(100).position(|w| w.iter().all(|&b| b == 0xAA)).expect("Payload not found");
+        let seq_num_start = packet.windows(8).position(|w| w == &7u64.to_le_bytes()).expect("seq_num not found");
+
+        for i in (payload_start..payload_start + 100).chain(seq_num_start..seq_num_start + 8) {
+            let mut corrupt = packet.clone();
+            corrupt[i] ^= 0x01;
+
+            assert!(!verify_checksum(ChecksumKind::Crc32, &get_root_as_message(&corrupt)), "Corrupt byte {} not caught", i);
+        }
+
+        // as does a message w/out a payload
+        let mut ack = construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 3).finished_data().to_vec();
+
+        assert!(verify_checksum(ChecksumKind::Crc32, &get_root_as_message(&ack)));
+
+        let last = ack.len() - 1;
+        ack[last] ^= 0x80;
+
+        assert!(!verify_checksum(ChecksumKind::Crc32, &get_root_as_message(&ack)));
+    }
+
+    #[test]
+    fn checksum_kinds() {
+        let kinds = [ChecksumKind::None, ChecksumKind::Crc32, ChecksumKind::Crc32c, ChecksumKind::Xxh3];
+
+        // the usual check values, in pieces
+        let check = |kind| {
+            let mut checksum = Checksum::new(kind);
+
+            checksum.update(b"1234");
+            checksum.update(b"56789");
+            checksum.finish()
+        };
+
+        assert_eq!(0, check(ChecksumKind::None));
+        assert_eq!(0xCBF4_3926, check(ChecksumKind::Crc32));
+        assert_eq!(0xE306_9283, check(ChecksumKind::Crc32c));
+        assert_eq!(xxh3_64(b"123456789") as u32, check(ChecksumKind::Xxh3));
+
+        for &kind in kinds.iter() {
+            let packet = construct_payload_message(kind, Type::Message, 0, 7, &[0xAA; 100]).finished_data().to_vec();
+
+            assert!(verify_checksum(kind, &get_root_as_message(&packet)), "{:?} didn't round-trip", kind);
+
+            // only none lets a corrupt payload through
+            let mut corrupt = packet.clone();
+            let payload_start = packet.windows(100).position(|w| w.iter().all(|&b| b == 0xAA)).expect("Payload not found");
+
+            corrupt[payload_start + 50] ^= 0x01;
+
+            assert_eq!(kind == ChecksumKind::None, verify_checksum(kind, &get_root_as_message(&corrupt)), "{:?}", kind);
+
+            // and none of the others take it
+            for &other in kinds.iter().filter(|&&other| other != kind) {
+                assert!(!verify_checksum(other, &get_root_as_message(&packet)), "{:?} took {:?}", other, kind);
+            }
+
+            // except a Connect, which is always checked the same way
+            let (connect, _) = construct_connect(None, 0, kind, None);
+
+            assert!(verify_checksum(kind, &get_root_as_message(connect.finished_data())), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn checksum_agreed() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        for &kind in [ChecksumKind::None, ChecksumKind::Crc32c, ChecksumKind::Xxh3].iter() {
+            let mock_socket = PacketDroppingSocket::new();
+            let duplex_socket = mock_socket.duplex();
+
+            let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+                let mut config = Configuration::default();
+                config.set_checksum(kind);
+
+                let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+                sender.write_all(&[0xAA; 3 * MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+                sender.finish().expect("Error calling finish");
+            }).expect("Error spawning send thread");
+
+            let mut config = Configuration::default();
+            config.set_checksum(kind);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut received = 0;
+
+            loop {
+                match recver.read(&mut buf).expect("Error calling read") {
+                    0 => break,
+                    amt => {
+                        assert!(buf[..amt].iter().all(|&b| b == 0xAA));
+                        received += amt;
+                    }
+                }
+            }
+
+            assert_eq!(3 * MAX_PAYLOAD_SIZE, received, "{:?}", kind);
+            send_handle.join().expect("Send thread panicked");
+        }
+    }
+
+    #[test]
+    fn checksum_mismatch_refused() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // the receiver turns away a sender that checksums some other way
+        let connect_handle = thread::Builder::new().name("connect".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_checksum(ChecksumKind::Crc32c);
+            config.set_connect_retries(1);
+            config.set_connect_timeout(Duration::from_millis(500));
+
+            assert!(Sender::<PacketDroppingSocket>::connect(mock_socket, &config).is_err());
+        }).expect("Error spawning connect thread");
+
+        let mut config = Configuration::default();
+        config.set_checksum(ChecksumKind::Xxh3);
+
+        let err = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).err().expect("Expected listen to fail");
+
+        assert_eq!(ErrorKind::ConnectionRefused, err.kind());
+        connect_handle.join().expect("Connect thread panicked");
+
+        // and the sender, a receiver that doesn't say, so only knows CRC-32
+        let mock_socket = PacketDroppingSocket::new();
+        let peer = mock_socket.duplex();
+
+        let peer_handle = thread::Builder::new().name("peer".into()).spawn(move || {
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+            let (_, addr) = peer.recv_from(&mut buf).expect("Error reading Connect");
+
+            peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 0).finished_data(), addr).expect("Error sending Acknowledge");
+        }).expect("Error spawning peer thread");
+
+        config.set_connect_retries(1);
+        config.set_connect_timeout(Duration::from_millis(500));
+
+        let err = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).err().expect("Connected to a receiver using CRC-32");
+
+        assert_eq!(ErrorKind::ConnectionRefused, err.kind());
+        assert!(err.to_string().contains("Xxh3"), "{}", err);
+        peer_handle.join().expect("Peer thread panicked");
+    }
+
+    /// A Connect w/version and magic set to whatever's given, rather than ours
+    fn versioned_connect(version: u16, magic: u32) -> Vec<u8> {
+        let mut fbb = FlatBufferBuilder::new_with_capacity(MAX_PACKET_SIZE);
+
+        let checksum = compute_checksum(ChecksumKind::Crc32, Type::Connect, 0, 0, None);
+        let msg = Message::create(&mut fbb, &MessageArgs { msg_type: Type::Connect, seq_num: 0, payload: None, checksum, mac: None, hash: None, ranges: None, stream_id: 0, version, magic });
+
+        fbb.finish(msg, None);
+
+        return fbb.finished_data().to_vec();
+    }
+
+    #[test]
+    fn protocol_version() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+
+        // only the Connect says, it's no bigger than it was for anything else
+        let connect = parse_message(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data()).map(|m| (m.version(), m.magic()));
+        let message = parse_message(construct_message(ChecksumKind::Crc32, Type::Finish, 0, 0).finished_data()).map(|m| (m.version(), m.magic()));
+
+        assert_eq!(Some((PROTOCOL_VERSION, PROTOCOL_MAGIC)), connect);
+        assert_eq!(Some((0, 0)), message);
+
+        // a version we don't speak is refused, w/out an Acknowledge
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(&versioned_connect(PROTOCOL_VERSION + 1, PROTOCOL_MAGIC), config.addr()).expect("Error sending Connect");
+
+        let err = Receiver::<PacketDroppingSocket>::listen(socket, &config).err().expect("Accepted another version");
+
+        assert_eq!(ErrorKind::Unsupported, err.kind());
+        assert!(err.to_string().contains(&format!("version {}", PROTOCOL_VERSION + 1)), "{}", err);
+        assert!(drain_acks(&peer).is_empty());
+
+        // as is one w/out the magic, whatever version it claims
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(&versioned_connect(PROTOCOL_VERSION, 0), config.addr()).expect("Error sending Connect");
+
+        let err = Receiver::<PacketDroppingSocket>::listen(socket, &config).err().expect("Accepted a Connect w/out the magic");
+
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert!(drain_acks(&peer).is_empty());
+    }
+
+    #[test]
+    fn error_variants() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        // a Connect from something that isn't qcp fails the handshake
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(&versioned_connect(PROTOCOL_VERSION, 0), config.addr()).expect("Error sending Connect");
+
+        match Receiver::<PacketDroppingSocket>::listen(socket, &config).err() {
+            Some(QcpError::Handshake(ref e)) if e.kind() == ErrorKind::InvalidData => (),
+            e => panic!("Expected a Handshake error, got {:?}", e)
+        }
+
+        // a v4 socket can't send to a v6 address at all, which is the socket failing, not the handshake
+        let mut config = Configuration::default();
+
+        config.set_addr("[::1]:1234".parse().unwrap());
+
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+
+        match Sender::<UdpSocket>::connect(socket, &config).err() {
+            Some(QcpError::Io(_)) => (),
+            e => panic!("Expected an Io error, got {:?}", e)
+        }
+    }
+
+    #[test]
+    fn sender_moves() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+        let moved :SocketAddr = "127.0.0.2:9090".parse().unwrap();
+        let data = (0..20 * MAX_PAYLOAD_SIZE).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let expected = data.clone();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_key(Some(b"secret".to_vec()));
+
+            let rebind = mock_socket.try_clone().expect("Error cloning socket");
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            sender.write_all(&data[..data.len() / 2]).expect("Error calling write_all");
+
+            // halfway through, the sender shows up from somewhere else, like after a NAT rebinding
+            rebind.rebind(moved);
+
+            sender.write_all(&data[data.len() / 2..]).expect("Error calling write_all");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let mut config = Configuration::default();
+        config.set_key(Some(b"secret".to_vec()));
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        let mut received = Vec::new();
+
+        loop {
+            match recver.read(&mut buf).expect("Error calling read") {
+                0 => break,
+                amt => received.extend_from_slice(&buf[..amt])
+            }
+        }
+
+        assert!(expected == received, "Received {} bytes, not what was sent", received.len());
+        assert_eq!(moved, *recver.remote_addr.lock().unwrap());
+        send_handle.join().expect("Send thread panicked");
+    }
+
+    #[test]
+    fn sender_cannot_move_unkeyed() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+
+        // w/out a key there's no telling the sender from anyone else, so it stays put
+        peer.rebind("127.0.0.2:9090".parse().unwrap());
+        peer.send_to(&data_message(0, &[0xAA; 16]), config.addr()).expect("Error sending data");
+
+        assert!(drain_acks(&peer).is_empty());
+        assert_eq!("127.0.0.1:8080".parse::<SocketAddr>().unwrap(), *recver.remote_addr.lock().unwrap());
+    }
+
+    #[test]
+    fn corrupt_packet_dropped() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let config = Configuration::default();
+        let peer = PacketDroppingSocket::new();
+        let socket = peer.duplex();
+
+        peer.send_to(construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0).finished_data(), config.addr()).expect("Error sending Connect");
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(socket, &config).expect("Couldn't create receiver");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+
+        let mut corrupt = data_message(0, &[0xAA; 16]);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0x01;
+
+        peer.send_to(&corrupt, config.addr()).expect("Error sending data");
+
+        // not ACKed, so it'll be retransmitted
+        assert!(drain_acks(&peer).is_empty());
+
+        peer.send_to(&data_message(0, &[0xBB; 16]), config.addr()).expect("Error sending data");
+
+        assert_eq!(vec![0], drain_acks(&peer));
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(16, recver.read(&mut buf).expect("Error calling read"));
+        assert!(buf[..16].iter().all(|&b| b == 0xBB));
+    }
+
+    #[test]
+    fn psk_accepted() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_key(Some(b"secret".to_vec()));
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            sender.write_all(&[0xAA; 100]).expect("Error calling write_all");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let mut config = Configuration::default();
+        config.set_key(Some(b"secret".to_vec()));
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        assert_eq!(100, recver.read(&mut buf).expect("Error calling read"));
+        assert_eq!(0, recver.read(&mut buf).expect("Error calling read"));
+
+        send_handle.join().expect("Send thread panicked");
+    }
+
+    #[test]
+    fn psk_rejected() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mut config = Configuration::default();
+        config.set_key(Some(b"secret".to_vec()));
+
+        // no MAC at all, and a MAC w/the wrong key
+        let connects = vec![construct_message(ChecksumKind::Crc32, Type::Connect, 0, 0), construct_connect(Some(b"guess"), 0, ChecksumKind::Crc32, None).0];
+
+        for connect in connects {
+            let peer = PacketDroppingSocket::new();
+            let socket = peer.duplex();
+
+            peer.send_to(connect.finished_data(), config.addr()).expect("Error sending Connect");
+
+            let err = Receiver::<PacketDroppingSocket>::listen(socket, &config).err().expect("Accepted an unauthenticated Connect");
+
+            assert_eq!(ErrorKind::PermissionDenied, err.kind());
+            assert!(drain_acks(&peer).is_empty());
+        }
+
+        // and the sender won't talk to a receiver that doesn't know the key, however many times it's asked
+        config.set_connect_timeout(Duration::from_millis(100));
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+
+            Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).is_ok()
+        }).expect("Error spawning recv thread");
+
+        let err = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).err().expect("Connected to an unauthenticated receiver");
+
+        assert_eq!(ErrorKind::PermissionDenied, err.kind());
+        assert!(recv_handle.join().expect("Recv thread panicked"));
+    }
+
+    #[test]
+    fn retransmit_backoff() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let peer = mock_socket.duplex();
+
+        let mut config = Configuration::default();
+        config.set_rto(Duration::from_millis(100));
+
+        // a receiver that accepts the Connect, and then never ACKs anything
+        let peer_handle = thread::Builder::new().name("peer".into()).spawn(move || {
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+            let mut arrivals = Vec::new();
+
+            let (_, addr) = peer.recv_from(&mut buf).expect("Error reading Connect");
+            peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 0).finished_data(), addr).expect("Error sending ACK");
+
+            while arrivals.len() < 3 {
+                let (amt, _) = peer.recv_from_timeout(&mut buf, Duration::from_secs(5)).expect("Error reading data");
+
+                assert_eq!(0, get_root_as_message(&buf[..amt]).seq_num());
+                arrivals.push(Instant::now());
+            }
+
+            arrivals
+        }).expect("Error spawning peer thread");
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        sender.write_all(&[0xAA; 16]).expect("Error calling write_all");
+
+        let arrivals = peer_handle.join().expect("Peer thread panicked");
+
+        let first = arrivals[1].duration_since(arrivals[0]);
+        let second = arrivals[2].duration_since(arrivals[1]);
+
+        info!("Retransmits after {:?} then {:?}", first, second);
+
+        // the scan runs every quarter RTO, so allow for that much slop
+        assert!(first >= Duration::from_millis(100) && first < Duration::from_millis(175), "{:?}", first);
+        assert!(second >= Duration::from_millis(200) && second < Duration::from_millis(275), "{:?}", second);
+    }
+
+    #[test]
+    fn duplicate_acks() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let peer = mock_socket.duplex();
+
+        // a receiver that ACKs everything twice, and throws in an ACK for something never sent
+        let peer_handle = thread::Builder::new().name("peer".into()).spawn(move || {
+            let mut buf = vec![0; MAX_PACKET_SIZE];
+
+            let (_, addr) = peer.recv_from(&mut buf).expect("Error reading Connect");
+            peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 0).finished_data(), addr).expect("Error sending ACK");
+
+            for _ in 0..2 {
+                let (amt, _) = peer.recv_from_timeout(&mut buf, Duration::from_secs(5)).expect("Error reading data");
+                let seq_num = get_root_as_message(&buf[..amt]).seq_num();
+
+                peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, seq_num).finished_data(), addr).expect("Error sending ACK");
+                peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, seq_num).finished_data(), addr).expect("Error sending ACK");
+                peer.send_to(construct_message(ChecksumKind::Crc32, Type::Acknowledge, 0, 5000).finished_data(), addr).expect("Error sending ACK");
+            }
+        }).expect("Error spawning peer thread");
+
+        let config = Configuration::default();
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        sender.write_all(&[0xAA; 16]).expect("Error calling write_all");
+        sender.flush().expect("Error calling flush");
+
+        // the ACK thread is still around to see this one acknowledged
+        sender.write_all(&[0xBB; 16]).expect("Error calling write_all");
+        sender.flush().expect("Error calling flush");
+
+        peer_handle.join().expect("Peer thread panicked");
+
+        assert!(!sender.window.is_closed());
+    }
+
+    #[test]
+    fn flush() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 10;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // drop the last chunk, so there's still something unacknowledged once write_all returns
+        let mock_socket = mock_socket.with_periodic_model(1000, CHUNKS as u64);
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for _ in 0..CHUNKS {
+                recver.read(&mut buf).expect("Error calling read");
+            }
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+        config.set_rto(Duration::from_millis(100));
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        for i in 0..CHUNKS {
+            sender.write_all(&vec![i as u8; MAX_PAYLOAD_SIZE]).expect("Error calling write_all");
+        }
+
+        let start = Instant::now();
+
+        assert!(sender.window.window().0 < sender.seq_num);
+
+        sender.flush().expect("Error calling flush");
+
+        // the last chunk was only acknowledged after its retransmit
+        assert!(start.elapsed() >= Duration::from_millis(50), "{:?}", start.elapsed());
+        assert_eq!((CHUNKS as u64, CHUNKS as u64 + config.window_size() as u64), sender.window.window());
+        assert_eq!(None, sender.window.find_first(|_| true));
+
+        recv_handle.join().expect("Recv thread panicked");
+    }
+
+    /// Makes 300 writes of 10 bytes each, returning how many packets they took and what the receiver read
+    fn small_writes(coalesce: bool) -> (u64, Vec<u8>) {
+        const WRITES :usize = 300;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut received = Vec::new();
+
+            loop {
+                let amt = recver.read(&mut buf).expect("Error calling read");
+
+                if amt == 0 {
+                    break;
+                }
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            received
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+        config.set_coalesce(coalesce);
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+        for i in 0..WRITES {
+            sender.write_all(&[i as u8; 10]).expect("Error calling write_all");
+        }
+
+        sender.flush().expect("Error calling flush");
+
+        let packets = sender.seq_num;
+
+        sender.finish().expect("Error calling finish");
+
+        return (packets, recv_handle.join().expect("Recv thread panicked"));
+    }
+
+    #[test]
+    fn reordering_stats() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // held up long enough for the packets behind to queue, so they can jump ahead
+        let mock_socket = mock_socket.with_delay(Duration::from_millis(20)).with_reordering(0.5);
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut received = Vec::new();
+
+            loop {
+                let amt = recver.read(&mut buf).expect("Error calling read");
+
+                if amt == 0 {
+                    break;
+                }
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            (received, recver.stats())
+        }).expect("Error spawning recv thread");
+
+        let config = Configuration::default();
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+        let data = (0..100 * MAX_PAYLOAD_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+
+        sender.write_all(&data).expect("Error calling write_all");
+        sender.finish().expect("Error calling finish");
+
+        let (received, stats) = recv_handle.join().expect("Recv thread panicked");
+
+        assert!(data == received);
+        assert_eq!(data.len() as u64, stats.bytes);
+        assert!(stats.out_of_order > 0, "{}", stats);
+        assert!(stats.max_reorder > 0, "{}", stats);
+    }
+
+    /// The fields of a flat JSON object of numbers, as from ProgressReport::to_json, or None if it isn't one
+    fn parse_json_numbers(line: &str) -> Option<Vec<(String, f64)>> {
+        let inner = line.trim().strip_prefix('{')?.strip_suffix('}')?;
+
+        return inner.split(',').map(|field| {
+            let mut parts = field.splitn(2, ':');
+            let key = parts.next()?.trim().strip_prefix('"')?.strip_suffix('"')?;
+            let value = parts.next()?.trim().parse::<f64>().ok()?;
+
+            Some((key.to_string(), value))
+        }).collect();
+    }
+
+    #[test]
+    fn progress_json() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let mock_socket = PacketDroppingSocket::new().with_delay(Duration::from_millis(5));
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            while recver.read(&mut buf).expect("Error calling read") != 0 {}
+        }).expect("Error spawning recv thread");
+
+        let config = Configuration::default();
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+        let data = vec![0xAB; 200 * MAX_PAYLOAD_SIZE];
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let report_lines = lines.clone();
+
+        sender.set_total_bytes(data.len() as u64);
+        sender.set_report(Duration::from_millis(10), Box::new(move |report| report_lines.lock().unwrap().push(report.to_json())));
+
+        sender.write_all(&data).expect("Error calling write_all");
+        sender.flush().expect("Error calling flush");
+        sender.finish().expect("Error calling finish");
+        recv_handle.join().expect("Recv thread panicked");
+
+        let lines = lines.lock().unwrap();
+        let reports = lines.iter().map(|line| parse_json_numbers(line).unwrap_or_else(|| panic!("Not JSON: {}", line))).collect::<Vec<_>>();
+
+        // more than the last, which always comes
+        assert!(reports.len() > 1, "{:?}", *lines);
+
+        for report in &reports {
+            let keys = report.iter().map(|&(ref key, _)| key.as_str()).collect::<Vec<_>>();
+
+            assert_eq!(vec!["acked", "total", "rate_bps", "rtt_ms"], keys);
+            assert_eq!(data.len() as f64, report[1].1);
+            assert!(report[3].1 > 0.0, "{:?}", report);
+        }
+
+        assert!(reports.windows(2).all(|w| w[0][0].1 <= w[1][0].1));
+        assert_eq!(data.len() as f64, reports.last().unwrap()[0].1);
+    }
+
+    /// Sends the same bytes w/payload_size, returning the number of packets it took and what was received
+    fn payload_sized(payload_size: Option<usize>) -> (u64, Vec<u8>) {
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let mut received = Vec::new();
+
+            loop {
+                let amt = recver.read(&mut buf).expect("Error calling read");
+
+                if amt == 0 {
+                    break;
+                }
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            received
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+        config.set_payload_size(payload_size);
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+        let data = (0..20_000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        sender.write_all(&data).expect("Error calling write_all");
+        sender.flush().expect("Error calling flush");
+
+        let packets = sender.seq_num;
+
+        sender.finish().expect("Error calling finish");
+
+        return (packets, recv_handle.join().expect("Recv thread panicked"));
+    }
+
+    #[test]
+    fn payload_size() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let expected = (0..20_000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        let (full, received) = payload_sized(None);
+
+        assert_eq!(((expected.len() + MAX_PAYLOAD_SIZE - 1) / MAX_PAYLOAD_SIZE) as u64, full);
+        assert!(expected == received);
+
+        // more, smaller packets, for the same bytes
+        let (smaller, received) = payload_sized(Some(500));
+
+        assert_eq!(40, smaller);
+        assert!(smaller > full);
+        assert!(expected == received);
+    }
+
+    #[test]
+    fn coalesce() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        let expected = (0..300).flat_map(|i| vec![i as u8; 10]).collect::<Vec<u8>>();
+
+        // as few packets as the bytes fit in
+        let (packets, received) = small_writes(true);
+
+        assert_eq!(((expected.len() + MAX_PAYLOAD_SIZE - 1) / MAX_PAYLOAD_SIZE) as u64, packets);
+        assert!(expected == received);
+
+        // or one for every write
+        let (packets, received) = small_writes(false);
+
+        assert_eq!(300, packets);
+        assert!(expected == received);
+    }
+
+    #[test]
+    fn progress() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 20;
+        const TOTAL :u64 = (CHUNKS * MAX_PAYLOAD_SIZE - 10) as u64;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // lose some along the way, so ACKs come back out of order
+        let mock_socket = mock_socket.with_periodic_model(7, 3);
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for _ in 0..CHUNKS {
+                recver.read(&mut buf).expect("Error calling read");
+            }
+        }).expect("Error spawning recv thread");
+
+        let mut config = Configuration::default();
+        config.set_rto(Duration::from_millis(100));
+
+        let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let calls_clone = calls.clone();
+
+        sender.set_total_bytes(TOTAL);
+        sender.set_progress(Box::new(move |acked, total| calls_clone.lock().unwrap().push((acked, total))));
+
+        sender.write_all(&vec![0xAA; TOTAL as usize]).expect("Error calling write_all");
+        sender.flush().expect("Error calling flush");
+
+        recv_handle.join().expect("Recv thread panicked");
+
+        let calls = calls.lock().unwrap();
+
+        assert_eq!(CHUNKS, calls.len());
+        assert!(calls.windows(2).all(|w| w[0].0 < w[1].0), "Not increasing: {:?}", *calls);
+        assert!(calls.iter().all(|&(_, total)| total == TOTAL));
+        assert_eq!(TOTAL, calls.last().unwrap().0);
+    }
+
+    #[test]
+    fn write_all_blocks_on_full_window() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 20;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+        let stats_socket = mock_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_window_size(4);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            // one write, several windows worth
+            let buf = (0..CHUNKS).flat_map(|i| vec![i as u8; MAX_PAYLOAD_SIZE]).collect::<Vec<u8>>();
+            let start = Instant::now();
+
+            sender.write_all(&buf).expect("Error calling write_all");
+
+            start.elapsed()
+        }).expect("Error spawning send thread");
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_window_size(4);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for i in 0..CHUNKS {
+                // a slow reader
+                thread::sleep(Duration::from_millis(20));
+
+                assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+                assert!(buf.iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+            }
+        }).expect("Error spawning recv thread");
+
+        let elapsed = send_handle.join().expect("Send thread panicked");
+        recv_handle.join().expect("Recv thread panicked");
+
+        // the sender can only get both windows (and the packet in hand) ahead of the reader
+        // so the last chunk can't go out until the reader has read 11 of them
+        assert!(elapsed >= Duration::from_millis(200), "write_all didn't block: {:?}", elapsed);
+
+        // backpressure, not loss: nothing was retransmitted
+        assert_eq!(1 + CHUNKS as u64, stats_socket.sent_count());
+    }
+
+    #[test]
+    fn nonblocking_write_all() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 20;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+        let stats_socket = mock_socket.try_clone().expect("Error cloning socket");
+
+        let buf = (0..CHUNKS).flat_map(|i| vec![i as u8; MAX_PAYLOAD_SIZE]).collect::<Vec<u8>>();
+        let send_buf = buf.clone();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_window_size(4);
+            config.set_nonblocking(true);
+            config.set_verify(true);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+            let mut would_block = 0;
+
+            // the same buf each time, until it's all gone
+            loop {
+                let start = Instant::now();
+
+                match sender.write_all(&send_buf) {
+                    Ok(()) => break,
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                        assert!(start.elapsed() < Duration::from_millis(100), "write_all blocked: {:?}", start.elapsed());
+                        would_block += 1;
+                        thread::sleep(Duration::from_millis(1));
+                    },
+                    Err(e) => panic!("Error calling write_all: {}", e)
+                }
+            }
+
+            sender.finish().expect("Error calling finish");
+
+            would_block
+        }).expect("Error spawning send thread");
+
+        let mut config = Configuration::default();
+        config.set_verify(true);
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut read_buf = vec![0; MAX_PAYLOAD_SIZE];
+        let mut received = Vec::new();
+
+        // the hash checks out too, so nothing was hashed twice
+        loop {
+            let amt = recver.read(&mut read_buf).expect("Error calling read");
+
+            if amt == 0 {
+                break;
+            }
+
+            received.extend_from_slice(&read_buf[..amt]);
+        }
+
+        let would_block = send_handle.join().expect("Send thread panicked");
+
+        assert!(would_block > 0, "write_all never returned WouldBlock");
+        assert!(buf == received);
+
+        // nothing was sent twice, the Connect, the chunks, and the Finish
+        assert_eq!(2 + CHUNKS as u64, stats_socket.sent_count());
+    }
+
+    #[test]
+    fn write_all_waits_idle() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const WINDOW_SIZE :usize = 4;
+        const CHUNKS :usize = WINDOW_SIZE * 5;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_window_size(WINDOW_SIZE);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+            let buf = vec![0xAA; CHUNKS * MAX_PAYLOAD_SIZE];
+
+            let start = Instant::now();
+            let cpu = thread_cpu_time();
+
+            sender.write_all(&buf).expect("Error calling write_all");
+
+            (start.elapsed(), thread_cpu_time() - cpu)
+        }).expect("Error spawning send thread");
+
+        let mut config = Configuration::default();
+        config.set_window_size(WINDOW_SIZE);
+
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+        for _ in 0..CHUNKS {
+            // a slow reader
+            thread::sleep(Duration::from_millis(25));
+
+            assert_eq!(MAX_PAYLOAD_SIZE, recver.read(&mut buf).expect("Error calling read"));
+        }
+
+        let (elapsed, cpu) = send_handle.join().expect("Send thread panicked");
+
+        // blocked most of the time, but asleep, not spinning
+        assert!(elapsed >= Duration::from_millis(250), "write_all didn't block: {:?}", elapsed);
+        assert!(cpu < elapsed / 10, "Used {:?} of CPU in {:?}", cpu, elapsed);
+    }
+
+    #[test]
+    fn unidirectional_recovery() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const BLOCK_SIZE :usize = 4;
+        const BLOCKS :usize = 8;
+        const PARTIAL :usize = 2;     // packets in the last block
+        const CHUNK_SIZE :usize = MAX_PAYLOAD_SIZE - PARITY_HEADER_SIZE;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // after the Connects, each block is its data packets then its parity: drop the 2nd data packet of every block
+        let offset = (UNIDIRECTIONAL_CONNECTS + 1) as u64;
+        let mock_socket = mock_socket.with_periodic_model(BLOCK_SIZE as u64 + 1, offset % (BLOCK_SIZE as u64 + 1));
+        let send_stats = mock_socket.try_clone().expect("Error cloning socket");
+        let recv_stats = duplex_socket.try_clone().expect("Error cloning socket");
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_unidirectional(true);
+            config.set_fec_block_size(BLOCK_SIZE);
+
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            // and a last block that's only half full
+            for i in 0..BLOCK_SIZE*BLOCKS + PARTIAL {
+                sender.write_all(&vec![i as u8; CHUNK_SIZE]).expect("Error calling write_all");
+            }
+
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            let mut config = Configuration::default();
+            config.set_unidirectional(true);
+            config.set_fec_block_size(BLOCK_SIZE);
+
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            for i in 0..BLOCK_SIZE*BLOCKS + PARTIAL {
+                assert_eq!(CHUNK_SIZE, recver.read(&mut buf).expect("Error calling read"));
+                assert!(buf[..CHUNK_SIZE].iter().all(|&b| b == i as u8), "Wrong payload for packet {}", i);
+            }
+
+            assert_eq!(0, recver.read(&mut buf).expect("Error calling read"));
+        }).expect("Error spawning recv thread");
+
+        send_handle.join().expect("Send thread panicked");
+        recv_handle.join().expect("Recv thread panicked");
+
+        // one packet lost from every block, the partial one too, all rebuilt from parity w/out a single ACK or retransmit
+        assert_eq!(BLOCKS as u64 + 1, send_stats.dropped_count());
+        assert_eq!((UNIDIRECTIONAL_CONNECTS + BLOCKS * (BLOCK_SIZE + 1) + PARTIAL + 1 + UNIDIRECTIONAL_CONNECTS) as u64, send_stats.sent_count());
+        assert_eq!(0, recv_stats.sent_count());
+    }
+
+    #[test]
+    fn two_streams() {
+        let _ = TermLogger::init(LevelFilter::Debug, Config::default());
+
+        const CHUNKS :usize = 100;
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        // lose some of the data, so both streams are retransmitting over the socket at once
+        let mock_socket = mock_socket.with_bernoulli_model(0.05);
+
+        // asked for 3, but the receiver only agrees to 2
+        let recv_handle = thread::Builder::new().name("recv".into()).spawn(move || {
+            Receiver::<PacketDroppingSocket>::listen_streams(duplex_socket, &Configuration::default(), 2).expect("Couldn't create receivers")
+        }).expect("Error spawning recv thread");
+
+        let senders = Sender::<PacketDroppingSocket>::connect_streams(mock_socket, &Configuration::default(), 3).expect("Couldn't call connect_streams");
+        let recvers = recv_handle.join().expect("Recv thread panicked");
+
+        assert_eq!(2, senders.len());
+        assert_eq!(2, recvers.len());
+
+        // each stream's chunks are their own, so anything delivered to the wrong stream, or out of order, shows
+        let chunk = |stream: usize, i: usize| vec![(stream * CHUNKS + i) as u8; MAX_PAYLOAD_SIZE];
+
+        let send_handles = senders.into_iter().enumerate().map(|(stream, mut sender)| thread::spawn(move || {
+            for i in 0..CHUNKS {
+                sender.write_all(&chunk(stream, i)).expect("Error calling write_all");
+            }
+
+            sender.finish().expect("Error calling finish");
+        })).collect::<Vec<_>>();
+
+        let recv_handles = recvers.into_iter().enumerate().map(|(stream, mut recver)| thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+
+            // a chunk is more than one packet on a stream that carries its id
+            loop {
+                let amt = recver.read(&mut buf).expect("Error calling read");
+
+                if amt == 0 {
+                    break;
+                }
+
+                received.extend_from_slice(&buf[..amt]);
+            }
+
+            assert_eq!(CHUNKS * MAX_PAYLOAD_SIZE, received.len());
+
+            for (i, data) in received.chunks(MAX_PAYLOAD_SIZE).enumerate() {
+                assert!(data == &chunk(stream, i)[..], "Wrong payload for chunk {} of stream {}", i, stream);
+            }
+        })).collect::<Vec<_>>();
+
+        for handle in send_handles {
+            handle.join().expect("Send thread panicked");
+        }
+
+        for handle in recv_handles {
+            handle.join().expect("Recv thread panicked");
+        }
     }
 }
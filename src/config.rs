@@ -1,18 +1,150 @@
-use clap::{Arg, ArgGroup, App};
+use clap::{self, Arg, ArgGroup, App};
+use log::LevelFilter;
 
 //use std::io::{Error as IOError, ErrorKind};
+use std::io;
 use std::fs::File;
-use std::path::PathBuf;
-use std::net::{SocketAddr};
+use std::path::{Path, PathBuf};
+use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs};
 use std::error::Error;
 use std::default::Default;
+use std::time::Duration;
+use std::env;
+use std::ffi::OsString;
 
+use bbr_transport::{MAX_STREAMS, MAX_PAYLOAD_SIZE, MAX_WINDOW_SIZE};
+use error::QcpError;
+
+
+/// How the bytes get to the other end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransportKind {
+    /// BBR over UDP
+    Bbr,
+    /// A plain TCP stream
+    Tcp,
+}
+
+/// How the sender decides how much can be in flight, over UDP
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CongestionKind {
+    /// From the measured bandwidth-delay product
+    Bbr,
+    /// Backs off on loss, as TCP Reno
+    Reno,
+    /// Backs off on loss, as TCP CUBIC
+    Cubic,
+}
+
+/// What checksums each packet over UDP, both ends have to use the same one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumKind {
+    /// Nothing, for links that already check what they carry
+    None,
+    /// CRC-32, as zlib and Ethernet
+    Crc32,
+    /// CRC-32C (Castagnoli), in hardware w/SSE4.2
+    Crc32c,
+    /// The low 32 bits of XXH3
+    Xxh3,
+}
+
+/// How the sender shows its progress, on stderr
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressFormat {
+    /// The percentage acknowledged, for a person
+    Percent,
+    /// A line of JSON every --progress-interval-ms, for another program
+    Json,
+}
+
+/// A block of addresses, as given to --allow: an address, and how many of its leading bits another has to share w/it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u32
+}
+
+impl Cidr {
+    /// Parses ADDR/LEN, or a lone address, which is a block of only it
+    pub fn parse(s: &str) -> Result<Cidr, Box<Error>> {
+        let (addr, prefix_len) = match s.find('/') {
+            Some(slash) => (s[..slash].parse::<IpAddr>()?, Some(s[slash+1..].parse::<u32>()?)),
+            None => (s.parse::<IpAddr>()?, None)
+        };
+
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+
+        if prefix_len > max_len {
+            return Err(From::from(format!("The prefix of {} is longer than its address", s)));
+        }
+
+        return Ok(Cidr { addr, prefix_len });
+    }
+
+    /// Whether ip is in the block
+    /// An IPv4 address mapped into IPv6, as a dual-stack socket sees it, is taken as the IPv4 one
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip
+        };
+
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => return (u32::from(net) ^ u32::from(ip)).checked_shr(32 - self.prefix_len).unwrap_or(0) == 0,
+            (IpAddr::V6(net), IpAddr::V6(ip)) => return (u128::from(net) ^ u128::from(ip)).checked_shr(128 - self.prefix_len).unwrap_or(0) == 0,
+            _ => return false
+        }
+    }
+}
 
 pub struct Configuration {
     sender: bool,
     addr: SocketAddr,
+    addrs: Vec<SocketAddr>,     // everything the host resolved to, addr first; the sender tries each in turn
+    local_addr: SocketAddr,     // what the sender binds to
+    dual_stack: bool,           // the receiver listens on IPv4 and IPv6 at once, w/one socket
+    unix_socket: Option<PathBuf>,   // the Unix domain socket the receiver binds to, and the sender sends to, in place of UDP
+    transport: TransportKind,
+    congestion: CongestionKind,
+    checksum: ChecksumKind,
     window_size: usize,
-    file: Option<PathBuf>,
+    max_window: Option<usize>,  // the most the sender's window grows to, tuned to the bandwidth-delay product; fixed at window_size if not given
+    payload_size: Option<usize>,    // the most the sender puts in a packet, if less than the path allows
+    recv_buffer_bytes: usize,   // payload bytes the receiver holds for the reader before it stops ACKing
+    so_rcvbuf: Option<usize>,   // the kernel receive buffer to ask for, if not the system's default
+    so_sndbuf: Option<usize>,   // the kernel send buffer to ask for, if not the system's default
+    max_rate: Option<u64>,      // the most bits per second the sender sends, if capped
+    nonblocking: bool,          // the sender's write_all returns WouldBlock instead of waiting for room
+    coalesce: bool,             // the sender holds small writes until there's a packet's worth, or it's flushed; off unless asked for
+    streams: usize,             // streams sharing the socket; the most the receiver agrees to
+    pull: Option<PathBuf>,      // where the sender puts what the receiver sends back, if it's sending back
+    push: Vec<PathBuf>,         // the files the receiver sends back
+    unidirectional: bool,
+    preserve: bool,
+    block_size: Option<u32>,    // send each file as a delta against the receiver's copy, in blocks this size
+    verify_only: bool,          // only compare the block hashes of each file w/the receiver's copy, sending and writing nothing
+    verify: bool,       // check a hash of everything sent once it's all received
+    fec_block_size: usize,
+    connect_only: bool,
+    rto: Duration,
+    max_retransmits: Option<u32>,   // timeouts in a row for one packet before the sender gives up on the receiver; retries forever if not given
+    ack_every: usize,           // packets the receiver takes before acknowledging them, w/out waiting for ack_delay
+    ack_delay: Duration,        // the longest a received packet waits to be acknowledged
+    connect_retries: usize,
+    connect_timeout: Duration,
+    idle_timeout: Duration,     // how long either end goes w/out hearing from the other before giving up on it
+    key: Option<Vec<u8>>,
+    encrypt: bool,
+    root: Option<PathBuf>,
+    output_dir: Option<PathBuf>,    // where the receiver puts the files, under the sender's names
+    append: bool,                   // the receiver adds to the end of what's there, instead of replacing it
+    allow: Vec<Cidr>,               // the only addresses the receiver takes a Connect from, any when empty
+    progress_format: ProgressFormat,
+    progress_interval: Duration,    // the least time between JSON progress lines
+    verbosity: u64,     // the number of times -v was given
+    files: Vec<PathBuf>,
 }
 
 impl Default for Configuration {
@@ -20,15 +152,130 @@ impl Default for Configuration {
         Configuration {
             sender: false,
             addr: "127.0.0.1:1234".parse().unwrap(),
+            addrs: vec!["127.0.0.1:1234".parse().unwrap()],
+            local_addr: "0.0.0.0:0".parse().unwrap(),
+            dual_stack: false,
+            unix_socket: None,
+            transport: TransportKind::Bbr,
+            congestion: CongestionKind::Bbr,
+            checksum: ChecksumKind::Crc32,
             window_size: 1024,
-            file: Some(PathBuf::from("/tmp/test"))
+            max_window: None,
+            payload_size: None,
+            recv_buffer_bytes: 4 * 1024 * 1024,
+            so_rcvbuf: None,
+            so_sndbuf: None,
+            max_rate: None,
+            nonblocking: false,
+            coalesce: false,
+            streams: 1,
+            pull: None,
+            push: Vec::new(),
+            unidirectional: false,
+            preserve: false,
+            block_size: None,
+            verify_only: false,
+            verify: false,
+            fec_block_size: 8,
+            connect_only: false,
+            rto: Duration::from_millis(1000),
+            max_retransmits: None,
+            ack_every: 32,
+            ack_delay: Duration::from_millis(5),
+            connect_retries: 3,
+            connect_timeout: Duration::from_millis(3000),
+            idle_timeout: Duration::from_secs(30),
+            key: None,
+            encrypt: false,
+            root: None,
+            output_dir: None,
+            append: false,
+            allow: Vec::new(),
+            progress_format: ProgressFormat::Percent,
+            progress_interval: Duration::from_millis(500),
+            verbosity: 0,
+            files: vec![PathBuf::from("/tmp/test")]
+        }
+    }
+}
+
+
+/// The file name that means stdin when sending, or stdout when receiving
+const STDIO :&str = "-";
+
+/// Parses a host and port into an address
+/// The host can be an IP address, a bracketed IPv6 address ([::1]), or either w/a port ([::1]:1234) which overrides port
+/// When ipv6 is set, the IPv4 wildcard (the default host) becomes the IPv6 one, and any other IPv4 address is an error
+fn parse_addr(host: &str, port: &str, ipv6: bool) -> Result<SocketAddr, Box<Error>> {
+    let mut addr = match host.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(_) => {
+            let ip = if host.starts_with('[') && host.ends_with(']') { &host[1..host.len()-1] } else { host };
+
+            SocketAddr::new(ip.parse()?, port.parse()?)
+        }
+    };
+
+    if ipv6 && addr.is_ipv4() {
+        if !addr.ip().is_unspecified() {
+            return Err(From::from(format!("--ipv6 given, but {} is an IPv4 address", addr.ip())));
         }
+
+        addr.set_ip(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
     }
+
+    return Ok(addr);
+}
+
+/// Resolves a host and port into addresses, all of the same family so one socket can try each of them
+/// IP addresses are parsed as they are by parse_addr, anything else is looked up
+/// When ipv6 is set only IPv6 addresses are kept, otherwise IPv4 ones are preferred
+fn resolve_addrs(host: &str, port: &str, ipv6: bool) -> Result<Vec<SocketAddr>, Box<Error>> {
+    return resolve_addrs_with(host, port, ipv6, |host, port| (host, port).to_socket_addrs().map(|addrs| addrs.collect()));
 }
 
+/// What resolve_addrs does, w/lookup doing the resolving, so it can be tested w/out DNS
+fn resolve_addrs_with<F>(host: &str, port: &str, ipv6: bool, lookup: F) -> Result<Vec<SocketAddr>, Box<Error>>
+    where F: FnOnce(&str, u16) -> io::Result<Vec<SocketAddr>> {
+    // no hostname is all digits and dots, and only IPv6 addresses have colons
+    if host.contains(':') || host.starts_with('[') || host.chars().all(|c| c.is_digit(10) || c == '.') {
+        return Ok(vec![parse_addr(host, port, ipv6)?]);
+    }
+
+    let addrs = match lookup(host, port.parse::<u16>()?) {
+        Ok(addrs) => addrs,
+        Err(e) => return Err(From::from(format!("Couldn't resolve {}: {}", host, e)))
+    };
+
+    let want_ipv6 = ipv6 || !addrs.iter().any(|a| a.is_ipv4());
+    let addrs = addrs.into_iter().filter(|a| a.is_ipv6() == want_ipv6).collect::<Vec<_>>();
+
+    if addrs.is_empty() {
+        return Err(From::from(format!("{} has no {} address", host, if ipv6 { "IPv6" } else { "IP" })));
+    }
+
+    debug!("RESOLVED {} TO {:?}", host, addrs);
+
+    return Ok(addrs);
+}
 
 impl Configuration {
-    pub fn new() -> Result<Configuration, Box<Error>> {
+    /// Parses the program's command line; a mistyped flag, --help, or --version is printed by clap, which exits
+    pub fn new() -> Result<Configuration, QcpError> {
+        return Configuration::parse_args(env::args_os()).map_err(|e| match e.downcast::<clap::Error>() {
+            Ok(e) => e.exit(),
+            Err(e) => QcpError::Config(e.to_string())
+        });
+    }
+
+    /// Parses the given command line, the first being the program name
+    /// Everything's returned as an error, even what clap would exit on, like a flag it doesn't know
+    pub fn from_args<I, T>(args: I) -> Result<Configuration, QcpError> where I: IntoIterator<Item = T>, T: Into<OsString> + Clone {
+        return Configuration::parse_args(args).map_err(|e| QcpError::Config(e.to_string()));
+    }
+
+    /// from_args, w/the error from whatever failed to parse or check out
+    fn parse_args<I, T>(args: I) -> Result<Configuration, Box<Error>> where I: IntoIterator<Item = T>, T: Into<OsString> + Clone {
         let matches = App::new("ets")
             .version("1.0")
             .author("William Speirs <bill.speirs@gmail.com>")
@@ -53,47 +300,673 @@ impl Configuration {
                 .value_name("PORT")
                 .default_value("1234")
                 .help("Port to connect to when sending, or listen on when receiving"))
+            .arg(Arg::with_name("ipv6")
+                .short("6")
+                .long("ipv6")
+                .help("Use IPv6; when receiving, listen on all IPv6 addresses unless --host is given"))
+            .arg(Arg::with_name("dual-stack")
+                .long("dual-stack")
+                .conflicts_with("send")
+                .help("Listen on all IPv4 and IPv6 addresses at once, w/one IPv6 socket that takes IPv4 too; not every system allows it"))
+            .arg(Arg::with_name("local-addr")
+                .long("local-addr")
+                .takes_value(true)
+                .value_name("HOST")
+                .requires("send")
+                .help("The address to send from; defaults to the wildcard of the same family as --host"))
+            .arg(Arg::with_name("local-port")
+                .long("local-port")
+                .takes_value(true)
+                .value_name("PORT")
+                .requires("send")
+                .help("The port to send from, for firewalls that want a fixed source port; defaults to any free port"))
+            .arg(Arg::with_name("unix-socket")
+                .long("unix-socket")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Send over a Unix domain socket at this path in place of UDP, for a receiver on the same host; the host and port are ignored"))
+            .arg(Arg::with_name("transport")
+                .long("transport")
+                .takes_value(true)
+                .possible_values(&["bbr", "tcp"])
+                .default_value("bbr")
+                .help("BBR over UDP, or plain TCP for when UDP is blocked"))
+            .arg(Arg::with_name("cc")
+                .long("cc")
+                .takes_value(true)
+                .possible_values(&["bbr", "reno", "cubic"])
+                .default_value("bbr")
+                .help("How the sender decides how much can be in flight over UDP"))
+            .arg(Arg::with_name("checksum")
+                .long("checksum")
+                .takes_value(true)
+                .possible_values(&["none", "crc32", "crc32c", "xxh3"])
+                .default_value("crc32")
+                .help("What checksums each packet over UDP, both ends must use the same one"))
             .arg(Arg::with_name("window-size")
                 .short("w")
                 .long("window-size")
                 .takes_value(true)
                 .default_value("1024")
                 .help("The size of the sliding window"))
+            .arg(Arg::with_name("max-window")
+                .long("max-window")
+                .takes_value(true)
+                .value_name("PACKETS")
+                .requires("send")
+                .conflicts_with("unidirectional")
+                .help("Grow or shrink the sliding window toward the bandwidth-delay product, up to this many packets; the receiver's --window-size has to be as large for it to get there"))
+            .arg(Arg::with_name("payload-size")
+                .long("payload-size")
+                .takes_value(true)
+                .value_name("BYTES")
+                .requires("send")
+                .help("Put at most this many bytes of data in each packet, even when the path fits more; for studying packetization"))
+            .arg(Arg::with_name("recv-buffer-bytes")
+                .long("recv-buffer-bytes")
+                .takes_value(true)
+                .default_value("4194304")
+                .help("When receiving, the most bytes to hold for a slow reader; past this nothing new is ACKed, so the sender slows down"))
+            .arg(Arg::with_name("so-rcvbuf")
+                .long("so-rcvbuf")
+                .takes_value(true)
+                .value_name("BYTES")
+                .help("The kernel receive buffer to ask for, so a fast link's packets aren't dropped before they're read; the kernel may clamp it"))
+            .arg(Arg::with_name("so-sndbuf")
+                .long("so-sndbuf")
+                .takes_value(true)
+                .value_name("BYTES")
+                .help("The kernel send buffer to ask for; the kernel may clamp it"))
+            .arg(Arg::with_name("max-rate")
+                .long("max-rate")
+                .takes_value(true)
+                .value_name("BITS")
+                .requires("send")
+                .help("The most bits per second to send, so other traffic on the link isn't starved"))
+            .arg(Arg::with_name("nonblocking")
+                .long("nonblocking")
+                .requires("send")
+                .help("Don't wait for room in the window when writing, try again once there is"))
+            .arg(Arg::with_name("coalesce")
+                .long("coalesce")
+                .requires("send")
+                .help("Hold writes of less than a packet until there's more, or the sender's flushed, instead of sending them right away"))
+            .arg(Arg::with_name("streams")
+                .long("streams")
+                .takes_value(true)
+                .default_value("1")
+                .help("Send the files over this many streams at once, each file whole on one; when receiving, the most to accept"))
+            .arg(Arg::with_name("pull")
+                .long("pull")
+                .takes_value(true)
+                .value_name("PATH")
+                .requires("send")
+                .conflicts_with("unidirectional")
+                .help("While sending, receive what the receiver sends back w/--push, into PATH"))
+            .arg(Arg::with_name("push")
+                .long("push")
+                .takes_value(true)
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["send", "unidirectional"])
+                .help("While receiving, send FILE back to the sender, which has to pass --pull; can be given more than once"))
+            .arg(Arg::with_name("rto-ms")
+                .long("rto-ms")
+                .takes_value(true)
+                .default_value("1000")
+                .help("The minimum time to wait for an ACK before retransmitting, in ms; doubles on each retransmit"))
+            .arg(Arg::with_name("max-retransmits")
+                .long("max-retransmits")
+                .takes_value(true)
+                .requires("send")
+                .help("Abort the transfer when a packet has timed out and been retransmitted this many times; retransmits forever if not given"))
+            .arg(Arg::with_name("ack-every")
+                .long("ack-every")
+                .takes_value(true)
+                .default_value("32")
+                .help("When receiving, acknowledge after this many packets at most; fewer ACKs, but the sender hears later"))
+            .arg(Arg::with_name("ack-delay-ms")
+                .long("ack-delay-ms")
+                .takes_value(true)
+                .default_value("5")
+                .help("When receiving, the longest to hold a packet's ACK waiting for more to send w/it, in ms"))
+            .arg(Arg::with_name("connect-retries")
+                .long("connect-retries")
+                .takes_value(true)
+                .default_value("3")
+                .help("The number of times to send the Connect before giving up on the receiver"))
+            .arg(Arg::with_name("connect-timeout-ms")
+                .long("connect-timeout-ms")
+                .takes_value(true)
+                .default_value("3000")
+                .help("How long to wait for the Connect to be acknowledged before sending it again, in ms"))
+            .arg(Arg::with_name("idle-timeout")
+                .long("idle-timeout")
+                .takes_value(true)
+                .default_value("30")
+                .help("Give up on the other end after hearing nothing from it for this long, in seconds; a quiet receiver is pinged, and a sender pings when it has nothing to send"))
+            .arg(Arg::with_name("unidirectional")
+                .long("unidirectional")
+                .help("No return path for ACKs; recover lost packets w/parity instead of retransmitting"))
+            .arg(Arg::with_name("preserve")
+                .long("preserve")
+                .requires("send")
+                .conflicts_with("unidirectional")
+                .help("Give the received files the same permissions and modification times"))
+            .arg(Arg::with_name("block-size")
+                .long("block-size")
+                .takes_value(true)
+                .value_name("BYTES")
+                .requires("send")
+                .help("Only send the blocks of this size that differ from the receiver's copy of each file; it keeps the rest"))
+            .arg(Arg::with_name("verify-only")
+                .long("verify-only")
+                .requires("send")
+                .requires("block-size")
+                .help("Compare each file w/the receiver's copy, a block at a time, and print the byte ranges that differ, as start-end w/the end exclusive; nothing is sent or written"))
+            .arg(Arg::with_name("verify")
+                .long("verify")
+                .help("Check a SHA-256 of everything sent once it's received, failing if it doesn't match; both ends must pass it"))
+            .arg(Arg::with_name("fec-block-size")
+                .long("fec-block-size")
+                .takes_value(true)
+                .default_value("8")
+                .help("The number of packets covered by each parity packet, when unidirectional"))
+            .arg(Arg::with_name("connect-only")
+                .long("connect-only")
+                .visible_alias("dry-run")
+                .requires("send")
+                .conflicts_with("unidirectional")
+                .help("Check the receiver is reachable, print what was learned about the path, and exit w/out sending"))
+            .arg(Arg::with_name("psk")
+                .long("psk")
+                .takes_value(true)
+                .value_name("SECRET")
+                .help("A secret shared w/the other end; the handshake proves both ends know it, and is refused otherwise"))
+            .arg(Arg::with_name("encrypt")
+                .long("encrypt")
+                .requires("psk")
+                .help("Encrypt every payload w/a key derived from --psk; both ends must pass it"))
+            .arg(Arg::with_name("root")
+                .long("root")
+                .takes_value(true)
+                .value_name("DIR")
+                .requires("recv")
+                .help("When receiving, confine the file to this directory; absolute paths and .. are refused"))
+            .arg(Arg::with_name("output-dir")
+                .long("output-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .conflicts_with_all(&["send", "push"])
+                .help("When receiving, put the files in this directory under the names the sender gave them, instead of at FILE"))
+            .arg(Arg::with_name("append")
+                .long("append")
+                .conflicts_with_all(&["send", "push"])
+                .help("When receiving, add what's received to the end of any file that's there already"))
+            .arg(Arg::with_name("truncate")
+                .long("truncate")
+                .conflicts_with_all(&["send", "append"])
+                .help("When receiving, replace any file that's there already w/what's received; the default"))
+            .arg(Arg::with_name("allow")
+                .long("allow")
+                .takes_value(true)
+                .value_name("CIDR")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with("send")
+                .help("When receiving, only take a connection from an address in CIDR, an IPv4 or IPv6 address w/an optional /LEN; can be given more than once"))
+            .arg(Arg::with_name("progress-format")
+                .long("progress-format")
+                .takes_value(true)
+                .possible_values(&["percent", "json"])
+                .default_value("percent")
+                .help("How the sender shows its progress on stderr: a percentage, or a line of JSON w/acked, total, rate_bps, and rtt_ms every --progress-interval-ms"))
+            .arg(Arg::with_name("progress-interval-ms")
+                .long("progress-interval-ms")
+                .takes_value(true)
+                .default_value("500")
+                .help("The least time between lines of JSON progress, in milliseconds"))
             .arg(Arg::with_name("v")
                 .short("v")
                 .multiple(true)
-                .help("Sets the level of verbosity"))
+                .help("Log more: -v for info, -vv for debug, -vvv for trace; only warnings w/out it"))
             .arg(Arg::with_name("FILE")
-                .required(true)
-                .help("The file to transfer")
+                .required_unless_one(&["connect-only", "output-dir"])
+                .multiple(true)
+                .help("The files to send, or where to receive them; a directory when more than one is sent, - for stdin or stdout")
                 .index(1))
-            .get_matches();
+            .get_matches_from_safe(args)?;
 
         // get the args
         let sender = matches.is_present("send");
-        let file = matches.value_of("FILE");
+        let files = matches.values_of("FILE").map_or(Vec::new(), |v| v.map(PathBuf::from).collect::<Vec<_>>());
         let host = matches.value_of("host").expect("Expected default host value");
         let port = matches.value_of("port").expect("Expected default port value");
-        let addr = SocketAddr::new(host.parse()?, port.parse()?);
+        let addrs = resolve_addrs(host, port, matches.is_present("ipv6"))?;
+        let addr = addrs[0];
+        let local_port = matches.value_of("local-port").unwrap_or("0");
+        let local_addr = match matches.value_of("local-addr") {
+            Some(local_host) => parse_addr(local_host, local_port, false)?,
+            None => SocketAddr::new(if addr.is_ipv6() { IpAddr::V6(Ipv6Addr::UNSPECIFIED) } else { IpAddr::V4(Ipv4Addr::UNSPECIFIED) }, local_port.parse()?)
+        };
+        let dual_stack = matches.is_present("dual-stack");
+        let transport = match matches.value_of("transport").expect("Expected default transport") {
+            "tcp" => TransportKind::Tcp,
+            _ => TransportKind::Bbr
+        };
+        let congestion = match matches.value_of("cc").expect("Expected default cc") {
+            "reno" => CongestionKind::Reno,
+            "cubic" => CongestionKind::Cubic,
+            _ => CongestionKind::Bbr
+        };
+        let checksum = match matches.value_of("checksum").expect("Expected default checksum") {
+            "none" => ChecksumKind::None,
+            "crc32c" => ChecksumKind::Crc32c,
+            "xxh3" => ChecksumKind::Xxh3,
+            _ => ChecksumKind::Crc32
+        };
         let window_size = matches.value_of("window-size").expect("Expected default window-size").parse::<usize>()?;
+        let max_window = match matches.value_of("max-window") {
+            Some(max_window) => Some(max_window.parse::<usize>()?),
+            None => None
+        };
+        let unix_socket = matches.value_of("unix-socket").map(PathBuf::from);
+        let payload_size = match matches.value_of("payload-size") {
+            Some(payload_size) => Some(payload_size.parse::<usize>()?),
+            None => None
+        };
+        let recv_buffer_bytes = matches.value_of("recv-buffer-bytes").expect("Expected default recv-buffer-bytes").parse::<usize>()?;
+        let so_rcvbuf = match matches.value_of("so-rcvbuf") {
+            Some(so_rcvbuf) => Some(so_rcvbuf.parse::<usize>()?),
+            None => None
+        };
+        let so_sndbuf = match matches.value_of("so-sndbuf") {
+            Some(so_sndbuf) => Some(so_sndbuf.parse::<usize>()?),
+            None => None
+        };
+        let max_rate = match matches.value_of("max-rate") {
+            Some(max_rate) => Some(max_rate.parse::<u64>()?),
+            None => None
+        };
+        let nonblocking = matches.is_present("nonblocking");
+        let coalesce = matches.is_present("coalesce");
+        let streams = matches.value_of("streams").expect("Expected default streams").parse::<usize>()?;
+        let pull = matches.value_of("pull").map(PathBuf::from);
+        let push = matches.values_of("push").map_or(Vec::new(), |v| v.map(PathBuf::from).collect::<Vec<_>>());
+        let unidirectional = matches.is_present("unidirectional");
+        let preserve = matches.is_present("preserve");
+        let block_size = match matches.value_of("block-size") {
+            Some(block_size) => Some(block_size.parse::<u32>()?),
+            None => None
+        };
+        let verify_only = matches.is_present("verify-only");
+        let verify = matches.is_present("verify");
+        let fec_block_size = matches.value_of("fec-block-size").expect("Expected default fec-block-size").parse::<usize>()?;
+        let connect_only = matches.is_present("connect-only");
+        let rto = Duration::from_millis(matches.value_of("rto-ms").expect("Expected default rto-ms").parse::<u64>()?);
+        let max_retransmits = match matches.value_of("max-retransmits") {
+            Some(max_retransmits) => Some(max_retransmits.parse::<u32>()?),
+            None => None
+        };
+        let ack_every = matches.value_of("ack-every").expect("Expected default ack-every").parse::<usize>()?;
+        let ack_delay = Duration::from_millis(matches.value_of("ack-delay-ms").expect("Expected default ack-delay-ms").parse::<u64>()?);
+        let connect_retries = matches.value_of("connect-retries").expect("Expected default connect-retries").parse::<usize>()?;
+        let connect_timeout = Duration::from_millis(matches.value_of("connect-timeout-ms").expect("Expected default connect-timeout-ms").parse::<u64>()?);
+        let idle_timeout = Duration::from_secs(matches.value_of("idle-timeout").expect("Expected default idle-timeout").parse::<u64>()?);
+        let root = matches.value_of("root").map(PathBuf::from);
+        let output_dir = matches.value_of("output-dir").map(PathBuf::from);
+        let append = matches.is_present("append");
+        let allow = match matches.values_of("allow") {
+            Some(allow) => allow.map(Cidr::parse).collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new()
+        };
+        let key = matches.value_of("psk").map(|k| k.as_bytes().to_vec());
+        let encrypt = matches.is_present("encrypt");
+        let progress_format = match matches.value_of("progress-format").expect("Expected default progress-format") {
+            "json" => ProgressFormat::Json,
+            _ => ProgressFormat::Percent
+        };
+        let progress_interval = Duration::from_millis(matches.value_of("progress-interval-ms").expect("Expected default progress-interval-ms").parse::<u64>()?);
+        let verbosity = matches.occurrences_of("v");
+
+        if key.as_ref().map_or(false, |k| k.is_empty()) {
+            return Err(From::from("psk must not be empty"));
+        }
+
+        if recv_buffer_bytes == 0 {
+            return Err(From::from("recv-buffer-bytes must be at least 1"));
+        }
+
+        if so_rcvbuf == Some(0) {
+            return Err(From::from("so-rcvbuf must be at least 1"));
+        }
+
+        if so_sndbuf == Some(0) {
+            return Err(From::from("so-sndbuf must be at least 1"));
+        }
+
+        if max_rate == Some(0) {
+            return Err(From::from("max-rate must be at least 1"));
+        }
+
+        // the window has a slot for every packet in it, allocated up front
+        if window_size == 0 || window_size > MAX_WINDOW_SIZE {
+            return Err(From::from(format!("window-size must be between 1 and {}", MAX_WINDOW_SIZE)));
+        }
+
+        if max_window.map_or(false, |max_window| max_window < window_size) {
+            return Err(From::from("max-window must be at least window-size"));
+        }
+
+        if max_window.map_or(false, |max_window| max_window > MAX_WINDOW_SIZE) {
+            return Err(From::from(format!("max-window must be no more than {}", MAX_WINDOW_SIZE)));
+        }
+
+        if payload_size.map_or(false, |payload_size| payload_size == 0 || payload_size > MAX_PAYLOAD_SIZE) {
+            return Err(From::from(format!("payload-size must be between 1 and {}", MAX_PAYLOAD_SIZE)));
+        }
+
+        if rto == Duration::from_millis(0) {
+            return Err(From::from("rto-ms must be at least 1"));
+        }
+
+        if max_retransmits == Some(0) {
+            return Err(From::from("max-retransmits must be at least 1"));
+        }
+
+        if ack_every == 0 {
+            return Err(From::from("ack-every must be at least 1"));
+        }
+
+        if ack_delay == Duration::from_millis(0) {
+            return Err(From::from("ack-delay-ms must be at least 1"));
+        }
+
+        if connect_retries == 0 {
+            return Err(From::from("connect-retries must be at least 1"));
+        }
+
+        if connect_timeout == Duration::from_millis(0) {
+            return Err(From::from("connect-timeout-ms must be at least 1"));
+        }
+
+        if idle_timeout == Duration::from_secs(0) {
+            return Err(From::from("idle-timeout must be at least 1"));
+        }
+
+        if progress_interval == Duration::from_millis(0) {
+            return Err(From::from("progress-interval-ms must be at least 1"));
+        }
+
+        // a v4 socket can't reach a v6 address, or the other way around
+        if local_addr.is_ipv6() != addr.is_ipv6() {
+            return Err(From::from(format!("local-addr {} and host {} must both be IPv4 or both be IPv6", local_addr.ip(), addr.ip())));
+        }
+
+        if !sender && files.len() > 1 {
+            return Err(From::from("Only one output path can be given when receiving"));
+        }
+
+        // a stream has no name or size, so it can't be one of several files, or be confined to a root
+        if files.len() > 1 && files.iter().any(|f| f == Path::new(STDIO)) {
+            return Err(From::from("stdin (-) can only be sent by itself"));
+        }
+
+        if preserve && files.iter().any(|f| f == Path::new(STDIO)) {
+            return Err(From::from("stdin (-) has no permissions or modification time to --preserve"));
+        }
+
+        if block_size == Some(0) {
+            return Err(From::from("block-size must be at least 1"));
+        }
+
+        // the receiver's copy is compared against a file, and its hashes need a way back
+        if block_size.is_some() && files.iter().any(|f| f == Path::new(STDIO)) {
+            return Err(From::from("stdin (-) can't be sent as a delta against the receiver's copy"));
+        }
+
+        if block_size.is_some() && unidirectional {
+            return Err(From::from("--block-size needs the receiver to send back its block hashes, which it can't when unidirectional"));
+        }
+
+        // the differences are printed as they're found, one stream at a time
+        if verify_only && (streams > 1 || pull.is_some() || connect_only) {
+            return Err(From::from("--verify-only can't be used w/--streams, --pull, or --connect-only"));
+        }
+
+        if root.is_some() && files.iter().any(|f| f == Path::new(STDIO)) {
+            return Err(From::from("--root can't be used when receiving to stdout (-)"));
+        }
+
+        // FEC blocks are counted in sequence numbers, which file headers would throw off
+        if unidirectional && files.len() > 1 {
+            return Err(From::from("Only one file can be sent when unidirectional"));
+        }
+
+        if fec_block_size == 0 {
+            return Err(From::from("fec-block-size must be at least 1"));
+        }
+
+        if streams == 0 || streams > MAX_STREAMS {
+            return Err(From::from(format!("streams must be between 1 and {}", MAX_STREAMS)));
+        }
+
+        // the streams agreed to come back in the Acknowledge, and each one is a file or more in a directory
+        if streams > 1 && (unidirectional || files.iter().any(|f| f == Path::new(STDIO))) {
+            return Err(From::from("--streams can't be used when unidirectional, or w/stdin or stdout (-)"));
+        }
+
+        if output_dir.is_some() && !files.is_empty() {
+            return Err(From::from("Give either a path to receive to, or --output-dir, not both"));
+        }
+
+        // the files go both ways on one stream each, named like any other
+        if (pull.is_some() || !push.is_empty()) && (streams > 1 || files.iter().any(|f| f == Path::new(STDIO))) {
+            return Err(From::from("--pull and --push can't be used w/--streams, or w/stdin or stdout (-)"));
+        }
+
+        // it's the wildcard of both families, there's no one address to bind
+        if dual_stack && !addr.ip().is_unspecified() {
+            return Err(From::from("--dual-stack listens on every address, it can't be used w/--host"));
+        }
+
+        if cfg!(not(unix)) && dual_stack {
+            return Err(From::from("--dual-stack only works on Unix"));
+        }
+
+        if dual_stack && unix_socket.is_some() {
+            return Err(From::from("--dual-stack can't be used w/--unix-socket"));
+        }
+
+        if cfg!(not(unix)) && unix_socket.is_some() {
+            return Err(From::from("--unix-socket only works on Unix"));
+        }
+
+        // a Unix domain socket only carries the one stream, one way
+        if unix_socket.is_some() && (streams > 1 || pull.is_some() || !push.is_empty() || connect_only) {
+            return Err(From::from("--unix-socket can't be used w/--streams, --pull, --push, or --connect-only"));
+        }
+
+        // TCP is only a plain stream of a single file
+        if transport == TransportKind::Tcp {
+            if unidirectional || connect_only || preserve || block_size.is_some() || verify || nonblocking || coalesce || max_rate.is_some() || max_window.is_some() || payload_size.is_some() || max_retransmits.is_some() || so_rcvbuf.is_some() || so_sndbuf.is_some() || unix_socket.is_some() || dual_stack || key.is_some() || streams > 1 || pull.is_some() || !push.is_empty() || output_dir.is_some() {
+                return Err(From::from("--unidirectional, --connect-only, --preserve, --block-size, --verify, --nonblocking, --coalesce, --max-rate, --max-window, --payload-size, --max-retransmits, --so-rcvbuf, --so-sndbuf, --unix-socket, --dual-stack, --psk, --streams, --pull, --push, and --output-dir only work w/the bbr transport"));
+            }
+
+            if matches.is_present("local-addr") || matches.is_present("local-port") {
+                return Err(From::from("--local-addr and --local-port only work w/the bbr transport"));
+            }
+
+            if !allow.is_empty() {
+                return Err(From::from("--allow only works w/the bbr transport"));
+            }
+
+            if files.len() > 1 {
+                return Err(From::from("Only one file can be sent w/the tcp transport"));
+            }
+
+            if congestion != CongestionKind::Bbr {
+                return Err(From::from("--cc only works w/the bbr transport, TCP has its own"));
+            }
+
+            if checksum != ChecksumKind::Crc32 {
+                return Err(From::from("--checksum only works w/the bbr transport, TCP has its own"));
+            }
+
+            if progress_format != ProgressFormat::Percent {
+                return Err(From::from("--progress-format only works w/the bbr transport, there are no ACKs to report on w/TCP"));
+            }
+        }
 
         debug!("ADDR: {:?}", addr);
 
-        if sender {
-            info!("Sending file {} to {}", file.unwrap(), addr);
+        if connect_only {
+            info!("Probing {}", addr);
+            return Ok(Configuration {
+                sender,
+                addr,
+                addrs,
+                local_addr,
+                dual_stack,
+                unix_socket,
+                transport,
+                congestion,
+                checksum,
+                window_size,
+                max_window,
+                payload_size,
+                recv_buffer_bytes,
+                so_rcvbuf,
+                so_sndbuf,
+                max_rate,
+                nonblocking,
+                coalesce,
+                streams,
+                pull,
+                push,
+                unidirectional,
+                preserve,
+                block_size,
+                verify_only,
+                verify,
+                fec_block_size,
+                connect_only,
+                rto,
+                max_retransmits,
+                ack_every,
+                ack_delay,
+                connect_retries,
+                connect_timeout,
+                idle_timeout,
+                key,
+                encrypt,
+                root,
+                output_dir,
+                append,
+                allow,
+                progress_format,
+                progress_interval,
+                verbosity,
+                files: Vec::new(),
+            });
+        } else if sender {
+            info!("Sending {} to {}", files.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join(", "), addr);
             return Ok(Configuration {
                 sender,
                 addr,
+                addrs,
+                local_addr,
+                dual_stack,
+                unix_socket,
+                transport,
+                congestion,
+                checksum,
                 window_size,
-                file: Some(PathBuf::from(file.unwrap())),
+                max_window,
+                payload_size,
+                recv_buffer_bytes,
+                so_rcvbuf,
+                so_sndbuf,
+                max_rate,
+                nonblocking,
+                coalesce,
+                streams,
+                pull,
+                push,
+                unidirectional,
+                preserve,
+                block_size,
+                verify_only,
+                verify,
+                fec_block_size,
+                connect_only,
+                rto,
+                max_retransmits,
+                ack_every,
+                ack_delay,
+                connect_retries,
+                connect_timeout,
+                idle_timeout,
+                key,
+                encrypt,
+                root,
+                output_dir,
+                append,
+                allow,
+                progress_format,
+                progress_interval,
+                verbosity,
+                files,
             });
         } else {
             info!("Receiving file, listening on {}", addr);
             return Ok(Configuration {
                 sender,
                 addr,
+                addrs,
+                local_addr,
+                dual_stack,
+                unix_socket,
+                transport,
+                congestion,
+                checksum,
                 window_size,
-                file: Some(PathBuf::from(file.unwrap()))
+                max_window,
+                payload_size,
+                recv_buffer_bytes,
+                so_rcvbuf,
+                so_sndbuf,
+                max_rate,
+                nonblocking,
+                coalesce,
+                streams,
+                pull,
+                push,
+                unidirectional,
+                preserve,
+                block_size,
+                verify_only,
+                verify,
+                fec_block_size,
+                connect_only,
+                rto,
+                max_retransmits,
+                ack_every,
+                ack_delay,
+                connect_retries,
+                connect_timeout,
+                idle_timeout,
+                key,
+                encrypt,
+                root,
+                output_dir,
+                append,
+                allow,
+                progress_format,
+                progress_interval,
+                verbosity,
+                files
             });
         }
 
@@ -107,12 +980,901 @@ impl Configuration {
         self.addr
     }
 
+    pub fn set_addr(&mut self, addr: SocketAddr) {
+        self.addr = addr;
+        self.addrs = vec![addr];
+    }
+
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Whether the receiver listens on IPv4 and IPv6 at once, see socket::bind_dual_stack
+    pub fn dual_stack(&self) -> bool {
+        self.dual_stack
+    }
+
+    pub fn set_dual_stack(&mut self, dual_stack: bool) {
+        self.dual_stack = dual_stack;
+    }
+
+    /// Whether the sender would send from the address it sends to, as when --local-port is --port on this host
+    /// It's nothing but talking to itself, as the receiver can't bind the same port
+    pub fn connects_to_self(&self) -> bool {
+        let local = self.local_addr;
+
+        return local.port() != 0 && self.addrs.iter().any(|addr| addr.port() == local.port() && (addr.ip() == local.ip() || (local.ip().is_unspecified() && addr.ip().is_loopback())));
+    }
+
+    pub fn unix_socket(&self) -> Option<&PathBuf> {
+        self.unix_socket.as_ref()
+    }
+
+    pub fn set_unix_socket(&mut self, unix_socket: Option<PathBuf>) {
+        self.unix_socket = unix_socket;
+    }
+
+    pub fn transport(&self) -> TransportKind {
+        self.transport
+    }
+
+    pub fn congestion(&self) -> CongestionKind {
+        self.congestion
+    }
+
+    pub fn set_congestion(&mut self, congestion: CongestionKind) {
+        self.congestion = congestion;
+    }
+
+    pub fn checksum(&self) -> ChecksumKind {
+        self.checksum
+    }
+
+    pub fn set_checksum(&mut self, checksum: ChecksumKind) {
+        self.checksum = checksum;
+    }
+
     pub fn window_size(&self) -> usize {
         self.window_size
     }
 
+    pub fn set_window_size(&mut self, window_size: usize) {
+        self.window_size = window_size;
+    }
+
+    pub fn max_window(&self) -> Option<usize> {
+        self.max_window
+    }
+
+    pub fn set_max_window(&mut self, max_window: Option<usize>) {
+        self.max_window = max_window;
+    }
+
+    pub fn payload_size(&self) -> Option<usize> {
+        self.payload_size
+    }
+
+    pub fn set_payload_size(&mut self, payload_size: Option<usize>) {
+        self.payload_size = payload_size;
+    }
+
+    pub fn recv_buffer_bytes(&self) -> usize {
+        self.recv_buffer_bytes
+    }
+
+    pub fn set_recv_buffer_bytes(&mut self, recv_buffer_bytes: usize) {
+        self.recv_buffer_bytes = recv_buffer_bytes;
+    }
+
+    /// The kernel receive buffer to ask for, if not the system's default
+    pub fn so_rcvbuf(&self) -> Option<usize> {
+        self.so_rcvbuf
+    }
+
+    pub fn set_so_rcvbuf(&mut self, so_rcvbuf: Option<usize>) {
+        self.so_rcvbuf = so_rcvbuf;
+    }
+
+    /// The kernel send buffer to ask for, if not the system's default
+    pub fn so_sndbuf(&self) -> Option<usize> {
+        self.so_sndbuf
+    }
+
+    pub fn set_so_sndbuf(&mut self, so_sndbuf: Option<usize>) {
+        self.so_sndbuf = so_sndbuf;
+    }
+
+    pub fn max_rate(&self) -> Option<u64> {
+        self.max_rate
+    }
+
+    pub fn set_max_rate(&mut self, max_rate: Option<u64>) {
+        self.max_rate = max_rate;
+    }
+
+    /// Whether the sender's write_all returns WouldBlock, instead of waiting, when the window is full
+    pub fn nonblocking(&self) -> bool {
+        self.nonblocking
+    }
+
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    /// Whether the sender's write_all holds less than a packet's worth until more is written, or it's flushed
+    pub fn coalesce(&self) -> bool {
+        self.coalesce
+    }
+
+    pub fn set_coalesce(&mut self, coalesce: bool) {
+        self.coalesce = coalesce;
+    }
+
+    /// The streams to send over, or the most to agree to when receiving
+    pub fn streams(&self) -> usize {
+        self.streams
+    }
+
+    /// Where to put what the receiver sends back, when it's sending back
+    pub fn pull(&self) -> Option<&PathBuf> {
+        self.pull.as_ref()
+    }
+
+    /// The files to send back to the sender, when receiving
+    pub fn push(&self) -> &[PathBuf] {
+        &self.push
+    }
+
+    /// Both ends are sending, w/--pull or --push
+    pub fn duplex(&self) -> bool {
+        self.pull.is_some() || !self.push.is_empty()
+    }
+
+    pub fn unidirectional(&self) -> bool {
+        self.unidirectional
+    }
+
+    pub fn set_unidirectional(&mut self, unidirectional: bool) {
+        self.unidirectional = unidirectional;
+    }
+
+    pub fn preserve(&self) -> bool {
+        self.preserve
+    }
+
+    pub fn set_preserve(&mut self, preserve: bool) {
+        self.preserve = preserve;
+    }
+
+    /// The block size to send each file as a delta in, if it's sent as one
+    pub fn block_size(&self) -> Option<u32> {
+        self.block_size
+    }
+
+    pub fn set_block_size(&mut self, block_size: Option<u32>) {
+        self.block_size = block_size;
+    }
+
+    /// Only compare each file w/the receiver's copy, in blocks of block_size, sending nothing
+    pub fn verify_only(&self) -> bool {
+        self.verify_only
+    }
+
+    pub fn set_verify_only(&mut self, verify_only: bool) {
+        self.verify_only = verify_only;
+    }
+
+    pub fn verify(&self) -> bool {
+        self.verify
+    }
+
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    pub fn fec_block_size(&self) -> usize {
+        self.fec_block_size
+    }
+
+    pub fn set_fec_block_size(&mut self, fec_block_size: usize) {
+        self.fec_block_size = fec_block_size;
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    pub fn set_rto(&mut self, rto: Duration) {
+        self.rto = rto;
+    }
+
+    /// How many times a packet's retransmitted after timing out before the sender gives up, or forever if None
+    pub fn max_retransmits(&self) -> Option<u32> {
+        self.max_retransmits
+    }
+
+    pub fn set_max_retransmits(&mut self, max_retransmits: Option<u32>) {
+        self.max_retransmits = max_retransmits;
+    }
+
+    /// Packets received before they're acknowledged, w/out waiting for ack_delay
+    pub fn ack_every(&self) -> usize {
+        self.ack_every
+    }
+
+    pub fn set_ack_every(&mut self, ack_every: usize) {
+        self.ack_every = ack_every;
+    }
+
+    /// The longest a received packet waits to be acknowledged
+    pub fn ack_delay(&self) -> Duration {
+        self.ack_delay
+    }
+
+    pub fn set_ack_delay(&mut self, ack_delay: Duration) {
+        self.ack_delay = ack_delay;
+    }
+
+    /// The number of times to send the Connect before giving up
+    pub fn connect_retries(&self) -> usize {
+        self.connect_retries
+    }
+
+    pub fn set_connect_retries(&mut self, connect_retries: usize) {
+        self.connect_retries = connect_retries;
+    }
+
+    /// How long to wait for each Connect to be acknowledged
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout
+    }
+
+    pub fn set_connect_timeout(&mut self, connect_timeout: Duration) {
+        self.connect_timeout = connect_timeout;
+    }
+
+    /// How long the sender goes w/out an ACK or Pong before giving up on the receiver
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    pub fn connect_only(&self) -> bool {
+        self.connect_only
+    }
+
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_ref().map(|k| k.as_slice())
+    }
+
+    pub fn set_key(&mut self, key: Option<Vec<u8>>) {
+        self.key = key;
+    }
+
+    pub fn encrypt(&self) -> bool {
+        self.encrypt
+    }
+
+    pub fn set_encrypt(&mut self, encrypt: bool) {
+        self.encrypt = encrypt;
+    }
+
+    pub fn root(&self) -> Option<&PathBuf> {
+        self.root.as_ref()
+    }
+
+    /// The directory to receive into under the sender's names, instead of a path
+    pub fn output_dir(&self) -> Option<&PathBuf> {
+        self.output_dir.as_ref()
+    }
+
+    pub fn append(&self) -> bool {
+        self.append
+    }
+
+    pub fn set_append(&mut self, append: bool) {
+        self.append = append;
+    }
+
+    /// Whether the receiver takes a Connect from ip: it's in one of the --allow blocks, or there aren't any
+    pub fn allowed(&self, ip: IpAddr) -> bool {
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    pub fn set_allow(&mut self, allow: Vec<Cidr>) {
+        self.allow = allow;
+    }
+
+    pub fn progress_format(&self) -> ProgressFormat {
+        self.progress_format
+    }
+
+    pub fn set_progress_format(&mut self, progress_format: ProgressFormat) {
+        self.progress_format = progress_format;
+    }
+
+    pub fn progress_interval(&self) -> Duration {
+        self.progress_interval
+    }
+
+    pub fn set_progress_interval(&mut self, progress_interval: Duration) {
+        self.progress_interval = progress_interval;
+    }
+
+    /// How much to log, from the number of times -v was given: only warnings w/out it, then info, debug, and trace
+    pub fn log_level(&self) -> LevelFilter {
+        match self.verbosity {
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::Trace
+        }
+    }
+
+    /// The first (or only) file; where to receive
     pub fn file(&self) -> &PathBuf {
-        self.file.as_ref().unwrap()
+        &self.files[0]
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// True when sending stdin, or receiving to stdout, instead of files
+    pub fn stdio(&self) -> bool {
+        self.files.len() == 1 && self.files[0] == Path::new(STDIO)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use std::path::PathBuf;
+
+    use log::LevelFilter;
+
+    use config::{parse_addr, resolve_addrs_with, Cidr, Configuration, CongestionKind, ChecksumKind, ProgressFormat, TransportKind};
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_v4() {
+        assert_eq!(addr("10.0.0.1:1234"), parse_addr("10.0.0.1", "1234", false).unwrap());
+        assert_eq!(addr("10.0.0.1:99"), parse_addr("10.0.0.1:99", "1234", false).unwrap());
+        assert!(parse_addr("10.0.0.1", "port", false).is_err());
+        assert!(parse_addr("10.0.0", "1234", false).is_err());
+    }
+
+    #[test]
+    fn parse_v6() {
+        assert_eq!(addr("[::1]:1234"), parse_addr("::1", "1234", false).unwrap());
+        assert_eq!(addr("[::1]:1234"), parse_addr("[::1]", "1234", false).unwrap());
+        assert_eq!(addr("[::1]:1234"), parse_addr("[::1]:1234", "5678", false).unwrap());
+        assert_eq!(addr("[fe80::1]:80"), parse_addr("[fe80::1]:80", "1234", true).unwrap());
+        assert!(parse_addr("[::1", "1234", false).is_err());
+        assert!(parse_addr("::1]", "1234", false).is_err());
+    }
+
+    #[test]
+    fn parse_ipv6_flag() {
+        // the default host follows the flag
+        assert_eq!(addr("[::]:1234"), parse_addr("0.0.0.0", "1234", true).unwrap());
+        assert_eq!(addr("0.0.0.0:1234"), parse_addr("0.0.0.0", "1234", false).unwrap());
+
+        assert!(parse_addr("127.0.0.1", "1234", true).is_err());
+        assert!(parse_addr("127.0.0.1:1234", "1234", true).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dual_stack_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--recv", "/tmp/test"]).expect("Error parsing defaults").dual_stack());
+
+        // either wildcard will do, only the port's used
+        let config = Configuration::from_args(vec!["qcp", "--recv", "--dual-stack", "--port", "4321", "/tmp/test"]).expect("Error parsing --dual-stack");
+
+        assert!(config.dual_stack());
+        assert_eq!(4321, config.addr().port());
+
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--dual-stack", "--ipv6", "/tmp/test"]).expect("Error parsing --dual-stack w/--ipv6").dual_stack());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--dual-stack", "--host", "127.0.0.1", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--dual-stack", "--transport", "tcp", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--dual-stack", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn resolve_hostname() {
+        // a resolver that knows localhost, by both families, and nothing else
+        let lookup = |host: &str, port: u16| -> io::Result<Vec<SocketAddr>> {
+            match host {
+                "localhost" => Ok(vec![SocketAddr::new("::1".parse().unwrap(), port), SocketAddr::new("127.0.0.1".parse().unwrap(), port)]),
+                _ => Err(io::Error::new(io::ErrorKind::NotFound, "Name or service not known"))
+            }
+        };
+
+        assert_eq!(vec![addr("127.0.0.1:1234")], resolve_addrs_with("localhost", "1234", false, lookup).expect("Error resolving localhost"));
+        assert_eq!(vec![addr("[::1]:1234")], resolve_addrs_with("localhost", "1234", true, lookup).expect("Error resolving localhost"));
+        assert!(resolve_addrs_with("qcp.invalid", "1234", false, lookup).is_err());
+
+        // IP addresses are never looked up
+        let no_lookup = |_: &str, _: u16| -> io::Result<Vec<SocketAddr>> { panic!("Looked up an IP address") };
+
+        assert_eq!(vec![addr("10.0.0.1:1234")], resolve_addrs_with("10.0.0.1", "1234", false, no_lookup).unwrap());
+        assert!(resolve_addrs_with("10.0.0", "1234", false, no_lookup).is_err());
+    }
+
+    #[test]
+    fn connect_options() {
+        let config = Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(3, config.connect_retries());
+        assert_eq!(Duration::from_millis(3000), config.connect_timeout());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--connect-retries", "10", "--connect-timeout-ms", "250", "/tmp/test"]).expect("Error parsing options");
+
+        assert_eq!(10, config.connect_retries());
+        assert_eq!(Duration::from_millis(250), config.connect_timeout());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--connect-retries", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--connect-retries", "many", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--connect-timeout-ms", "0", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn idle_timeout_option() {
+        let config = Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(Duration::from_secs(30), config.idle_timeout());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--idle-timeout", "5", "/tmp/test"]).expect("Error parsing --idle-timeout");
+
+        assert_eq!(Duration::from_secs(5), config.idle_timeout());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--idle-timeout", "0", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn max_retransmits_option() {
+        assert_eq!(None, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").max_retransmits());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--max-retransmits", "5", "/tmp/test"]).expect("Error parsing --max-retransmits");
+
+        assert_eq!(Some(5), config.max_retransmits());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--max-retransmits", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--max-retransmits", "5", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--transport", "tcp", "--max-retransmits", "5", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn ack_options() {
+        let config = Configuration::from_args(vec!["qcp", "--recv", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(32, config.ack_every());
+        assert_eq!(Duration::from_millis(5), config.ack_delay());
+
+        let config = Configuration::from_args(vec!["qcp", "--recv", "--ack-every", "8", "--ack-delay-ms", "20", "/tmp/test"]).expect("Error parsing options");
+
+        assert_eq!(8, config.ack_every());
+        assert_eq!(Duration::from_millis(20), config.ack_delay());
+
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--ack-every", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--ack-delay-ms", "0", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn pull_and_push_options() {
+        let config = Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert!(!config.duplex());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--pull", "/tmp/back", "/tmp/test"]).expect("Error parsing --pull");
+
+        assert_eq!(Some(&PathBuf::from("/tmp/back")), config.pull());
+        assert!(config.duplex());
+
+        let config = Configuration::from_args(vec!["qcp", "--recv", "--push", "/tmp/a", "--push", "/tmp/b", "/tmp/test"]).expect("Error parsing --push");
+
+        assert_eq!(&[PathBuf::from("/tmp/a"), PathBuf::from("/tmp/b")], config.push());
+        assert_eq!(&[PathBuf::from("/tmp/test")], config.files());
+        assert!(config.duplex());
+
+        // each only makes sense on its own end; clap's errors come back from from_args, rather than exiting
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--pull", "/tmp/back", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--push", "/tmp/a", "/tmp/test"]).is_err());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--pull", "/tmp/back", "--streams", "2", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--pull", "/tmp/back", "-"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--pull", "/tmp/back", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn output_dir_option() {
+        let config = Configuration::from_args(vec!["qcp", "--recv", "--output-dir", "/tmp/downloads"]).expect("Error parsing --output-dir");
+
+        assert_eq!(Some(&PathBuf::from("/tmp/downloads")), config.output_dir());
+        assert!(config.files().is_empty());
+
+        // one or the other, and TCP has no names to go by
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--output-dir", "/tmp/downloads", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--output-dir", "/tmp/downloads", "--transport", "tcp"]).is_err());
+    }
+
+    #[test]
+    fn append_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--recv", "/tmp/test"]).expect("Error parsing defaults").append());
+        assert!(!Configuration::from_args(vec!["qcp", "--recv", "--truncate", "/tmp/test"]).expect("Error parsing --truncate").append());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--append", "/tmp/test"]).expect("Error parsing --append").append());
+
+        // it's up to the receiver, and it's one or the other
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--append", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--append", "--truncate", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn cc_option() {
+        assert_eq!(CongestionKind::Bbr, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").congestion());
+        assert_eq!(CongestionKind::Reno, Configuration::from_args(vec!["qcp", "--send", "--cc", "reno", "/tmp/test"]).expect("Error parsing reno").congestion());
+        assert_eq!(CongestionKind::Cubic, Configuration::from_args(vec!["qcp", "--send", "--cc", "cubic", "/tmp/test"]).expect("Error parsing cubic").congestion());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--cc", "reno", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn cidr() {
+        let v4 = Cidr::parse("10.1.0.0/16").expect("Error parsing v4");
+
+        assert!(v4.contains("10.1.2.3".parse().unwrap()));
+        assert!(v4.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!v4.contains("10.2.0.1".parse().unwrap()));
+        assert!(!v4.contains("::1".parse().unwrap()));
+
+        let v6 = Cidr::parse("2001:db8::/32").expect("Error parsing v6");
+
+        assert!(v6.contains("2001:db8:1::5".parse().unwrap()));
+        assert!(!v6.contains("2001:db9::5".parse().unwrap()));
+        assert!(!v6.contains("10.1.2.3".parse().unwrap()));
+
+        // a lone address is only it, and /0 is everything of its family
+        let one = Cidr::parse("192.168.1.7").expect("Error parsing address");
+
+        assert!(one.contains("192.168.1.7".parse().unwrap()));
+        assert!(!one.contains("192.168.1.8".parse().unwrap()));
+        assert!(Cidr::parse("0.0.0.0/0").expect("Error parsing /0").contains("8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("::/0").expect("Error parsing /0").contains("2001:db8::1".parse().unwrap()));
+
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("::/129").is_err());
+        assert!(Cidr::parse("10.0.0/8").is_err());
+        assert!(Cidr::parse("10.0.0.0/x").is_err());
+    }
+
+    #[test]
+    fn allow_option() {
+        let config = Configuration::from_args(vec!["qcp", "--recv", "--allow", "10.0.0.0/8", "--allow", "2001:db8::/32", "/tmp/test"]).expect("Error parsing --allow");
+
+        assert!(config.allowed("10.9.8.7".parse().unwrap()));
+        assert!(config.allowed("2001:db8::1".parse().unwrap()));
+        assert!(!config.allowed("192.168.0.1".parse().unwrap()));
+
+        // anyone, w/out it
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "/tmp/test"]).expect("Error parsing defaults").allowed("192.168.0.1".parse().unwrap()));
+
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--allow", "10.0.0.0/40", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--allow", "10.0.0.0/8", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn checksum_option() {
+        assert_eq!(ChecksumKind::Crc32, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").checksum());
+        assert_eq!(ChecksumKind::None, Configuration::from_args(vec!["qcp", "--send", "--checksum", "none", "/tmp/test"]).expect("Error parsing none").checksum());
+        assert_eq!(ChecksumKind::Crc32c, Configuration::from_args(vec!["qcp", "--recv", "--checksum", "crc32c", "/tmp/test"]).expect("Error parsing crc32c").checksum());
+        assert_eq!(ChecksumKind::Xxh3, Configuration::from_args(vec!["qcp", "--send", "--checksum", "xxh3", "/tmp/test"]).expect("Error parsing xxh3").checksum());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--checksum", "xxh3", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn progress_format_option() {
+        let config = Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(ProgressFormat::Percent, config.progress_format());
+        assert_eq!(Duration::from_millis(500), config.progress_interval());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--progress-format", "json", "--progress-interval-ms", "100", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(ProgressFormat::Json, config.progress_format());
+        assert_eq!(Duration::from_millis(100), config.progress_interval());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--progress-format", "xml", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--progress-interval-ms", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--progress-format", "json", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn transport_option() {
+        let config = Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(TransportKind::Bbr, config.transport());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--transport", "tcp", "/tmp/test"]).expect("Error parsing tcp");
+
+        assert_eq!(TransportKind::Tcp, config.transport());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--transport", "tcp", "--unidirectional", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--transport", "tcp", "/tmp/a", "/tmp/b"]).is_err());
+    }
+
+    #[test]
+    fn local_addr_option() {
+        // any free port on the wildcard of the receiver's family
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "10.0.0.1", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(addr("0.0.0.0:0"), config.local_addr());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "::1", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(addr("[::]:0"), config.local_addr());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "10.0.0.1", "--local-addr", "10.0.0.2", "--local-port", "5000", "/tmp/test"]).expect("Error parsing options");
+
+        assert_eq!(addr("10.0.0.2:5000"), config.local_addr());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "::1", "--local-port", "5000", "/tmp/test"]).expect("Error parsing options");
+
+        assert_eq!(addr("[::]:5000"), config.local_addr());
+
+        // the families have to match
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--host", "::1", "--local-addr", "10.0.0.2", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--host", "10.0.0.1", "--local-addr", "::1", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--local-port", "port", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn connects_to_self() {
+        assert!(!Configuration::from_args(vec!["qcp", "--send", "--host", "127.0.0.1", "/tmp/test"]).expect("Error parsing defaults").connects_to_self());
+
+        // the wildcard includes loopback
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "127.0.0.1", "--port", "5000", "--local-port", "5000", "/tmp/test"]).expect("Error parsing options");
+
+        assert!(config.connects_to_self());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "10.0.0.1", "--port", "5000", "--local-addr", "10.0.0.1", "--local-port", "5000", "/tmp/test"]).expect("Error parsing options");
+
+        assert!(config.connects_to_self());
+
+        // another port, or another host, is fine
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "127.0.0.1", "--port", "5000", "--local-port", "5001", "/tmp/test"]).expect("Error parsing options");
+
+        assert!(!config.connects_to_self());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--host", "10.0.0.1", "--port", "5000", "--local-port", "5000", "/tmp/test"]).expect("Error parsing options");
+
+        assert!(!config.connects_to_self());
+    }
+
+    #[test]
+    fn stdio_option() {
+        assert!(Configuration::from_args(vec!["qcp", "--send", "-"]).expect("Error parsing stdin").stdio());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "-"]).expect("Error parsing stdout").stdio());
+        assert!(!Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing file").stdio());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "-", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--root", "/tmp", "-"]).is_err());
+    }
+
+    #[test]
+    fn recv_buffer_option() {
+        let config = Configuration::from_args(vec!["qcp", "--recv", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(4 * 1024 * 1024, config.recv_buffer_bytes());
+
+        let config = Configuration::from_args(vec!["qcp", "--recv", "--recv-buffer-bytes", "65536", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(65536, config.recv_buffer_bytes());
+
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--recv-buffer-bytes", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--recv-buffer-bytes", "lots", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn socket_buffer_options() {
+        let config = Configuration::from_args(vec!["qcp", "--recv", "/tmp/test"]).expect("Error parsing defaults");
+
+        assert_eq!(None, config.so_rcvbuf());
+        assert_eq!(None, config.so_sndbuf());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--so-rcvbuf", "8388608", "--so-sndbuf", "4194304", "/tmp/test"]).expect("Error parsing options");
+
+        assert_eq!(Some(8388608), config.so_rcvbuf());
+        assert_eq!(Some(4194304), config.so_sndbuf());
+
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--so-rcvbuf", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--so-sndbuf", "8M", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--so-rcvbuf", "8388608", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn max_rate_option() {
+        assert_eq!(None, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").max_rate());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--max-rate", "50000000", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(Some(50_000_000), config.max_rate());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--max-rate", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--max-rate", "50M", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--max-rate", "1000", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn window_size_option() {
+        assert_eq!(1024, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").window_size());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--window-size", "65536", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(65536, config.window_size());
+
+        // the largest there's room for, and past it
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--window-size", "1048576", "/tmp/test"]).is_ok());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--window-size", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--window-size", "1048577", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--window-size", "10000000000", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--window-size", "100000000000000000000000", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--max-window", "10000000000", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn max_window_option() {
+        assert_eq!(None, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").max_window());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--max-window", "65536", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(Some(65536), config.max_window());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--max-window", "512", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--max-window", "4096", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn unix_socket_option() {
+        assert_eq!(None, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").unix_socket());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--unix-socket", "/tmp/qcp.sock", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(Some(&PathBuf::from("/tmp/qcp.sock")), config.unix_socket());
+
+        let config = Configuration::from_args(vec!["qcp", "--recv", "--unix-socket", "/tmp/qcp.sock", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(Some(&PathBuf::from("/tmp/qcp.sock")), config.unix_socket());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--unix-socket", "/tmp/qcp.sock", "--streams", "2", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--unix-socket", "/tmp/qcp.sock", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn payload_size_option() {
+        assert_eq!(None, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").payload_size());
+
+        let config = Configuration::from_args(vec!["qcp", "--send", "--payload-size", "500", "/tmp/test"]).expect("Error parsing option");
+
+        assert_eq!(Some(500), config.payload_size());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--payload-size", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--payload-size", "1453", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--payload-size", "500", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--payload-size", "500", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn preserve_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").preserve());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--preserve", "/tmp/test"]).expect("Error parsing option").preserve());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--preserve", "-"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--preserve", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn connect_only_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").connect_only());
+
+        // no files needed, nothing's sent
+        let config = Configuration::from_args(vec!["qcp", "--send", "--connect-only"]).expect("Error parsing option");
+
+        assert!(config.connect_only());
+        assert!(config.files().is_empty());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--dry-run"]).expect("Error parsing alias").connect_only());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--dry-run", "--transport", "tcp"]).is_err());
+    }
+
+    #[test]
+    fn block_size_option() {
+        assert_eq!(None, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").block_size());
+        assert_eq!(Some(65536), Configuration::from_args(vec!["qcp", "--send", "--block-size", "65536", "/tmp/test"]).expect("Error parsing option").block_size());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--block-size", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--block-size", "64K", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--block-size", "4096", "-"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--block-size", "4096", "--unidirectional", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--block-size", "4096", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn verify_only_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").verify_only());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--verify-only", "--block-size", "4096", "/tmp/test"]).expect("Error parsing option").verify_only());
+
+        // there are no blocks to compare w/out a size, and nothing to compare on the receiving end
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--verify-only", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--verify-only", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--verify-only", "--block-size", "4096", "--streams", "2", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--verify-only", "--block-size", "4096", "--pull", "/tmp/back", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn verify_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--recv", "/tmp/test"]).expect("Error parsing defaults").verify());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--verify", "/tmp/test"]).expect("Error parsing recv").verify());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--verify", "/tmp/test"]).expect("Error parsing send").verify());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--verify", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn nonblocking_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").nonblocking());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--nonblocking", "/tmp/test"]).expect("Error parsing option").nonblocking());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--nonblocking", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn coalesce_option() {
+        assert!(!Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").coalesce());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--coalesce", "/tmp/test"]).expect("Error parsing option").coalesce());
+
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--coalesce", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--coalesce", "--transport", "tcp", "/tmp/test"]).is_err());
+    }
+
+    #[test]
+    fn streams_option() {
+        assert_eq!(1, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").streams());
+        assert_eq!(4, Configuration::from_args(vec!["qcp", "--send", "--streams", "4", "/tmp/a", "/tmp/b"]).expect("Error parsing option").streams());
+        assert_eq!(4, Configuration::from_args(vec!["qcp", "--recv", "--streams", "4", "/tmp/dir"]).expect("Error parsing option").streams());
+
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--streams", "0", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--streams", "65", "/tmp/test"]).is_err());
+
+        // nothing to say how many were agreed to, and a stream can't be split
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--streams", "2", "--unidirectional", "/tmp/test"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--streams", "2", "-"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--recv", "--streams", "2", "-"]).is_err());
+        assert!(Configuration::from_args(vec!["qcp", "--send", "--streams", "2", "--transport", "tcp", "/tmp/test"]).is_err());
     }
 
+    #[test]
+    fn verbosity() {
+        assert_eq!(LevelFilter::Warn, Configuration::from_args(vec!["qcp", "--send", "/tmp/test"]).expect("Error parsing defaults").log_level());
+        assert_eq!(LevelFilter::Info, Configuration::from_args(vec!["qcp", "--send", "-v", "/tmp/test"]).expect("Error parsing -v").log_level());
+        assert_eq!(LevelFilter::Debug, Configuration::from_args(vec!["qcp", "--send", "-vv", "/tmp/test"]).expect("Error parsing -vv").log_level());
+        assert_eq!(LevelFilter::Trace, Configuration::from_args(vec!["qcp", "--send", "-v", "-v", "-v", "/tmp/test"]).expect("Error parsing -v -v -v").log_level());
+
+        // there's nothing past trace
+        assert_eq!(LevelFilter::Trace, Configuration::from_args(vec!["qcp", "--recv", "-vvvvvv", "/tmp/test"]).expect("Error parsing -vvvvvv").log_level());
+    }
 }
@@ -0,0 +1,436 @@
+//! Congestion control strategies: how many bytes the sender keeps in flight
+//! The sender tells its strategy about every ACK, loss, and timeout, and only sends while there's room in its window
+//! BBR sizes the window from the measured bandwidth-delay product, Reno and CUBIC back off on loss
+
+use std::time::{Duration, Instant};
+
+use config::CongestionKind;
+
+const CWND_GAIN :f64 = 2.0;         // keep this many BDPs in flight, so the pipe stays full while ACKs are on their way back
+const MIN_CWND :u64 = 16;           // BBR never goes below this many packets in flight, so a few losses can't stall the sender
+const INITIAL_CWND :u64 = 10;       // Reno and CUBIC start w/this many packets, as in RFC 6928
+const LOSS_MIN_CWND :u64 = 2;       // and never back off below this many
+const LOSS_WINDOW :u64 = 1;         // Reno and CUBIC start over from this many packets after a retransmit timeout, as in RFC 5681
+const CUBIC_C :f64 = 0.4;           // how quickly CUBIC grows back toward where the last loss was, from RFC 8312
+const CUBIC_BETA :f64 = 0.7;        // what CUBIC's window is multiplied by on loss
+
+/// Decides how many bytes can be in flight
+pub trait CongestionControl: Send {
+    /// Called when bytes are acknowledged, rtt after they were sent
+    /// rtt is None if they were retransmitted, as there's no telling which send the ACK is for
+    fn on_ack(&mut self, bytes: u64, rtt: Option<Duration>);
+
+    /// Called when bytes are retransmitted because they were NACKed
+    fn on_loss(&mut self, bytes: u64);
+
+    /// Called when bytes are retransmitted because their retransmit timer ran out
+    /// Nothing's getting through, which is worse than a loss
+    fn on_timeout(&mut self, bytes: u64);
+
+    /// Called after each ACK w/the sender's estimates of the path's bandwidth, in bytes/sec, and min RTT
+    /// Only BBR needs these, the others go by ACKs and losses alone
+    fn on_estimate(&mut self, _bandwidth: f64, _min_rtt: Duration) {
+    }
+
+    /// The congestion window, in bytes; the sender also never has more than its window size in flight
+    fn cwnd(&self) -> u64;
+}
+
+/// Creates the strategy for kind, counting packets of mss bytes
+pub fn new(kind: CongestionKind, mss: usize) -> Box<dyn CongestionControl> {
+    match kind {
+        CongestionKind::Bbr => Box::new(Bbr::new(mss)),
+        CongestionKind::Reno => Box::new(Reno::new(mss)),
+        CongestionKind::Cubic => Box::new(Cubic::new(mss))
+    }
+}
+
+fn secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+/// A couple bandwidth-delay products; unlimited until there's an estimate, so only the window size applies
+pub struct Bbr {
+    mss: u64,
+    bandwidth: Option<f64>,
+    min_rtt: Option<Duration>
+}
+
+impl Bbr {
+    pub fn new(mss: usize) -> Bbr {
+        Bbr { mss: mss as u64, bandwidth: None, min_rtt: None }
+    }
+}
+
+impl CongestionControl for Bbr {
+    fn on_ack(&mut self, _bytes: u64, _rtt: Option<Duration>) {
+    }
+
+    // BBR doesn't treat loss as a sign of congestion
+    fn on_loss(&mut self, _bytes: u64) {
+    }
+
+    // nor a timeout, its estimates age out on their own
+    fn on_timeout(&mut self, _bytes: u64) {
+    }
+
+    fn on_estimate(&mut self, bandwidth: f64, min_rtt: Duration) {
+        self.bandwidth = Some(bandwidth);
+        self.min_rtt = Some(min_rtt);
+    }
+
+    fn cwnd(&self) -> u64 {
+        match (self.bandwidth, self.min_rtt) {
+            (Some(bw), Some(rtt)) => ((CWND_GAIN * bw * secs(rtt)).ceil() as u64).max(MIN_CWND * self.mss),
+            _ => u64::max_value()
+        }
+    }
+}
+
+/// Only one back off per round trip: the losses from a single congestion event all arrive together
+/// Returns true if a loss now should back off, and if so starts a new round trip of ignoring them
+fn start_recovery(recovery_until: &mut Option<Instant>, rtt: Duration) -> bool {
+    let now = Instant::now();
+
+    if recovery_until.map_or(false, |until| now < until) {
+        return false;
+    }
+
+    *recovery_until = Some(now + rtt);
+
+    return true;
+}
+
+/// TCP Reno, as in RFC 5681: doubles every round trip until the first loss, then grows a packet per round trip and halves on loss
+pub struct Reno {
+    mss: u64,
+    cwnd: u64,
+    ssthresh: u64,      // slow start below this, congestion avoidance above it
+    rtt: Duration,      // the latest sample
+    recovery_until: Option<Instant>     // losses until then are from the same congestion event
+}
+
+impl Reno {
+    pub fn new(mss: usize) -> Reno {
+        Reno { mss: mss as u64, cwnd: INITIAL_CWND * mss as u64, ssthresh: u64::max_value(), rtt: Duration::from_secs(0), recovery_until: None }
+    }
+
+    fn in_slow_start(&self) -> bool {
+        self.cwnd < self.ssthresh
+    }
+}
+
+impl CongestionControl for Reno {
+    fn on_ack(&mut self, bytes: u64, rtt: Option<Duration>) {
+        if let Some(rtt) = rtt {
+            self.rtt = rtt;
+        }
+
+        if self.in_slow_start() {
+            self.cwnd += bytes;
+        } else {
+            // a window's worth of ACKs adds up to one packet
+            self.cwnd += (self.mss * bytes / self.cwnd).max(1);
+        }
+    }
+
+    fn on_loss(&mut self, _bytes: u64) {
+        if !start_recovery(&mut self.recovery_until, self.rtt) {
+            return;
+        }
+
+        self.ssthresh = (self.cwnd / 2).max(LOSS_MIN_CWND * self.mss);
+        self.cwnd = self.ssthresh;
+    }
+
+    // back to slow start, from a single packet, up to half of where the window was
+    fn on_timeout(&mut self, _bytes: u64) {
+        if start_recovery(&mut self.recovery_until, self.rtt) {
+            self.ssthresh = (self.cwnd / 2).max(LOSS_MIN_CWND * self.mss);
+        }
+
+        self.cwnd = LOSS_WINDOW * self.mss;
+    }
+
+    fn cwnd(&self) -> u64 {
+        self.cwnd
+    }
+}
+
+/// CUBIC, as in RFC 8312: after a loss, grows quickly back toward where it was, slowly past it, then quickly again
+/// Never slower than Reno would be
+pub struct Cubic {
+    mss: u64,
+    cwnd: f64,          // bytes, fractional so the tiny increments near w_max aren't lost
+    ssthresh: f64,
+    w_max: f64,         // the window, in packets, at the last loss
+    epoch: Option<(Instant, f64, f64)>,     // since the last loss: when the first ACK came, K, and the window in packets then
+    rtt: Duration,
+    recovery_until: Option<Instant>
+}
+
+impl Cubic {
+    pub fn new(mss: usize) -> Cubic {
+        Cubic { mss: mss as u64, cwnd: (INITIAL_CWND * mss as u64) as f64, ssthresh: ::std::f64::MAX, w_max: 0.0, epoch: None, rtt: Duration::from_secs(0), recovery_until: None }
+    }
+
+    fn on_ack_at(&mut self, bytes: u64, rtt: Option<Duration>, now: Instant) {
+        let mss = self.mss as f64;
+
+        if let Some(rtt) = rtt {
+            self.rtt = rtt;
+        }
+
+        let rtt = self.rtt;
+
+        if self.cwnd < self.ssthresh {
+            self.cwnd += bytes as f64;
+            return;
+        }
+
+        let cwnd = self.cwnd / mss;
+        let w_max = self.w_max;
+        let (start, k, origin) = *self.epoch.get_or_insert_with(|| {
+            // already past the last loss, so there's nothing to grow back toward
+            if cwnd >= w_max {
+                (now, 0.0, cwnd)
+            } else {
+                (now, ((w_max - cwnd) / CUBIC_C).cbrt(), w_max)
+            }
+        });
+
+        // where the cubic will be a round trip from now, and where Reno would be
+        let t = secs(now.duration_since(start) + rtt);
+        let target = CUBIC_C * (t - k).powi(3) + origin;
+        let reno = self.w_max * CUBIC_BETA + 3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA) * t / secs(rtt).max(0.001);
+        let target = target.max(reno);
+        let acked = bytes as f64 / mss;
+
+        if target > cwnd {
+            self.cwnd += mss * acked * (target - cwnd) / cwnd;
+        } else {
+            self.cwnd += mss * acked / (100.0 * cwnd);
+        }
+    }
+
+    /// Remembers where the window was, and sets ssthresh to a fraction of it
+    fn back_off(&mut self) {
+        let cwnd = self.cwnd / self.mss as f64;
+
+        // losing again before getting back to the last w_max means there's less to go around, so let it go sooner
+        self.w_max = if cwnd < self.w_max { cwnd * (1.0 + CUBIC_BETA) / 2.0 } else { cwnd };
+        self.epoch = None;
+        self.ssthresh = (self.cwnd * CUBIC_BETA).max((LOSS_MIN_CWND * self.mss) as f64);
+    }
+}
+
+impl CongestionControl for Cubic {
+    fn on_ack(&mut self, bytes: u64, rtt: Option<Duration>) {
+        self.on_ack_at(bytes, rtt, Instant::now());
+    }
+
+    fn on_loss(&mut self, _bytes: u64) {
+        if !start_recovery(&mut self.recovery_until, self.rtt) {
+            return;
+        }
+
+        self.back_off();
+        self.cwnd = self.ssthresh;
+    }
+
+    // the same back off as a loss, but the window starts over from a single packet, in slow start up to where it would've been
+    fn on_timeout(&mut self, _bytes: u64) {
+        if start_recovery(&mut self.recovery_until, self.rtt) {
+            self.back_off();
+        }
+
+        self.epoch = None;
+        self.cwnd = (LOSS_WINDOW * self.mss) as f64;
+    }
+
+    fn cwnd(&self) -> u64 {
+        self.cwnd as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use congestion::{CongestionControl, Bbr, Reno, Cubic, CUBIC_BETA, INITIAL_CWND, LOSS_MIN_CWND, LOSS_WINDOW, MIN_CWND};
+
+    const MSS :u64 = 1000;
+    const RTT :Duration = Duration::from_millis(1);
+
+    /// ACKs a whole window, one packet at a time
+    fn ack_window<C: CongestionControl>(cc: &mut C) {
+        for _ in 0..(cc.cwnd() / MSS) {
+            cc.on_ack(MSS, Some(RTT));
+        }
+    }
+
+    #[test]
+    fn reno_slow_start() {
+        let mut reno = Reno::new(MSS as usize);
+
+        assert_eq!(INITIAL_CWND * MSS, reno.cwnd());
+        assert!(reno.in_slow_start());
+
+        // doubles every round trip
+        ack_window(&mut reno);
+        assert_eq!(2 * INITIAL_CWND * MSS, reno.cwnd());
+
+        ack_window(&mut reno);
+        assert_eq!(4 * INITIAL_CWND * MSS, reno.cwnd());
+        assert!(reno.in_slow_start());
+    }
+
+    #[test]
+    fn reno_congestion_avoidance() {
+        let mut reno = Reno::new(MSS as usize);
+
+        ack_window(&mut reno);
+        ack_window(&mut reno);
+
+        // the first loss halves the window, and ends slow start
+        reno.on_loss(MSS);
+
+        assert_eq!(2 * INITIAL_CWND * MSS, reno.cwnd());
+        assert!(!reno.in_slow_start());
+
+        // the rest of the losses from the same round trip don't count
+        reno.on_loss(MSS);
+        reno.on_loss(MSS);
+
+        assert_eq!(2 * INITIAL_CWND * MSS, reno.cwnd());
+
+        // now only about a packet per round trip
+        let before = reno.cwnd();
+
+        ack_window(&mut reno);
+
+        assert!(reno.cwnd() > before && reno.cwnd() <= before + MSS, "{} -> {}", before, reno.cwnd());
+        assert!(!reno.in_slow_start());
+
+        // a loss in a later round trip halves it again
+        thread::sleep(RTT * 2);
+        let before = reno.cwnd();
+
+        reno.on_loss(MSS);
+
+        assert_eq!(before / 2, reno.cwnd());
+    }
+
+    #[test]
+    fn reno_floor() {
+        let mut reno = Reno::new(MSS as usize);
+
+        for _ in 0..10 {
+            reno.on_ack(MSS, Some(RTT));
+            thread::sleep(RTT * 2);
+            reno.on_loss(MSS);
+        }
+
+        assert_eq!(LOSS_MIN_CWND * MSS, reno.cwnd());
+    }
+
+    #[test]
+    fn reno_timeout() {
+        let mut reno = Reno::new(MSS as usize);
+
+        ack_window(&mut reno);
+        ack_window(&mut reno);
+
+        // starts over from a single packet, w/slow start ending at half the window
+        reno.on_timeout(MSS);
+
+        assert_eq!(LOSS_WINDOW * MSS, reno.cwnd());
+        assert_eq!(2 * INITIAL_CWND * MSS, reno.ssthresh);
+        assert!(reno.in_slow_start());
+
+        // the rest of the window timing out doesn't halve it again
+        reno.on_timeout(MSS);
+        reno.on_timeout(MSS);
+
+        assert_eq!(2 * INITIAL_CWND * MSS, reno.ssthresh);
+
+        // then doubles back up to there
+        ack_window(&mut reno);
+        assert_eq!(2 * LOSS_WINDOW * MSS, reno.cwnd());
+    }
+
+    #[test]
+    fn retransmit_acks_skip_rtt() {
+        let mut reno = Reno::new(MSS as usize);
+
+        reno.on_ack(MSS, Some(RTT));
+        reno.on_ack(MSS, None);
+
+        // still counted, but w/out the sample
+        assert_eq!((INITIAL_CWND + 2) * MSS, reno.cwnd());
+        assert_eq!(RTT, reno.rtt);
+
+        let mut cubic = Cubic::new(MSS as usize);
+
+        cubic.on_ack(MSS, Some(RTT));
+        cubic.on_ack(MSS, None);
+
+        assert_eq!((INITIAL_CWND + 2) * MSS, cubic.cwnd());
+        assert_eq!(RTT, cubic.rtt);
+    }
+
+    #[test]
+    fn cubic_grows_back() {
+        let mut cubic = Cubic::new(MSS as usize);
+
+        // slow start, same as Reno
+        ack_window(&mut cubic);
+        assert_eq!(2 * INITIAL_CWND * MSS, cubic.cwnd());
+
+        cubic.on_loss(MSS);
+
+        let w_max = 2 * INITIAL_CWND * MSS;
+        let after_loss = cubic.cwnd();
+
+        assert_eq!((w_max as f64 * CUBIC_BETA) as u64, after_loss);
+
+        // a long round trip, so Reno's growth doesn't overtake the cubic
+        // it's back to w_max at K, about 2.5s after the loss counting the round trip; never past it before
+        let rtt = Duration::from_secs(1);
+        let start = Instant::now();
+
+        for i in 0..15 {
+            cubic.on_ack_at(MSS, Some(rtt), start + Duration::from_millis(100) * i);
+        }
+
+        assert!(cubic.cwnd() > after_loss && cubic.cwnd() < w_max, "{}", cubic.cwnd());
+
+        // and well past it after
+        for i in 15..60 {
+            cubic.on_ack_at(MSS, Some(rtt), start + Duration::from_millis(100) * i);
+        }
+
+        assert!(cubic.cwnd() > w_max, "{}", cubic.cwnd());
+    }
+
+    #[test]
+    fn bbr_cwnd_from_bdp() {
+        let mut bbr = Bbr::new(MSS as usize);
+
+        // no estimate yet
+        assert_eq!(u64::max_value(), bbr.cwnd());
+
+        // 1MB/s over 100ms is 100KB, twice that's in flight
+        bbr.on_estimate(1_000_000.0, Duration::from_millis(100));
+        assert_eq!(200_000, bbr.cwnd());
+
+        // losses don't matter
+        bbr.on_loss(MSS);
+        assert_eq!(200_000, bbr.cwnd());
+
+        // a couple BDPs is only a handful of packets, so the floor kicks in
+        bbr.on_estimate(100_000.0, Duration::from_millis(20));
+        assert_eq!(MIN_CWND * MSS, bbr.cwnd());
+    }
+}
@@ -0,0 +1,76 @@
+//! CRC-32 (IEEE 802.3, as used by zlib and Ethernet) for checksumming packets
+
+const POLYNOMIAL :u32 = 0xEDB8_8320;   // reversed
+
+const TABLE :[u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+/// A running CRC-32, for checksumming data that isn't all in one place
+pub struct Crc32 {
+    crc: u32
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { crc: 0xFFFF_FFFF }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.crc = TABLE[((self.crc ^ b as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+/// The CRC-32 of bytes
+#[cfg(test)]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+
+    crc.update(bytes);
+
+    return crc.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use crc32::{crc32, Crc32};
+
+    #[test]
+    fn known_values() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+        assert_eq!(0x414F_A339, crc32(b"The quick brown fox jumps over the lazy dog"));
+
+        // the same, in pieces
+        let mut crc = Crc32::new();
+
+        crc.update(b"The quick brown ");
+        crc.update(b"fox jumps over the lazy dog");
+
+        assert_eq!(0x414F_A339, crc.finish());
+    }
+}
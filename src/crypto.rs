@@ -0,0 +1,192 @@
+//! ChaCha20-Poly1305 (RFC 8439), w/the chacha20poly1305 crate, for encrypting payloads w/a key derived in the handshake
+
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, Payload};
+
+use sha256::{hmac_sha256, mac_eq};
+
+pub const KEY_SIZE :usize = 32;
+pub const NONCE_SIZE :usize = 12;
+pub const TAG_SIZE :usize = 16;    // added to every encrypted payload
+pub const SESSION_ID_SIZE :usize = 8;
+pub const AUTH_OVERHEAD :usize = SESSION_ID_SIZE + TAG_SIZE;   // added to every tagged packet
+
+const SESSION_LABEL :&[u8] = b"qcp session";
+const PACKET_LABEL :&[u8] = b"qcp packet";
+const SESSION_ID_LABEL :&[u8] = b"qcp session id";
+
+/// Derives the key for a transfer from the pre-shared key, and the nonces from the Connect and its Acknowledge
+/// A fresh nonce from each end every handshake means a fresh key, so sequence numbers can be reused as nonces,
+/// and a replayed Connect can't bring back an old transfer's key
+pub fn derive_key(psk: &[u8], nonce: &[u8]) -> [u8; KEY_SIZE] {
+    hmac_sha256(psk, &[SESSION_LABEL, nonce])
+}
+
+/// Encrypts and authenticates payloads w/ChaCha20-Poly1305
+/// A nonce must never be used twice w/the same key
+#[derive(Clone)]
+pub struct Cipher {
+    aead: ChaCha20Poly1305
+}
+
+impl Cipher {
+    pub fn new(key: [u8; KEY_SIZE]) -> Cipher {
+        Cipher { aead: ChaCha20Poly1305::new(Key::from_slice(&key)) }
+    }
+
+    /// Encrypts plaintext, returning the ciphertext w/the tag on the end
+    pub fn seal(&self, nonce: &[u8; NONCE_SIZE], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        return self.aead.encrypt(Nonce::from_slice(nonce), Payload { msg: plaintext, aad }).expect("Payload too large to encrypt");
+    }
+
+    /// Checks the tag on the end of sealed, and decrypts the rest
+    /// Returns None if sealed was tampered w/, or wasn't sealed w/this key, nonce, and aad
+    pub fn open(&self, nonce: &[u8; NONCE_SIZE], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        return self.aead.decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad }).ok();
+    }
+}
+
+/// Tags every packet after the handshake, so nothing can be injected into, or altered in, a transfer
+/// Keyed separately from the Cipher, as packets are tagged whether or not payloads are encrypted
+/// The session id goes before the tag, so a packet from a sender that's moved can be told apart from a stranger's
+#[derive(Clone)]
+pub struct PacketAuth {
+    key: [u8; KEY_SIZE],
+    session_id: [u8; SESSION_ID_SIZE]
+}
+
+impl PacketAuth {
+    /// Derives the key, and the session id, from the pre-shared key and the handshake's nonces, like derive_key
+    pub fn new(psk: &[u8], nonce: &[u8]) -> PacketAuth {
+        let mut session_id = [0u8; SESSION_ID_SIZE];
+
+        session_id.copy_from_slice(&hmac_sha256(psk, &[SESSION_ID_LABEL, nonce])[..SESSION_ID_SIZE]);
+
+        PacketAuth { key: hmac_sha256(psk, &[PACKET_LABEL, nonce]), session_id }
+    }
+
+    /// The tag over the whole packet and the session id, truncated HMAC-SHA256
+    fn tag(&self, packet: &[u8]) -> [u8; TAG_SIZE] {
+        let mut tag = [0u8; TAG_SIZE];
+
+        tag.copy_from_slice(&hmac_sha256(&self.key, &[packet, &self.session_id])[..TAG_SIZE]);
+
+        return tag;
+    }
+
+    /// The packet w/the session id and its tag on the end
+    pub fn sign(&self, packet: &[u8]) -> Vec<u8> {
+        let mut signed = Vec::with_capacity(packet.len() + AUTH_OVERHEAD);
+
+        self.sign_into(packet, &mut signed);
+
+        return signed;
+    }
+
+    /// Like sign, but appends to buf, so a buffer can be reused
+    pub fn sign_into(&self, packet: &[u8], buf: &mut Vec<u8>) {
+        buf.extend_from_slice(packet);
+        buf.extend_from_slice(&self.session_id);
+        buf.extend_from_slice(&self.tag(packet));
+    }
+
+    /// Checks the session id and tag on the end of signed, returning the packet without them
+    /// None if it's too short to have them, or they're not the right ones
+    pub fn verify<'a>(&self, signed: &'a [u8]) -> Option<&'a [u8]> {
+        if signed.len() < AUTH_OVERHEAD {
+            return None;
+        }
+
+        let (packet, trailer) = signed.split_at(signed.len() - AUTH_OVERHEAD);
+        let (session_id, tag) = trailer.split_at(SESSION_ID_SIZE);
+
+        // another session's, no need to compute the tag
+        if session_id != self.session_id {
+            return None;
+        }
+
+        if !mac_eq(&self.tag(packet), tag) {
+            return None;
+        }
+
+        return Some(packet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto::{Cipher, PacketAuth, KEY_SIZE, NONCE_SIZE, TAG_SIZE, SESSION_ID_SIZE, AUTH_OVERHEAD};
+
+    fn unhex(s: &str) -> Vec<u8> {
+        let s = s.replace(" ", "");
+
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i+2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn aead_known_values() {
+        // RFC 8439, 2.8.2
+        let mut key = [0u8; KEY_SIZE];
+        let mut nonce = [0u8; NONCE_SIZE];
+
+        for i in 0..KEY_SIZE {
+            key[i] = 0x80 + i as u8;
+        }
+
+        nonce.copy_from_slice(&unhex("070000004041424344454647"));
+
+        let aad = unhex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let cipher = Cipher::new(key);
+        let sealed = cipher.seal(&nonce, &aad, plaintext);
+
+        assert_eq!(plaintext.len() + TAG_SIZE, sealed.len());
+        assert_eq!(unhex("d31a8d34648e60db7b86afbc53ef7ec2"), &sealed[..16]);
+        assert_eq!(unhex("1ae10b594f09e26a7e902ecbd0600691"), &sealed[plaintext.len()..]);
+
+        assert_eq!(&plaintext[..], &cipher.open(&nonce, &aad, &sealed).expect("Error opening")[..]);
+
+        // any change is caught
+        let mut tampered = sealed.clone();
+        tampered[3] ^= 0x01;
+
+        assert!(cipher.open(&nonce, &aad, &tampered).is_none());
+        assert!(cipher.open(&nonce, &aad[1..], &sealed).is_none());
+        assert!(cipher.open(&[0; NONCE_SIZE], &aad, &sealed).is_none());
+        assert!(Cipher::new([0; KEY_SIZE]).open(&nonce, &aad, &sealed).is_none());
+        assert!(cipher.open(&nonce, &aad, &sealed[..TAG_SIZE - 1]).is_none());
+    }
+
+    #[test]
+    fn packet_auth() {
+        let auth = PacketAuth::new(b"secret", b"nonce");
+        let signed = auth.sign(b"packet");
+
+        assert_eq!(b"packet".len() + AUTH_OVERHEAD, signed.len());
+        assert_eq!(Some(&b"packet"[..]), auth.verify(&signed));
+
+        // any change, or another session's key, is caught
+        let mut tampered = signed.clone();
+        tampered[0] ^= 0x01;
+
+        assert!(auth.verify(&tampered).is_none());
+        assert!(auth.verify(&signed[..signed.len() - 1]).is_none());
+        assert!(auth.verify(&signed[..TAG_SIZE - 1]).is_none());
+        assert!(auth.verify(b"packet").is_none());
+        assert!(PacketAuth::new(b"secret", b"other nonce").verify(&signed).is_none());
+
+        // as is another session's id
+        let mut tampered = signed.clone();
+        tampered[b"packet".len()] ^= 0x01;
+
+        assert!(auth.verify(&tampered).is_none());
+
+        // which is the same for both ends of a session, and only that session
+        let session_id = |auth: &PacketAuth| auth.sign(b"")[..SESSION_ID_SIZE].to_vec();
+
+        assert_eq!(session_id(&auth), session_id(&PacketAuth::new(b"secret", b"nonce")));
+        assert_ne!(session_id(&auth), session_id(&PacketAuth::new(b"secret", b"other nonce")));
+        assert_ne!(session_id(&auth), session_id(&PacketAuth::new(b"other secret", b"nonce")));
+    }
+}
@@ -0,0 +1,100 @@
+//! The errors connecting, listening, and parsing the command line return, so a caller can tell what went wrong w/out reading the message
+//! Reading and writing a Transport still return io::Error, as Read and Write do; a QcpError converts to one of the same kind
+
+use std::error::Error;
+use std::fmt;
+use std::io::{Error as IOError, ErrorKind};
+
+
+/// Everything but Config keeps the io::Error it came from, so its kind and message aren't lost
+#[derive(Debug)]
+pub enum QcpError {
+    /// The other end turned us down, or isn't qcp, or speaks another version of it
+    Handshake(IOError),
+    /// The other end broke the protocol, like agreeing to more streams than it was asked for
+    Protocol(IOError),
+    /// The socket, or a file
+    Io(IOError),
+    /// A packet in the handshake was corrupted on the way
+    Checksum(IOError),
+    /// The other end never answered
+    Timeout(IOError),
+    /// An option, or a combination of them, that can't work
+    Config(String)
+}
+
+impl QcpError {
+    /// The kind of the io::Error underneath, or InvalidInput for a Config error
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            QcpError::Handshake(ref e) | QcpError::Protocol(ref e) | QcpError::Io(ref e) | QcpError::Checksum(ref e) | QcpError::Timeout(ref e) => e.kind(),
+            QcpError::Config(_) => ErrorKind::InvalidInput
+        }
+    }
+}
+
+impl fmt::Display for QcpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QcpError::Handshake(ref e) => write!(f, "Handshake failed: {}", e),
+            QcpError::Protocol(ref e) => write!(f, "Protocol error: {}", e),
+            QcpError::Io(ref e) => write!(f, "{}", e),
+            QcpError::Checksum(ref e) => write!(f, "Corrupt packet: {}", e),
+            QcpError::Timeout(ref e) => write!(f, "Timed out: {}", e),
+            QcpError::Config(ref msg) => write!(f, "Invalid configuration: {}", msg)
+        }
+    }
+}
+
+impl Error for QcpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            QcpError::Handshake(ref e) | QcpError::Protocol(ref e) | QcpError::Io(ref e) | QcpError::Checksum(ref e) | QcpError::Timeout(ref e) => Some(e),
+            QcpError::Config(_) => None
+        }
+    }
+}
+
+impl From<IOError> for QcpError {
+    fn from(e: IOError) -> QcpError {
+        return QcpError::Io(e);
+    }
+}
+
+/// So ? works in functions that return io::Error; the QcpError is inside it, for get_ref to find
+impl From<QcpError> for IOError {
+    fn from(e: QcpError) -> IOError {
+        match e {
+            QcpError::Io(e) => return e,
+            e => return IOError::new(e.kind(), e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error as IOError, ErrorKind};
+
+    use error::QcpError;
+
+    #[test]
+    fn into_io_error() {
+        // an Io error is unwrapped, not wrapped again
+        let err = IOError::from(QcpError::from(IOError::new(ErrorKind::NotFound, "gone")));
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.get_ref().expect("No inner error").downcast_ref::<QcpError>().is_none());
+
+        // anything else keeps its kind, and can be found inside
+        let err = IOError::from(QcpError::Handshake(IOError::new(ErrorKind::ConnectionRefused, "no")));
+
+        assert_eq!(ErrorKind::ConnectionRefused, err.kind());
+
+        match err.get_ref().and_then(|e| e.downcast_ref::<QcpError>()) {
+            Some(&QcpError::Handshake(_)) => (),
+            e => panic!("Expected a Handshake error inside, got {:?}", e)
+        }
+
+        assert_eq!(ErrorKind::InvalidInput, IOError::from(QcpError::Config("bad".to_string())).kind());
+    }
+}
@@ -0,0 +1,125 @@
+//! Forward error correction, for links w/out a return path for ACKs
+//! Every block of data packets is followed by a parity packet, the XOR of the block's payloads,
+//! from which any single packet missing from the block can be rebuilt
+
+pub const PARITY_HEADER_SIZE :usize = 2;    // payloads are prefixed w/their length, so short ones can be rebuilt
+
+/// The running XOR of a block of payloads
+pub struct Parity {
+    bytes: Vec<u8>,
+    count: usize    // the number of payloads XORed in so far
+}
+
+impl Parity {
+    pub fn new() -> Parity {
+        Parity { bytes: Vec::new(), count: 0 }
+    }
+
+    /// XOR a payload into the parity
+    pub fn add(&mut self, payload: &[u8]) {
+        let len = payload.len();
+
+        if len > u16::max_value() as usize {
+            panic!("Payload too large for parity: {}", len);
+        }
+
+        // shorter payloads are implicitly padded w/zeros
+        if self.bytes.len() < PARITY_HEADER_SIZE + len {
+            self.bytes.resize(PARITY_HEADER_SIZE + len, 0);
+        }
+
+        self.bytes[0] ^= (len >> 8) as u8;
+        self.bytes[1] ^= len as u8;
+
+        for (b, p) in self.bytes[PARITY_HEADER_SIZE..].iter_mut().zip(payload) {
+            *b ^= *p;
+        }
+
+        self.count += 1;
+    }
+
+    /// The number of payloads XORed in so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The parity itself, what's sent in a Parity message
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Given the parity of the whole block, rebuild the one payload missing from this one
+    /// Returns None if the parity isn't consistent w/the payloads we have
+    pub fn recover(&self, parity: &[u8]) -> Option<Vec<u8>> {
+        if parity.len() < PARITY_HEADER_SIZE || parity.len() < self.bytes.len() {
+            return None;
+        }
+
+        let mut missing = parity.to_vec();
+
+        for (m, b) in missing.iter_mut().zip(&self.bytes) {
+            *m ^= *b;
+        }
+
+        let len = ((missing[0] as usize) << 8) | missing[1] as usize;
+
+        if PARITY_HEADER_SIZE + len > missing.len() {
+            return None;
+        }
+
+        missing.truncate(PARITY_HEADER_SIZE + len);
+
+        return Some(missing.split_off(PARITY_HEADER_SIZE));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fec::Parity;
+
+    #[test]
+    fn recover_any_one() {
+        let block :Vec<Vec<u8>> = vec![vec![1; 100], vec![2; 37], vec![], vec![4; 100], vec![5; 99]];
+
+        let mut parity = Parity::new();
+
+        for payload in &block {
+            parity.add(payload);
+        }
+
+        assert_eq!(block.len(), parity.count());
+
+        for missing in 0..block.len() {
+            let mut partial = Parity::new();
+
+            for (i, payload) in block.iter().enumerate() {
+                if i != missing {
+                    partial.add(payload);
+                }
+            }
+
+            assert_eq!(Some(block[missing].clone()), partial.recover(parity.bytes()));
+        }
+    }
+
+    #[test]
+    fn bad_parity() {
+        let mut partial = Parity::new();
+
+        partial.add(&[1; 10]);
+
+        assert_eq!(None, partial.recover(&[]));
+        assert_eq!(None, partial.recover(&[0; 5]));
+
+        // claims a payload longer than the parity
+        let mut parity = Parity::new();
+
+        parity.add(&[1; 10]);
+        parity.add(&[2; 8]);
+
+        let mut bytes = parity.bytes().to_vec();
+        bytes[1] ^= 0xFF;
+
+        assert_eq!(None, partial.recover(&bytes));
+    }
+}
@@ -0,0 +1,88 @@
+//! Quick-Copy, for quickly copying large or many files between computers, as a library
+//! BbrSender and BbrReceiver move a stream of bytes over an already-bound UdpSocket, set up by a Configuration
+//! transfer sends and receives files over them, or any other Transport, the way the qcp binary does
+//!
+//! ```
+//! extern crate qcp;
+//!
+//! use std::net::UdpSocket;
+//! use std::thread;
+//!
+//! use qcp::{BbrSender, BbrReceiver, Configuration, Transport};
+//!
+//! let recv_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+//! let mut config = Configuration::default();
+//!
+//! // the sender connects to wherever the receiver is bound
+//! config.set_addr(recv_socket.local_addr().unwrap());
+//!
+//! let recv_handle = thread::spawn(move || {
+//!     let mut recver = BbrReceiver::listen(recv_socket, &Configuration::default()).expect("Couldn't create receiver");
+//!     let mut buf = vec![0; 1500];
+//!     let mut received = Vec::new();
+//!
+//!     loop {
+//!         let amt = recver.read(&mut buf).expect("Error calling read");
+//!
+//!         // the sender finished
+//!         if amt == 0 {
+//!             break;
+//!         }
+//!
+//!         received.extend_from_slice(&buf[0..amt]);
+//!     }
+//!
+//!     received
+//! });
+//!
+//! let send_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+//! let mut sender = BbrSender::connect(send_socket, &config).expect("Couldn't call connect");
+//!
+//! sender.write_all(b"hello over loopback").expect("Error calling write_all");
+//! sender.finish().expect("Error calling finish");
+//!
+//! assert_eq!(b"hello over loopback".to_vec(), recv_handle.join().expect("Recv thread panicked"));
+//! ```
+
+#[macro_use] extern crate clap;
+extern crate flatbuffers;
+#[macro_use] extern crate log;
+extern crate rand;
+extern crate libc;
+extern crate chacha20poly1305;
+extern crate hmac;
+extern crate sha2;
+extern crate crc32c;
+extern crate xxhash_rust;
+#[cfg(test)] extern crate simplelog;
+
+use std::net::UdpSocket;
+
+pub mod config;
+pub mod error;
+pub mod transport;
+pub mod tcp_transport;
+pub mod bbr_transport;
+mod bbr;
+mod congestion;
+mod crc32;
+mod sha256;
+mod crypto;
+mod message_generated;
+pub mod sliding_window;
+mod pool;
+pub mod socket;
+mod fec;
+pub mod sandbox;
+pub mod transfer;
+
+pub use config::Configuration;
+pub use error::QcpError;
+pub use transport::Transport;
+pub use bbr_transport::{FileHeader, TransferStats, ReceiverStats, ProgressReport};
+
+/// Sends over a UdpSocket via BBR, see Sender::connect
+pub type BbrSender = bbr_transport::Sender<UdpSocket>;
+
+/// Receives over a UdpSocket via BBR, see Receiver::listen
+pub type BbrReceiver = bbr_transport::Receiver<UdpSocket>;
@@ -1,75 +1,283 @@
-#[macro_use] extern crate clap;
-extern crate flatbuffers;
+extern crate qcp;
 #[macro_use] extern crate log;
 extern crate simplelog;
-extern crate rand;
 
 
-use std::io::Error as IOError;
-use std::process::exit;
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write, Error as IOError, ErrorKind};
+use std::fs;
 use std::error::Error;
-use std::net::{SocketAddr, UdpSocket};
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use simplelog::{TermLogger, LevelFilter, Config};
+use simplelog::{WriteLogger, LevelFilter, Config};
 
-mod config;
-mod transport;
-mod tcp_transport;
-mod bbr_transport;
-mod message_generated;
-mod sliding_window;
-mod socket;
+use qcp::{BbrSender, BbrReceiver, Configuration, Transport};
+use qcp::config::{ProgressFormat, TransportKind};
+use qcp::bbr_transport::{BbrConnection, Sender};
+#[cfg(unix)]
+use qcp::bbr_transport::Receiver;
+use qcp::{tcp_transport, transfer, sandbox, socket};
+use qcp::socket::Socket;
+#[cfg(unix)]
+use qcp::socket::UnixDatagramSocket;
 
-use config::Configuration;
-use transport::Transport;
+/// Connects to the receiver over whichever transport was asked for
+/// total_bytes is what's about to be sent, for showing progress, if it's known
+fn connect(config: &Configuration, total_bytes: Option<u64>) -> Result<Box<Transport>, IOError> {
+    if config.transport() == TransportKind::Tcp {
+        return Ok(Box::new(tcp_transport::connect(config)?));
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(path) = config.unix_socket() {
+            let socket = UnixDatagramSocket::connect_to(path)?;
+
+            return Ok(with_progress(Sender::connect(socket, config)?, config, total_bytes));
+        }
+    }
+
+    let socket = socket::bind(config.local_addr(), "--local-port")?;
+
+    return Ok(with_progress(BbrSender::connect(socket, config)?, config, total_bytes));
+}
+
+/// Shows the percentage of total_bytes acknowledged as the sender goes, if it's known, or else the bytes acknowledged
+/// Or a line of JSON every so often, w/a total of 0 if it's not
+fn with_progress<T: Socket + Send + Sync + 'static>(mut sender: Sender<T>, config: &Configuration, total_bytes: Option<u64>) -> Box<Transport> {
+    if config.progress_format() == ProgressFormat::Json {
+        sender.set_total_bytes(total_bytes.unwrap_or(0));
+        sender.set_report(config.progress_interval(), Box::new(|report| eprintln!("{}", report.to_json())));
 
-use bbr_transport::{Sender, Receiver, MAX_PAYLOAD_SIZE};
+        return Box::new(sender);
+    }
+
+    let total_bytes = match total_bytes {
+        Some(total_bytes) => total_bytes,
+        None => {
+            let last_mib = AtomicU64::new(0);
+
+            // every MiB, rather than every ACK
+            sender.set_progress(Box::new(move |acked, _total| {
+                let mib = acked >> 20;
+
+                if last_mib.swap(mib, Ordering::Relaxed) != mib {
+                    eprint!("\r{} bytes", acked);
+                }
+            }));
+
+            return Box::new(sender);
+        }
+    };
+    let last_percent = AtomicU64::new(0);
+
+    // show the percentage acknowledged, every time it changes
+    sender.set_total_bytes(total_bytes);
+    sender.set_progress(Box::new(move |acked, total| {
+        let percent = if total == 0 { 100 } else { acked * 100 / total };
+
+        if last_percent.swap(percent, Ordering::Relaxed) != percent {
+            eprint!("\r{:3}%", percent);
+        }
+    }));
+
+    return Box::new(sender);
+}
+
+/// Waits for a sender over whichever transport was asked for
+fn listen(config: &Configuration) -> Result<Box<Transport>, IOError> {
+    if config.transport() == TransportKind::Tcp {
+        return Ok(Box::new(tcp_transport::listen(config)?));
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(path) = config.unix_socket() {
+            let socket = UnixDatagramSocket::bind(path)?;
+
+            return Ok(Box::new(Receiver::listen(socket, config)?));
+        }
+    }
+
+    let socket = bind_receiver(config)?;
+
+    return Ok(Box::new(BbrReceiver::listen(socket, config)?));
+}
+
+/// Binds the receiver's socket, to IPv4 and IPv6 at once w/--dual-stack
+fn bind_receiver(config: &Configuration) -> Result<UdpSocket, IOError> {
+    #[cfg(unix)]
+    {
+        if config.dual_stack() {
+            return socket::bind_dual_stack(config.addr().port(), "--port");
+        }
+    }
+
+    return socket::bind(config.addr(), "--port");
+}
 
 fn main() -> Result<(), Box<Error>> {
-    TermLogger::init(LevelFilter::Debug, Config::default()).unwrap();
+    // before the arguments are parsed, so a warning along the way isn't lost
+    // stdout is for the data when receiving to it, so keep the logging out of it
+    WriteLogger::init(LevelFilter::Trace, Config::default(), io::stderr()).unwrap();
+    log::set_max_level(LevelFilter::Warn);
 
     let config = Configuration::new()?;
 
+    // as much as -v asked for, from here on
+    log::set_max_level(config.log_level());
+
     if config.sender() {
-        let remote_addr = config.addr();
-        let local_addr = SocketAddr::new("0.0.0.0".parse().unwrap(), 1234);
-        let socket = UdpSocket::bind(local_addr)?;
+        // nothing but its own packets would come back, so the handshake just times out
+        if config.connects_to_self() {
+            warn!("Sending from {} to {}, which is this same socket; --local-port is probably meant to be something other than --port", config.local_addr(), config.addr());
+        }
+
+        if config.connect_only() {
+            let socket = socket::bind(config.local_addr(), "--local-port")?;
+            let result = BbrSender::probe(socket, &config)?;
+
+            println!("{}", result);
+
+            return Ok( () );
+        }
+
+        // each file whole on one of the streams, w/out progress or stats, as they're all going at once
+        if config.streams() > 1 {
+            let socket = socket::bind(config.local_addr(), "--local-port")?;
+            let senders = BbrSender::connect_streams(socket, &config, config.streams())?;
+
+            transfer::send_streams(senders, config.files(), config.preserve(), config.block_size())?;
+
+            return Ok( () );
+        }
 
-        let mut sender = Sender::<UdpSocket>::connect(socket, &config)?;
-        let mut file = OpenOptions::new().read(true).create(false).open(config.file())?;
+        // receiving what the receiver sends back, while sending ours
+        if let Some(pull) = config.pull() {
+            let socket = socket::bind(config.local_addr(), "--local-port")?;
+            let (sender, mut recver) = BbrConnection::<UdpSocket>::connect(socket, &config)?.split();
 
-        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+            let written = transfer::exchange(sender, &mut recver, config.files(), config.preserve(), config.block_size(), pull)?;
 
-        loop {
-            let amt = file.read(&mut buf)?;
+            info!("Received {} back", written.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join(", "));
 
-            if amt == 0 {
-                break;
+            return Ok( () );
+        }
+
+        // there's no telling how much stdin has
+        let file_size = if config.stdio() {
+            None
+        } else {
+            let mut file_size = 0;
+
+            // the first to say a file isn't there, so say which
+            for file in config.files() {
+                file_size += fs::metadata(file).map_err(|e| transfer::open_error(e, "source file", file))?.len();
             }
 
-            sender.write_all(&buf[0..amt]);
+            Some(file_size)
+        };
+
+        let mut transport = connect(&config, file_size)?;
+
+        if config.stdio() {
+            let mut reader :Box<Read + Send> = Box::new(io::stdin());
+
+            // announced as a stream of unknown length, when there's a way to
+            if transport.sends_headers() {
+                transfer::send_stream(&mut *transport, &mut reader)?;
+            } else {
+                transfer::run_sender(&mut *transport, &mut reader)?;
+            }
+        } else if config.verify_only() {
+            let verified = transfer::verify_files(&mut *transport, config.files(), config.block_size().expect("Expected block-size w/verify-only"))?;
+
+            // a line for each range that differs, so identical files print nothing
+            for (file, ranges) in verified {
+                for range in ranges {
+                    println!("{}: {}-{}", file.display(), range.start, range.end);
+                }
+            }
+        } else {
+            transfer::send_files(&mut *transport, config.files(), config.preserve(), config.block_size())?;
         }
-    } else {
-        let local_addr = config.addr();
-        let socket = UdpSocket::bind(local_addr)?;
 
-        let mut recver = Receiver::<UdpSocket>::listen(socket, &config)?;
-        let mut file = OpenOptions::new().write(true).create(true).open(config.file())?;
+        // make sure everything made it, before saying we're done
+        transport.flush()?;
+        transport.finish()?;
 
-        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        // past the percentage
+        if config.progress_format() == ProgressFormat::Percent {
+            eprintln!();
+        }
 
-        loop {
-            let amt = recver.read(&mut buf)?;
+        if let Some(stats) = transport.stats() {
+            println!("{}", stats);
 
-            if amt == 0 {
-                break;
+            // a delta skips whatever the receiver already has
+            if let Some(file_size) = file_size.filter(|_| config.block_size().is_none()) {
+                if stats.bytes != file_size {
+                    warn!("Sent {} bytes, but the files are {} bytes", stats.bytes, file_size);
+                }
             }
+        }
+    } else if config.stdio() {
+        let mut transport = listen(&config)?;
 
-            file.write_all(&buf[0..amt]);
+        // there's only the one stream to write to, and a lone file's attributes mean nothing to it
+        match transport.next_file()? {
+            Some(ref header) if !header.lone && !header.name.is_empty() => return Err(From::from(IOError::new(ErrorKind::InvalidData, format!("Sender is sending more than one file, starting w/{}; they can't all go to stdout", header.name)))),
+            Some(ref header) if header.block_size.is_some() => return Err(From::from(IOError::new(ErrorKind::InvalidData, "Sender is sending a delta, but there's no copy at stdout to keep any of"))),
+            _ => ()
         }
+
+        let mut writer :Box<Write + Send> = Box::new(io::stdout());
+        let received = transfer::run_receiver(&mut *transport, &mut writer)?;
+
+        writer.flush()?;
+
+        info!("Received {} bytes", received);
+    } else {
+        // the directory to put the sender's files in by name, or else where to put them
+        let target = match config.output_dir() {
+            Some(dir) => dir,
+            None => config.file()
+        };
+
+        // never write outside of the root, if there is one
+        let path = match config.root() {
+            Some(root) => sandbox::resolve(root, target)?,
+            None => target.clone()
+        };
+
+        let written = if config.streams() > 1 {
+            let socket = bind_receiver(&config)?;
+            let recvers = BbrReceiver::listen_streams(socket, &config, config.streams())?;
+
+            transfer::recv_streams(recvers, &path, config.append())?
+        } else if config.duplex() {
+            let socket = bind_receiver(&config)?;
+            let (sender, mut recver) = BbrConnection::<UdpSocket>::listen(socket, &config)?.split();
+
+            // attributes only go w/--preserve, which is for the sending end
+            transfer::exchange(sender, &mut recver, config.push(), false, None, &path)?
+        } else if config.output_dir().is_some() {
+            let mut transport = listen(&config)?;
+
+            transfer::recv_into(&mut *transport, &path, config.append())?
+        } else {
+            let mut transport = listen(&config)?;
+
+            transfer::recv_files(&mut *transport, &path, config.append())?
+        };
+
+        let mut file_size = 0;
+
+        for file in &written {
+            file_size += fs::metadata(file)?.len();
+        }
+
+        info!("Received {} bytes", file_size);
     }
 
     Ok( () )
@@ -24,11 +24,22 @@ pub enum Type {
   Disconnect = 2,
   Acknowledge = 3,
   Message = 4,
+  Parity = 5,
+  Finish = 6,
+  NegativeAcknowledge = 7,
+  FileStart = 8,
+  FileEnd = 9,
+  Reverse = 10,
+  Probe = 11,
+  SelectiveAck = 12,
+  Ping = 13,
+  Pong = 14,
+  Skip = 15,
 
 }
 
 const ENUM_MIN_TYPE: i8 = 0;
-const ENUM_MAX_TYPE: i8 = 4;
+const ENUM_MAX_TYPE: i8 = 15;
 
 impl<'a> flatbuffers::Follow<'a> for Type {
   type Inner = Self;
@@ -62,21 +73,43 @@ impl flatbuffers::Push for Type {
 }
 
 #[allow(non_camel_case_types)]
-const ENUM_VALUES_TYPE:[Type; 5] = [
+const ENUM_VALUES_TYPE:[Type; 16] = [
   Type::Error,
   Type::Connect,
   Type::Disconnect,
   Type::Acknowledge,
-  Type::Message
+  Type::Message,
+  Type::Parity,
+  Type::Finish,
+  Type::NegativeAcknowledge,
+  Type::FileStart,
+  Type::FileEnd,
+  Type::Reverse,
+  Type::Probe,
+  Type::SelectiveAck,
+  Type::Ping,
+  Type::Pong,
+  Type::Skip
 ];
 
 #[allow(non_camel_case_types)]
-const ENUM_NAMES_TYPE:[&'static str; 5] = [
+const ENUM_NAMES_TYPE:[&'static str; 16] = [
     "Error",
     "Connect",
     "Disconnect",
     "Acknowledge",
-    "Message"
+    "Message",
+    "Parity",
+    "Finish",
+    "NegativeAcknowledge",
+    "FileStart",
+    "FileEnd",
+    "Reverse",
+    "Probe",
+    "SelectiveAck",
+    "Ping",
+    "Pong",
+    "Skip"
 ];
 
 pub fn enum_name_type(e: Type) -> &'static str {
@@ -84,6 +117,67 @@ pub fn enum_name_type(e: Type) -> &'static str {
   ENUM_NAMES_TYPE[index]
 }
 
+// struct SeqRange, aligned to 8
+#[repr(C, align(8))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SeqRange {
+  start_: u64,
+  end_: u64,
+} // pub struct SeqRange
+impl flatbuffers::SafeSliceAccess for SeqRange {}
+impl<'a> flatbuffers::Follow<'a> for SeqRange {
+  type Inner = &'a SeqRange;
+  #[inline]
+  fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+    <&'a SeqRange>::follow(buf, loc)
+  }
+}
+impl<'a> flatbuffers::Follow<'a> for &'a SeqRange {
+  type Inner = &'a SeqRange;
+  #[inline]
+  fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+    flatbuffers::follow_cast_ref::<SeqRange>(buf, loc)
+  }
+}
+impl<'b> flatbuffers::Push for SeqRange {
+    type Output = SeqRange;
+    #[inline]
+    fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+        let src = unsafe {
+            ::std::slice::from_raw_parts(self as *const SeqRange as *const u8, Self::size())
+        };
+        dst.copy_from_slice(src);
+    }
+}
+impl<'b> flatbuffers::Push for &'b SeqRange {
+    type Output = SeqRange;
+
+    #[inline]
+    fn push(&self, dst: &mut [u8], _rest: &[u8]) {
+        let src = unsafe {
+            ::std::slice::from_raw_parts(*self as *const SeqRange as *const u8, Self::size())
+        };
+        dst.copy_from_slice(src);
+    }
+}
+
+
+impl SeqRange {
+  pub fn new<'a>(_start: u64, _end: u64) -> Self {
+    SeqRange {
+      start_: _start.to_little_endian(),
+      end_: _end.to_little_endian(),
+
+    }
+  }
+  pub fn start<'a>(&'a self) -> u64 {
+    self.start_.from_little_endian()
+  }
+  pub fn end<'a>(&'a self) -> u64 {
+    self.end_.from_little_endian()
+  }
+}
+
 pub enum MessageOffset {}
 #[derive(Copy, Clone, Debug, PartialEq)]
 
@@ -114,7 +208,14 @@ impl<'a> Message<'a> {
         args: &'args MessageArgs<'args>) -> flatbuffers::WIPOffset<Message<'bldr>> {
       let mut builder = MessageBuilder::new(_fbb);
       builder.add_seq_num(args.seq_num);
+      builder.add_magic(args.magic);
+      builder.add_stream_id(args.stream_id);
+      if let Some(x) = args.ranges { builder.add_ranges(x); }
+      if let Some(x) = args.hash { builder.add_hash(x); }
+      if let Some(x) = args.mac { builder.add_mac(x); }
+      builder.add_checksum(args.checksum);
       if let Some(x) = args.payload { builder.add_payload(x); }
+      builder.add_version(args.version);
       builder.add_msg_type(args.msg_type);
       builder.finish()
     }
@@ -122,6 +223,13 @@ impl<'a> Message<'a> {
     pub const VT_MSG_TYPE: flatbuffers::VOffsetT = 4;
     pub const VT_SEQ_NUM: flatbuffers::VOffsetT = 6;
     pub const VT_PAYLOAD: flatbuffers::VOffsetT = 8;
+    pub const VT_CHECKSUM: flatbuffers::VOffsetT = 10;
+    pub const VT_MAC: flatbuffers::VOffsetT = 12;
+    pub const VT_HASH: flatbuffers::VOffsetT = 14;
+    pub const VT_RANGES: flatbuffers::VOffsetT = 16;
+    pub const VT_STREAM_ID: flatbuffers::VOffsetT = 18;
+    pub const VT_VERSION: flatbuffers::VOffsetT = 20;
+    pub const VT_MAGIC: flatbuffers::VOffsetT = 22;
 
   #[inline]
   pub fn msg_type(&self) -> Type {
@@ -135,12 +243,47 @@ impl<'a> Message<'a> {
   pub fn payload(&self) -> Option<&'a [u8]> {
     self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(Message::VT_PAYLOAD, None).map(|v| v.safe_slice())
   }
+  #[inline]
+  pub fn checksum(&self) -> u32 {
+    self._tab.get::<u32>(Message::VT_CHECKSUM, Some(0)).unwrap()
+  }
+  #[inline]
+  pub fn mac(&self) -> Option<&'a [u8]> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(Message::VT_MAC, None).map(|v| v.safe_slice())
+  }
+  #[inline]
+  pub fn hash(&self) -> Option<&'a [u8]> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(Message::VT_HASH, None).map(|v| v.safe_slice())
+  }
+  #[inline]
+  pub fn ranges(&self) -> Option<&'a [SeqRange]> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<SeqRange>>>(Message::VT_RANGES, None).map(|v| v.safe_slice() )
+  }
+  #[inline]
+  pub fn stream_id(&self) -> u32 {
+    self._tab.get::<u32>(Message::VT_STREAM_ID, Some(0)).unwrap()
+  }
+  #[inline]
+  pub fn version(&self) -> u16 {
+    self._tab.get::<u16>(Message::VT_VERSION, Some(0)).unwrap()
+  }
+  #[inline]
+  pub fn magic(&self) -> u32 {
+    self._tab.get::<u32>(Message::VT_MAGIC, Some(0)).unwrap()
+  }
 }
 
 pub struct MessageArgs<'a> {
     pub msg_type: Type,
     pub seq_num: u64,
     pub payload: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
+    pub checksum: u32,
+    pub mac: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
+    pub hash: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
+    pub ranges: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a , SeqRange>>>,
+    pub stream_id: u32,
+    pub version: u16,
+    pub magic: u32,
 }
 impl<'a> Default for MessageArgs<'a> {
     #[inline]
@@ -149,6 +292,13 @@ impl<'a> Default for MessageArgs<'a> {
             msg_type: Type::Error,
             seq_num: 0,
             payload: None,
+            checksum: 0,
+            mac: None,
+            hash: None,
+            ranges: None,
+            stream_id: 0,
+            version: 0,
+            magic: 0,
         }
     }
 }
@@ -170,6 +320,34 @@ impl<'a: 'b, 'b> MessageBuilder<'a, 'b> {
     self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Message::VT_PAYLOAD, payload);
   }
   #[inline]
+  pub fn add_checksum(&mut self, checksum: u32) {
+    self.fbb_.push_slot::<u32>(Message::VT_CHECKSUM, checksum, 0);
+  }
+  #[inline]
+  pub fn add_mac(&mut self, mac: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Message::VT_MAC, mac);
+  }
+  #[inline]
+  pub fn add_hash(&mut self, hash: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Message::VT_HASH, hash);
+  }
+  #[inline]
+  pub fn add_ranges(&mut self, ranges: flatbuffers::WIPOffset<flatbuffers::Vector<'b , SeqRange>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Message::VT_RANGES, ranges);
+  }
+  #[inline]
+  pub fn add_stream_id(&mut self, stream_id: u32) {
+    self.fbb_.push_slot::<u32>(Message::VT_STREAM_ID, stream_id, 0);
+  }
+  #[inline]
+  pub fn add_version(&mut self, version: u16) {
+    self.fbb_.push_slot::<u16>(Message::VT_VERSION, version, 0);
+  }
+  #[inline]
+  pub fn add_magic(&mut self, magic: u32) {
+    self.fbb_.push_slot::<u32>(Message::VT_MAGIC, magic, 0);
+  }
+  #[inline]
   pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> MessageBuilder<'a, 'b> {
     let start = _fbb.start_table();
     MessageBuilder {
@@ -0,0 +1,152 @@
+//! A free list of packet buffers, so the data path doesn't allocate one for every packet
+//! A buffer is checked out w/get, and goes back on the list when it's dropped: once it's acknowledged or read
+
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct BufferPoolInner {
+    free: Mutex<Vec<Vec<u8>>>,
+    capacity: usize,        // the room each new buffer starts w/
+    max_free: usize,        // buffers kept on the list, the rest are freed when they come back
+    allocated: AtomicUsize  // buffers get had to allocate, as the list was empty
+}
+
+/// A pool of buffers; clones share the same free list
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<BufferPoolInner>
+}
+
+/// A buffer checked out of a BufferPool, returned to it when dropped
+pub struct PooledBuf {
+    buf: Vec<u8>,
+    pool: Arc<BufferPoolInner>
+}
+
+impl BufferPool {
+    /// New buffers start out w/room for capacity bytes; no more than max_free are kept once returned
+    pub fn new(capacity: usize, max_free: usize) -> BufferPool {
+        let inner = BufferPoolInner { free: Mutex::new(Vec::new()), capacity, max_free, allocated: AtomicUsize::new(0) };
+
+        BufferPool { inner: Arc::new(inner) }
+    }
+
+    /// An empty buffer, one that was returned if there is one
+    pub fn get(&self) -> PooledBuf {
+        let buf = self.inner.free.lock().unwrap().pop();
+
+        let buf = match buf {
+            Some(buf) => buf,
+            None => {
+                self.inner.allocated.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(self.inner.capacity)
+            }
+        };
+
+        PooledBuf { buf, pool: self.inner.clone() }
+    }
+
+    /// Takes a buffer allocated elsewhere into the pool, it's returned like any other once dropped
+    pub fn wrap(&self, buf: Vec<u8>) -> PooledBuf {
+        PooledBuf { buf, pool: self.inner.clone() }
+    }
+
+    /// The number of buffers get has allocated, not counting those it reused
+    pub fn allocated(&self) -> usize {
+        self.inner.allocated.load(Ordering::Relaxed)
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+/// Another buffer from the same pool, w/the same contents
+impl Clone for PooledBuf {
+    fn clone(&self) -> PooledBuf {
+        let mut buf = BufferPool { inner: self.pool.clone() }.get();
+
+        buf.extend_from_slice(&self.buf);
+
+        return buf;
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        let mut buf = mem::replace(&mut self.buf, Vec::new());
+        let mut free = self.pool.free.lock().unwrap();
+
+        if free.len() < self.pool.max_free {
+            buf.clear();
+            free.push(buf);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use pool::BufferPool;
+
+    #[test]
+    fn reuse() {
+        let pool = BufferPool::new(1500, 4);
+
+        let mut buf = pool.get();
+
+        buf.extend_from_slice(b"hello");
+        assert_eq!(1, pool.allocated());
+        assert!(buf.capacity() >= 1500);
+
+        drop(buf);
+
+        // the same one, emptied
+        let buf = pool.get();
+
+        assert!(buf.is_empty());
+        assert_eq!(1, pool.allocated());
+
+        // a second one out at the same time needs another
+        let other = buf.clone();
+
+        assert_eq!(2, pool.allocated());
+        assert!(other.is_empty());
+    }
+
+    #[test]
+    fn max_free() {
+        let pool = BufferPool::new(16, 2);
+
+        let bufs = (0..4).map(|_| pool.get()).collect::<Vec<_>>();
+
+        assert_eq!(4, pool.allocated());
+
+        // only 2 are kept, so the other 2 are allocated again
+        drop(bufs);
+
+        let bufs = (0..4).map(|_| pool.get()).collect::<Vec<_>>();
+
+        assert_eq!(6, pool.allocated());
+        drop(bufs);
+
+        // wrapped buffers are kept too, up to the same limit
+        let wrapped = pool.wrap(vec![1, 2, 3]);
+
+        assert_eq!(&[1, 2, 3], &wrapped[..]);
+        drop(wrapped);
+        assert_eq!(6, pool.allocated());
+    }
+}
@@ -0,0 +1,348 @@
+use std::io::{Error as IOError, ErrorKind};
+use std::path::{Path, PathBuf, Component};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+#[cfg(not(unix))]
+use std::fs::OpenOptions;
+
+/// Resolves a received file name against root, making sure the result can't land outside of it
+/// Absolute names and names w/.. are rejected outright; anything else is canonicalized, following symlinks,
+/// and rejected if it ends up outside of root
+pub fn resolve(root: &Path, name: &Path) -> Result<PathBuf, IOError> {
+    let root = fs::canonicalize(root)?;
+
+    for component in name.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => (),
+            _ => return Err(IOError::new(ErrorKind::PermissionDenied, format!("Refusing file name: {}", name.display())))
+        }
+    }
+
+    let file_name = match name.file_name() {
+        Some(f) => f,
+        None => return Err(IOError::new(ErrorKind::InvalidInput, format!("No file name: {}", name.display())))
+    };
+
+    let path = root.join(name);
+
+    // the file itself might not exist yet, but its directory must
+    let parent = fs::canonicalize(path.parent().expect("Joined path has no parent"))?;
+    let mut resolved = parent.join(file_name);
+
+    // an existing file could be a symlink pointing anywhere, including somewhere that doesn't exist yet
+    if fs::symlink_metadata(&resolved).is_ok() {
+        resolved = match fs::canonicalize(&resolved) {
+            Ok(p) => p,
+            Err(_) => return Err(IOError::new(ErrorKind::PermissionDenied, format!("Refusing dangling symlink: {}", name.display())))
+        };
+    }
+
+    if !resolved.starts_with(&root) {
+        return Err(IOError::new(ErrorKind::PermissionDenied, format!("{} resolves outside of {}", name.display(), root.display())));
+    }
+
+    debug!("Resolved {} to {}", name.display(), resolved.display());
+
+    return Ok(resolved);
+}
+
+/// The parts of name to walk down from a root, refusing anything that could climb out of it
+fn components(name: &Path) -> Result<Vec<&OsStr>, IOError> {
+    let mut parts = Vec::new();
+
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => (),
+            _ => return Err(IOError::new(ErrorKind::PermissionDenied, format!("Refusing file name: {}", name.display())))
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(IOError::new(ErrorKind::InvalidInput, format!("No file name: {}", name.display())));
+    }
+
+    return Ok(parts);
+}
+
+/// Opens part of dir, never following a symlink, so nothing swapped in after resolve can lead outside of the root
+#[cfg(unix)]
+fn open_at(dir: &File, part: &OsStr, flags: libc::c_int) -> Result<File, IOError> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let name = match CString::new(part.as_bytes()) {
+        Ok(name) => name,
+        Err(_) => return Err(IOError::new(ErrorKind::InvalidInput, "File name has a NUL in it"))
+    };
+
+    let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags | libc::O_NOFOLLOW | libc::O_CLOEXEC, 0o666 as libc::c_uint) };
+
+    if fd < 0 {
+        let err = IOError::last_os_error();
+
+        // what O_NOFOLLOW does w/a symlink; w/O_DIRECTORY too, Linux says it's not a directory instead
+        if err.raw_os_error() == Some(libc::ELOOP) || (err.raw_os_error() == Some(libc::ENOTDIR) && is_symlink_at(dir, &name)) {
+            return Err(IOError::new(ErrorKind::PermissionDenied, format!("Refusing symlink: {}", part.to_string_lossy())));
+        }
+
+        return Err(err);
+    }
+
+    return Ok(unsafe { File::from_raw_fd(fd) });
+}
+
+/// Whether name, in dir, is itself a symlink
+#[cfg(unix)]
+fn is_symlink_at(dir: &File, name: &::std::ffi::CStr) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let mut stat :libc::stat = unsafe { ::std::mem::zeroed() };
+
+    if unsafe { libc::fstatat(dir.as_raw_fd(), name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) } < 0 {
+        return false;
+    }
+
+    return stat.st_mode & libc::S_IFMT == libc::S_IFLNK;
+}
+
+/// Walks down parts from root, one directory at a time
+#[cfg(unix)]
+fn open_dirs(root: &Path, parts: &[&OsStr]) -> Result<File, IOError> {
+    let mut dir = File::open(root)?;
+
+    for part in parts {
+        dir = open_at(&dir, part, libc::O_RDONLY | libc::O_DIRECTORY)?;
+    }
+
+    return Ok(dir);
+}
+
+/// Creates, or truncates, the file name under root, the same names as resolve allows
+/// Every directory along the way is opened from the one before it, and none of them, nor the file, can be a symlink;
+/// unlike resolve then open, there's no window for a symlink to be swapped in between the two
+#[cfg(unix)]
+pub fn create(root: &Path, name: &Path) -> Result<File, IOError> {
+    let parts = components(name)?;
+    let (file_name, dirs) = parts.split_last().expect("No components in a checked name");
+    let dir = open_dirs(root, dirs)?;
+
+    return open_at(&dir, file_name, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC);
+}
+
+#[cfg(not(unix))]
+pub fn create(root: &Path, name: &Path) -> Result<File, IOError> {
+    return File::create(resolve(root, name)?);
+}
+
+/// Like create, but keeps whatever's already in the file, and opens it for reading too
+#[cfg(unix)]
+pub fn open(root: &Path, name: &Path) -> Result<File, IOError> {
+    let parts = components(name)?;
+    let (file_name, dirs) = parts.split_last().expect("No components in a checked name");
+    let dir = open_dirs(root, dirs)?;
+
+    return open_at(&dir, file_name, libc::O_RDWR | libc::O_CREAT);
+}
+
+#[cfg(not(unix))]
+pub fn open(root: &Path, name: &Path) -> Result<File, IOError> {
+    return OpenOptions::new().read(true).write(true).create(true).open(resolve(root, name)?);
+}
+
+/// Like open, but only for reading, so nothing is created if it isn't there
+#[cfg(unix)]
+pub fn open_read(root: &Path, name: &Path) -> Result<File, IOError> {
+    let parts = components(name)?;
+    let (file_name, dirs) = parts.split_last().expect("No components in a checked name");
+    let dir = open_dirs(root, dirs)?;
+
+    return open_at(&dir, file_name, libc::O_RDONLY);
+}
+
+#[cfg(not(unix))]
+pub fn open_read(root: &Path, name: &Path) -> Result<File, IOError> {
+    return File::open(resolve(root, name)?);
+}
+
+/// Creates the directory name under root, if it isn't there already; its parent must be
+/// Like create, nothing along the way can be a symlink
+#[cfg(unix)]
+pub fn create_dir(root: &Path, name: &Path) -> Result<(), IOError> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let parts = components(name)?;
+    let (dir_name, dirs) = parts.split_last().expect("No components in a checked name");
+    let parent = open_dirs(root, dirs)?;
+
+    let c_name = match CString::new(dir_name.as_bytes()) {
+        Ok(c_name) => c_name,
+        Err(_) => return Err(IOError::new(ErrorKind::InvalidInput, "File name has a NUL in it"))
+    };
+
+    if unsafe { libc::mkdirat(parent.as_raw_fd(), c_name.as_ptr(), 0o777) } != 0 {
+        let err = IOError::last_os_error();
+
+        if err.kind() != ErrorKind::AlreadyExists {
+            return Err(err);
+        }
+    }
+
+    // whatever's there has to be a directory, and not a link to one
+    open_at(&parent, dir_name, libc::O_RDONLY | libc::O_DIRECTORY)?;
+
+    return Ok( () );
+}
+
+#[cfg(not(unix))]
+pub fn create_dir(root: &Path, name: &Path) -> Result<(), IOError> {
+    let path = resolve(root, name)?;
+
+    if !path.is_dir() {
+        fs::create_dir(&path)?;
+    }
+
+    return Ok( () );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    use sandbox::{resolve, create, create_dir, open};
+
+    /// A fresh directory to use as the root
+    fn make_root(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("qcp_sandbox_{}_{}", name, process::id()));
+
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).expect("Error creating root");
+
+        return fs::canonicalize(root).expect("Error canonicalizing root");
+    }
+
+    #[test]
+    fn confined() {
+        let root = make_root("confined");
+
+        assert_eq!(root.join("file"), resolve(&root, Path::new("file")).expect("Error resolving"));
+        assert_eq!(root.join("sub").join("file"), resolve(&root, Path::new("sub/file")).expect("Error resolving"));
+        assert_eq!(root.join("sub").join("file"), resolve(&root, Path::new("./sub/./file")).expect("Error resolving"));
+
+        // directories must already exist
+        assert!(resolve(&root, Path::new("missing/file")).is_err());
+
+        fs::remove_dir_all(&root).expect("Error removing root");
+    }
+
+    #[test]
+    fn malicious_names() {
+        let root = make_root("malicious");
+
+        for name in &["../../etc/passwd", "/etc/passwd", "sub/../../file", "..", "sub/.."] {
+            let err = resolve(&root, Path::new(name)).expect_err(name);
+
+            assert_eq!(ErrorKind::PermissionDenied, err.kind(), "{}", name);
+        }
+
+        assert!(resolve(&root, Path::new("")).is_err());
+
+        fs::remove_dir_all(&root).expect("Error removing root");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_escape() {
+        use std::os::unix::fs::symlink;
+
+        let root = make_root("symlink");
+        let outside = make_root("symlink_outside");
+
+        symlink(&outside, root.join("dir_link")).expect("Error creating symlink");
+        symlink(outside.join("file"), root.join("file_link")).expect("Error creating symlink");
+        fs::write(outside.join("file"), b"outside").expect("Error writing file");
+
+        // links to places inside the root are fine
+        symlink(root.join("sub"), root.join("inside_link")).expect("Error creating symlink");
+
+        assert_eq!(ErrorKind::PermissionDenied, resolve(&root, Path::new("dir_link/file")).expect_err("dir_link").kind());
+        assert_eq!(ErrorKind::PermissionDenied, resolve(&root, Path::new("file_link")).expect_err("file_link").kind());
+
+        // writing through a dangling link would create the file outside
+        symlink(outside.join("new_file"), root.join("dangling_link")).expect("Error creating symlink");
+        assert_eq!(ErrorKind::PermissionDenied, resolve(&root, Path::new("dangling_link")).expect_err("dangling_link").kind());
+        assert_eq!(root.join("sub").join("file"), resolve(&root, Path::new("inside_link/file")).expect("Error resolving"));
+
+        fs::remove_dir_all(&root).expect("Error removing root");
+        fs::remove_dir_all(&outside).expect("Error removing outside");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn create_refuses_symlinks() {
+        use std::io::Write;
+        use std::os::unix::fs::symlink;
+
+        let root = make_root("create");
+        let outside = make_root("create_outside");
+
+        create(&root, Path::new("sub/file")).and_then(|mut f| f.write_all(b"inside")).expect("Error creating file");
+        assert_eq!(b"inside".to_vec(), fs::read(root.join("sub").join("file")).expect("Error reading file"));
+
+        create_dir(&root, Path::new("sub/dir")).expect("Error creating dir");
+        create_dir(&root, Path::new("sub/dir")).expect("Error creating existing dir");
+        create(&root, Path::new("sub/dir/file")).expect("Error creating file in new dir");
+
+        // as if swapped in after resolve checked them; even a link inside the root is refused, there's no telling when it was made
+        symlink(&outside, root.join("dir_link")).expect("Error creating symlink");
+        symlink(outside.join("file"), root.join("file_link")).expect("Error creating symlink");
+        symlink(root.join("sub"), root.join("inside_link")).expect("Error creating symlink");
+
+        assert_eq!(ErrorKind::PermissionDenied, create(&root, Path::new("dir_link/file")).expect_err("dir_link").kind());
+        assert_eq!(ErrorKind::PermissionDenied, create(&root, Path::new("file_link")).expect_err("file_link").kind());
+        assert_eq!(ErrorKind::PermissionDenied, create(&root, Path::new("inside_link/file")).expect_err("inside_link").kind());
+        assert_eq!(ErrorKind::PermissionDenied, create_dir(&root, Path::new("dir_link")).expect_err("dir_link").kind());
+        assert_eq!(ErrorKind::PermissionDenied, create(&root, Path::new("../file")).expect_err("..").kind());
+
+        // nothing but the sub make_root puts in every root
+        let outside_names = fs::read_dir(&outside).expect("Error reading outside").map(|e| e.expect("Error reading entry").file_name()).collect::<Vec<_>>();
+
+        assert_eq!(vec![OsStr::new("sub").to_os_string()], outside_names);
+        assert!(fs::read_dir(outside.join("sub")).expect("Error reading outside sub").next().is_none());
+
+        fs::remove_dir_all(&root).expect("Error removing root");
+        fs::remove_dir_all(&outside).expect("Error removing outside");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn open_keeps_contents() {
+        use std::io::Read;
+        use std::os::unix::fs::symlink;
+
+        let root = make_root("open");
+
+        fs::write(root.join("sub").join("file"), b"already here").expect("Error writing file");
+
+        let mut contents = Vec::new();
+
+        open(&root, Path::new("sub/file")).and_then(|mut f| f.read_to_end(&mut contents)).expect("Error opening file");
+        assert_eq!(b"already here".to_vec(), contents);
+
+        // created if it's not there, and never through a link
+        open(&root, Path::new("sub/new")).expect("Error opening new file");
+        symlink(root.join("sub").join("file"), root.join("file_link")).expect("Error creating symlink");
+
+        assert_eq!(ErrorKind::PermissionDenied, open(&root, Path::new("file_link")).expect_err("file_link").kind());
+
+        fs::remove_dir_all(&root).expect("Error removing root");
+    }
+}
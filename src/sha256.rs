@@ -0,0 +1,102 @@
+//! SHA-256 and HMAC-SHA256, w/the sha2 and hmac crates, for authenticating the handshake and checking transfers
+
+use hmac::{Hmac, Mac};
+use sha2::{self, Digest};
+
+pub const DIGEST_SIZE :usize = 32;
+
+/// A running SHA-256, for hashing data that isn't all in one place
+pub struct Sha256 {
+    inner: sha2::Sha256
+}
+
+impl Sha256 {
+    pub fn new() -> Sha256 {
+        Sha256 { inner: sha2::Sha256::new() }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.inner.update(bytes);
+    }
+
+    pub fn finish(self) -> [u8; DIGEST_SIZE] {
+        return self.inner.finalize().into();
+    }
+}
+
+/// The SHA-256 of bytes
+pub fn sha256(bytes: &[u8]) -> [u8; DIGEST_SIZE] {
+    let mut hash = Sha256::new();
+
+    hash.update(bytes);
+
+    return hash.finish();
+}
+
+/// The HMAC-SHA256 of the concatenation of the pieces of data, under key
+pub fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+    let mut mac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+
+    for piece in data {
+        mac.update(piece);
+    }
+
+    return mac.finalize().into_bytes().into();
+}
+
+/// Compares a MAC we computed to one we were sent, in time that doesn't depend on where they differ
+pub fn mac_eq(expected: &[u8], got: &[u8]) -> bool {
+    if expected.len() != got.len() {
+        return false;
+    }
+
+    return expected.iter().zip(got.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use sha256::{sha256, hmac_sha256, mac_eq, Sha256};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn known_values() {
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", hex(&sha256(b"")));
+        assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", hex(&sha256(b"abc")));
+        assert_eq!("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+                   hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")));
+
+        // a million a's, in pieces that don't line up w/the blocks
+        let mut hash = Sha256::new();
+
+        for _ in 0..1000 {
+            hash.update(&[b'a'; 999]);
+        }
+
+        hash.update(&[b'a'; 1000]);
+
+        assert_eq!("cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0", hex(&hash.finish()));
+    }
+
+    #[test]
+    fn hmac_known_values() {
+        // RFC 4231, test cases 1, 2, and 6
+        assert_eq!("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+                   hex(&hmac_sha256(&[0x0b; 20], &[b"Hi There"])));
+        assert_eq!("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                   hex(&hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"])));
+        assert_eq!("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+                   hex(&hmac_sha256(&[0xaa; 131], &[b"Test Using Larger Than Block-Size Key - Hash Key First"])));
+    }
+
+    #[test]
+    fn mac_compare() {
+        let mac = hmac_sha256(b"key", &[b"data"]);
+
+        assert!(mac_eq(&mac, &mac));
+        assert!(!mac_eq(&mac, &mac[..31]));
+        assert!(!mac_eq(&mac, &hmac_sha256(b"kez", &[b"data"])));
+    }
+}
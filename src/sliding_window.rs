@@ -1,19 +1,54 @@
 use std::clone::Clone;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard, Condvar};
 use std::thread;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicI64, AtomicUsize, AtomicBool, Ordering};
 
 struct SlidingWindowData<T> {
     items: Vec<Option<T>>,
     head: usize,    // first index in the vector
-    tail: usize,    // last index in the vector
+    tail: u64,      // one past the last location inserted; a location, not an index, so a full ring isn't mistaken for an empty one
 }
 
 /// A sliding window that holds items of type T
 pub struct SlidingWindow<T> {
-    start: AtomicUsize,     // first item in the window; TODO: change to AtomicI64
-    size: usize,  // size of the window, needed so we can access w/out getting the Mutex
-    inner: Mutex<SlidingWindowData<T>>
+    start: AtomicI64,   // first location in the window; signed, so location math is checked against it instead of wrapping
+    size: AtomicUsize,  // size of the window, needed so we can access w/out getting the Mutex; only changed w/the Mutex held
+    closed: AtomicBool, // no more items are coming, or going; wakes anyone waiting on the window
+    inner: Mutex<SlidingWindowData<T>>,
+    changed: Condvar    // signaled w/the Mutex held whenever an item is inserted, or the window slides, grows, or closes
+}
+
+/// Closes the window when dropped, including when the thread holding it panics
+/// Hold one in each thread feeding or draining a window, so the other side sees an error instead of waiting forever
+pub struct CloseOnDrop<T> where T: Clone {
+    window: Arc<SlidingWindow<T>>
+}
+
+impl <T> CloseOnDrop<T> where T: Clone {
+    pub fn new(window: Arc<SlidingWindow<T>>) -> CloseOnDrop<T> {
+        CloseOnDrop { window }
+    }
+}
+
+impl <T> Drop for CloseOnDrop<T> where T: Clone {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            warn!("Thread panicked, closing window");
+        }
+
+        self.window.close();
+    }
+}
+
+/// A location as a signed number, so it can be checked against start w/out wrapping
+/// Sequence numbers never get anywhere near this big, but one off the wire could be
+fn signed(loc: u64) -> Result<i64, &'static str> {
+    if loc > i64::MAX as u64 {
+        return Err("loc too large");
+    }
+
+    return Ok(loc as i64);
 }
 
 impl <T> SlidingWindow<T> where T: Clone {
@@ -21,36 +56,141 @@ impl <T> SlidingWindow<T> where T: Clone {
     pub fn new(window_size: usize) -> SlidingWindow<T> {
         let items = vec![None; window_size];
 
-        let inner = SlidingWindowData { items, head: 0, tail: 1 };
+        let inner = SlidingWindowData { items, head: 0, tail: 0 };
 
         SlidingWindow {
-            start: AtomicUsize::new(0),
-            size: window_size,
-            inner: Mutex::new(inner)
+            start: AtomicI64::new(0),
+            size: AtomicUsize::new(window_size),
+            closed: AtomicBool::new(false),
+            inner: Mutex::new(inner),
+            changed: Condvar::new()
         }
     }
 
+    /// Like new, but the window starts at start instead of 0, as if that many items had already been through it
+    #[cfg(test)]
+    pub fn new_at(window_size: usize, start: u64) -> SlidingWindow<T> {
+        let items = vec![None; window_size];
+        let start = signed(start).expect("start too large");
+
+        let inner = SlidingWindowData { items, head: 0, tail: start as u64 };
+
+        SlidingWindow {
+            start: AtomicI64::new(start),
+            size: AtomicUsize::new(window_size),
+            closed: AtomicBool::new(false),
+            inner: Mutex::new(inner),
+            changed: Condvar::new()
+        }
+    }
+
+    /// The first location in the window
+    fn start(&self) -> i64 {
+        self.start.load(Ordering::Acquire)
+    }
+
+    /// The index into items of loc, or an error if it's outside [start, start+window_size)
+    /// The offset from start is checked against the size while it's still 64 bits, so it can't be truncated on a 32-bit target
+    /// Only call this w/the lock held, so start can't move before the index is used
+    fn index(&self, inner: &SlidingWindowData<T>, loc: i64) -> Result<usize, &'static str> {
+        let offset = match loc.checked_sub(self.start()) {
+            Some(offset) if offset < 0 => return Err("loc < start"),
+            Some(offset) => offset,
+            None => return Err("loc < start")
+        };
+
+        if offset >= inner.items.len() as i64 {
+            return Err("loc >= end");
+        }
+
+        return Ok((offset as usize + inner.head) % inner.items.len());
+    }
+
+    /// Lock the inner data, recovering it if another thread panicked while holding the lock
+    /// Every change under the lock leaves the window consistent, so the data is still good
+    fn lock(&self) -> MutexGuard<SlidingWindowData<T>> {
+        self.inner.lock().unwrap_or_else(|e| {
+            warn!("Recovering poisoned window lock");
+            e.into_inner()
+        })
+    }
+
+    /// Close the window: anyone waiting in insert or pop gets an error, instead of waiting forever
+    /// Items already in the window can still be popped or removed
+    pub fn close(&self) {
+        // under the lock, so a waiter can't check closed and then miss the wake up
+        let _inner = self.lock();
+
+        self.closed.store(true, Ordering::Release);
+        self.changed.notify_all();
+    }
+
+    /// Returns true if the window has been closed
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
     /// Insert an item at a given location in the window
     /// Any inserts outside of [start, start+window_size) will return None
     /// Otherwise, the value that was in the window position is returned
+    /// Returns an error once the window is closed
     pub fn insert(&self, loc: u64, item: T) -> Result<(), &str> {
-        if loc < self.start.load(Ordering::Acquire) as u64 {
+        let loc = signed(loc)?;
+
+        if loc < self.start() {
             return Err("loc < start");
         }
 
-        // wait until room is made for this insert
-        while loc >= (self.start.load(Ordering::Acquire) + self.size) as u64 {
-            let window = self.window();
-            warn!("Yielding thread on insert: {} -> {}; {}", window.0, window.1, loc);
-            thread::yield_now();
+        if self.is_closed() {
+            return Err("window closed");
+        }
+
+        // wait until room is made for this insert, sleeping until the window changes
+        let mut inner = self.lock();
+        let mut warned = false;
+
+        while loc - self.start() >= inner.items.len() as i64 {
+            if !warned {
+                let window = self.window();
+                warn!("Waiting on insert: {} -> {}; {}", window.0, window.1, loc);
+                warned = true;
+            }
+
+            if self.is_closed() {
+                return Err("window closed");
+            }
+
+            inner = self.changed.wait(inner).unwrap_or_else(|e| e.into_inner());
+        }
+
+        return self.inner_insert(&mut inner, loc, item);
+    }
+
+    /// Like insert, but never waits for room
+    /// Returns an error right away if loc is past the end of the window
+    pub fn try_insert(&self, loc: u64, item: T) -> Result<(), &str> {
+        let loc = signed(loc)?;
+
+        if self.is_closed() {
+            return Err("window closed");
         }
 
-        // lock the mutex here
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.lock();
+
+        if loc - self.start() >= inner.items.len() as i64 {
+            return Err("window full");
+        }
+
+        return self.inner_insert(&mut inner, loc, item);
+    }
 
-        let index : usize = ((loc as usize - self.start.load(Ordering::Acquire)) + inner.head) % inner.items.len();
+    /// Puts the item at loc, once there's room for it
+    /// Only call this w/the lock held
+    fn inner_insert(&self, inner: &mut SlidingWindowData<T>, loc: i64, item: T) -> Result<(), &'static str> {
+        // the window might have slid past it while we were getting the lock
+        let index = self.index(inner, loc)?;
 
-        debug!("INDEX: {}, LOC: {}, START: {}, HEAD: {}", index, loc, self.start.load(Ordering::Acquire), inner.head);
+        debug!("INDEX: {}, LOC: {}, START: {}, HEAD: {}", index, loc, self.start(), inner.head);
 
         if inner.items[index].is_some() {
             return Err("Value already set");
@@ -60,20 +200,48 @@ impl <T> SlidingWindow<T> where T: Clone {
         inner.items[index] = Some(item);
 
         // update our tail
-        if index >= inner.tail {
-            inner.tail = (index + 1) % inner.items.len();
+        if loc as u64 >= inner.tail {
+            inner.tail = loc as u64 + 1;
         }
 
+        // it might be the one pop is waiting for
+        self.changed.notify_all();
+
         return Ok( () );
     }
 
+    /// Waits, for no longer than timeout, until loc is inside the window
+    /// Returns true if it is, false if the wait timed out or the window closed
+    pub fn wait_for_room(&self, loc: u64, timeout: Duration) -> bool {
+        let loc = match signed(loc) {
+            Ok(loc) => loc,
+            Err(_) => return false
+        };
+        let deadline = Instant::now() + timeout;
+        let mut inner = self.lock();
+
+        while loc - self.start() >= inner.items.len() as i64 {
+            let now = Instant::now();
+
+            if self.is_closed() || now >= deadline {
+                return false;
+            }
+
+            inner = self.changed.wait_timeout(inner, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
+        }
+
+        return true;
+    }
+
     /// Removes an item in the window, given a location relative to the index
     /// ie, you have to compute loc - start already, and pass that in
-    fn inner_remove(&self, relative_loc: u64) -> Option<T> {
-        // lock the mutex here
-        let mut inner = self.inner.lock().unwrap();
+    /// When close_gaps is set, removing the head also slides the window over any empty slots
+    /// that follow it (items already removed); otherwise it only slides past the removed item
+    fn inner_remove(&self, inner: &mut SlidingWindowData<T>, relative_loc: u64, close_gaps: bool) -> Option<T> {
+        // checked before it's cut down to a usize
+        assert!(relative_loc < inner.items.len() as u64, "Removing {} past the start, in a window of {}", relative_loc, inner.items.len());
 
-        let index : usize = (relative_loc as usize + inner.head) % self.size;
+        let index : usize = (relative_loc as usize + inner.head) % inner.items.len();
 
         if inner.items[index].is_none() {
             return None;
@@ -88,11 +256,14 @@ impl <T> SlidingWindow<T> where T: Clone {
 
                     // keep closing the window, if we're not at the end
                     // and the items are None
-                    if inner.head == inner.tail || inner.items[inner.head].is_some() {
+                    if !close_gaps || self.start() as u64 >= inner.tail || inner.items[inner.head].is_some() {
                         break;
                     }
                 }
 
+                // there's room for more now
+                self.changed.notify_all();
+
             }
 
             return Some(ret);
@@ -103,74 +274,230 @@ impl <T> SlidingWindow<T> where T: Clone {
     /// Removes the item at the location
     /// Returns None if there is no item there, and does not slide the window
     pub fn remove(&self, loc: u64) -> Result<T, &str> {
-        let start = self.start.load(Ordering::Acquire);
+        let loc = signed(loc)?;
 
-        if loc < start as u64 {
-            return Err("loc < start");
-        } else if loc >= (start + self.size) as u64  {
-            return Err("loc >= end");
-        }
+        // lock the mutex here, so start can't move under us
+        let mut inner = self.lock();
+
+        self.index(&inner, loc)?;
 
-        match self.inner_remove(loc - start as u64) {
+        let relative_loc = (loc - self.start()) as u64;
+
+        match self.inner_remove(&mut inner, relative_loc, true) {
             None => Err("Value is none"),
             Some(t) => Ok(t)
         }
     }
 
+    /// Calls f on the item at the location, w/out removing it or sliding the window
+    /// Returns whatever f returns, or an error if there is no item there
+    pub fn update<F, R>(&self, loc: u64, f: F) -> Result<R, &str> where F: FnOnce(&mut T) -> R {
+        let loc = signed(loc)?;
+
+        // lock the mutex here, so start can't move under us
+        let mut inner = self.lock();
+        let index = self.index(&inner, loc)?;
+
+        match inner.items[index].as_mut() {
+            None => Err("Value is none"),
+            Some(t) => Ok(f(t))
+        }
+    }
+
+    /// Calls f on the items at [from, to) all at once, w/out removing them or sliding the window
+    /// Returns whatever f returns, or an error if any location is outside the window or has no item
+    pub fn inspect_range<F, R>(&self, from: u64, to: u64, f: F) -> Result<R, &str> where F: FnOnce(&[&T]) -> R {
+        // lock the mutex here, so start can't move under us
+        let inner = self.lock();
+        let mut items = Vec::with_capacity(to.saturating_sub(from).min(inner.items.len() as u64) as usize);
+
+        for loc in from..to {
+            let index = self.index(&inner, signed(loc)?)?;
+
+            match inner.items[index].as_ref() {
+                None => return Err("Value is none"),
+                Some(t) => items.push(t)
+            }
+        }
+
+        return Ok(f(&items));
+    }
+
     /// Returns the first element in the window
     /// Saves you from having to do:
     /// let (start, end) = w.window();
     /// let t = w.remove(start);
-    pub fn pop(&self) -> T {
+    /// Unlike remove, the window only slides by one, as empty slots after the first
+    /// element are items that haven't been inserted yet
+    /// Returns an error if the window is closed while waiting for the first element
+    pub fn pop(&self) -> Result<T, &str> {
+        let mut inner = self.lock();
+        let mut warned = false;
+
         loop {
-            let res = self.inner_remove(0);
+            if let Some(t) = self.inner_remove(&mut inner, 0, false) {
+                return Ok(t);
+            }
+
+            // items inserted before closing are still popped, by the remove above
+            if self.is_closed() {
+                return Err("window closed");
+            }
 
-            if res.is_none() {
+            if !warned {
                 let window = self.window();
-                warn!("Yielding on a pop: {} -> {}", window.0, window.1);
-                thread::yield_now();
-            } else {
-                return res.unwrap();
+                warn!("Waiting on a pop: {} -> {}", window.0, window.1);
+                warned = true;
             }
+
+            // sleep until something's inserted
+            inner = self.changed.wait(inner).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Like pop, but waits no longer than timeout for the first element
+    /// Returns None if it times out, or the window is closed w/nothing left to pop
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut inner = self.lock();
+
+        loop {
+            if let Some(t) = self.inner_remove(&mut inner, 0, false) {
+                return Some(t);
+            }
+
+            let now = Instant::now();
+
+            if self.is_closed() || now >= deadline {
+                return None;
+            }
+
+            // sleep until something's inserted, or the time's up
+            inner = self.changed.wait_timeout(inner, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
         }
     }
 
-    /// Find the first item in the window that satisfies the predicate
-    pub fn find_first<P>(&self, mut predicate: P) -> Option<usize> where P: FnMut(&T) -> bool {
-        let inner = self.inner.lock().unwrap();
+    /// Find the location of the first item in the window that satisfies the predicate
+    pub fn find_first<P>(&self, mut predicate: P) -> Option<u64> where P: FnMut(&T) -> bool {
+        let inner = self.lock();
+        let start = self.start() as u64;
         let mut cur = inner.head;
 
-        while cur != inner.tail {
+        // walk from the head up to the last location inserted, which may wrap around the end of the ring
+        // the span's capped at the size before it's a usize, so it can't be truncated on a 32-bit target
+        for offset in 0..inner.tail.saturating_sub(start).min(inner.items.len() as u64) {
             if inner.items[cur].is_some() {
                 let item = inner.items[cur].as_ref().unwrap();
 
                 if predicate(item) {
-                    return Some(cur + self.start.load(Ordering::Acquire));
+                    // translate the ring index back into a location
+                    return Some(start + offset);
                 }
             }
 
-            cur = (cur + 1) % self.size; // increment w/wrap
+            cur = (cur + 1) % inner.items.len(); // increment w/wrap
         }
 
         return None;
     }
 
+    /// Returns the locations in [from, to) that are in the window, but have no item
+    pub fn missing(&self, from: u64, to: u64) -> Vec<u64> {
+        // lock the mutex here, so start can't move under us
+        let inner = self.lock();
+        let start = self.start() as u64;
+
+        let from = from.max(start);
+        let to = to.min(start + inner.items.len() as u64);
+
+        // everything in [from, to) is in the window now, so the index can't fail
+        (from..to).filter(|&loc| {
+            let index = self.index(&inner, loc as i64).expect("Location outside the window");
+            inner.items[index].is_none()
+        }).collect()
+    }
+
+    /// The number of items in the window
+    pub fn len(&self) -> usize {
+        return self.lock().items.iter().filter(|item| item.is_some()).count();
+    }
+
+    /// Returns true if every location in the window has an item
+    pub fn is_full(&self) -> bool {
+        return self.lock().items.iter().all(|item| item.is_some());
+    }
+
+    /// Calls f on every item in the window in location order, w/what it returned for the one before, starting w/init
+    /// Returns what it returned for the last one, or init if the window is empty
+    pub fn fold<B, F>(&self, init: B, mut f: F) -> B where F: FnMut(B, &T) -> B {
+        let inner = self.lock();
+        let len = inner.items.len();
+
+        return (0..len).filter_map(|offset| inner.items[(inner.head + offset) % len].as_ref()).fold(init, |acc, item| f(acc, item));
+    }
+
+    /// Change the size of the window, keeping every item at its location
+    /// Fails if there are items at locations past the new end, rather than dropping them
+    pub fn resize(&self, new_size: usize) -> Result<(), &str> {
+        if new_size == 0 {
+            return Err("new_size == 0");
+        }
+
+        // lock the mutex here, so nothing moves while we copy
+        let mut inner = self.lock();
+        let start = self.start() as u64;
+        let used = inner.tail.saturating_sub(start).min(inner.items.len() as u64) as usize;   // slots from the head through the last item
+
+        if used > new_size {
+            return Err("Items past the new end of the window");
+        }
+
+        let old_size = inner.items.len();
+        let mut items = vec![None; new_size];
+
+        for (offset, item) in items.iter_mut().enumerate().take(used) {
+            let index = (inner.head + offset) % old_size;
+            *item = inner.items[index].take();
+        }
+
+        inner.items = items;
+        inner.head = 0;
+
+        self.size.store(new_size, Ordering::Release);
+        self.changed.notify_all();
+
+        debug!("Resized window from {} to {}", old_size, new_size);
+
+        return Ok( () );
+    }
+
     /// Get the [start, end) of the window
     pub fn window(&self) -> (u64, u64) {
-        let start :u64 = self.start.load(Ordering::Acquire) as u64;
+        let start :u64 = self.start() as u64;
 
-        (start, start + self.size as u64)
+        (start, start + self.size.load(Ordering::Acquire) as u64)
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::{Arc, Mutex};
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
+
+    use libc::{clock_gettime, timespec, CLOCK_THREAD_CPUTIME_ID};
+
+    use sliding_window::{SlidingWindow, CloseOnDrop};
 
-    use sliding_window::SlidingWindow;
+    /// The CPU time used by the calling thread, to check that waiting doesn't spin
+    pub fn thread_cpu_time() -> Duration {
+        let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
+
+        unsafe { clock_gettime(CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
 
     #[test]
     fn create_insert() {
@@ -208,6 +535,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn try_insert() {
+        let sw = Arc::new(SlidingWindow::<&str>::new(3));
+
+        assert!(sw.try_insert(0, "a").is_ok());
+        assert!(sw.try_insert(1, "b").is_ok());
+        assert!(sw.try_insert(1, "b").is_err());
+        assert!(sw.try_insert(2, "c").is_ok());
+
+        // past the end, so it's refused instead of waiting
+        let start = Instant::now();
+
+        assert_eq!(Err("window full"), sw.try_insert(3, "d"));
+        assert!(start.elapsed() < Duration::from_millis(100), "{:?}", start.elapsed());
+
+        // while insert still waits for room
+        let sw_clone = sw.clone();
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            sw_clone.remove(0).expect("Error removing item 0");
+        });
+
+        let start = Instant::now();
+
+        assert!(sw.insert(3, "d").is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(150), "{:?}", start.elapsed());
+
+        // and now there's room
+        assert_eq!(Err("window full"), sw.try_insert(4, "e"));
+        sw.remove(1).expect("Error removing item 1");
+        assert!(sw.try_insert(4, "e").is_ok());
+
+        sw.close();
+        assert_eq!(Err("window closed"), sw.try_insert(5, "f"));
+    }
+
     #[test]
     fn pop_test() {
         let mut sw = SlidingWindow::<&str>::new(16);
@@ -218,9 +582,9 @@ mod tests {
         assert!(sw.insert(2, "c").is_ok());
         assert_eq!((0,16), sw.window());
 
-        assert_eq!("a", sw.pop());
-        assert_eq!("b", sw.pop());
-        assert_eq!("c", sw.pop());
+        assert_eq!(Ok("a"), sw.pop());
+        assert_eq!(Ok("b"), sw.pop());
+        assert_eq!(Ok("c"), sw.pop());
     }
 
     #[test]
@@ -263,4 +627,484 @@ mod tests {
         assert_eq!(Ok("f"), sw.remove(5));
         assert_eq!((6,22), sw.window());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pop_waits_for_gaps() {
+        let sw = SlidingWindow::<&str>::new(16);
+
+        // 1 hasn't arrived yet
+        assert!(sw.insert(0, "a").is_ok());
+        assert!(sw.insert(2, "c").is_ok());
+
+        assert_eq!(Ok("a"), sw.pop());
+        assert_eq!((1,17), sw.window());
+
+        assert!(sw.insert(1, "b").is_ok());
+
+        assert_eq!(Ok("b"), sw.pop());
+        assert_eq!(Ok("c"), sw.pop());
+        assert_eq!((3,19), sw.window());
+    }
+
+    #[test]
+    fn update_test() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        assert!(sw.insert(0, 1).is_ok());
+        assert!(sw.insert(1, 2).is_ok());
+
+        // updating the first item doesn't slide the window
+        assert_eq!(Ok(1), sw.update(0, |t| { *t += 10; 1 }));
+        assert_eq!((0,4), sw.window());
+
+        assert_eq!(Ok(11), sw.remove(0));
+        assert!(sw.update(0, |t| *t += 1).is_err());
+        assert!(sw.update(2, |t| *t += 1).is_err());
+        assert!(sw.update(5, |t| *t += 1).is_err());
+        assert_eq!(Ok(2), sw.remove(1));
+    }
+
+    #[test]
+    fn inspect_range_test() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        // slide the window, so the range wraps around the end of the ring
+        for i in 0..3 {
+            assert!(sw.insert(i, i as u32).is_ok());
+        }
+
+        assert_eq!(Ok(0), sw.remove(0));
+        assert_eq!(Ok(1), sw.remove(1));
+
+        for i in 3..6 {
+            assert!(sw.insert(i, i as u32).is_ok());
+        }
+
+        assert_eq!(Ok(vec![2, 3, 4, 5]), sw.inspect_range(2, 6, |items| items.iter().map(|&&t| t).collect::<Vec<_>>()));
+        assert_eq!(Ok(0), sw.inspect_range(4, 4, |items| items.len()));
+        assert_eq!((2,6), sw.window());
+
+        // a gap, or past either end of the window
+        assert_eq!(Ok(3), sw.remove(3));
+        assert!(sw.inspect_range(2, 5, |items| items.len()).is_err());
+        assert!(sw.inspect_range(1, 3, |items| items.len()).is_err());
+        assert!(sw.inspect_range(4, 7, |items| items.len()).is_err());
+    }
+
+    #[test]
+    fn len_test() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        assert_eq!(0, sw.len());
+        assert!(!sw.is_full());
+
+        for i in 0..4 {
+            assert!(sw.insert(i, i as u32).is_ok());
+        }
+
+        assert_eq!(4, sw.len());
+        assert!(sw.is_full());
+
+        // slide the window, so what's inserted next wraps around the end of the ring
+        assert_eq!(Ok(0), sw.remove(0));
+        assert_eq!(Ok(1), sw.remove(1));
+        assert_eq!(2, sw.len());
+        assert!(!sw.is_full());
+
+        assert!(sw.insert(4, 4).is_ok());
+        assert!(sw.insert(5, 5).is_ok());
+        assert_eq!(4, sw.len());
+        assert!(sw.is_full());
+        assert_eq!(vec![2, 3, 4, 5], sw.fold(Vec::new(), |mut v, &t| { v.push(t); v }));
+
+        // a gap in the middle doesn't slide the window, but it's one less
+        assert_eq!(Ok(4), sw.remove(4));
+        assert_eq!(3, sw.len());
+        assert!(!sw.is_full());
+        assert_eq!(10, sw.fold(0, |sum, &t| sum + t));
+
+        assert_eq!(Ok(2), sw.pop());
+        assert_eq!(Ok(3), sw.pop());
+        assert_eq!(1, sw.len());
+        assert_eq!((4, 8), sw.window());
+    }
+
+    #[test]
+    fn find_first_after_slide() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        // slide the window, so the ring's head is no longer at index 0
+        for i in 0..3 {
+            assert!(sw.insert(i, i as u32).is_ok());
+        }
+
+        assert_eq!(Ok(0), sw.remove(0));
+        assert_eq!(Ok(1), sw.remove(1));
+        assert_eq!((2,6), sw.window());
+
+        let loc = sw.find_first(|&t| t == 2).expect("Didn't find item");
+
+        assert_eq!(2, loc);
+        assert_eq!(Ok(2), sw.remove(loc));
+    }
+
+    #[test]
+    fn find_first_across_wrap() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        // go around the ring once and a half, so the head is past the wrap point
+        for i in 0..6 {
+            assert!(sw.insert(i, i as u32).is_ok());
+            assert_eq!(Ok(i as u32), sw.remove(i));
+        }
+
+        assert_eq!((6,10), sw.window());
+
+        // these straddle the end of the ring
+        for i in 6..10 {
+            assert!(sw.insert(i, i as u32).is_ok());
+        }
+
+        for i in 6..10 {
+            let loc = sw.find_first(|&t| t == i as u32).expect("Didn't find item");
+
+            assert_eq!(i, loc);
+        }
+
+        // the oldest item, as the retransmit scan looks for
+        let loc = sw.find_first(|_| true).expect("Didn't find item");
+
+        assert_eq!(6, loc);
+        assert_eq!(Ok(6), sw.remove(loc));
+        assert_eq!(Ok(9), sw.remove(9));
+    }
+
+    #[test]
+    fn find_first_visits_all() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        // nothing inserted, so nothing to visit, not even the empty slots
+        let mut visited = Vec::new();
+
+        assert_eq!(None, sw.find_first(|&t| { visited.push(t); false }));
+        assert!(visited.is_empty());
+
+        for i in 0..3 {
+            assert!(sw.insert(i, i as u32).is_ok());
+            assert_eq!(Ok(i as u32), sw.remove(i));
+        }
+
+        // straddling the end of the ring, w/a gap before the last
+        for &i in &[3, 4, 6] {
+            assert!(sw.insert(i, i as u32).is_ok());
+        }
+
+        assert_eq!(None, sw.find_first(|&t| { visited.push(t); false }));
+        assert_eq!(vec![3, 4, 6], visited);
+    }
+
+    #[test]
+    fn past_u32_max() {
+        const START :u64 = u32::MAX as u64 - 1;
+
+        // a location truncated to 32 bits would land at the start of the ring instead
+        let sw = SlidingWindow::<u64>::new_at(4, START);
+
+        assert_eq!((START, START + 4), sw.window());
+
+        for loc in START..START + 4 {
+            assert!(sw.insert(loc, loc).is_ok());
+        }
+
+        assert_eq!(Err("window full"), sw.try_insert(START + 4, 0));
+        assert!(sw.try_insert(1, 1).is_err());
+        assert_eq!(Some(u32::MAX as u64 + 1), sw.find_first(|&t| t > u32::MAX as u64));
+
+        // slide past the 32-bit boundary, and wrap the ring
+        assert_eq!(Ok(START), sw.pop());
+        assert_eq!(Ok(START + 1), sw.pop());
+        assert!(sw.insert(START + 4, START + 4).is_ok());
+        assert!(sw.insert(START + 5, START + 5).is_ok());
+
+        assert_eq!(Ok(START + 3), sw.remove(START + 3));
+        assert_eq!(vec![START + 3], sw.missing(0, u64::MAX));
+        assert_eq!(Ok(vec![START + 4, START + 5]), sw.inspect_range(START + 4, START + 6, |items| items.iter().map(|&&t| t).collect::<Vec<_>>()));
+        assert_eq!(Ok(START + 2), sw.pop());
+        assert_eq!((START + 3, START + 7), sw.window());
+        assert_eq!(2, sw.len());
+    }
+
+    #[test]
+    fn resize_grow() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        // wrap the ring, so the copy has to unwrap it
+        for i in 0..3 {
+            assert!(sw.insert(i, i as u32).is_ok());
+            assert_eq!(Ok(i as u32), sw.remove(i));
+        }
+
+        assert!(sw.insert(3, 3).is_ok());
+        assert!(sw.insert(5, 5).is_ok());
+        assert!(sw.insert(6, 6).is_ok());
+
+        assert!(sw.resize(8).is_ok());
+        assert_eq!((3,11), sw.window());
+
+        // room for more, and everything where it was
+        assert!(sw.insert(10, 10).is_ok());
+        assert_eq!(vec![4, 7, 8, 9], sw.missing(0, 11));
+        assert_eq!(Some(5), sw.find_first(|&t| t == 5));
+
+        assert_eq!(Ok(3), sw.pop());
+        assert!(sw.insert(4, 4).is_ok());
+        assert_eq!(Ok(4), sw.pop());
+        assert_eq!(Ok(5), sw.pop());
+        assert_eq!(Ok(6), sw.pop());
+        assert_eq!(Ok(10), sw.remove(10));
+    }
+
+    #[test]
+    fn resize_shrink() {
+        let sw = SlidingWindow::<u32>::new(8);
+
+        for i in 0..5 {
+            assert!(sw.insert(i, i as u32).is_ok());
+        }
+
+        assert_eq!(Ok(0), sw.pop());
+        assert_eq!(Ok(1), sw.pop());
+
+        // 2, 3, 4 still fit
+        assert!(sw.resize(3).is_ok());
+        assert_eq!((2,5), sw.window());
+        assert_eq!(Err("loc >= end"), sw.remove(5));
+
+        assert_eq!(Ok(2), sw.pop());
+        assert!(sw.insert(5, 5).is_ok());
+        assert_eq!(Ok(3), sw.pop());
+        assert_eq!(Ok(4), sw.pop());
+        assert_eq!(Ok(5), sw.pop());
+    }
+
+    #[test]
+    fn resize_shrink_outstanding() {
+        let sw = SlidingWindow::<u32>::new(8);
+
+        assert!(sw.insert(0, 0).is_ok());
+        assert!(sw.insert(5, 5).is_ok());
+
+        // 5 would be past the end
+        assert!(sw.resize(5).is_err());
+        assert!(sw.resize(0).is_err());
+        assert_eq!((0,8), sw.window());
+
+        // nothing was lost
+        assert_eq!(Ok(0), sw.remove(0));
+        assert_eq!(Ok(5), sw.remove(5));
+
+        assert!(sw.resize(5).is_ok());
+        assert_eq!((6,11), sw.window());
+    }
+
+    #[test]
+    fn missing_test() {
+        let sw = SlidingWindow::<u32>::new(8);
+
+        assert!(sw.insert(0, 0).is_ok());
+        assert!(sw.insert(2, 2).is_ok());
+        assert!(sw.insert(5, 5).is_ok());
+
+        assert_eq!(vec![1, 3, 4], sw.missing(0, 5));
+        assert_eq!(vec![3], sw.missing(3, 4));
+
+        // only what's in the window
+        assert_eq!(Ok(0), sw.pop());
+        assert_eq!(vec![1, 3, 4, 6, 7, 8], sw.missing(0, 100));
+    }
+
+    #[test]
+    fn panic_closes_window() {
+        let sw = Arc::new(SlidingWindow::<u32>::new(4));
+
+        assert!(sw.insert(0, 0).is_ok());
+        assert!(sw.insert(1, 1).is_ok());
+
+        let sw_clone = sw.clone();
+
+        // panic while holding the lock, poisoning it
+        let res = thread::spawn(move || {
+            let _guard = CloseOnDrop::new(sw_clone.clone());
+
+            sw_clone.update(1, |_| panic!("Deliberate panic")).is_ok()
+        }).join();
+
+        assert!(res.is_err());
+        assert!(sw.is_closed());
+
+        // what's already in the window can still be read, then we get an error instead of waiting
+        assert_eq!(Ok(0), sw.pop());
+        assert_eq!(Ok(1), sw.pop());
+        assert_eq!(Err("window closed"), sw.pop());
+        assert_eq!(Err("window closed"), sw.insert(2, 2));
+    }
+
+    #[test]
+    fn wait_for_room() {
+        let window = Arc::new(SlidingWindow::<u64>::new(4));
+
+        // already in the window
+        assert!(window.wait_for_room(3, Duration::from_millis(10)));
+
+        // nobody makes room
+        let start = Instant::now();
+
+        assert!(!window.wait_for_room(4, Duration::from_millis(100)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        window.insert(0, 0).expect("Error inserting");
+
+        let popper = window.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            popper.pop().expect("Error popping");
+        });
+
+        assert!(window.wait_for_room(4, Duration::from_secs(5)));
+
+        handle.join().expect("Pop thread panicked");
+    }
+
+    #[test]
+    fn insert_waits_idle() {
+        let sw = Arc::new(SlidingWindow::<u32>::new(2));
+
+        assert!(sw.insert(0, 0).is_ok());
+        assert!(sw.insert(1, 1).is_ok());
+
+        let sw_clone = sw.clone();
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            sw_clone.remove(0).expect("Error removing item 0");
+        });
+
+        let cpu = thread_cpu_time();
+
+        assert!(sw.insert(2, 2).is_ok());
+
+        // asleep the whole time, not spinning
+        let cpu = thread_cpu_time() - cpu;
+        assert!(cpu < Duration::from_millis(50), "Used {:?} of CPU waiting", cpu);
+    }
+
+    #[test]
+    fn pop_waits_idle() {
+        let sw = Arc::new(SlidingWindow::<u32>::new(4));
+        let sw_clone = sw.clone();
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            sw_clone.insert(0, 7).expect("Error inserting item 0");
+        });
+
+        let cpu = thread_cpu_time();
+
+        assert_eq!(Ok(7), sw.pop());
+
+        // asleep the whole time, not spinning
+        let cpu = thread_cpu_time() - cpu;
+        assert!(cpu < Duration::from_millis(50), "Used {:?} of CPU waiting", cpu);
+    }
+
+    #[test]
+    fn pop_timeout() {
+        let sw = Arc::new(SlidingWindow::<u32>::new(4));
+
+        // nothing there
+        let start = Instant::now();
+
+        assert_eq!(None, sw.pop_timeout(Duration::from_millis(100)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // something inserted while waiting
+        let sw_clone = sw.clone();
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            sw_clone.insert(0, 7).expect("Error inserting item 0");
+        });
+
+        assert_eq!(Some(7), sw.pop_timeout(Duration::from_secs(5)));
+
+        // what's left is still popped after closing, then there's nothing
+        sw.insert(1, 8).expect("Error inserting item 1");
+        sw.close();
+
+        assert_eq!(Some(8), sw.pop_timeout(Duration::from_secs(5)));
+        assert_eq!(None, sw.pop_timeout(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn close_wakes_insert() {
+        let sw = Arc::new(SlidingWindow::<u32>::new(2));
+
+        assert!(sw.insert(0, 0).is_ok());
+        assert!(sw.insert(1, 1).is_ok());
+
+        let sw_clone = sw.clone();
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            sw_clone.close();
+        });
+
+        // waits for room that never comes, until the window is closed
+        assert_eq!(Err("window closed"), sw.insert(2, 2));
+    }
+
+    #[test]
+    fn out_of_range() {
+        let sw = SlidingWindow::<u32>::new(4);
+
+        assert_eq!(Err("loc too large"), sw.insert(u64::max_value(), 0));
+        assert_eq!(Err("loc too large"), sw.remove(u64::max_value()));
+        assert_eq!(Err("loc >= end"), sw.remove(4));
+        assert!(!sw.wait_for_room(u64::max_value(), Duration::from_millis(1)));
+
+        assert!(sw.insert(0, 0).is_ok());
+        assert_eq!(Ok(0), sw.pop());
+
+        assert_eq!(Err("loc < start"), sw.remove(0));
+        assert_eq!(Err("loc < start"), sw.update(0, |_| ()));
+    }
+
+    #[test]
+    fn insert_remove_at_start() {
+        const COUNT :u64 = 20_000;
+
+        let sw = Arc::new(SlidingWindow::<u64>::new(4));
+        let sw_clone = sw.clone();
+
+        let remover = thread::spawn(move || {
+            for loc in 0..COUNT {
+                assert_eq!(Ok(loc), sw_clone.pop());
+            }
+        });
+
+        for loc in 0..COUNT {
+            sw.insert(loc, loc).expect("Error inserting");
+
+            // the start is sliding past these while we insert, so they must fail one way or the other, never wrap
+            for stale in loc.saturating_sub(2)..(loc + 1) {
+                match sw.insert(stale, stale) {
+                    Err("loc < start") | Err("Value already set") => (),
+                    res => panic!("Inserting {} at {} returned {:?}", stale, loc, res)
+                }
+            }
+        }
+
+        remover.join().expect("Remover panicked");
+        assert_eq!((COUNT, COUNT + 4), sw.window());
+    }
+}
@@ -3,16 +3,105 @@ use std::io;
 use std::time::Duration;
 use std::fmt::Debug;
 use std::marker::Sized;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::net::{Ipv4Addr, SocketAddrV4};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::{UnixDatagram, SocketAddr as UnixSocketAddr};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::process;
+#[cfg(unix)]
+use std::sync::{Arc, Mutex};
 
 pub trait Socket: Sized {
     fn send_to<A: ToSocketAddrs + Debug>(&self, buf: &[u8], addr: A) -> io::Result<usize>;
 
     fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
 
+    /// Only talk to addr from now on: send and recv go to and come from it, and packets from anywhere else are dropped
+    fn connect(&self, addr: SocketAddr) -> io::Result<()>;
+
+    /// Sends to the address given to connect
+    fn send(&self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Sends each packet to its address, in order, returning how many were sent
+    /// An error after the first packet is sent stops the batch short, rather than failing it
+    /// One send_to at a time, unless the socket can do better, like w/sendmmsg
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        for (i, &(buf, addr)) in packets.iter().enumerate() {
+            if let Err(e) = self.send_to(buf, addr) {
+                if i == 0 {
+                    return Err(e);
+                }
+
+                return Ok(i);
+            }
+        }
+
+        return Ok(packets.len());
+    }
+
+    /// Receives from the address given to connect
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Waits at most dur for a packet, like recv_from_timeout, then takes as many more as are already waiting, one into each buffer
+    /// Each buffer's length and where its packet came from are set beside it; returns how many were filled
+    /// An error after the first packet stops the batch short, rather than failing it
+    /// One recv_from_timeout at a time, unless the socket can do better, like w/recvmmsg
+    fn recv_batch(&self, packets: &mut [(Vec<u8>, usize, SocketAddr)], dur: Duration) -> io::Result<usize> {
+        for i in 0..packets.len() {
+            // only the first waits
+            let wait = if i == 0 { dur } else { Duration::from_secs(0) };
+            let (ref mut buf, ref mut len, ref mut addr) = packets[i];
+
+            match self.recv_from_timeout(buf, wait) {
+                Ok((amt, from)) => {
+                    *len = amt;
+                    *addr = from;
+                },
+                Err(e) => {
+                    if i == 0 {
+                        return Err(e);
+                    }
+
+                    return Ok(i);
+                }
+            }
+        }
+
+        return Ok(packets.len());
+    }
+
+    /// Waits at most dur for a single packet, without changing the socket's read timeout
+    /// Returns an error of kind WouldBlock if no packet arrives in time
+    fn recv_from_timeout(&self, buf: &mut [u8], dur: Duration) -> io::Result<(usize, SocketAddr)>;
+
     fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()>;
 
     fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()>;
 
+    /// Sets the Don't Fragment bit on everything sent, so a packet too large for the path fails w/EMSGSIZE
+    fn set_dont_fragment(&self, on: bool) -> io::Result<()>;
+
+    /// Asks the kernel for a receive buffer of size bytes, to hold what arrives between reads; it can grant more or less
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()>;
+
+    /// The receive buffer the kernel granted; Linux doubles what it was asked for, to leave room for its bookkeeping
+    fn recv_buffer_size(&self) -> io::Result<usize>;
+
+    /// Asks the kernel for a send buffer of size bytes; like the receive buffer, it can grant more or less
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()>;
+
+    fn send_buffer_size(&self) -> io::Result<usize>;
+
+    /// The address this socket receives on, which the sender tells the receiver to send its ACKs to
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
     fn try_clone(&self) -> io::Result<Self>;
 }
 
@@ -25,6 +114,133 @@ impl Socket for UdpSocket {
         return UdpSocket::recv_from(self, buf);
     }
 
+    fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        return UdpSocket::connect(self, addr);
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        return UdpSocket::send(self, buf);
+    }
+
+    /// All of them w/one sendmmsg, or as many as the kernel takes
+    #[cfg(target_os = "linux")]
+    fn send_batch(&self, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        use std::mem;
+        use libc::{sendmmsg, mmsghdr, iovec, c_uint, c_void};
+
+        if packets.is_empty() {
+            return Ok(0);
+        }
+
+        let addrs = packets.iter().map(|&(_, ref addr)| sockaddr_of(addr)).collect::<Vec<_>>();
+        let mut iovecs = packets.iter().map(|&(buf, _)| iovec { iov_base: buf.as_ptr() as *mut c_void, iov_len: buf.len() }).collect::<Vec<_>>();
+
+        // zeroed first, as some libcs have padding fields that can't be named
+        let mut msgs = iovecs.iter_mut().zip(addrs.iter()).map(|(iov, &(ref addr, addr_len))| {
+            let mut msg :mmsghdr = unsafe { mem::zeroed() };
+
+            msg.msg_hdr.msg_name = addr as *const _ as *mut c_void;
+            msg.msg_hdr.msg_namelen = addr_len;
+            msg.msg_hdr.msg_iov = iov as *mut iovec;
+            msg.msg_hdr.msg_iovlen = 1;
+
+            msg
+        }).collect::<Vec<_>>();
+
+        loop {
+            let ret = unsafe { sendmmsg(self.as_raw_fd(), msgs.as_mut_ptr(), msgs.len() as c_uint, 0) };
+
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+
+                // interrupted before anything was sent, so send it all again
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+
+                return Err(err);
+            }
+
+            return Ok(ret as usize);
+        }
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        return UdpSocket::recv(self, buf);
+    }
+
+    /// As many as are waiting, once the first shows up, w/one recvmmsg
+    #[cfg(target_os = "linux")]
+    fn recv_batch(&self, packets: &mut [(Vec<u8>, usize, SocketAddr)], dur: Duration) -> io::Result<usize> {
+        use std::mem;
+        use std::ptr;
+        use libc::{recvmmsg, mmsghdr, iovec, sockaddr_storage, socklen_t, c_uint, c_void, MSG_DONTWAIT};
+
+        if packets.is_empty() {
+            return Ok(0);
+        }
+
+        wait_readable(self.as_raw_fd(), dur)?;
+
+        let mut addrs :Vec<sockaddr_storage> = vec![unsafe { mem::zeroed() }; packets.len()];
+        let mut iovecs = packets.iter_mut().map(|&mut (ref mut buf, _, _)| iovec { iov_base: buf.as_mut_ptr() as *mut c_void, iov_len: buf.len() }).collect::<Vec<_>>();
+
+        // zeroed first, as some libcs have padding fields that can't be named
+        let mut msgs = iovecs.iter_mut().zip(addrs.iter_mut()).map(|(iov, addr)| {
+            let mut msg :mmsghdr = unsafe { mem::zeroed() };
+
+            msg.msg_hdr.msg_name = addr as *mut sockaddr_storage as *mut c_void;
+            msg.msg_hdr.msg_namelen = mem::size_of::<sockaddr_storage>() as socklen_t;
+            msg.msg_hdr.msg_iov = iov as *mut iovec;
+            msg.msg_hdr.msg_iovlen = 1;
+
+            msg
+        }).collect::<Vec<_>>();
+
+        // never waits, as there's something to read; another clone could have read it first, which is WouldBlock like a timeout
+        let received = loop {
+            let ret = unsafe { recvmmsg(self.as_raw_fd(), msgs.as_mut_ptr(), msgs.len() as c_uint, MSG_DONTWAIT, ptr::null_mut()) };
+
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+
+                return Err(err);
+            }
+
+            break ret as usize;
+        };
+
+        for (packet, (msg, addr)) in packets.iter_mut().zip(msgs.iter().zip(addrs.iter())).take(received) {
+            packet.1 = msg.msg_len as usize;
+            packet.2 = socket_addr_of(addr)?;
+        }
+
+        return Ok(received);
+    }
+
+    #[cfg(unix)]
+    fn recv_from_timeout(&self, buf: &mut [u8], dur: Duration) -> io::Result<(usize, SocketAddr)> {
+        wait_readable(self.as_raw_fd(), dur)?;
+
+        return UdpSocket::recv_from(self, buf);
+    }
+
+    #[cfg(not(unix))]
+    fn recv_from_timeout(&self, buf: &mut [u8], dur: Duration) -> io::Result<(usize, SocketAddr)> {
+        // no poll available, fall back to swapping the read timeout
+        let prev = UdpSocket::read_timeout(self)?;
+
+        UdpSocket::set_read_timeout(self, Some(dur))?;
+        let ret = UdpSocket::recv_from(self, buf);
+        UdpSocket::set_read_timeout(self, prev)?;
+
+        return ret;
+    }
+
     fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
         return UdpSocket::set_read_timeout(self, dur);
     }
@@ -33,30 +249,490 @@ impl Socket for UdpSocket {
         return UdpSocket::set_write_timeout(self, dur);
     }
 
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn set_dont_fragment(&self, on: bool) -> io::Result<()> {
+        use std::mem;
+        use libc::{setsockopt, c_int, c_void, socklen_t, IPPROTO_IP, IPPROTO_IPV6, IP_MTU_DISCOVER, IP_PMTUDISC_DO, IP_PMTUDISC_DONT,
+                   IPV6_MTU_DISCOVER, IPV6_PMTUDISC_DO, IPV6_PMTUDISC_DONT};
+
+        let (level, name, value) :(c_int, c_int, c_int) = if self.local_addr()?.is_ipv6() {
+            (IPPROTO_IPV6, IPV6_MTU_DISCOVER, if on { IPV6_PMTUDISC_DO } else { IPV6_PMTUDISC_DONT })
+        } else {
+            (IPPROTO_IP, IP_MTU_DISCOVER, if on { IP_PMTUDISC_DO } else { IP_PMTUDISC_DONT })
+        };
+
+        let ret = unsafe { setsockopt(self.as_raw_fd(), level, name, &value as *const c_int as *const c_void, mem::size_of::<c_int>() as socklen_t) };
+
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        return Ok( () );
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn set_dont_fragment(&self, _on: bool) -> io::Result<()> {
+        return Err(io::Error::new(io::ErrorKind::Other, "Setting the Don't Fragment bit is not supported on this platform"));
+    }
+
+    #[cfg(unix)]
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        return set_buffer_size(self.as_raw_fd(), libc::SO_RCVBUF, size);
+    }
+
+    #[cfg(unix)]
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        return buffer_size(self.as_raw_fd(), libc::SO_RCVBUF);
+    }
+
+    #[cfg(unix)]
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        return set_buffer_size(self.as_raw_fd(), libc::SO_SNDBUF, size);
+    }
+
+    #[cfg(unix)]
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        return buffer_size(self.as_raw_fd(), libc::SO_SNDBUF);
+    }
+
+    #[cfg(not(unix))]
+    fn set_recv_buffer_size(&self, _size: usize) -> io::Result<()> {
+        return Err(io::Error::new(io::ErrorKind::Other, "Setting socket buffer sizes is not supported on this platform"));
+    }
+
+    #[cfg(not(unix))]
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        return Err(io::Error::new(io::ErrorKind::Other, "Getting socket buffer sizes is not supported on this platform"));
+    }
+
+    #[cfg(not(unix))]
+    fn set_send_buffer_size(&self, _size: usize) -> io::Result<()> {
+        return Err(io::Error::new(io::ErrorKind::Other, "Setting socket buffer sizes is not supported on this platform"));
+    }
+
+    #[cfg(not(unix))]
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        return Err(io::Error::new(io::ErrorKind::Other, "Getting socket buffer sizes is not supported on this platform"));
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        return UdpSocket::local_addr(self);
+    }
+
     fn try_clone(&self) -> io::Result<Self> {
         return UdpSocket::try_clone(self);
     }
 }
 
+/// Waits at most dur for fd to have something to read
+/// Returns an error of kind WouldBlock if nothing arrives in time
+#[cfg(unix)]
+fn wait_readable(fd: RawFd, dur: Duration) -> io::Result<()> {
+    use std::time::Instant;
+    use libc::{poll, pollfd, c_int, POLLIN};
+
+    let deadline = Instant::now() + dur;
+    let mut fds = pollfd { fd, events: POLLIN, revents: 0 };
+
+    loop {
+        let now = Instant::now();
+        let remaining = if deadline > now { deadline - now } else { Duration::new(0, 0) };
+
+        // round up to the next ms, so we never spin with a 0 timeout before the deadline
+        let millis = remaining.as_secs() * 1000 + ((remaining.subsec_nanos() + 999_999) / 1_000_000) as u64;
+        let millis = if millis > c_int::max_value() as u64 { c_int::max_value() } else { millis as c_int };
+
+        let ret = unsafe { poll(&mut fds, 1, millis) };
+
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+
+            // interrupted by a signal, wait out the rest of the deadline
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return Err(err);
+        } else if ret == 0 {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "Timed out waiting for packet"));
+        }
+
+        return Ok( () );
+    }
+}
+
+/// Asks for a buffer of size bytes on fd, SO_RCVBUF or SO_SNDBUF as option says
+/// More than fits in an int is as much as can be asked for
+#[cfg(unix)]
+fn set_buffer_size(fd: RawFd, option: libc::c_int, size: usize) -> io::Result<()> {
+    use std::mem;
+    use libc::{setsockopt, c_int, c_void, socklen_t, SOL_SOCKET};
+
+    let value = size.min(c_int::max_value() as usize) as c_int;
+    let ret = unsafe { setsockopt(fd, SOL_SOCKET, option, &value as *const c_int as *const c_void, mem::size_of::<c_int>() as socklen_t) };
+
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    return Ok( () );
+}
+
+/// The buffer fd was granted, SO_RCVBUF or SO_SNDBUF as option says
+#[cfg(unix)]
+fn buffer_size(fd: RawFd, option: libc::c_int) -> io::Result<usize> {
+    use std::mem;
+    use libc::{getsockopt, c_int, c_void, socklen_t, SOL_SOCKET};
+
+    let mut value :c_int = 0;
+    let mut len = mem::size_of::<c_int>() as socklen_t;
+    let ret = unsafe { getsockopt(fd, SOL_SOCKET, option, &mut value as *mut c_int as *mut c_void, &mut len) };
+
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    return Ok(value as usize);
+}
+
+/// Removes the path a UnixDatagramSocket is bound to, once the socket and all its clones are dropped
+#[cfg(unix)]
+struct Unlink(PathBuf);
+
+#[cfg(unix)]
+impl Drop for Unlink {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// A Socket over a Unix domain datagram socket, for transfers between processes on the same host
+/// There's only ever the one peer, so the SocketAddrs the Socket trait deals in are all UNIX_PEER_ADDR, and stand for it
+/// The receiver learns the sender's path from the first packet it gets; the sender binds a path of its own, next to the receiver's, to hear back on
+#[cfg(unix)]
+pub struct UnixDatagramSocket {
+    socket: UnixDatagram,
+    peer: Arc<Mutex<Option<PathBuf>>>,  // where send_to sends, once it's known
+    _bound: Arc<Unlink>                 // the path this end is bound to
+}
+
+/// The address every packet on a UnixDatagramSocket comes from, and every send_to goes to, whatever it's given
+#[cfg(unix)]
+pub const UNIX_PEER_ADDR :SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+
+#[cfg(unix)]
+impl UnixDatagramSocket {
+    /// Binds to path, to wait for a sender to send to it
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixDatagramSocket> {
+        let path = path.as_ref();
+        let socket = UnixDatagram::bind(path).map_err(|e| match e.kind() {
+            io::ErrorKind::AddrInUse => io::Error::new(e.kind(), format!("{} is already in use, or left over; remove it, or pick another w/--unix-socket", path.display())),
+            _ => io::Error::new(e.kind(), format!("Cannot bind to {}: {}", path.display(), e))
+        })?;
+
+        return Ok(UnixDatagramSocket { socket, peer: Arc::new(Mutex::new(None)), _bound: Arc::new(Unlink(path.to_path_buf())) });
+    }
+
+    /// Binds to a path of its own, named for path and this process, to send to whoever's bound to path
+    pub fn connect_to<P: AsRef<Path>>(path: P) -> io::Result<UnixDatagramSocket> {
+        let path = path.as_ref();
+        let mut local = path.as_os_str().to_os_string();
+
+        local.push(format!(".{}", process::id()));
+
+        let mut socket = UnixDatagramSocket::bind(local)?;
+
+        socket.peer = Arc::new(Mutex::new(Some(path.to_path_buf())));
+
+        return Ok(socket);
+    }
+
+    /// Where send_to sends
+    fn peer(&self) -> io::Result<PathBuf> {
+        return self.peer.lock().unwrap().clone().ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "Nothing has been received yet, so there's no one to send to"));
+    }
+
+    /// The packet's length, noting where it came from, if it has a path to send back to
+    fn received(&self, amt: usize, addr: UnixSocketAddr) -> (usize, SocketAddr) {
+        if let Some(path) = addr.as_pathname() {
+            let mut peer = self.peer.lock().unwrap();
+
+            if peer.as_ref().map_or(true, |peer| peer != path) {
+                *peer = Some(path.to_path_buf());
+            }
+        }
+
+        return (amt, UNIX_PEER_ADDR);
+    }
+}
+
+#[cfg(unix)]
+impl Socket for UnixDatagramSocket {
+    fn send_to<A: ToSocketAddrs + Debug>(&self, buf: &[u8], _addr: A) -> io::Result<usize> {
+        return self.socket.send_to(buf, self.peer()?);
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (amt, addr) = self.socket.recv_from(buf)?;
+
+        return Ok(self.received(amt, addr));
+    }
+
+    /// Only talk to the peer from now on, whatever addr is
+    fn connect(&self, _addr: SocketAddr) -> io::Result<()> {
+        return self.socket.connect(self.peer()?);
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        return self.socket.send(buf);
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        return self.socket.recv(buf);
+    }
+
+    fn recv_from_timeout(&self, buf: &mut [u8], dur: Duration) -> io::Result<(usize, SocketAddr)> {
+        wait_readable(self.socket.as_raw_fd(), dur)?;
+
+        return self.recv_from(buf);
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        return self.socket.set_read_timeout(dur);
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        return self.socket.set_write_timeout(dur);
+    }
+
+    /// Nothing is fragmented on the way to another process, so there's nothing to set
+    fn set_dont_fragment(&self, _on: bool) -> io::Result<()> {
+        return Ok( () );
+    }
+
+    fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        return set_buffer_size(self.socket.as_raw_fd(), libc::SO_RCVBUF, size);
+    }
+
+    fn recv_buffer_size(&self) -> io::Result<usize> {
+        return buffer_size(self.socket.as_raw_fd(), libc::SO_RCVBUF);
+    }
+
+    fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        return set_buffer_size(self.socket.as_raw_fd(), libc::SO_SNDBUF, size);
+    }
+
+    fn send_buffer_size(&self) -> io::Result<usize> {
+        return buffer_size(self.socket.as_raw_fd(), libc::SO_SNDBUF);
+    }
+
+    /// A path isn't a SocketAddr, and there's only ever the one peer to send to anyway
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "A Unix domain socket has no SocketAddr"));
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        return Ok(UnixDatagramSocket { socket: self.socket.try_clone()?, peer: self.peer.clone(), _bound: self._bound.clone() });
+    }
+}
+
+/// Binds a UdpSocket to addr, w/an error that says which flag picks another, not only that the address is taken
+pub fn bind(addr: SocketAddr, flag: &str) -> io::Result<UdpSocket> {
+    return UdpSocket::bind(addr).map_err(|e| bind_error(e, addr, flag));
+}
+
+/// Binds a UdpSocket to port on every IPv6 address w/IPV6_V6ONLY cleared, so it gets IPv4 packets too
+/// They come from the IPv4 address mapped into IPv6 (::ffff:a.b.c.d), and what's sent back to that goes out as IPv4
+/// One socket instead of one per family, so the receiver reads from it like any other; but not every system
+/// lets the option be cleared (OpenBSD doesn't), which fails the bind
+/// std binds a socket as it makes it, too soon to clear the option, so it's made w/libc
+#[cfg(unix)]
+pub fn bind_dual_stack(port: u16, flag: &str) -> io::Result<UdpSocket> {
+    use std::mem;
+    use std::net::{Ipv6Addr, SocketAddrV6};
+    use std::os::unix::io::FromRawFd;
+    use libc::{socket, setsockopt, bind, c_int, c_void, socklen_t, sockaddr, sockaddr_in6, sa_family_t, AF_INET6, SOCK_DGRAM, IPPROTO_IPV6, IPV6_V6ONLY};
+
+    let addr = SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0));
+
+    let fd = unsafe { socket(AF_INET6, SOCK_DGRAM, 0) };
+
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // owned from here on, so it's closed on any error below
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+
+    let off :c_int = 0;
+
+    if unsafe { setsockopt(fd, IPPROTO_IPV6, IPV6_V6ONLY, &off as *const c_int as *const c_void, mem::size_of::<c_int>() as socklen_t) } != 0 {
+        let e = io::Error::last_os_error();
+
+        return Err(io::Error::new(e.kind(), format!("Cannot listen on IPv4 and IPv6 at once here: {}", e)));
+    }
+
+    // the wildcard address, zeroed already
+    let mut sin6 :sockaddr_in6 = unsafe { mem::zeroed() };
+
+    sin6.sin6_family = AF_INET6 as sa_family_t;
+    sin6.sin6_port = port.to_be();
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly"))]
+    {
+        sin6.sin6_len = mem::size_of::<sockaddr_in6>() as u8;
+    }
+
+    if unsafe { bind(fd, &sin6 as *const sockaddr_in6 as *const sockaddr, mem::size_of::<sockaddr_in6>() as socklen_t) } != 0 {
+        return Err(bind_error(io::Error::last_os_error(), addr, flag));
+    }
+
+    return Ok(socket);
+}
+
+/// The error binding to addr failed w/, saying which flag picks another address
+fn bind_error(e: io::Error, addr: SocketAddr, flag: &str) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::AddrInUse => return io::Error::new(e.kind(), format!("Port {} is already in use on {}, pick another w/{}", addr.port(), addr.ip(), flag)),
+        io::ErrorKind::AddrNotAvailable => return io::Error::new(e.kind(), format!("Cannot bind to {}, it's not an address of this host: {}", addr.ip(), e)),
+        _ => return io::Error::new(e.kind(), format!("Cannot bind to {}: {}", addr, e))
+    }
+}
+
+/// The C socket address for addr, w/its length, for the calls std doesn't wrap
+#[cfg(target_os = "linux")]
+fn sockaddr_of(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    use std::mem;
+    use libc::{sockaddr_storage, sockaddr_in, sockaddr_in6, in_addr, in6_addr, sa_family_t, socklen_t, AF_INET, AF_INET6};
+
+    let mut storage :sockaddr_storage = unsafe { mem::zeroed() };
+
+    let len = match *addr {
+        SocketAddr::V4(ref addr) => {
+            let sin = unsafe { &mut *(&mut storage as *mut sockaddr_storage as *mut sockaddr_in) };
+
+            sin.sin_family = AF_INET as sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr = in_addr { s_addr: u32::from(*addr.ip()).to_be() };
+
+            mem::size_of::<sockaddr_in>()
+        },
+        SocketAddr::V6(ref addr) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut sockaddr_storage as *mut sockaddr_in6) };
+
+            sin6.sin6_family = AF_INET6 as sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr = in6_addr { s6_addr: addr.ip().octets() };
+            sin6.sin6_scope_id = addr.scope_id();
+
+            mem::size_of::<sockaddr_in6>()
+        }
+    };
+
+    return (storage, len as socklen_t);
+}
+
+/// The address in a C socket address filled in by the kernel, as recvmmsg does
+#[cfg(target_os = "linux")]
+fn socket_addr_of(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    use std::net::{Ipv6Addr, SocketAddrV6};
+    use libc::{sockaddr_storage, sockaddr_in, sockaddr_in6, AF_INET, AF_INET6};
+
+    match storage.ss_family as libc::c_int {
+        AF_INET => {
+            let sin = unsafe { &*(storage as *const sockaddr_storage as *const sockaddr_in) };
+
+            return Ok(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)), u16::from_be(sin.sin_port))));
+        },
+        AF_INET6 => {
+            let sin6 = unsafe { &*(storage as *const sockaddr_storage as *const sockaddr_in6) };
+
+            return Ok(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(sin6.sin6_addr.s6_addr), u16::from_be(sin6.sin6_port), sin6.sin6_flowinfo, sin6.sin6_scope_id)));
+        },
+        family => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Packet from an address of family {}, not IPv4 or IPv6", family)))
+    }
+}
+
 pub mod mocks {
     use std::net::{ToSocketAddrs, SocketAddr, IpAddr, Ipv4Addr};
     use std::io;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use socket::Socket;
     use std::fmt::Debug;
     use std::collections::VecDeque;
-    use std::sync::{Arc, Mutex};
-    use std::thread;
-    use std::cell::RefCell;
+    use std::sync::{Arc, Mutex, Condvar};
     use rand::{Rng, XorShiftRng, SeedableRng};
 
+    const MOCK_BUFFER_SIZE :usize = 212_992;    // each buffer until it's set, Linux's usual default
+
+    /// One direction of the simulated link
+    /// Both ends of a duplex pair, and all of their clones, share the same queues
+    struct PacketQueue {
+        packets: Mutex<VecDeque<(Instant, SocketAddr, Vec<u8>)>>,  // each packet w/when it can be read, and where it came from
+        ready: Condvar
+    }
+
+    impl PacketQueue {
+        fn new() -> Arc<PacketQueue> {
+            Arc::new(PacketQueue { packets: Mutex::new(VecDeque::new()), ready: Condvar::new() })
+        }
+    }
+
+    /// Decides which packets sent on the mock are dropped
+    #[derive(Clone, Debug)]
+    enum LossModel {
+        /// Each packet is dropped independently, with the same probability
+        Bernoulli { loss: f64 },
+        /// Gilbert-Elliott: a good and a bad state, each w/its own loss probability
+        /// Packets are dropped in bursts while the link sits in the bad state
+        Burst { to_bad: f64, to_good: f64, good_loss: f64, bad_loss: f64, bad: bool },
+        /// Deterministic: drops the packets sent at offset, offset + every, offset + 2*every, ...
+        Periodic { every: u64, offset: u64 }
+    }
+
     struct PacketDroppingSocketInner {
-        send_queue: Box<VecDeque<Vec<u8>>>,
-        recv_queue: Box<VecDeque<Vec<u8>>>,
-        read_timeout: Duration,
-        rng: XorShiftRng
+        send_queue: Arc<PacketQueue>,
+        recv_queue: Arc<PacketQueue>,
+        read_timeout: Option<Duration>,
+        connected: Option<SocketAddr>,     // set by connect; there's only ever one peer, so it's just checked
+        addr: SocketAddr,   // where packets sent on this end come from, as far as the other end can tell
+        rng: XorShiftRng,
+        loss_model: LossModel,
+        delay: Duration,    // how long a packet sent on this end takes to arrive
+        jitter: Duration,   // the most a packet sent on this end arrives after delay
+        reorder: f64,       // probability a packet sent on this end jumps ahead of the one queued before it
+        duplicate: f64,     // probability a packet sent on this end arrives twice
+        path_mtu: Option<usize>,    // the largest packet that can be sent on this end w/the Don't Fragment bit set
+        dont_fragment: bool,
+        recv_buffer_size: usize,    // whatever set_recv_buffer_size asked for, all of it granted
+        send_buffer_size: usize,
+        recv_error: Option<io::ErrorKind>,  // returned by the next recv_from, instead of a packet
+        tamper: Option<Box<FnMut(&mut Vec<u8>) + Send>>,   // changes each packet sent on this end that isn't dropped
+        sent: u64,      // number of calls to send_to
+        dropped: u64    // number of those packets that were dropped
+    }
+
+    impl PacketDroppingSocketInner {
+        /// Flips a coin, according to the loss model, to see if the next packet is dropped
+        fn drop_packet(&mut self) -> bool {
+            match self.loss_model {
+                LossModel::Bernoulli { loss } => self.rng.gen_bool(loss),
+                LossModel::Burst { to_bad, to_good, good_loss, bad_loss, ref mut bad } => {
+                    // the current state decides the fate of this packet, then we transition
+                    let dropped = self.rng.gen_bool(if *bad { bad_loss } else { good_loss });
+
+                    *bad = if *bad { !self.rng.gen_bool(to_good) } else { self.rng.gen_bool(to_bad) };
+
+                    dropped
+                },
+                // sent already counts this packet
+                LossModel::Periodic { every, offset } => (self.sent - 1) % every == offset
+            }
+        }
     }
 
+    /// A mock socket; clones made with try_clone share the same state, like an OS socket
     pub struct PacketDroppingSocket {
         inner: Arc<Mutex<PacketDroppingSocketInner>>
     }
@@ -64,79 +740,345 @@ pub mod mocks {
     impl PacketDroppingSocket {
         pub fn new() -> Self {
             let inner = PacketDroppingSocketInner {
-                send_queue: Box::new(VecDeque::<Vec<u8>>::new()),
-                recv_queue: Box::new(VecDeque::<Vec<u8>>::new()),
-                read_timeout: Duration::new(0, 0),
-                rng: XorShiftRng::from_seed([0xAB; 16])
+                send_queue: PacketQueue::new(),
+                recv_queue: PacketQueue::new(),
+                read_timeout: None,
+                connected: None,
+                addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080),
+                rng: XorShiftRng::from_seed([0xAB; 16]),
+                loss_model: LossModel::Bernoulli { loss: 0.0 },
+                delay: Duration::from_secs(0),
+                jitter: Duration::from_secs(0),
+                reorder: 0.0,
+                duplicate: 0.0,
+                path_mtu: None,
+                dont_fragment: false,
+                recv_buffer_size: MOCK_BUFFER_SIZE,
+                send_buffer_size: MOCK_BUFFER_SIZE,
+                recv_error: None,
+                tamper: None,
+                sent: 0,
+                dropped: 0
             };
 
             PacketDroppingSocket { inner: Arc::new(Mutex::new(inner)) }
         }
 
+        /// Creates the other end of the link: what this socket sends, the duplex receives and vice versa
         pub fn duplex(&self) -> Self {
-            let mut inner = self.inner.lock().unwrap();
+            let inner = self.inner.lock().unwrap();
 
             let new_inner = PacketDroppingSocketInner {
                 send_queue: inner.recv_queue.clone(),
                 recv_queue: inner.send_queue.clone(),
-                read_timeout: inner.read_timeout.clone(),
-                rng: inner.rng.clone()
+                read_timeout: inner.read_timeout,
+                connected: None,
+                addr: inner.addr,
+                rng: inner.rng.clone(),
+                loss_model: inner.loss_model.clone(),
+                delay: inner.delay,
+                jitter: inner.jitter,
+                reorder: inner.reorder,
+                duplicate: inner.duplicate,
+                path_mtu: inner.path_mtu,
+                dont_fragment: false,
+                recv_buffer_size: MOCK_BUFFER_SIZE,
+                send_buffer_size: MOCK_BUFFER_SIZE,
+                recv_error: None,
+                tamper: None,
+                sent: 0,
+                dropped: 0
             };
 
             PacketDroppingSocket { inner: Arc::new(Mutex::new(new_inner)) }
         }
-    }
 
-    impl Socket for PacketDroppingSocket {
-        fn send_to<A: ToSocketAddrs + Debug>(&self, buf: &[u8], addr: A) -> io::Result<usize> {
-            let mut inner = self.inner.lock().unwrap();
+        /// Drops each packet sent on this end independently, w/the given probability
+        pub fn with_bernoulli_model(self, loss: f64) -> Self {
+            self.inner.lock().unwrap().loss_model = LossModel::Bernoulli { loss };
 
-            // flip a coin to see if the packet makes it into the socket queue
-            if inner.rng.gen_bool(1.0) {
-                debug!("Called send_to; adding packet");
+            self
+        }
 
-                inner.send_queue.push_back(buf.to_vec());
-            } else {
-                debug!("Called send_to; packet dropped");
-            }
+        /// Drops packets sent on this end in bursts, using a Gilbert-Elliott model
+        /// The link starts in the good state; to_bad and to_good are the per-packet transition probabilities,
+        /// and good_loss and bad_loss are the probabilities of dropping a packet in each state
+        pub fn with_burst_model(self, to_bad: f64, to_good: f64, good_loss: f64, bad_loss: f64) -> Self {
+            self.inner.lock().unwrap().loss_model = LossModel::Burst { to_bad, to_good, good_loss, bad_loss, bad: false };
 
-            return Ok(buf.len());
+            self
         }
 
-        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
-            let mut inner = self.inner.lock().unwrap();
+        /// Drops every packet sent on this end whose index (counting from 0) is offset modulo every
+        pub fn with_periodic_model(self, every: u64, offset: u64) -> Self {
+            self.inner.lock().unwrap().loss_model = LossModel::Periodic { every, offset };
 
-            let mut packet = inner.recv_queue.pop_front();
+            self
+        }
 
-            if packet.is_none() {
-                debug!("No packets, going to sleep for {:?}", inner.read_timeout);
-                thread::sleep(inner.read_timeout);
-                packet = inner.recv_queue.pop_front();
+        /// Delays every packet sent on this end by delay, so a round trip takes the delays of both ends
+        pub fn with_delay(self, delay: Duration) -> Self {
+            self.inner.lock().unwrap().delay = delay;
+
+            self
+        }
+
+        /// Delays each packet sent on this end by up to jitter more than with_delay, picked at random per packet
+        /// The link is still first in first out, so a packet held up holds up the ones behind it
+        pub fn with_jitter(self, jitter: Duration) -> Self {
+            self.inner.lock().unwrap().jitter = jitter;
+
+            self
+        }
+
+        /// Swaps each packet sent on this end w/the one queued ahead of it, w/the given probability
+        pub fn with_reordering(self, reorder: f64) -> Self {
+            self.inner.lock().unwrap().reorder = reorder;
+
+            self
+        }
+
+        /// Delivers each packet sent on this end twice, w/the given probability
+        pub fn with_duplication(self, duplicate: f64) -> Self {
+            self.inner.lock().unwrap().duplicate = duplicate;
+
+            self
+        }
+
+        /// Rejects packets larger than mtu sent on this end w/EMSGSIZE, once the Don't Fragment bit is set
+        /// Setting the bit fails w/out this, so nothing is sent probing for the path MTU
+        pub fn with_path_mtu(self, mtu: usize) -> Self {
+            self.inner.lock().unwrap().path_mtu = Some(mtu);
+
+            self
+        }
+
+        /// Passes each packet sent on this end through tamper before it's queued, to corrupt it in ways a checksum won't catch
+        pub fn with_tamper<F: FnMut(&mut Vec<u8>) + Send + 'static>(self, tamper: F) -> Self {
+            self.inner.lock().unwrap().tamper = Some(Box::new(tamper));
+
+            self
+        }
+
+        /// Drops every packet sent on this end from now on, like the link went down or the peer died
+        /// Unlike the with_ models, it can be done once the socket's in use, through a clone
+        pub fn cut(&self) {
+            self.inner.lock().unwrap().loss_model = LossModel::Bernoulli { loss: 1.0 };
+        }
+
+        /// Makes packets sent on this end from now on come from addr, like a NAT rebinding or the peer changing networks
+        /// Packets already on their way still come from the old one; as w/cut, it can be done through a clone
+        pub fn rebind(&self, addr: SocketAddr) {
+            self.inner.lock().unwrap().addr = addr;
+        }
+
+        /// Makes the next recv_from (or recv_from_timeout) on this end fail w/kind, like a socket error would
+        pub fn fail_next_recv(&self, kind: io::ErrorKind) {
+            self.inner.lock().unwrap().recv_error = Some(kind);
+        }
+
+        /// The number of packets sent on this end, including dropped ones
+        pub fn sent_count(&self) -> u64 {
+            self.inner.lock().unwrap().sent
+        }
+
+        /// The number of packets sent on this end that were dropped
+        pub fn dropped_count(&self) -> u64 {
+            self.inner.lock().unwrap().dropped
+        }
+
+        /// The number of packets sent on this socket that the other end hasn't read yet
+        pub fn queued(&self) -> usize {
+            let send_queue = { self.inner.lock().unwrap().send_queue.clone() };
+            let packets = send_queue.packets.lock().unwrap();
+
+            return packets.len();
+        }
+
+        /// Waits for a packet to show up in the receive queue
+        /// A timeout of None waits forever, otherwise None is returned if nothing arrives in time
+        fn wait_for_packet(&self, timeout: Option<Duration>) -> Option<(SocketAddr, Vec<u8>)> {
+            // grab the queue and release the lock, so other clones can send while we wait
+            let recv_queue = { self.inner.lock().unwrap().recv_queue.clone() };
+            let mut packets = recv_queue.packets.lock().unwrap();
+
+            if packets.is_empty() {
+                debug!("No packets, waiting for {:?}", timeout);
             }
 
-            let mut packet_len = 0;
+            let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+            loop {
+                let now = Instant::now();
+                let due = packets.front().map(|&(due, _, _)| due);
+
+                // the packet at the front can't be read until its delay is up
+                if due.map_or(false, |due| due <= now) {
+                    return packets.pop_front().map(|(_, addr, packet)| (addr, packet));
+                }
+
+                if deadline.map_or(false, |deadline| now >= deadline) {
+                    return None;
+                }
 
-            if inner.rng.gen_bool(1.0) {
-                if packet.is_some() {
-                    debug!("Called recv_from; packet read");
-                    let packet = packet.unwrap();
-                    buf[..packet.len()].copy_from_slice(packet.as_slice());
-                    packet_len = packet.len();
-                } else {
+                let wake = match (due, deadline) {
+                    (Some(due), Some(deadline)) => Some(due.min(deadline)),
+                    (due, None) => due,
+                    (None, deadline) => deadline
+                };
+
+                packets = match wake {
+                    None => recv_queue.ready.wait(packets).unwrap(),
+                    Some(wake) => recv_queue.ready.wait_timeout(packets, wake - now).unwrap().0
+                };
+            }
+        }
+
+        /// Copies the packet, if there is one, into buf; drops already happened when it was sent
+        /// Like UDP, a packet longer than buf is truncated to fit
+        /// Returns the number of bytes copied, and where the packet came from
+        fn deliver(&self, packet: Option<(SocketAddr, Vec<u8>)>, buf: &mut [u8]) -> (usize, SocketAddr) {
+            let (addr, packet) = match packet {
+                Some(packet) => packet,
+                None => {
                     debug!("Called recv_from; no packets");
+                    return (0, SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080));
+                }
+            };
+
+            let len = packet.len().min(buf.len());
+
+            if len < packet.len() {
+                debug!("Called recv_from; packet truncated from {} to {} bytes", packet.len(), len);
+            } else {
+                debug!("Called recv_from; packet read");
+            }
+
+            buf[..len].copy_from_slice(&packet[..len]);
+
+            return (len, addr);
+        }
+    }
+
+    impl Socket for PacketDroppingSocket {
+        fn send_to<A: ToSocketAddrs + Debug>(&self, buf: &[u8], _addr: A) -> io::Result<usize> {
+            let mut inner = self.inner.lock().unwrap();
+
+            // what the kernel does w/the Don't Fragment bit set, once it knows the path MTU
+            if inner.dont_fragment && inner.path_mtu.map_or(false, |mtu| buf.len() > mtu) {
+                debug!("Called send_to; packet too large: {}", buf.len());
+                return Err(io::Error::from_raw_os_error(libc::EMSGSIZE));
+            }
+
+            inner.sent += 1;
+
+            // flip a coin to see if the packet makes it into the socket queue
+            if !inner.drop_packet() {
+                debug!("Called send_to; adding packet");
+
+                let mut packet = buf.to_vec();
+
+                if let Some(ref mut tamper) = inner.tamper {
+                    tamper(&mut packet);
                 }
+
+                // only flip the coins that can come up, so the loss model sees the same rolls as before
+                let (duplicate, reorder) = (inner.duplicate, inner.reorder);
+                let copies = if duplicate > 0.0 && inner.rng.gen_bool(duplicate) { 2 } else { 1 };
+                let reorder = reorder > 0.0 && inner.rng.gen_bool(reorder);
+                let jitter = inner.jitter;
+                let jitter = if jitter > Duration::from_secs(0) { jitter * inner.rng.gen_range(0, 1000) / 1000 } else { jitter };
+                let due = Instant::now() + inner.delay + jitter;
+                let addr = inner.addr;
+
+                {
+                    let mut packets = inner.send_queue.packets.lock().unwrap();
+
+                    for _ in 0..copies {
+                        let len = packets.len();
+
+                        // it can't be read before the packet it jumped, or that one would hold it up
+                        if reorder && len > 0 {
+                            debug!("Called send_to; reordering packet");
+                            let jumped = packets[len - 1].0;
+                            packets.insert(len - 1, (jumped, addr, packet.clone()));
+                        } else {
+                            packets.push_back((due, addr, packet.clone()));
+                        }
+                    }
+                }
+
+                inner.send_queue.ready.notify_all();
             } else {
-                debug!("Called recv_from; packet dropped");
+                debug!("Called send_to; packet dropped");
+                inner.dropped += 1;
+            }
+
+            return Ok(buf.len());
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+            let (read_timeout, recv_error) = {
+                let mut inner = self.inner.lock().unwrap();
+
+                (inner.read_timeout, inner.recv_error.take())
+            };
+
+            if let Some(kind) = recv_error {
+                debug!("Called recv_from; failing w/{:?}", kind);
+                return Err(io::Error::new(kind, "Mock recv error"));
+            }
+
+            let packet = self.wait_for_packet(read_timeout);
+
+            return Ok( self.deliver(packet, buf) )
+        }
+
+        fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+            debug!("Called connect: {}", addr);
+            self.inner.lock().unwrap().connected = Some(addr);
+
+            return Ok( () );
+        }
+
+        fn send(&self, buf: &[u8]) -> io::Result<usize> {
+            let addr = { self.inner.lock().unwrap().connected };
+
+            match addr {
+                Some(addr) => return self.send_to(buf, addr),
+                None => return Err(io::Error::new(io::ErrorKind::NotConnected, "Called send before connect"))
+            }
+        }
+
+        fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.inner.lock().unwrap().connected.is_none() {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "Called recv before connect"));
             }
 
-            return Ok( (packet_len, SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080)) )
+            return self.recv_from(buf).map(|(amt, _)| amt);
+        }
+
+        fn recv_from_timeout(&self, buf: &mut [u8], dur: Duration) -> io::Result<(usize, SocketAddr)> {
+            if let Some(kind) = self.inner.lock().unwrap().recv_error.take() {
+                debug!("Called recv_from_timeout; failing w/{:?}", kind);
+                return Err(io::Error::new(kind, "Mock recv error"));
+            }
+
+            let packet = self.wait_for_packet(Some(dur));
+
+            if packet.is_none() {
+                debug!("Called recv_from_timeout; timed out after {:?}", dur);
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "Timed out waiting for packet"));
+            }
+
+            return Ok( self.deliver(packet, buf) )
         }
 
         fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
             debug!("Called set_read_timeout: {:?}", dur);
             let mut inner = self.inner.lock().unwrap();
 
-            inner.read_timeout = dur.unwrap();
+            inner.read_timeout = dur;
 
             return Ok( () );
         }
@@ -146,18 +1088,581 @@ pub mod mocks {
             return Ok( () );
         }
 
+        fn set_dont_fragment(&self, on: bool) -> io::Result<()> {
+            debug!("Called set_dont_fragment: {}", on);
+
+            let mut inner = self.inner.lock().unwrap();
+
+            // like a platform w/out the option, so the sender doesn't probe unless the test asks for it
+            if inner.path_mtu.is_none() {
+                return Err(io::Error::new(io::ErrorKind::Other, "No path MTU set on the mock socket"));
+            }
+
+            inner.dont_fragment = on;
+
+            return Ok( () );
+        }
+
+        fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+            debug!("Called set_recv_buffer_size: {}", size);
+            self.inner.lock().unwrap().recv_buffer_size = size;
+
+            return Ok( () );
+        }
+
+        fn recv_buffer_size(&self) -> io::Result<usize> {
+            return Ok(self.inner.lock().unwrap().recv_buffer_size);
+        }
+
+        fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+            debug!("Called set_send_buffer_size: {}", size);
+            self.inner.lock().unwrap().send_buffer_size = size;
+
+            return Ok( () );
+        }
+
+        fn send_buffer_size(&self) -> io::Result<usize> {
+            return Ok(self.inner.lock().unwrap().send_buffer_size);
+        }
+
+        /// What the other end sees packets from this one come from
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            return Ok(self.inner.lock().unwrap().addr);
+        }
+
         fn try_clone(&self) -> io::Result<Self> {
             debug!("Called try_clone");
-            let inner = self.inner.lock().unwrap();
 
-            let new_inner = PacketDroppingSocketInner {
-                send_queue: inner.send_queue.clone(),
-                recv_queue: inner.recv_queue.clone(),
-                read_timeout: inner.read_timeout.clone(),
-                rng: inner.rng.clone()
-            };
+            return Ok( PacketDroppingSocket { inner: self.inner.clone() } );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::net::{SocketAddr, UdpSocket, IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use socket::{Socket, bind};
+    #[cfg(unix)]
+    use socket::bind_dual_stack;
+    use socket::mocks::PacketDroppingSocket;
+
+    const DATA_TAG :u8 = 0xDA;
+    const ACK_TAG :u8 = 0xAC;
+
+    #[test]
+    fn bind_in_use() {
+        let first = UdpSocket::bind("127.0.0.1:0").expect("Error binding");
+        let addr = first.local_addr().expect("Error getting local addr");
+
+        // the same port again says how to pick another
+        let err = bind(addr, "--local-port").expect_err("Bound a port already in use");
+
+        assert_eq!(ErrorKind::AddrInUse, err.kind());
+        assert!(err.to_string().contains("--local-port"), "{}", err);
+        assert!(err.to_string().contains(&addr.port().to_string()), "{}", err);
+
+        // once it's free, it binds like any other
+        drop(first);
+
+        bind(addr, "--local-port").expect("Error binding a free port");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dual_stack() {
+        let socket = bind_dual_stack(0, "--port").expect("Error binding");
+        let port = socket.local_addr().expect("Error getting local addr").port();
+        let mut buf = vec![0; 8];
+
+        // an IPv4 sender is seen at its address mapped into IPv6, and hears back at it
+        let v4 = UdpSocket::bind("127.0.0.1:0").expect("Error binding IPv4");
+
+        v4.send_to(&[DATA_TAG], (Ipv4Addr::LOCALHOST, port)).expect("Error sending over IPv4");
+
+        let (amt, from) = socket.recv_from_timeout(&mut buf, Duration::from_secs(1)).expect("Error receiving from IPv4");
+
+        assert_eq!(&[DATA_TAG], &buf[..amt]);
+        assert_eq!(IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped()), from.ip());
+
+        socket.send_to(&[ACK_TAG], from).expect("Error answering IPv4");
+        v4.set_read_timeout(Some(Duration::from_secs(1))).expect("Error setting read timeout");
+        assert_eq!(1, v4.recv_from(&mut buf).expect("Error receiving over IPv4").0);
+        assert_eq!(ACK_TAG, buf[0]);
+
+        // an IPv6 one as itself, on the same socket
+        let v6 = UdpSocket::bind("[::1]:0").expect("Error binding IPv6");
+
+        v6.send_to(&[DATA_TAG], (Ipv6Addr::LOCALHOST, port)).expect("Error sending over IPv6");
+
+        let (amt, from) = socket.recv_from_timeout(&mut buf, Duration::from_secs(1)).expect("Error receiving from IPv6");
+
+        assert_eq!(&[DATA_TAG], &buf[..amt]);
+        assert_eq!(v6.local_addr().expect("Error getting local addr"), from);
+
+        // and taken like any other port
+        let err = bind_dual_stack(port, "--port").expect_err("Bound a port already in use");
+
+        assert_eq!(ErrorKind::AddrInUse, err.kind());
+        assert!(err.to_string().contains("--port"), "{}", err);
+    }
+
+    #[test]
+    fn clones_share_queues() {
+        let sender = PacketDroppingSocket::new();
+        let sender_clone = sender.try_clone().expect("Error cloning socket");
+        let recver = sender.duplex();
 
-            return Ok( PacketDroppingSocket { inner: Arc::new(Mutex::new(new_inner)) } );
+        sender.set_read_timeout(Some(Duration::from_millis(100))).expect("Error setting read timeout");
+        recver.set_read_timeout(Some(Duration::from_millis(100))).expect("Error setting read timeout");
+
+        let mut buf = vec![0; 8];
+
+        // sent on the original, read on the other end
+        sender.send_to(&[DATA_TAG], "127.0.0.1:1234").expect("Error sending");
+        assert_eq!(1, recver.recv_from(&mut buf).expect("Error receiving").0);
+        assert_eq!(DATA_TAG, buf[0]);
+
+        // sent on the other end, read on the clone
+        recver.send_to(&[ACK_TAG], "127.0.0.1:8080").expect("Error sending");
+        assert_eq!(1, sender_clone.recv_from(&mut buf).expect("Error receiving").0);
+        assert_eq!(ACK_TAG, buf[0]);
+
+        // nothing should be left over for the original
+        assert_eq!(0, sender.recv_from(&mut buf).expect("Error receiving").0);
+    }
+
+    #[test]
+    fn bidirectional_stress() {
+        const COUNT :u16 = 1000;
+
+        let sender = PacketDroppingSocket::new();
+        let recver = sender.duplex();
+
+        let ack_reader = sender.try_clone().expect("Error cloning socket");
+        let data_reader = recver.try_clone().expect("Error cloning socket");
+
+        ack_reader.set_read_timeout(Some(Duration::from_secs(1))).expect("Error setting read timeout");
+        data_reader.set_read_timeout(Some(Duration::from_secs(1))).expect("Error setting read timeout");
+
+        // sender's writer: pushes data packets on the original socket
+        let writer_handle = thread::spawn(move || {
+            for i in 0..COUNT {
+                let packet = [DATA_TAG, (i >> 8) as u8, i as u8];
+                sender.send_to(&packet, "127.0.0.1:1234").expect("Error sending data");
+            }
+        });
+
+        // receiver: reads data on the clone, ACKs on the original
+        let recv_handle = thread::spawn(move || {
+            let mut buf = vec![0; 8];
+            let mut expected = 0;
+
+            while expected < COUNT {
+                let (amt, _) = data_reader.recv_from(&mut buf).expect("Error receiving data");
+
+                assert_eq!(3, amt, "Timed out waiting for data {}", expected);
+                assert_eq!(DATA_TAG, buf[0], "ACK misrouted to the data reader");
+                assert_eq!(expected, ((buf[1] as u16) << 8) | buf[2] as u16);
+
+                buf[0] = ACK_TAG;
+                recver.send_to(&buf[..amt], "127.0.0.1:8080").expect("Error sending ACK");
+                expected += 1;
+            }
+        });
+
+        // sender's reader: reads ACKs on the clone
+        let mut buf = vec![0; 8];
+        let mut expected = 0;
+
+        while expected < COUNT {
+            let (amt, _) = ack_reader.recv_from(&mut buf).expect("Error receiving ACK");
+
+            assert_eq!(3, amt, "Timed out waiting for ACK {}", expected);
+            assert_eq!(ACK_TAG, buf[0], "Data misrouted to the ACK reader");
+            assert_eq!(expected, ((buf[1] as u16) << 8) | buf[2] as u16);
+
+            expected += 1;
+        }
+
+        writer_handle.join().expect("Writer thread panicked");
+        recv_handle.join().expect("Receiver thread panicked");
+    }
+
+    #[test]
+    fn connected() {
+        let socket = PacketDroppingSocket::new();
+        let peer = socket.duplex();
+        let mut buf = vec![0; 8];
+
+        assert_eq!(ErrorKind::NotConnected, socket.send(&[DATA_TAG]).expect_err("Expected send to fail").kind());
+
+        socket.connect("127.0.0.1:1234".parse().unwrap()).expect("Error connecting");
+        peer.connect("127.0.0.1:8080".parse().unwrap()).expect("Error connecting");
+
+        socket.send(&[DATA_TAG]).expect("Error sending");
+        assert_eq!(1, peer.recv(&mut buf).expect("Error receiving"));
+        assert_eq!(DATA_TAG, buf[0]);
+
+        // the real thing: only the connected peer gets through
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let peer = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let stranger = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let addr = socket.local_addr().expect("Error getting local addr");
+
+        Socket::connect(&socket, peer.local_addr().expect("Error getting local addr")).expect("Error connecting");
+
+        stranger.send_to(&[DATA_TAG], addr).expect("Error sending");
+        peer.send_to(&[ACK_TAG], addr).expect("Error sending");
+
+        assert_eq!(1, Socket::recv(&socket, &mut buf).expect("Error receiving"));
+        assert_eq!(ACK_TAG, buf[0]);
+
+        Socket::send(&socket, &[DATA_TAG]).expect("Error sending");
+        assert_eq!(1, peer.recv(&mut buf).expect("Error receiving"));
+        assert_eq!(DATA_TAG, buf[0]);
+    }
+
+    #[test]
+    fn udp_recv_timeout() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let mut buf = vec![0; 8];
+
+        let start = Instant::now();
+        let ret = Socket::recv_from_timeout(&socket, &mut buf, Duration::from_millis(200));
+
+        assert_eq!(ErrorKind::WouldBlock, ret.expect_err("Expected a timeout").kind());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // the persistent timeout should be left alone
+        assert_eq!(None, socket.read_timeout().expect("Error getting read timeout"));
+
+        // and a packet that's already waiting is returned
+        let addr = socket.local_addr().expect("Error getting local addr");
+        Socket::send_to(&socket, &[DATA_TAG], addr).expect("Error sending");
+
+        let (amt, _) = Socket::recv_from_timeout(&socket, &mut buf, Duration::from_millis(200)).expect("Error receiving");
+        assert_eq!(1, amt);
+        assert_eq!(DATA_TAG, buf[0]);
+    }
+
+    #[test]
+    fn udp_send_batch() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let recver = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let addr = recver.local_addr().expect("Error getting local addr");
+
+        let packets = (0..10u8).map(|i| vec![DATA_TAG, i]).collect::<Vec<_>>();
+        let batch = packets.iter().map(|p| (&p[..], addr)).collect::<Vec<_>>();
+
+        assert_eq!(10, Socket::send_batch(&socket, &batch).expect("Error sending batch"));
+        assert_eq!(0, Socket::send_batch(&socket, &[]).expect("Error sending empty batch"));
+
+        let mut buf = vec![0; 8];
+
+        for i in 0..10u8 {
+            let (amt, _) = Socket::recv_from_timeout(&recver, &mut buf, Duration::from_millis(200)).expect("Error receiving");
+
+            assert_eq!(&[DATA_TAG, i], &buf[..amt]);
+        }
+    }
+
+    #[test]
+    fn mock_send_batch() {
+        let socket = PacketDroppingSocket::new();
+        let peer = socket.duplex();
+        let addr :SocketAddr = "127.0.0.1:8080".parse().unwrap();
+
+        let packets = (0..10u8).map(|i| vec![DATA_TAG, i]).collect::<Vec<_>>();
+        let batch = packets.iter().map(|p| (&p[..], addr)).collect::<Vec<_>>();
+
+        assert_eq!(10, socket.send_batch(&batch).expect("Error sending batch"));
+        assert_eq!(10, socket.sent_count());
+
+        let mut buf = vec![0; 8];
+
+        // all of them, in order
+        for i in 0..10u8 {
+            let (amt, _) = peer.recv_from_timeout(&mut buf, Duration::from_millis(200)).expect("Error receiving");
+
+            assert_eq!(&[DATA_TAG, i], &buf[..amt]);
+        }
+
+        assert!(peer.recv_from_timeout(&mut buf, Duration::from_millis(10)).is_err());
+    }
+
+    #[test]
+    fn udp_recv_batch() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let recver = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+        let addr = recver.local_addr().expect("Error getting local addr");
+        let from = socket.local_addr().expect("Error getting local addr");
+
+        for i in 0..8u8 {
+            socket.send_to(&[DATA_TAG, i], addr).expect("Error sending");
+        }
+
+        // room for more than are waiting; loopback delivers them as they're sent, so they all are
+        let mut packets = vec![(vec![0; 8], 0, addr); 16];
+
+        assert_eq!(8, Socket::recv_batch(&recver, &mut packets, Duration::from_millis(200)).expect("Error receiving batch"));
+
+        for (i, &(ref buf, len, src)) in packets[..8].iter().enumerate() {
+            assert_eq!(&[DATA_TAG, i as u8], &buf[..len]);
+            assert_eq!(from, src);
+        }
+
+        assert_eq!(ErrorKind::WouldBlock, Socket::recv_batch(&recver, &mut packets, Duration::from_millis(10)).expect_err("Received from nobody").kind());
+    }
+
+    #[test]
+    fn mock_recv_batch() {
+        let socket = PacketDroppingSocket::new();
+        let peer = socket.duplex();
+        let addr :SocketAddr = "127.0.0.1:8080".parse().unwrap();
+
+        for i in 0..8u8 {
+            socket.send_to(&[DATA_TAG, i], addr).expect("Error sending");
+        }
+
+        let mut packets = vec![(vec![0; 8], 0, addr); 16];
+
+        // all of them, in order, w/one call
+        assert_eq!(8, peer.recv_batch(&mut packets, Duration::from_millis(200)).expect("Error receiving batch"));
+        assert_eq!(0, socket.queued());
+
+        for (i, &(ref buf, len, _)) in packets[..8].iter().enumerate() {
+            assert_eq!(&[DATA_TAG, i as u8], &buf[..len]);
+        }
+
+        assert!(peer.recv_batch(&mut packets, Duration::from_millis(10)).is_err());
+    }
+
+    #[test]
+    fn mock_recv_timeout() {
+        let socket = PacketDroppingSocket::new();
+        let peer = socket.duplex();
+        let mut buf = vec![0; 8];
+
+        let start = Instant::now();
+        let ret = socket.recv_from_timeout(&mut buf, Duration::from_millis(200));
+
+        assert_eq!(ErrorKind::WouldBlock, ret.expect_err("Expected a timeout").kind());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        peer.send_to(&[ACK_TAG], "127.0.0.1:8080").expect("Error sending");
+
+        let (amt, _) = socket.recv_from_timeout(&mut buf, Duration::from_millis(200)).expect("Error receiving");
+        assert_eq!(1, amt);
+        assert_eq!(ACK_TAG, buf[0]);
+    }
+
+    #[test]
+    fn mock_rebind() {
+        let socket = PacketDroppingSocket::new();
+        let peer = socket.duplex();
+        let moved :SocketAddr = "127.0.0.2:9090".parse().unwrap();
+        let mut buf = vec![0; 8];
+
+        // what's sent before the move still comes from the old address
+        peer.send_to(&[1], "127.0.0.1:8080").expect("Error sending");
+        peer.try_clone().expect("Error cloning").rebind(moved);
+        peer.send_to(&[2], "127.0.0.1:8080").expect("Error sending");
+
+        let (_, from) = socket.recv_from(&mut buf).expect("Error receiving");
+        assert_eq!("127.0.0.1:8080".parse::<SocketAddr>().unwrap(), from);
+
+        let (_, from) = socket.recv_from_timeout(&mut buf, Duration::from_millis(200)).expect("Error receiving");
+        assert_eq!(moved, from);
+        assert_eq!(2, buf[0]);
+    }
+
+    #[test]
+    fn burst_model_drops_in_runs() {
+        let socket = PacketDroppingSocket::new().with_burst_model(0.05, 0.25, 0.0, 1.0);
+        let peer = socket.duplex();
+        let mut buf = vec![0; 8];
+
+        for i in 0..1000u16 {
+            socket.send_to(&[(i >> 8) as u8, i as u8], "127.0.0.1:1234").expect("Error sending");
+        }
+
+        // walk what made it through, counting the runs of missing packets
+        let mut expected = 0u16;
+        let mut runs = 0;
+
+        while let Ok((amt, _)) = peer.recv_from_timeout(&mut buf, Duration::from_millis(10)) {
+            assert_eq!(2, amt);
+
+            let i = ((buf[0] as u16) << 8) | buf[1] as u16;
+
+            if i != expected {
+                runs += 1;
+            }
+
+            expected = i + 1;
+        }
+
+        if expected != 1000 {
+            runs += 1;
         }
+
+        let dropped = socket.dropped_count();
+
+        assert_eq!(1000, socket.sent_count());
+        assert!(runs > 0);
+
+        // an average burst of 1/to_good = 4 packets; independent losses would mostly be runs of 1
+        assert!(dropped as f64 / runs as f64 > 2.0, "{} drops in {} runs", dropped, runs);
+    }
+
+    #[test]
+    fn delay() {
+        let socket = PacketDroppingSocket::new().with_delay(Duration::from_millis(100));
+        let peer = socket.duplex();
+        let mut buf = vec![0; 8];
+
+        let start = Instant::now();
+
+        socket.send_to(&[DATA_TAG], "127.0.0.1:1234").expect("Error sending");
+
+        // not there yet
+        assert!(peer.recv_from_timeout(&mut buf, Duration::from_millis(50)).is_err());
+
+        peer.recv_from_timeout(&mut buf, Duration::from_millis(200)).expect("Error receiving");
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(DATA_TAG, buf[0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn jitter() {
+        let socket = PacketDroppingSocket::new().with_delay(Duration::from_millis(50)).with_jitter(Duration::from_millis(50));
+        let peer = socket.duplex();
+        let mut buf = vec![0; 8];
+
+        let start = Instant::now();
+
+        for i in 0..10 {
+            socket.send_to(&[i], "127.0.0.1:1234").expect("Error sending");
+        }
+
+        // never before the delay, never more than the delay and jitter after, and still in order
+        assert!(peer.recv_from_timeout(&mut buf, Duration::from_millis(40)).is_err());
+
+        for i in 0..10 {
+            peer.recv_from_timeout(&mut buf, Duration::from_millis(200)).expect("Error receiving");
+            assert_eq!(i, buf[0]);
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_millis(150), "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn dont_fragment() {
+        let socket = PacketDroppingSocket::new().with_path_mtu(100);
+
+        // w/out the bit, it's fragmented and makes it
+        socket.send_to(&[DATA_TAG; 200], "127.0.0.1:1234").expect("Error sending");
+
+        socket.set_dont_fragment(true).expect("Error setting Don't Fragment");
+
+        let err = socket.send_to(&[DATA_TAG; 101], "127.0.0.1:1234").expect_err("Expected send to fail");
+
+        assert_eq!(Some(libc::EMSGSIZE), err.raw_os_error());
+        socket.send_to(&[DATA_TAG; 100], "127.0.0.1:1234").expect("Error sending");
+
+        // and the real thing accepts it
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+
+        Socket::set_dont_fragment(&socket, true).expect("Error setting Don't Fragment");
+        Socket::set_dont_fragment(&socket, false).expect("Error clearing Don't Fragment");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn udp_buffer_sizes() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Couldn't bind socket");
+
+        // well under what any kernel clamps to; Linux grants double, for its bookkeeping
+        Socket::set_recv_buffer_size(&socket, 16384).expect("Error setting receive buffer");
+        let small = Socket::recv_buffer_size(&socket).expect("Error getting receive buffer");
+
+        Socket::set_recv_buffer_size(&socket, 65536).expect("Error setting receive buffer");
+        let large = Socket::recv_buffer_size(&socket).expect("Error getting receive buffer");
+
+        assert!(small >= 16384, "Asked for 16384, got {}", small);
+        assert!(large >= 65536, "Asked for 65536, got {}", large);
+        assert!(large > small);
+
+        Socket::set_send_buffer_size(&socket, 65536).expect("Error setting send buffer");
+
+        assert!(Socket::send_buffer_size(&socket).expect("Error getting send buffer") >= 65536);
+    }
+
+    #[test]
+    fn bernoulli_model() {
+        let lossless = PacketDroppingSocket::new();
+        let lossy = PacketDroppingSocket::new().with_bernoulli_model(0.5);
+
+        for _ in 0..1000 {
+            lossless.send_to(&[DATA_TAG], "127.0.0.1:1234").expect("Error sending");
+            lossy.send_to(&[DATA_TAG], "127.0.0.1:1234").expect("Error sending");
+        }
+
+        assert_eq!(0, lossless.dropped_count());
+        assert_eq!(1000, lossless.queued());
+
+        assert!(lossy.dropped_count() > 400 && lossy.dropped_count() < 600);
+        assert_eq!(1000 - lossy.dropped_count() as usize, lossy.queued());
+    }
+
+    #[test]
+    fn reordering_and_duplication() {
+        let sender = PacketDroppingSocket::new().with_reordering(0.5).with_duplication(0.2);
+        let recver = sender.duplex();
+
+        recver.set_read_timeout(Some(Duration::from_millis(100))).expect("Error setting read timeout");
+
+        for i in 0..100 {
+            sender.send_to(&[i as u8], "127.0.0.1:1234").expect("Error sending");
+        }
+
+        let mut buf = vec![0; 8];
+        let mut received = Vec::new();
+
+        while recver.recv_from(&mut buf).expect("Error receiving").0 == 1 {
+            received.push(buf[0]);
+        }
+
+        // nothing's dropped, but some arrive out of order, and some more than once
+        assert_eq!(100, sender.sent_count());
+        assert_eq!(0, sender.dropped_count());
+        assert!(received.windows(2).any(|w| w[0] > w[1]));
+        assert!(received.len() > 100);
+
+        received.sort();
+        received.dedup();
+
+        assert_eq!((0..100).collect::<Vec<u8>>(), received);
+    }
+
+    #[test]
+    fn truncates_long_packets() {
+        let sender = PacketDroppingSocket::new();
+        let recver = sender.duplex();
+
+        recver.set_read_timeout(Some(Duration::from_millis(100))).expect("Error setting read timeout");
+
+        sender.send_to(&[DATA_TAG; 16], "127.0.0.1:1234").expect("Error sending");
+
+        // like UDP, the rest of the packet is lost rather than overrunning buf
+        let mut buf = vec![0; 8];
+
+        assert_eq!(8, recver.recv_from(&mut buf).expect("Error receiving").0);
+        assert_eq!(vec![DATA_TAG; 8], buf);
+    }
+}
@@ -1,7 +1,12 @@
-use std::io::{Read, Write, Error as IOError};
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, TcpListener};
+//! A plain TCP stream, for when UDP is blocked or BBR isn't wanted
+//! Only a single file can be sent; TCP takes care of everything else
+
+use std::io::{Read, Write, Error as IOError, ErrorKind};
+use std::net::{Shutdown, TcpStream, TcpListener};
+use std::time::Duration;
 
 use config::Configuration;
+use error::QcpError;
 use transport::Transport;
 
 
@@ -10,34 +15,136 @@ impl Transport for TcpStream {
         return Read::read(self, buf);
     }
 
+    /// Reads w/the socket's read timeout set, then clears it so read blocks again
+    fn recv_timeout(&mut self, buf: &mut[u8], timeout: Duration) -> Result<Option<usize>, IOError> {
+        // a zero timeout is an error to the socket
+        self.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+
+        let res = Read::read(self, buf);
+
+        self.set_read_timeout(None)?;
+
+        match res {
+            Ok(amt) => return Ok(Some(amt)),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e)
+        }
+    }
+
     fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
         return Write::write_all(self, buf);
     }
-}
 
-pub struct Sender { }
+    fn flush(&mut self) -> Result<(), IOError> {
+        return Write::flush(self);
+    }
 
-impl Sender {
-    pub fn new(config: Configuration) -> impl Transport {
-        let stream = TcpStream::connect("127.0.0.1:1234").unwrap();
-        info!("Opened connection to: {}", stream.peer_addr().unwrap());
+    /// Closes our side of the stream, so the receiver's read returns 0
+    fn finish(&mut self) -> Result<(), IOError> {
+        Write::flush(self)?;
 
-        return stream
+        return self.shutdown(Shutdown::Write);
     }
+}
+
+/// Connects to the receiver at the first of config.addrs() that answers
+pub fn connect(config: &Configuration) -> Result<TcpStream, QcpError> {
+    let stream = TcpStream::connect(config.addrs())?;
+
+    info!("Opened connection to: {}", stream.peer_addr()?);
+
+    return Ok(stream);
+}
+
+/// Listens on config.addr(), and waits for a sender to connect
+pub fn listen(config: &Configuration) -> Result<TcpStream, QcpError> {
+    let listener = TcpListener::bind(config.addr())?;
+
+    return accept(&listener);
+}
+
+/// Waits for a sender to connect to listener
+pub fn accept(listener: &TcpListener) -> Result<TcpStream, QcpError> {
+    let (stream, addr) = listener.accept()?;
+
+    info!("Got connection from: {}", addr);
 
+    return Ok(stream);
 }
 
-pub struct Receiver { }
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::process;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
-impl Receiver {
-    pub fn new(config: Configuration) -> impl Transport {
-        let bind_stream = TcpListener::bind("127.0.0.1:1234").unwrap();
+    use config::Configuration;
+    use tcp_transport::{connect, accept};
+    use transfer::{send_files, recv_files};
+    use transport::Transport;
 
-        let (stream, addr) = bind_stream.accept().unwrap();
+    #[test]
+    fn loopback_file() {
+        let dir = env::temp_dir().join(format!("qcp_tcp_loopback_{}", process::id()));
+        let src = dir.join("src.bin");
+        let dst = dir.join("dst.bin");
 
-        info!("Got connection from: {}", addr);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
 
-        return stream;
+        let data = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+
+        File::create(&src).and_then(|mut f| f.write_all(&data)).expect("Error writing file");
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Error binding");
+        let mut config = Configuration::default();
+
+        config.set_addr(listener.local_addr().expect("Error getting local addr"));
+
+        let files = vec![src];
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let mut sender = connect(&config).expect("Couldn't connect");
+
+            send_files(&mut sender, &files, false, None).expect("Error sending file");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let mut recver = accept(&listener).expect("Couldn't accept");
+        let written = recv_files(&mut recver, &dst, false).expect("Error receiving file");
+
+        send_handle.join().expect("Send thread panicked");
+
+        assert_eq!(vec![dst.clone()], written);
+        assert!(data == fs::read(&dst).expect("Error reading file"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn recv_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Error binding");
+        let mut sender = TcpStream::connect(listener.local_addr().expect("Error getting local addr")).expect("Couldn't connect");
+        let mut recver = accept(&listener).expect("Couldn't accept");
+        let mut buf = vec![0; 16];
+
+        // nothing sent yet
+        let start = Instant::now();
+
+        assert_eq!(None, recver.recv_timeout(&mut buf, Duration::from_millis(100)).expect("Error reading"));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        Transport::write_all(&mut sender, b"hello").expect("Error writing");
+
+        assert_eq!(Some(5), recver.recv_timeout(&mut buf, Duration::from_secs(5)).expect("Error reading"));
+        assert_eq!(b"hello", &buf[..5]);
+
+        // and it's back to blocking, until the end of the stream
+        sender.finish().expect("Error calling finish");
+
+        assert_eq!(0, Transport::read(&mut recver, &mut buf).expect("Error reading"));
+    }
+}
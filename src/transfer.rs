@@ -0,0 +1,1415 @@
+//! Moving files, or any other stream, over any Transport
+//! A single file is sent as a plain stream; more than one are each announced w/a FileHeader, and land in a directory
+//! When the transport can announce files, a single file gets a FileHeader too, marked lone, so a receiver w/--output-dir knows its name
+//! A file can also be sent as a delta: the receiver sends back the hashes of its copy's blocks, and only the blocks that differ are sent
+//! Or only verified: the same hashes come back, and the blocks that differ are reported instead of sent
+//! A stream of unknown length, like stdin from a pipe, is announced w/UNKNOWN_LEN, and the Finish tells the receiver how much there was
+
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Error as IOError, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+use bbr_transport::{FileHeader, MAX_PAYLOAD_SIZE, UNKNOWN_LEN};
+use transport::Transport;
+use sha256::{sha256, DIGEST_SIZE};
+use sandbox;
+
+const WOULD_BLOCK_RETRY :Duration = Duration::from_millis(1);    // how long to wait for a nonblocking transport to have room
+const DISK_QUEUE :usize = 64;   // buffers read ahead of the transport, or received ahead of the disk
+
+/// Writes all of buf, trying again whenever a nonblocking transport says it would block
+/// A copy has nothing else to do in the meantime
+fn write_retrying<T: Transport + ?Sized>(transport: &mut T, buf: &[u8]) -> Result<(), IOError> {
+    loop {
+        match transport.write_all(buf) {
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(WOULD_BLOCK_RETRY),
+            res => return res
+        }
+    }
+}
+
+/// A buffer that's been passed back to be filled again, or a new one
+fn reuse(free: &mpsc::Receiver<Vec<u8>>) -> Vec<u8> {
+    let mut buf = free.try_recv().unwrap_or_else(|_| Vec::with_capacity(MAX_PAYLOAD_SIZE));
+
+    buf.resize(MAX_PAYLOAD_SIZE, 0);
+
+    return buf;
+}
+
+/// What a disk thread returned, or an error if it panicked
+fn join_disk(handle: thread::ScopedJoinHandle<Result<(), IOError>>) -> Result<(), IOError> {
+    return handle.join().unwrap_or_else(|_| Err(IOError::new(ErrorKind::Other, "The disk thread panicked")));
+}
+
+/// Copies everything from reader to the transport, returning the number of bytes copied
+/// The reader can be a file, stdin, or anything else; the caller still needs to finish the transport
+/// It's read on a thread of its own, up to DISK_QUEUE buffers ahead, so the transport isn't left waiting on the disk
+pub fn run_sender<T: Transport + ?Sized, R: Read + Send + ?Sized>(transport: &mut T, reader: &mut R) -> Result<u64, IOError> {
+    let (full_tx, full_rx) = mpsc::sync_channel::<Vec<u8>>(DISK_QUEUE);
+    let (free_tx, free_rx) = mpsc::channel();
+
+    return thread::scope(|scope| -> Result<u64, IOError> {
+        let reading = scope.spawn(move || -> Result<(), IOError> {
+            loop {
+                let mut buf = reuse(&free_rx);
+                let amt = reader.read(&mut buf)?;
+
+                if amt == 0 {
+                    return Ok( () );
+                }
+
+                buf.truncate(amt);
+
+                // the transport failed, and that's the error that matters
+                if full_tx.send(buf).is_err() {
+                    return Ok( () );
+                }
+            }
+        });
+
+        let mut total = 0;
+        let mut sent = Ok( () );
+
+        // until the reading thread is done, and drops its end
+        for buf in full_rx.iter() {
+            if let Err(e) = write_retrying(transport, &buf) {
+                sent = Err(e);
+                break;
+            }
+
+            total += buf.len() as u64;
+
+            let _ = free_tx.send(buf);
+        }
+
+        // so the reading thread isn't left waiting for room
+        drop(full_rx);
+
+        let read = join_disk(reading);
+
+        sent?;
+        read?;
+
+        return Ok(total);
+    });
+}
+
+/// Reads exactly buf.len() bytes from the transport, however many reads that takes
+fn read_full<T: Transport + ?Sized>(transport: &mut T, buf: &mut [u8]) -> Result<(), IOError> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match transport.read(&mut buf[filled..])? {
+            0 => return Err(IOError::new(ErrorKind::UnexpectedEof, "The other side stopped before sending all its block hashes")),
+            amt => filled += amt
+        }
+    }
+
+    return Ok( () );
+}
+
+/// Reads as much of buf as the reader has left, so it's only short at the end
+/// Returns the number of bytes read
+fn read_block<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize, IOError> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            amt => filled += amt
+        }
+    }
+
+    return Ok(filled);
+}
+
+/// Copies from the transport to writer until read returns 0, returning the number of bytes copied
+/// The writer can be a file, stdout, or anything else
+/// It's written on a thread of its own, up to DISK_QUEUE buffers behind, so the transport's drained even while the disk is busy
+pub fn run_receiver<T: Transport + ?Sized, W: Write + Send + ?Sized>(transport: &mut T, writer: &mut W) -> Result<u64, IOError> {
+    let (full_tx, full_rx) = mpsc::sync_channel::<Vec<u8>>(DISK_QUEUE);
+    let (free_tx, free_rx) = mpsc::channel();
+
+    return thread::scope(|scope| -> Result<u64, IOError> {
+        let writing = scope.spawn(move || -> Result<(), IOError> {
+            // until the transport's done, and its end is dropped
+            for buf in full_rx.iter() {
+                writer.write_all(&buf)?;
+
+                let _ = free_tx.send(buf);
+            }
+
+            return Ok( () );
+        });
+
+        let mut total = 0;
+
+        let received = loop {
+            let mut buf = reuse(&free_rx);
+
+            let amt = match transport.read(&mut buf) {
+                Ok(amt) => amt,
+                Err(e) => break Err(e)
+            };
+
+            if amt == 0 {
+                break Ok( () );
+            }
+
+            buf.truncate(amt);
+
+            // the writing thread failed, and that's the error that matters
+            if full_tx.send(buf).is_err() {
+                break Ok( () );
+            }
+
+            total += amt as u64;
+        };
+
+        // so the writing thread finishes what's queued, and stops
+        drop(full_tx);
+
+        let written = join_disk(writing);
+
+        received?;
+        written?;
+
+        return Ok(total);
+    });
+}
+
+/// The file's Unix permissions, if it has them
+#[cfg(unix)]
+fn file_mode(metadata: &Metadata) -> Option<u32> {
+    return Some(metadata.permissions().mode());
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &Metadata) -> Option<u32> {
+    return None;
+}
+
+/// Sets the file's Unix permissions, only the permission bits so the sender can't make anything setuid
+#[cfg(unix)]
+fn set_mode(file: &File, mode: u32) -> Result<(), IOError> {
+    return file.set_permissions(fs::Permissions::from_mode(mode & 0o777));
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: u32) -> Result<(), IOError> {
+    return Ok( () );
+}
+
+/// Sets aside len bytes on disk for the file up front, so a large one isn't fragmented, and one that won't fit fails before anything's received
+#[cfg(target_os = "linux")]
+fn preallocate(file: &File, len: u64) -> Result<(), IOError> {
+    use std::os::unix::io::AsRawFd;
+
+    if len == 0 {
+        return Ok( () );
+    }
+
+    if len > libc::off_t::max_value() as u64 {
+        return Err(IOError::new(ErrorKind::Other, format!("File of {} bytes is too large for this system", len)));
+    }
+
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len as libc::off_t) } {
+        0 => return Ok( () ),
+        libc::ENOSPC | libc::EFBIG => return Err(IOError::new(ErrorKind::Other, format!("Not enough room on disk for the file's {} bytes", len))),
+        // the filesystem can't set space aside, so at least it's the right length
+        libc::EOPNOTSUPP | libc::EINVAL => return file.set_len(len),
+        code => return Err(IOError::from_raw_os_error(code))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate(file: &File, len: u64) -> Result<(), IOError> {
+    return file.set_len(len);
+}
+
+/// Gives the file whichever of the sender's attributes came w/its header
+/// The contents have to be written first, or writing them would change the mtime
+fn apply_attributes(file: &File, header: &FileHeader) -> Result<(), IOError> {
+    if let Some(mode) = header.mode {
+        set_mode(file, mode)?;
+    }
+
+    if let Some(mtime) = header.mtime {
+        file.set_modified(mtime)?;
+    }
+
+    return Ok( () );
+}
+
+/// An error opening path, saying which file it was and what for, as the error alone doesn't
+/// role is what it is to the transfer, like "source file"
+pub fn open_error(e: IOError, role: &str, path: &Path) -> IOError {
+    IOError::new(e.kind(), format!("Could not open {} {}: {}", role, path.display(), e))
+}
+
+/// Sends the files, w/their permissions and modification times when preserve is set
+/// When there's a block_size, each is sent as a delta against the receiver's copy, see send_delta
+/// The caller still needs to finish the sender
+pub fn send_files<T: Transport + ?Sized>(sender: &mut T, files: &[PathBuf], preserve: bool, block_size: Option<u32>) -> Result<(), IOError> {
+    // nothing to announce it w/, so the receiver puts it wherever it was told to
+    if files.len() == 1 && !preserve && block_size.is_none() && !sender.sends_headers() {
+        let mut file = File::open(&files[0]).map_err(|e| open_error(e, "source file", &files[0]))?;
+
+        run_sender(sender, &mut file)?;
+
+        return Ok( () );
+    }
+
+    return send_headed(sender, files, preserve, files.len() == 1, block_size);
+}
+
+/// Sends everything reader has as the only file, w/out a name, its length unknown until it's all sent, like stdin from a pipe
+/// Returns the number of bytes sent; the caller still needs to finish the sender
+pub fn send_stream<T: Transport + ?Sized, R: Read + Send + ?Sized>(sender: &mut T, reader: &mut R) -> Result<u64, IOError> {
+    sender.start_file(&FileHeader { name: String::new(), len: UNKNOWN_LEN, mode: None, mtime: None, lone: true, block_size: None, verify_only: false })?;
+
+    let sent = run_sender(sender, reader)?;
+
+    sender.end_file()?;
+
+    return Ok(sent);
+}
+
+/// Sends each file after its FileHeader, marked as the only one when lone is set
+/// A lone file's name is only a suggestion, the receiver usually knows where to put it; it's left out if there isn't one
+fn send_headed<T: Transport + ?Sized>(sender: &mut T, files: &[PathBuf], preserve: bool, lone: bool, block_size: Option<u32>) -> Result<(), IOError> {
+    for path in files {
+        let mut file = File::open(path).map_err(|e| open_error(e, "source file", path))?;
+        let metadata = file.metadata()?;
+        let len = metadata.len();
+
+        let name = file_name(path, lone)?;
+        let (mode, mtime) = if preserve { (file_mode(&metadata), Some(metadata.modified()?)) } else { (None, None) };
+
+        info!("Sending {} ({} bytes)", path.display(), len);
+
+        sender.start_file(&FileHeader { name, len, mode, mtime, lone, block_size, verify_only: false })?;
+
+        let sent = match block_size {
+            Some(block_size) => send_delta(sender, &mut file, len, block_size)?,
+            None => run_sender(sender, &mut file)?
+        };
+
+        if sent != len {
+            warn!("Sent {} bytes of {}, but it was {} bytes", sent, path.display(), len);
+        }
+
+        sender.end_file()?;
+    }
+
+    return Ok( () );
+}
+
+/// Only the name, where it came from is none of the receiver's business
+/// A lone file's name is left out if there isn't one
+fn file_name(path: &Path, lone: bool) -> Result<String, IOError> {
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => return Ok(name.to_string()),
+        None if lone => return Ok(String::new()),
+        None => return Err(IOError::new(ErrorKind::InvalidInput, format!("No usable file name: {}", path.display())))
+    }
+}
+
+/// Compares each of the files w/the receiver's copy, a block_size block at a time, w/out sending any of it
+/// Returns the byte ranges of each file that differ, merged where they meet; what the receiver doesn't have, or has past the end, differs too
+/// The caller still needs to finish the sender
+pub fn verify_files<T: Transport + ?Sized>(sender: &mut T, files: &[PathBuf], block_size: u32) -> Result<Vec<(PathBuf, Vec<Range<u64>>)>, IOError> {
+    let lone = files.len() == 1;
+    let mut buf = vec![0; block_size as usize];
+    let mut verified = Vec::new();
+
+    for path in files {
+        let mut file = File::open(path).map_err(|e| open_error(e, "source file", path))?;
+        let len = file.metadata()?.len();
+        let name = file_name(path, lone)?;
+
+        info!("Verifying {} ({} bytes)", path.display(), len);
+
+        sender.start_file(&FileHeader { name, len, mode: None, mtime: None, lone, block_size: Some(block_size), verify_only: true })?;
+
+        let hashes = read_block_hashes(sender, len, block_size)?;
+        let mut their_len = [0u8; 8];
+
+        read_full(sender, &mut their_len)?;
+
+        let their_len = u64::from_be_bytes(their_len);
+        let mut differ = Vec::new();
+        let mut offset = 0;
+
+        for block in 0.. {
+            let amt = read_block(&mut file, &mut buf)?;
+
+            if amt == 0 {
+                break;
+            }
+
+            if hashes.get(block).map_or(true, |hash| *hash != sha256(&buf[..amt])) {
+                add_range(&mut differ, offset..offset + amt as u64);
+            }
+
+            offset += amt as u64;
+        }
+
+        if their_len > offset {
+            add_range(&mut differ, offset..their_len);
+        }
+
+        sender.end_file()?;
+        verified.push((path.clone(), differ));
+    }
+
+    return Ok(verified);
+}
+
+/// Adds range to the end of ranges, as part of the last one if it starts where that one ends
+fn add_range(ranges: &mut Vec<Range<u64>>, range: Range<u64>) {
+    if let Some(last) = ranges.last_mut() {
+        if last.end == range.start {
+            last.end = range.end;
+            return;
+        }
+    }
+
+    ranges.push(range);
+}
+
+/// Reads the block hashes recv_delta sends back: how many there are, then the SHA-256 of each block in turn
+/// There's never more than one for each of the len bytes' blocks
+fn read_block_hashes<T: Transport + ?Sized>(sender: &mut T, len: u64, block_size: u32) -> Result<Vec<[u8; DIGEST_SIZE]>, IOError> {
+    let mut count = [0u8; 8];
+
+    read_full(sender, &mut count)?;
+
+    let count = u64::from_be_bytes(count);
+    let blocks = (len + block_size as u64 - 1) / block_size as u64;
+
+    if count > blocks {
+        return Err(IOError::new(ErrorKind::InvalidData, format!("Receiver sent {} block hashes, but there are only {} blocks", count, blocks)));
+    }
+
+    let mut hashes = vec![[0u8; DIGEST_SIZE]; count as usize];
+
+    for hash in hashes.iter_mut() {
+        read_full(sender, hash)?;
+    }
+
+    return Ok(hashes);
+}
+
+/// Sends the file announced w/block_size, only the blocks that differ from the receiver's copy
+/// Each run of blocks w/the same hash as the receiver's is a Skip instead, so it keeps the bytes it has
+/// Returns the number of bytes sent or skipped
+fn send_delta<T: Transport + ?Sized>(sender: &mut T, file: &mut File, len: u64, block_size: u32) -> Result<u64, IOError> {
+    let hashes = read_block_hashes(sender, len, block_size)?;
+    let mut buf = vec![0; block_size as usize];
+    let mut same = 0;   // bytes the receiver already has, not skipped yet
+    let mut total = 0;
+
+    for block in 0.. {
+        let amt = read_block(file, &mut buf)?;
+
+        if amt == 0 {
+            break;
+        }
+
+        if hashes.get(block).map_or(false, |hash| *hash == sha256(&buf[..amt])) {
+            same += amt as u64;
+        } else {
+            if same > 0 {
+                sender.skip(same)?;
+                same = 0;
+            }
+
+            write_retrying(sender, &buf[..amt])?;
+        }
+
+        total += amt as u64;
+    }
+
+    if same > 0 {
+        sender.skip(same)?;
+    }
+
+    return Ok(total);
+}
+
+/// What read_block_hashes reads: how many blocks of reader there are, then the SHA-256 of each, only as far as the sender's len
+/// The sender's last block can be short, so only that much of ours is hashed; if ours is shorter still, it can't match
+fn hash_blocks<R: Read + ?Sized>(reader: &mut R, len: u64, block_size: u32) -> Result<Vec<u8>, IOError> {
+    let mut buf = vec![0; block_size as usize];
+    let mut hashes = Vec::new();
+    let mut offset = 0;
+
+    while offset < len {
+        let want = (len - offset).min(block_size as u64) as usize;
+
+        if read_block(reader, &mut buf[..want])? < want {
+            break;
+        }
+
+        hashes.push(sha256(&buf[..want]));
+        offset += want as u64;
+    }
+
+    debug!("Sending back the hashes of {} blocks", hashes.len());
+
+    let mut msg = (hashes.len() as u64).to_be_bytes().to_vec();
+
+    for hash in &hashes {
+        msg.extend_from_slice(hash);
+    }
+
+    return Ok(msg);
+}
+
+/// Sends back the hashes of file's blocks, then its length, for verify_files to compare w/its own copy; none of the file comes after
+/// A file the receiver doesn't have is as good as an empty one
+fn recv_verify<T: Transport + ?Sized>(recver: &mut T, file: Option<File>, header: &FileHeader) -> Result<(), IOError> {
+    let block_size = header.block_size.expect("Verify only w/out a block size");
+    let (mut msg, len) = match file {
+        Some(mut file) => (hash_blocks(&mut file, header.len, block_size)?, file.metadata()?.len()),
+        None => (hash_blocks(&mut io::empty(), header.len, block_size)?, 0)
+    };
+
+    msg.extend_from_slice(&len.to_be_bytes());
+    recver.write_all(&msg)?;
+
+    let mut buf = [0u8; 1];
+
+    if recver.read(&mut buf)? > 0 || recver.take_skip().is_some() {
+        return Err(IOError::new(ErrorKind::InvalidData, format!("Sender sent {}, which was only to be verified", header.name)));
+    }
+
+    return Ok( () );
+}
+
+/// The file opened, or None if it isn't there, as when only verifying against it
+fn existing(file: Result<File, IOError>, path: &Path) -> Result<Option<File>, IOError> {
+    match file {
+        Ok(file) => return Ok(Some(file)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(open_error(e, "destination file", path))
+    }
+}
+
+/// Receives the file the sender is sending as a delta into file, keeping whatever of what's there it skips
+/// First the hashes of file's blocks go back, only as far as the sender's len, see send_delta; it's cut to the sender's length at the end
+/// Returns the number of bytes received or kept
+fn recv_delta<T: Transport + ?Sized>(recver: &mut T, file: &mut File, len: u64, block_size: u32) -> Result<u64, IOError> {
+    let msg = hash_blocks(file, len, block_size)?;
+
+    recver.write_all(&msg)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut buf = vec![0; block_size as usize];
+    let mut total = 0;
+
+    loop {
+        let amt = recver.read(&mut buf)?;
+
+        if amt == 0 {
+            match recver.take_skip() {
+                // what's in the file already is the same, so leave it
+                Some(skip) => {
+                    total += skip;
+                    file.seek(SeekFrom::Start(total))?;
+                    continue;
+                },
+                None => break
+            }
+        }
+
+        file.write_all(&buf[..amt])?;
+        total += amt as u64;
+    }
+
+    // anything past the sender's copy isn't part of it
+    file.set_len(total)?;
+
+    return Ok(total);
+}
+
+/// Receives the file header announced into file, as a delta against what's in it if that's how it's being sent
+/// Otherwise file is preallocated to the announced length first, past what's there already when appending, unless there isn't one
+/// Returns the number of bytes received, or kept
+fn recv_file<T: Transport + ?Sized>(recver: &mut T, file: &mut File, header: &FileHeader, append: bool) -> Result<u64, IOError> {
+    if let Some(block_size) = header.block_size {
+        // a delta is the sender's whole file, there's nothing to add it to
+        if append {
+            return Err(IOError::new(ErrorKind::InvalidInput, format!("Sender is sending {} as a delta, which can't be appended", header.name)));
+        }
+
+        return recv_delta(recver, file, header.len, block_size);
+    }
+
+    // a stream's length is only known at its end, so the file grows as it arrives
+    if header.len == UNKNOWN_LEN {
+        if append {
+            file.seek(SeekFrom::End(0))?;
+        }
+
+        return run_receiver(recver, file);
+    }
+
+    let start = if append { file.seek(SeekFrom::End(0))? } else { 0 };
+
+    preallocate(file, start + header.len)?;
+
+    let received = run_receiver(recver, file)?;
+
+    // the sender sent less than it announced, so the rest of what was set aside isn't part of the file
+    if received < header.len {
+        file.set_len(start + received)?;
+    }
+
+    return Ok(received);
+}
+
+/// Receives into path: the file itself when one is sent, or the directory to put them in when more are
+/// The sender's file names are never trusted to stay in that directory on their own
+/// When appending, what's received goes after whatever's in each file already; otherwise it replaces it
+/// Returns the paths written
+pub fn recv_files<T: Transport + ?Sized>(recver: &mut T, path: &Path, append: bool) -> Result<Vec<PathBuf>, IOError> {
+    let header = match recver.next_file()? {
+        // the only file, w/its attributes
+        Some(ref header) if header.lone || header.name.is_empty() => {
+            // nothing's written when only verifying, not even an empty file
+            let written = if header.verify_only {
+                recv_verify(recver, existing(File::open(path), path)?, header)?;
+
+                Vec::new()
+            } else {
+                // a delta is against what's there, so it's kept
+                let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(header.block_size.is_none() && !append).open(path).map_err(|e| open_error(e, "destination file", path))?;
+
+                recv_file(recver, &mut file, header, append)?;
+                apply_attributes(&file, header)?;
+
+                vec![path.to_path_buf()]
+            };
+
+            // read on through the Finish, it's only the end of the file so far
+            if let Some(next) = recver.next_file()? {
+                return Err(IOError::new(ErrorKind::InvalidData, format!("Another file after the only one: {}", next.name)));
+            }
+
+            return Ok(written);
+        },
+        Some(header) => header,
+        None => {
+            let mut file = OpenOptions::new().write(true).create(true).truncate(!append).append(append).open(path).map_err(|e| open_error(e, "destination file", path))?;
+
+            run_receiver(recver, &mut file)?;
+
+            return Ok(vec![path.to_path_buf()]);
+        }
+    };
+
+    return recv_dir(recver, path, header, append);
+}
+
+/// Receives into the directory dir, each file under the name the sender gave it, even the only one, as w/--output-dir
+/// The sender's file names are never trusted to stay in that directory on their own
+/// Returns the paths written
+pub fn recv_into<T: Transport + ?Sized>(recver: &mut T, dir: &Path, append: bool) -> Result<Vec<PathBuf>, IOError> {
+    match recver.next_file()? {
+        Some(ref header) if header.name.is_empty() => return Err(IOError::new(ErrorKind::InvalidData, "Sender didn't name the file, receive it to a path instead")),
+        Some(header) => return recv_dir(recver, dir, header, append),
+        None => return Err(IOError::new(ErrorKind::InvalidData, "Sender sent a plain stream w/out a name, receive it to a path instead"))
+    }
+}
+
+/// Receives the files into the directory path, starting w/the one header announced
+/// Returns the paths written, none of those only verified
+fn recv_dir<T: Transport + ?Sized>(recver: &mut T, path: &Path, mut header: FileHeader, append: bool) -> Result<Vec<PathBuf>, IOError> {
+    if !header.verify_only {
+        fs::create_dir_all(path).map_err(|e| open_error(e, "destination directory", path))?;
+    }
+
+    let mut written = Vec::new();
+
+    loop {
+        if header.verify_only {
+            let file_path = path.join(&header.name);
+
+            recv_verify(recver, existing(sandbox::open_read(path, Path::new(&header.name)), &file_path)?, &header)?;
+
+            header = match recver.next_file()? {
+                Some(header) => header,
+                None => return Ok(written)
+            };
+
+            continue;
+        }
+
+        // opened relative to path w/o following symlinks, so one swapped in can't lead the write elsewhere
+        // a delta is against what's there, so it's kept, as it is when appending
+        let file_path = path.join(&header.name);
+        let file = if header.block_size.is_some() || append { sandbox::open(path, Path::new(&header.name)) } else { sandbox::create(path, Path::new(&header.name)) };
+        let mut file = file.map_err(|e| open_error(e, "destination file", &file_path))?;
+
+        if header.len == UNKNOWN_LEN {
+            info!("Receiving {} (length unknown)", file_path.display());
+        } else {
+            info!("Receiving {} ({} bytes)", file_path.display(), header.len);
+        }
+
+        let received = recv_file(recver, &mut file, &header, append)?;
+
+        if received != header.len && header.len != UNKNOWN_LEN {
+            warn!("Received {} bytes of {}, but it should be {} bytes", received, file_path.display(), header.len);
+        }
+
+        apply_attributes(&file, &header)?;
+        written.push(file_path);
+
+        header = match recver.next_file()? {
+            Some(header) => header,
+            None => return Ok(written)
+        };
+    }
+}
+
+/// Sends the files over several streams at once, each file whole on one of them, dealt out in turn
+/// Every file is named, even the only one on its stream, as they all go in the receiver's directory
+/// Over only one stream, as when the receiver agreed to no more, it's the same as send_files
+/// Finishes every stream, and returns the first error any of them had
+pub fn send_streams<T: Transport + Send + 'static>(senders: Vec<T>, files: &[PathBuf], preserve: bool, block_size: Option<u32>) -> Result<(), IOError> {
+    let streams = senders.len();
+
+    let handles = senders.into_iter().enumerate().map(|(stream, mut sender)| {
+        let files = files.iter().skip(stream).step_by(streams).cloned().collect::<Vec<_>>();
+
+        thread::spawn(move || {
+            if streams == 1 {
+                send_files(&mut sender, &files, preserve, block_size)?;
+            } else {
+                send_headed(&mut sender, &files, preserve, false, block_size)?;
+            }
+
+            sender.finish()
+        })
+    }).collect::<Vec<_>>();
+
+    return join_streams(handles).map(|_| ());
+}
+
+/// Receives what send_streams sent into the directory path, each stream on a thread of its own
+/// Over only one stream it's the same as recv_files
+/// Returns the paths written, by stream, or the first error any of them had
+pub fn recv_streams<T: Transport + Send + 'static>(recvers: Vec<T>, path: &Path, append: bool) -> Result<Vec<PathBuf>, IOError> {
+    let streams = recvers.len();
+
+    let handles = recvers.into_iter().map(|mut recver| {
+        let path = path.to_path_buf();
+
+        thread::spawn(move || {
+            if streams == 1 {
+                return recv_files(&mut recver, &path, append);
+            }
+
+            match recver.next_file()? {
+                Some(ref header) if header.lone || header.name.is_empty() => return Err(IOError::new(ErrorKind::InvalidData, "Unnamed file on one of several streams")),
+                Some(header) => return recv_dir(&mut recver, &path, header, append),
+                // more streams than files, there's nothing on this one
+                None if recver.read(&mut [0; 1])? == 0 => return Ok(Vec::new()),
+                None => return Err(IOError::new(ErrorKind::InvalidData, "Data w/out a file header on one of several streams"))
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    return join_streams(handles).map(|written| written.into_iter().flatten().collect());
+}
+
+/// Sends the files over sender while receiving into path over recver, both at once, as w/--pull and --push
+/// What's received replaces whatever's there
+/// Finishes the sender, and returns the paths written, or the first error either way had
+pub fn exchange<S: Transport + Send + 'static, R: Transport + ?Sized>(mut sender: S, recver: &mut R, files: &[PathBuf], preserve: bool, block_size: Option<u32>, path: &Path) -> Result<Vec<PathBuf>, IOError> {
+    let files = files.to_vec();
+
+    let handle = thread::spawn(move || {
+        send_files(&mut sender, &files, preserve, block_size)?;
+
+        sender.finish()
+    });
+
+    let written = recv_files(recver, path, false);
+    let sent = join_streams(vec![handle]);
+
+    return written.and_then(|written| sent.map(|_| written));
+}
+
+/// Waits for every stream's thread, returning what each returned, or the first error any of them had
+fn join_streams<R>(handles: Vec<thread::JoinHandle<Result<R, IOError>>>) -> Result<Vec<R>, IOError> {
+    let mut results = Vec::new();
+    let mut first_err = None;
+
+    for handle in handles {
+        match handle.join() {
+            Ok(Ok(result)) => results.push(result),
+            Ok(Err(e)) => { first_err.get_or_insert(e); },
+            Err(_) => { first_err.get_or_insert(IOError::new(ErrorKind::Other, "A stream's thread panicked")); }
+        }
+    }
+
+    match first_err {
+        Some(e) => return Err(e),
+        None => return Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Cursor, Error as IOError, ErrorKind, Read, Write};
+    use std::ops::Range;
+    use std::path::PathBuf;
+    use std::process;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use bbr_transport::{FileHeader, Sender, Receiver, MAX_PAYLOAD_SIZE};
+    use config::Configuration;
+    use socket::mocks::PacketDroppingSocket;
+    use transfer::{send_files, send_stream, verify_files, recv_files, recv_into, run_sender, run_receiver};
+    use transport::Transport;
+    use transport::mocks::InMemoryTransport;
+
+    /// A Transport that reads what it was given, and keeps what's written, so no sockets are needed
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>
+    }
+
+    impl Transport for Pipe {
+        fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
+            return self.input.read(buf);
+        }
+
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
+            self.output.extend_from_slice(buf);
+            return Ok( () );
+        }
+    }
+
+    #[test]
+    fn default_recv_timeout() {
+        let mut pipe = Pipe { input: Cursor::new(b"hello".to_vec()), output: Vec::new() };
+        let mut buf = vec![0; 16];
+
+        // a Pipe can't time out, so it's a plain read
+        assert_eq!(Some(5), pipe.recv_timeout(&mut buf, Duration::from_millis(1)).expect("Error reading"));
+        assert_eq!(b"hello", &buf[..5]);
+        assert_eq!(Some(0), pipe.recv_timeout(&mut buf, Duration::from_millis(1)).expect("Error reading"));
+    }
+
+    #[test]
+    fn open_errors() {
+        let dir = env::temp_dir().join(format!("qcp_open_errors_{}", process::id()));
+        let missing = dir.join("missing.bin");
+
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        // the error says which file, not just that there's no such file
+        let mut pipe = Pipe { input: Cursor::new(Vec::new()), output: Vec::new() };
+        let err = send_files(&mut pipe, &[missing.clone()], false, None).expect_err("Sent a file that isn't there");
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&missing.display().to_string()), "{}", err);
+        assert!(err.to_string().contains("source file"), "{}", err);
+
+        // and where the receiver couldn't write
+        let target = dir.join("no_such_dir").join("out.bin");
+        let mut pipe = Pipe { input: Cursor::new(b"hello".to_vec()), output: Vec::new() };
+        let err = recv_files(&mut pipe, &target, false).expect_err("Received into a directory that isn't there");
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&target.display().to_string()), "{}", err);
+        assert!(err.to_string().contains("destination file"), "{}", err);
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn streams() {
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        // anything that reads, like stdin
+        let mut pipe = Pipe { input: Cursor::new(Vec::new()), output: Vec::new() };
+        let mut reader :Box<Read + Send> = Box::new(Cursor::new(data.clone()));
+
+        assert_eq!(data.len() as u64, run_sender(&mut pipe, &mut reader).expect("Error sending"));
+        assert!(data == pipe.output);
+
+        // anything that writes, like stdout
+        let mut pipe = Pipe { input: Cursor::new(data.clone()), output: Vec::new() };
+        let mut received = Vec::new();
+
+        assert_eq!(data.len() as u64, run_receiver(&mut pipe, &mut received).expect("Error receiving"));
+        assert!(data == received);
+    }
+
+    /// A Writer that fails every write, like a disk that's full
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _buf: &[u8]) -> Result<usize, IOError> {
+            return Err(IOError::new(ErrorKind::Other, "No space left on device"));
+        }
+
+        fn flush(&mut self) -> Result<(), IOError> {
+            return Ok( () );
+        }
+    }
+
+    #[test]
+    fn write_errors() {
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        // a transport that's already finished can't be written to, and run_sender says so
+        let mut transport = InMemoryTransport::new();
+        let mut reader = Cursor::new(data.clone());
+
+        transport.finish().expect("Error finishing");
+
+        let err = run_sender(&mut transport, &mut reader).expect_err("Sent over a finished transport");
+
+        assert_eq!(ErrorKind::BrokenPipe, err.kind());
+
+        // nor is the writer's error dropped on the receiving end
+        let mut pipe = Pipe { input: Cursor::new(data), output: Vec::new() };
+        let err = run_receiver(&mut pipe, &mut FullDisk).expect_err("Received onto a full disk");
+
+        assert_eq!(ErrorKind::Other, err.kind());
+        assert!(err.to_string().contains("No space left"), "{}", err);
+    }
+
+    /// A Transport that reads what it was given, noting when it's all been read
+    struct Draining {
+        input: Cursor<Vec<u8>>,
+        drained: Option<Instant>
+    }
+
+    impl Transport for Draining {
+        fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
+            let amt = self.input.read(buf)?;
+
+            if amt == 0 && self.drained.is_none() {
+                self.drained = Some(Instant::now());
+            }
+
+            return Ok(amt);
+        }
+
+        fn write_all(&mut self, _buf: &[u8]) -> Result<(), IOError> {
+            return Ok( () );
+        }
+    }
+
+    /// A Writer that sleeps through every write, like a disk that's seeking
+    struct SlowWriter {
+        written: Vec<u8>,
+        delay: Duration
+    }
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, IOError> {
+            thread::sleep(self.delay);
+            self.written.extend_from_slice(buf);
+
+            return Ok(buf.len());
+        }
+
+        fn flush(&mut self) -> Result<(), IOError> {
+            return Ok( () );
+        }
+    }
+
+    #[test]
+    fn slow_writer() {
+        const CHUNKS :usize = 20;
+        const DELAY :Duration = Duration::from_millis(10);
+
+        let data = (0..CHUNKS * MAX_PAYLOAD_SIZE).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut transport = Draining { input: Cursor::new(data.clone()), drained: None };
+        let mut writer = SlowWriter { written: Vec::new(), delay: DELAY };
+
+        let start = Instant::now();
+
+        assert_eq!(data.len() as u64, run_receiver(&mut transport, &mut writer).expect("Error receiving"));
+
+        // everything was read while the writer was still on the first few chunks
+        let drained = transport.drained.expect("Never read to the end") - start;
+
+        assert!(drained < DELAY * CHUNKS as u32 / 4, "Took {:?} to read everything", drained);
+        assert!(start.elapsed() >= DELAY * CHUNKS as u32);
+        assert!(data == writer.written);
+    }
+
+    /// A Transport that announces a lone file w/header, then reads what it was given
+    /// Notes how long the file at path is when the first read comes, before anything's written to it
+    struct Announcing {
+        header: Option<FileHeader>,
+        input: Cursor<Vec<u8>>,
+        path: PathBuf,
+        len_at_first_read: Option<u64>
+    }
+
+    impl Transport for Announcing {
+        fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
+            if self.len_at_first_read.is_none() {
+                self.len_at_first_read = Some(fs::metadata(&self.path)?.len());
+            }
+
+            return self.input.read(buf);
+        }
+
+        fn write_all(&mut self, _buf: &[u8]) -> Result<(), IOError> {
+            return Ok( () );
+        }
+
+        fn next_file(&mut self) -> Result<Option<FileHeader>, IOError> {
+            return Ok(self.header.take());
+        }
+    }
+
+    #[test]
+    fn preallocated() {
+        let dir = env::temp_dir().join(format!("qcp_preallocated_{}", process::id()));
+        let dst = dir.join("dst.bin");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        let data = (0..10_000).map(|i| i as u8).collect::<Vec<u8>>();
+        let announce = |len: u64, data: &[u8]| Announcing {
+            header: Some(FileHeader { name: "dst.bin".to_string(), len, mode: None, mtime: None, lone: true, block_size: None, verify_only: false }),
+            input: Cursor::new(data.to_vec()),
+            path: dst.clone(),
+            len_at_first_read: None
+        };
+
+        // the whole length is there before any of it's received
+        let mut transport = announce(data.len() as u64, &data);
+
+        recv_files(&mut transport, &dst, false).expect("Error receiving file");
+
+        assert_eq!(Some(data.len() as u64), transport.len_at_first_read);
+        assert!(data == fs::read(&dst).expect("Error reading file"));
+
+        // a sender that announces more than it sends doesn't leave the rest behind
+        let mut transport = announce(2 * data.len() as u64, &data);
+
+        recv_files(&mut transport, &dst, false).expect("Error receiving file");
+
+        assert_eq!(Some(2 * data.len() as u64), transport.len_at_first_read);
+        assert!(data == fs::read(&dst).expect("Error reading file"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn truncate_and_append() {
+        let dir = env::temp_dir().join(format!("qcp_truncate_and_append_{}", process::id()));
+        let dst = dir.join("dst.bin");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        let announce = |data: &[u8], block_size: Option<u32>| Announcing {
+            header: Some(FileHeader { name: "dst.bin".to_string(), len: data.len() as u64, mode: None, mtime: None, lone: true, block_size, verify_only: false }),
+            input: Cursor::new(data.to_vec()),
+            path: dst.clone(),
+            len_at_first_read: None
+        };
+
+        // nothing's left of a longer file that was there
+        for &announced in &[false, true] {
+            fs::write(&dst, vec![0xFF; 10_000]).expect("Error writing file");
+
+            if announced {
+                recv_files(&mut announce(b"hello", None), &dst, false).expect("Error receiving file");
+            } else {
+                recv_files(&mut Pipe { input: Cursor::new(b"hello".to_vec()), output: Vec::new() }, &dst, false).expect("Error receiving stream");
+            }
+
+            assert_eq!(b"hello".to_vec(), fs::read(&dst).expect("Error reading file"));
+
+            // and when appending it goes after what's there
+            if announced {
+                recv_files(&mut announce(b" world", None), &dst, true).expect("Error appending file");
+            } else {
+                recv_files(&mut Pipe { input: Cursor::new(b" world".to_vec()), output: Vec::new() }, &dst, true).expect("Error appending stream");
+            }
+
+            assert_eq!(b"hello world".to_vec(), fs::read(&dst).expect("Error reading file"));
+        }
+
+        // or in a file that wasn't there yet
+        fs::remove_file(&dst).expect("Error removing file");
+        recv_files(&mut announce(b"hello", None), &dst, true).expect("Error appending to a new file");
+
+        assert_eq!(b"hello".to_vec(), fs::read(&dst).expect("Error reading file"));
+
+        // a delta replaces the whole file, so there's nothing to append it to
+        let err = recv_files(&mut announce(b"hello", Some(4)), &dst, true).expect_err("Appended a delta");
+
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(b"hello".to_vec(), fs::read(&dst).expect("Error reading file"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn preallocate_too_large() {
+        let dir = env::temp_dir().join(format!("qcp_preallocate_too_large_{}", process::id()));
+        let dst = dir.join("dst.bin");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        let header = FileHeader { name: "dst.bin".to_string(), len: 1 << 62, mode: None, mtime: None, lone: true, block_size: None, verify_only: false };
+        let mut transport = Announcing { header: Some(header), input: Cursor::new(vec![0; 16]), path: dst.clone(), len_at_first_read: None };
+
+        // turned away before anything's read
+        let err = recv_files(&mut transport, &dst, false).expect_err("Received a file that won't fit");
+
+        assert_eq!(ErrorKind::Other, err.kind());
+        assert!(err.to_string().contains("room"), "{}", err);
+        assert_eq!(None, transport.len_at_first_read);
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn two_files() {
+        let dir = env::temp_dir().join(format!("qcp_two_files_{}", process::id()));
+        let src = dir.join("src");
+        let dst = dir.join("dst");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&src).expect("Error creating source dir");
+
+        let first = (0..5000).map(|i| i as u8).collect::<Vec<u8>>();
+        let second = b"the second file".to_vec();
+
+        File::create(src.join("first.bin")).and_then(|mut f| f.write_all(&first)).expect("Error writing first file");
+        File::create(src.join("second.txt")).and_then(|mut f| f.write_all(&second)).expect("Error writing second file");
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let files = vec![src.join("first.bin"), src.join("second.txt")];
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            send_files(&mut sender, &files, false, None).expect("Error sending files");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let written = recv_files(&mut recver, &dst, false).expect("Error receiving files");
+
+        send_handle.join().expect("Send thread panicked");
+
+        let dst = fs::canonicalize(&dst).expect("Error canonicalizing");
+
+        assert_eq!(vec![dst.join("first.bin"), dst.join("second.txt")], written);
+        assert!(first == fs::read(dst.join("first.bin")).expect("Error reading first file"));
+        assert!(second == fs::read(dst.join("second.txt")).expect("Error reading second file"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn preserve() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = env::temp_dir().join(format!("qcp_preserve_{}", process::id()));
+        let src = dir.join("tool.sh");
+        let dst = dir.join("copy.sh");
+        let mtime = UNIX_EPOCH + Duration::new(1_500_000_000, 500_000_000);
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        {
+            let mut file = File::create(&src).expect("Error creating file");
+
+            file.write_all(b"#!/bin/sh\necho hello\n").expect("Error writing file");
+            file.set_permissions(fs::Permissions::from_mode(0o755)).expect("Error setting mode");
+            file.set_modified(mtime).expect("Error setting mtime");
+        }
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let files = vec![src.clone()];
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            send_files(&mut sender, &files, true, None).expect("Error sending file");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let written = recv_files(&mut recver, &dst, false).expect("Error receiving file");
+
+        send_handle.join().expect("Send thread panicked");
+
+        // still the file itself, not a directory of one
+        assert_eq!(vec![dst.clone()], written);
+        assert!(fs::read(&src).expect("Error reading source") == fs::read(&dst).expect("Error reading copy"));
+
+        let metadata = fs::metadata(&dst).expect("Error reading metadata");
+
+        assert_eq!(0o755, metadata.permissions().mode() & 0o777);
+        assert_eq!(mtime, metadata.modified().expect("Error reading mtime"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn delta() {
+        const BLOCK_SIZE :usize = 4096;
+
+        let dir = env::temp_dir().join(format!("qcp_delta_{}", process::id()));
+        let src = dir.join("new.bin");
+        let dst = dir.join("old.bin");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        // ten whole blocks and a short one; the receiver's copy differs in one block, and has more after the end
+        let data = (0..10 * BLOCK_SIZE + 1000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let mut old = data.clone();
+
+        for b in &mut old[3 * BLOCK_SIZE..4 * BLOCK_SIZE] {
+            *b = !*b;
+        }
+
+        old.extend_from_slice(&[0xAA; 500]);
+
+        File::create(&src).and_then(|mut f| f.write_all(&data)).expect("Error writing new file");
+        File::create(&dst).and_then(|mut f| f.write_all(&old)).expect("Error writing old file");
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let files = vec![src.clone()];
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            send_files(&mut sender, &files, false, Some(BLOCK_SIZE as u32)).expect("Error sending file");
+            sender.finish().expect("Error calling finish");
+
+            sender.bytes_transferred()
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let written = recv_files(&mut recver, &dst, false).expect("Error receiving file");
+
+        let sent = send_handle.join().expect("Send thread panicked");
+
+        // only the block that differs
+        assert_eq!(vec![dst.clone()], written);
+        assert_eq!(BLOCK_SIZE as u64, sent);
+        assert!(data == fs::read(&dst).expect("Error reading file"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn unknown_length() {
+        let dir = env::temp_dir().join(format!("qcp_unknown_length_{}", process::id()));
+        let dst = dir.join("stream.bin");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        // longer than the receiver's 0-byte file, which grows as it arrives
+        let data = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let expected = data.clone();
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            let sent = send_stream(&mut sender, &mut Cursor::new(data)).expect("Error sending stream");
+            sender.finish().expect("Error calling finish");
+
+            sent
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let written = recv_files(&mut recver, &dst, false).expect("Error receiving stream");
+
+        let sent = send_handle.join().expect("Send thread panicked");
+
+        assert_eq!(vec![dst.clone()], written);
+        assert_eq!(expected.len() as u64, sent);
+        assert!(expected == fs::read(&dst).expect("Error reading file"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn verify_only() {
+        const BLOCK_SIZE :usize = 4096;
+
+        /// Verifies src against the receiver's dst, returning the ranges that differ and the bytes sent
+        fn verify(src: &PathBuf, dst: &PathBuf) -> (Vec<Range<u64>>, u64) {
+            let mock_socket = PacketDroppingSocket::new();
+            let duplex_socket = mock_socket.duplex();
+
+            let files = vec![src.clone()];
+            let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+                let config = Configuration::default();
+                let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+                let mut verified = verify_files(&mut sender, &files, BLOCK_SIZE as u32).expect("Error verifying file");
+                sender.finish().expect("Error calling finish");
+
+                assert_eq!(1, verified.len());
+                (verified.remove(0).1, sender.bytes_transferred())
+            }).expect("Error spawning send thread");
+
+            let config = Configuration::default();
+            let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+
+            // nothing's written
+            assert!(recv_files(&mut recver, dst, false).expect("Error receiving file").is_empty());
+
+            return send_handle.join().expect("Send thread panicked");
+        }
+
+        let dir = env::temp_dir().join(format!("qcp_verify_only_{}", process::id()));
+        let src = dir.join("src.bin");
+        let dst = dir.join("dst.bin");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        // ten whole blocks and a short one
+        let data = (0..10 * BLOCK_SIZE + 1000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+
+        File::create(&src).and_then(|mut f| f.write_all(&data)).expect("Error writing source file");
+        File::create(&dst).and_then(|mut f| f.write_all(&data)).expect("Error writing destination file");
+
+        assert_eq!((Vec::new(), 0), verify(&src, &dst));
+
+        // one byte of one block
+        let mut old = data.clone();
+
+        old[3 * BLOCK_SIZE + 10] ^= 0xFF;
+        File::create(&dst).and_then(|mut f| f.write_all(&old)).expect("Error writing destination file");
+
+        assert_eq!((vec![3 * BLOCK_SIZE as u64..4 * BLOCK_SIZE as u64], 0), verify(&src, &dst));
+        assert!(old == fs::read(&dst).expect("Error reading file"));
+
+        // the receiver doesn't have it at all, and it isn't created
+        fs::remove_file(&dst).expect("Error removing destination file");
+
+        assert_eq!((vec![0..data.len() as u64], 0), verify(&src, &dst));
+        assert!(!dst.exists());
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn output_dir() {
+        let dir = env::temp_dir().join(format!("qcp_output_dir_{}", process::id()));
+        let src = dir.join("report.txt");
+        let dst = dir.join("downloads");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Error creating dir");
+
+        let data = b"the only file".to_vec();
+
+        File::create(&src).and_then(|mut f| f.write_all(&data)).expect("Error writing file");
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let files = vec![src.clone()];
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+
+            send_files(&mut sender, &files, false, None).expect("Error sending file");
+            sender.finish().expect("Error calling finish");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let written = recv_into(&mut recver, &dst, false).expect("Error receiving file");
+
+        send_handle.join().expect("Send thread panicked");
+
+        let dst = fs::canonicalize(&dst).expect("Error canonicalizing");
+
+        // under the name it was sent w/, even though it's the only file
+        assert_eq!(vec![dst.join("report.txt")], written);
+        assert!(data == fs::read(dst.join("report.txt")).expect("Error reading file"));
+
+        fs::remove_dir_all(&dir).expect("Error removing dir");
+    }
+
+    #[test]
+    fn output_dir_traversal() {
+        let dir = env::temp_dir().join(format!("qcp_output_dir_traversal_{}", process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+
+        let mock_socket = PacketDroppingSocket::new();
+        let duplex_socket = mock_socket.duplex();
+
+        let send_handle = thread::Builder::new().name("send".into()).spawn(move || {
+            let config = Configuration::default();
+            let mut sender = Sender::<PacketDroppingSocket>::connect(mock_socket, &config).expect("Couldn't call connect");
+            let header = FileHeader { name: "../../etc/passwd".to_string(), len: 4, mode: None, mtime: None, lone: true, block_size: None, verify_only: false };
+
+            // no finish, the receiver gives up on the name before anything's acknowledged
+            sender.start_file(&header).expect("Error starting file");
+            sender.write_all(b"root").expect("Error writing file");
+            sender.end_file().expect("Error ending file");
+        }).expect("Error spawning send thread");
+
+        let config = Configuration::default();
+        let mut recver = Receiver::<PacketDroppingSocket>::listen(duplex_socket, &config).expect("Couldn't create receiver");
+        let err = recv_into(&mut recver, &dir, false).expect_err("Received a file outside of the directory");
+
+        assert_eq!(ErrorKind::PermissionDenied, err.kind());
+
+        send_handle.join().expect("Send thread panicked");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
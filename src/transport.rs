@@ -1,9 +1,246 @@
-use std::io::{Error as IOError};
+use std::io::{Error as IOError, ErrorKind};
+use std::time::Duration;
+
+use bbr_transport::{FileHeader, TransferStats};
 
 pub trait Transport {
     /// Read up to buf.len() bytes from the underlying transport
+    /// What's read is a separate stream from what's written; neither waits on the other
     fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError>;
 
+    /// Like read, but waits no longer than timeout for something to arrive
+    /// Returns None if nothing did, otherwise what read would have
+    /// A transport that can't time out a read just reads, waiting as long as that takes
+    fn recv_timeout(&mut self, buf: &mut[u8], _timeout: Duration) -> Result<Option<usize>, IOError> {
+        return self.read(buf).map(Some);
+    }
+
     /// Write all buf.len() bytes to the underlying transport
+    /// Any size buf is accepted; this blocks until the transport has room for all of it
     fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError>;
-}
\ No newline at end of file
+
+    /// Block until everything written has been delivered
+    fn flush(&mut self) -> Result<(), IOError> {
+        return Ok( () );
+    }
+
+    /// Tell the other side nothing more is coming, and wait for everything written to be delivered
+    fn finish(&mut self) -> Result<(), IOError> {
+        return self.flush();
+    }
+
+    /// Announce the next file, when sending more than one
+    fn start_file(&mut self, header: &FileHeader) -> Result<(), IOError> {
+        return Err(IOError::new(ErrorKind::InvalidInput, format!("Cannot send more than one file: {}", header.name)));
+    }
+
+    /// Whether start_file works, so even a lone file can be sent w/its name
+    fn sends_headers(&self) -> bool {
+        return false;
+    }
+
+    /// Mark the end of the file announced by start_file
+    fn end_file(&mut self) -> Result<(), IOError> {
+        return Ok( () );
+    }
+
+    /// Tell the other side to keep the next len bytes of its copy of the file announced by start_file, instead of sending them
+    fn skip(&mut self, len: u64) -> Result<(), IOError> {
+        return Err(IOError::new(ErrorKind::InvalidInput, format!("Cannot skip {} bytes of what the other side has", len)));
+    }
+
+    /// How many bytes of its copy the other side said to keep, once read has returned 0 for it
+    /// None at the end of the file, or when the transport can't skip
+    fn take_skip(&mut self) -> Option<u64> {
+        return None;
+    }
+
+    /// Wait for the next file announced by the other side
+    /// None when it's a single stream, or at the end of the transfer
+    fn next_file(&mut self) -> Result<Option<FileHeader>, IOError> {
+        return Ok(None);
+    }
+
+    /// What was sent and how it went, when the transport keeps track
+    fn stats(&self) -> Option<TransferStats> {
+        return None;
+    }
+}
+
+pub mod mocks {
+    use std::collections::VecDeque;
+    use std::io::{Error as IOError, ErrorKind};
+    use std::sync::{Arc, Mutex, Condvar};
+    use std::time::{Duration, Instant};
+
+    use transport::Transport;
+
+    struct Loopback {
+        bytes: VecDeque<u8>,    // written, but not yet read
+        finished: bool          // finish was called, so once bytes is empty, read returns 0
+    }
+
+    /// A Transport that moves bytes through memory, for testing what runs over one w/out any sockets at all
+    /// What's written is read back, by it or any of its clones, so one end can write while a clone reads
+    /// read waits for something to be written, or for finish
+    #[derive(Clone)]
+    pub struct InMemoryTransport {
+        loopback: Arc<(Mutex<Loopback>, Condvar)>
+    }
+
+    impl InMemoryTransport {
+        pub fn new() -> Self {
+            let loopback = Loopback { bytes: VecDeque::new(), finished: false };
+
+            InMemoryTransport { loopback: Arc::new((Mutex::new(loopback), Condvar::new())) }
+        }
+
+        /// The bytes written, but not yet read
+        pub fn unread(&self) -> usize {
+            self.loopback.0.lock().unwrap().bytes.len()
+        }
+
+        /// Moves up to buf.len() bytes out of loopback, returning how many
+        fn take(loopback: &mut Loopback, buf: &mut [u8]) -> usize {
+            let amt = buf.len().min(loopback.bytes.len());
+
+            for (dst, src) in buf.iter_mut().zip(loopback.bytes.drain(..amt)) {
+                *dst = src;
+            }
+
+            return amt;
+        }
+    }
+
+    impl Transport for InMemoryTransport {
+        fn read(&mut self, buf: &mut[u8]) -> Result<usize, IOError> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            let (ref lock, ref written) = *self.loopback;
+            let mut loopback = lock.lock().unwrap();
+
+            while loopback.bytes.is_empty() && !loopback.finished {
+                loopback = written.wait(loopback).unwrap();
+            }
+
+            return Ok(InMemoryTransport::take(&mut loopback, buf));
+        }
+
+        fn recv_timeout(&mut self, buf: &mut[u8], timeout: Duration) -> Result<Option<usize>, IOError> {
+            if buf.is_empty() {
+                return Ok(Some(0));
+            }
+
+            let (ref lock, ref written) = *self.loopback;
+            let mut loopback = lock.lock().unwrap();
+            let deadline = Instant::now() + timeout;
+
+            while loopback.bytes.is_empty() && !loopback.finished {
+                let now = Instant::now();
+
+                if now >= deadline {
+                    return Ok(None);
+                }
+
+                loopback = written.wait_timeout(loopback, deadline - now).unwrap().0;
+            }
+
+            return Ok(Some(InMemoryTransport::take(&mut loopback, buf)));
+        }
+
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), IOError> {
+            let (ref lock, ref written) = *self.loopback;
+            let mut loopback = lock.lock().unwrap();
+
+            if loopback.finished {
+                return Err(IOError::new(ErrorKind::BrokenPipe, "Cannot write after finish"));
+            }
+
+            loopback.bytes.extend(buf);
+            written.notify_all();
+
+            return Ok( () );
+        }
+
+        /// Once what's been written is read, read returns 0
+        fn finish(&mut self) -> Result<(), IOError> {
+            let (ref lock, ref written) = *self.loopback;
+
+            lock.lock().unwrap().finished = true;
+            written.notify_all();
+
+            return Ok( () );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::thread;
+    use std::time::Duration;
+
+    use transport::Transport;
+    use transport::mocks::InMemoryTransport;
+
+    #[test]
+    fn in_memory_loopback() {
+        let mut transport = InMemoryTransport::new();
+        let data = (0..1000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        transport.write_all(&data[..600]).expect("Error calling write_all");
+        transport.write_all(&data[600..]).expect("Error calling write_all");
+
+        assert_eq!(1000, transport.unread());
+
+        // less than what was written at a time, and across the writes
+        let mut buf = vec![0; 256];
+        let mut received = Vec::new();
+
+        while received.len() < data.len() {
+            let amt = transport.read(&mut buf).expect("Error calling read");
+
+            assert!(amt > 0 && amt <= 256);
+            received.extend_from_slice(&buf[..amt]);
+        }
+
+        assert!(data == received);
+        assert_eq!(0, transport.unread());
+
+        // nothing there, and nothing coming yet
+        assert_eq!(None, transport.recv_timeout(&mut buf, Duration::from_millis(10)).expect("Error calling recv_timeout"));
+
+        // then the end
+        transport.finish().expect("Error calling finish");
+
+        assert_eq!(0, transport.read(&mut buf).expect("Error calling read"));
+        assert_eq!(ErrorKind::BrokenPipe, transport.write_all(b"more").expect_err("Wrote after finish").kind());
+    }
+
+    #[test]
+    fn in_memory_clones() {
+        let mut writer = InMemoryTransport::new();
+        let mut reader = writer.clone();
+
+        // the read waits for the write from the other thread
+        let write_handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+
+            writer.write_all(b"hello").expect("Error calling write_all");
+            writer.finish().expect("Error calling finish");
+        });
+
+        let mut buf = vec![0; 3];
+
+        assert_eq!(3, reader.read(&mut buf).expect("Error calling read"));
+        assert_eq!(b"hel", &buf[..]);
+        assert_eq!(Some(2), reader.recv_timeout(&mut buf, Duration::from_secs(1)).expect("Error calling recv_timeout"));
+        assert_eq!(b"lo", &buf[..2]);
+
+        write_handle.join().expect("Write thread panicked");
+
+        assert_eq!(0, reader.read(&mut buf).expect("Error calling read"));
+    }
+}